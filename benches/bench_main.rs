@@ -57,7 +57,7 @@ fn main() {
     println!("\n--- Parser ---");
 
     results.push(bench("parser", "echo hello", 10_000, || {
-        let _ = rush::parser::parse("echo hello", 0, &[], false, &std::collections::HashMap::new());
+        let _ = rush::parser::parse("echo hello", 0, "rush", &[], false, &std::collections::HashMap::new());
     }));
 
     results.push(bench(
@@ -65,12 +65,12 @@ fn main() {
         "echo \"hello $HOME world\"",
         10_000,
         || {
-            let _ = rush::parser::parse("echo \"hello $HOME world\"", 0, &[], false, &std::collections::HashMap::new());
+            let _ = rush::parser::parse("echo \"hello $HOME world\"", 0, "rush", &[], false, &std::collections::HashMap::new());
         },
     ));
 
     results.push(bench("parser", "ls | grep Cargo | head -1", 10_000, || {
-        let _ = rush::parser::parse("ls | grep Cargo | head -1", 0, &[], false, &std::collections::HashMap::new());
+        let _ = rush::parser::parse("ls | grep Cargo | head -1", 0, "rush", &[], false, &std::collections::HashMap::new());
     }));
 
     results.push(bench(
@@ -78,20 +78,20 @@ fn main() {
         "cat < /dev/null > /dev/null 2> /dev/null",
         10_000,
         || {
-            let _ = rush::parser::parse("cat < /dev/null > /dev/null 2> /dev/null", 0, &[], false, &std::collections::HashMap::new());
+            let _ = rush::parser::parse("cat < /dev/null > /dev/null 2> /dev/null", 0, "rush", &[], false, &std::collections::HashMap::new());
         },
     ));
 
     results.push(bench("parser", "sleep 1 &", 10_000, || {
-        let _ = rush::parser::parse("sleep 1 &", 0, &[], false, &std::collections::HashMap::new());
+        let _ = rush::parser::parse("sleep 1 &", 0, "rush", &[], false, &std::collections::HashMap::new());
     }));
 
     results.push(bench("parser", "echo hello && echo world", 10_000, || {
-        let _ = rush::parser::parse("echo hello && echo world", 0, &[], false, &std::collections::HashMap::new());
+        let _ = rush::parser::parse("echo hello && echo world", 0, "rush", &[], false, &std::collections::HashMap::new());
     }));
 
     results.push(bench("parser", "a || b ; c && d", 10_000, || {
-        let _ = rush::parser::parse("a || b ; c && d", 0, &[], false, &std::collections::HashMap::new());
+        let _ = rush::parser::parse("a || b ; c && d", 0, "rush", &[], false, &std::collections::HashMap::new());
     }));
 
     for r in &results {
@@ -123,7 +123,7 @@ fn main() {
     println!("\n--- Spawn (posix_spawnp) ---");
 
     results.push(bench("spawn", "/bin/true (posix_spawnp)", 1_000, || {
-        match rush::spawn::spawn(&["/bin/true"], 0, None, None, None, &[], &[]) {
+        match rush::spawn::spawn(&["/bin/true"], None, None, 0, None, None, None, &[], &[]) {
             Ok(pid) => {
                 let mut status = 0i32;
                 unsafe { libc::waitpid(pid, &mut status, 0); }
@@ -132,6 +132,14 @@ fn main() {
         }
     }));
 
+    let mut ctx = rush::spawn::SpawnContext::new();
+    results.push(bench("spawn", "/bin/true (SpawnContext reuse)", 1_000, || {
+        if let Ok(pid) = ctx.spawn_program("/bin/true", &["/bin/true"], None, None, 0, None, None, None, &[], &[]) {
+            let mut status = 0i32;
+            unsafe { libc::waitpid(pid, &mut status, 0); }
+        }
+    }));
+
     for r in &results {
         r.print();
     }
@@ -141,7 +149,7 @@ fn main() {
     println!("\n--- Full pipeline (parse + spawn + wait) ---");
 
     results.push(bench("full", "/bin/echo hello > /dev/null", 1_000, || {
-        if let Ok(Some(list)) = rush::parser::parse("/bin/echo hello > /dev/null", 0, &[], false, &std::collections::HashMap::new()) {
+        if let Ok(Some(list)) = rush::parser::parse("/bin/echo hello > /dev/null", 0, "rush", &[], false, &std::collections::HashMap::new()) {
             rush::executor::execute(&mut shell, &list, "/bin/echo hello > /dev/null");
         }
     }));
@@ -159,6 +167,19 @@ fn main() {
         let _ = rush::glob::expand("*.rs");
     }));
 
+    results.push(bench("glob", "matches_pattern(\"*[0-9].t?t\") x100", 10_000, || {
+        for _ in 0..100 {
+            let _ = rush::glob::matches_pattern("*[0-9].t?t", "report_2024_final3.txt");
+        }
+    }));
+
+    let compiled = rush::glob::Pattern::new("*[0-9].t?t");
+    results.push(bench("glob", "Pattern::matches(\"*[0-9].t?t\") x100", 10_000, || {
+        for _ in 0..100 {
+            let _ = compiled.matches("report_2024_final3.txt");
+        }
+    }));
+
     for r in &results {
         r.print();
     }
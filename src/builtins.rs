@@ -4,7 +4,7 @@
 //! `try_exec()` が `Some(status)` を返せばビルトインとして処理済み、
 //! `None` なら外部コマンドとしてexecutorに委ねる。
 //!
-//! ## 対応ビルトイン（32 種）
+//! ## 対応ビルトイン（35 種）
 //!
 //! - シェル制御: `exit`, `cd`（`cd -` / OLDPWD 対応）, `exec`
 //! - 出力: `pwd`, `echo`（`-n` 対応）
@@ -12,13 +12,14 @@
 //! - ジョブコントロール: `jobs`, `fg`, `bg`, `wait`
 //! - エイリアス: `alias`, `unalias`（`-a` 全削除）
 //! - スクリプト: `source` / `.`（ファイル行単位実行、`if`/`fi`・`for`/`while`/`until`・`case`/`esac`・関数定義対応）
-//! - 情報: `type`
+//! - 情報: `help`（一覧・書式表示）, `type`, `hash`（コマンド位置キャッシュの一覧・`-r` クリア・`-d` 個別削除）, `times`（累積 CPU 時間）
 //! - 実行制御: `command`（`-v` パス表示、エイリアスバイパス）, `builtin`（ビルトイン限定実行）
 //! - フロー制御: `true` / `:`（常に 0）, `false`（常に 1）, `return`（関数・source からの早期脱出）, `break`（ループ脱出）, `continue`（ループ次反復）
 //! - 条件判定: `test` / `[`（文字列・整数・ファイル判定、`!` 否定）
 //! - 出力: `printf`（`%s`, `%d`, `%x`, `%o`, 幅指定、ゼロパディング、エスケープ）
-//! - ディレクトリスタック: `pushd`（スタックに積んで移動）, `popd`（ポップして移動）, `dirs`（一覧）
+//! - ディレクトリスタック: `pushd`（スタックに積んで移動・`+N`/`-N` で回転）, `popd`（ポップして移動）, `dirs`（一覧）
 //! - 関数: `local`（ローカル変数設定）, `shift`（位置パラメータシフト）, `unset -f`（関数削除）
+//! - オプション: `set`（`-e` / `-u` / `-o pipefail`）, `shopt`（`dotglob` / `nullglob` / `failglob` / `nocaseglob` などの切り替え）
 //! - シグナル: `trap`（`trap 'cmd' SIGNAL`、一覧、`-` でリセット）
 //! - 履歴: `history`（main.rs で特別扱い、`-c` クリア、`N` 件表示）
 
@@ -26,9 +27,9 @@ use std::env;
 use std::io::Write;
 use std::path::Path;
 
-use crate::job::{self, JobStatus};
+use crate::job::{self, Job, JobStatus, JobTable};
 use crate::shell::Shell;
-use crate::{executor, parser};
+use crate::{executor, glob, parser};
 
 /// コマンド名がビルトインかどうかを判定する。
 ///
@@ -38,7 +39,7 @@ use crate::{executor, parser};
 /// - [`complete`](crate::complete): ビルトイン名のリストを補完候補に使用（`BUILTINS` 定数と同期）
 pub fn is_builtin(name: &str) -> bool {
     matches!(name, "exit" | "cd" | "pwd" | "echo" | "export" | "unset"
-                 | "jobs" | "fg" | "bg" | "type" | "source" | "."
+                 | "jobs" | "fg" | "bg" | "type" | "hash" | "times" | "source" | "."
                  | "alias" | "unalias" | "history"
                  | "command" | "builtin" | "read" | "exec" | "wait"
                  | "true" | "false" | ":" | "return"
@@ -47,11 +48,58 @@ pub fn is_builtin(name: &str) -> bool {
                  | "trap"
                  | "break" | "continue"
                  | "local" | "shift"
-                 | "set"
+                 | "set" | "shopt"
                  | "eval"
-                 | "declare")
+                 | "declare"
+                 | "help")
 }
 
+/// ビルトインごとの書式。`help` ビルトインで使用する（[`is_builtin`] と同期）。
+const BUILTIN_HELP: &[(&str, &str)] = &[
+    (".", ". filename [arguments]"),
+    (":", ": [arguments]"),
+    ("[", "[ arg... ]"),
+    ("alias", "alias [name[=value] ...]"),
+    ("bg", "bg [job_spec]"),
+    ("break", "break [n]"),
+    ("builtin", "builtin shell-builtin [arg ...]"),
+    ("cd", "cd [dir | -]"),
+    ("command", "command [-v] command [arg ...]"),
+    ("continue", "continue [n]"),
+    ("declare", "declare [-a] [name[=value] ...]"),
+    ("dirs", "dirs [-clpv]"),
+    ("echo", "echo [-n] [--] [arg ...]"),
+    ("eval", "eval [arg ...]"),
+    ("exec", "exec [command [arg ...]]"),
+    ("exit", "exit [n]"),
+    ("export", "export [-n] [-p] [name[=value] ...]"),
+    ("false", "false"),
+    ("fg", "fg [job_spec]"),
+    ("hash", "hash [-r] [-d] [name ...]"),
+    ("help", "help [name]"),
+    ("history", "history [-c] [n]"),
+    ("jobs", "jobs [-lp] [job_spec ...]"),
+    ("local", "local [-] [name[=value] ...]"),
+    ("popd", "popd [+N | -N]"),
+    ("printf", "printf format [arguments]"),
+    ("pushd", "pushd [dir | +N | -N]"),
+    ("pwd", "pwd"),
+    ("read", "read [-p prompt] [name ...]"),
+    ("return", "return [n]"),
+    ("set", "set [-eu] [-o option] [arg ...]"),
+    ("shift", "shift [n]"),
+    ("shopt", "shopt [-pqsu] [optname ...]"),
+    ("source", "source filename [arguments]"),
+    ("test", "test [expr]"),
+    ("times", "times"),
+    ("trap", "trap [-] [action] [signal ...]"),
+    ("true", "true"),
+    ("type", "type [-afpt] name [name ...]"),
+    ("unalias", "unalias [-a] name [name ...]"),
+    ("unset", "unset [-f] [-v] [name ...]"),
+    ("wait", "wait [-n] [id ...]"),
+];
+
 /// ビルトインコマンドの実行を試みる。
 ///
 /// 出力系ビルトイン (pwd, echo, export, jobs) はリダイレクト対応のため `stdout` writer に書き込む。
@@ -66,19 +114,21 @@ pub fn try_exec(shell: &mut Shell, args: &[&str], stdout: &mut dyn Write) -> Opt
         "cd" => Some(builtin_cd(args, stdout)),
         "pwd" => Some(builtin_pwd(stdout)),
         "echo" => Some(builtin_echo(args, stdout)),
-        "export" => Some(builtin_export(args, stdout)),
+        "export" => Some(builtin_export(shell, args, stdout)),
         "unset" => Some(builtin_unset(shell, args)),
-        "jobs" => Some(builtin_jobs(shell, stdout)),
+        "jobs" => Some(builtin_jobs(shell, args, stdout)),
         "fg" => Some(builtin_fg(shell, args)),
         "bg" => Some(builtin_bg(shell, args)),
         "type" => Some(builtin_type(shell, args, stdout)),
+        "hash" => Some(builtin_hash(shell, args, stdout)),
+        "times" => Some(builtin_times(stdout)),
         "source" | "." => Some(builtin_source(shell, args)),
         "alias" => Some(builtin_alias(shell, args, stdout)),
         "unalias" => Some(builtin_unalias(shell, args)),
         "command" => Some(builtin_command(shell, args, stdout)),
         "builtin" => Some(builtin_builtin(shell, args, stdout)),
         "read" => Some(builtin_read_with_shell(shell, args)),
-        "exec" => Some(builtin_exec(args)),
+        "exec" => Some(builtin_exec(shell, args)),
         "wait" => Some(builtin_wait(shell, args)),
         "true" | ":" => Some(0),
         "false" => Some(1),
@@ -89,13 +139,15 @@ pub fn try_exec(shell: &mut Shell, args: &[&str], stdout: &mut dyn Write) -> Opt
         "printf" => Some(builtin_printf(args, stdout)),
         "pushd" => Some(builtin_pushd(shell, args, stdout)),
         "popd" => Some(builtin_popd(shell, args, stdout)),
-        "dirs" => Some(builtin_dirs(shell, stdout)),
+        "dirs" => Some(builtin_dirs(shell, args, stdout)),
         "trap" => Some(builtin_trap(shell, args, stdout)),
-        "local" => Some(builtin_local(args)),
+        "local" => Some(builtin_local(shell, args)),
         "shift" => Some(builtin_shift(shell, args)),
         "set" => Some(builtin_set(shell, args, stdout)),
+        "shopt" => Some(builtin_shopt(shell, args, stdout)),
         "eval" => Some(builtin_eval(shell, args)),
         "declare" => Some(builtin_declare(shell, args, stdout)),
+        "help" => Some(builtin_help(args, stdout)),
         _ => None,
     }
 }
@@ -115,10 +167,9 @@ fn builtin_exit(shell: &mut Shell, args: &[&str]) -> i32 {
 
 /// `cd [dir]` — カレントディレクトリを変更する。引数省略時は `$HOME` に移動。
 /// `cd -` で OLDPWD に移動し、新ディレクトリを stdout に表示する。
-/// 成功時は `OLDPWD` 環境変数を更新する。
+/// 引数のチルダ（`~`, `~user`）はここでも展開する。
+/// 成功時は `PWD` / `OLDPWD` 環境変数を更新する。
 fn builtin_cd(args: &[&str], stdout: &mut dyn Write) -> i32 {
-    let oldpwd = env::current_dir().ok().map(|p| p.to_string_lossy().to_string());
-
     let (target, print_dir) = if args.len() > 1 && args[1] == "-" {
         match env::var("OLDPWD") {
             Ok(old) => (old, true),
@@ -128,7 +179,7 @@ fn builtin_cd(args: &[&str], stdout: &mut dyn Write) -> i32 {
             }
         }
     } else if args.len() > 1 {
-        (args[1].to_string(), false)
+        (parser::expand_tilde(args[1]).into_owned(), false)
     } else {
         match env::var("HOME") {
             Ok(home) => (home, false),
@@ -139,27 +190,79 @@ fn builtin_cd(args: &[&str], stdout: &mut dyn Write) -> i32 {
         }
     };
 
-    if let Err(e) = env::set_current_dir(Path::new(&target)) {
+    if let Err(e) = change_directory(&target) {
         eprintln!("rush: cd: {}: {}", target, e);
         1
     } else {
-        if let Some(old) = oldpwd {
-            env::set_var("OLDPWD", &old);
-        }
         if print_dir {
-            if let Ok(cwd) = env::current_dir() {
-                let _ = writeln!(stdout, "{}", cwd.display());
+            if let Ok(pwd) = env::var("PWD") {
+                let _ = writeln!(stdout, "{}", pwd);
             }
         }
         0
     }
 }
 
+/// カレントディレクトリを `target` に変更し、`PWD` / `OLDPWD` を更新する。
+///
+/// 相対パスは現在の論理パス（`$PWD`）に連結し、`.` と `..` を字句的に解決した
+/// 論理パスへ移動する（bash の `cd -L` 相当）。論理パスへの移動に失敗した場合は
+/// `target` をそのまま `chdir` し、`PWD` には物理パスを設定する。
+fn change_directory(target: &str) -> std::io::Result<()> {
+    let current = logical_cwd()?;
+
+    let joined = if target.starts_with('/') {
+        target.to_string()
+    } else {
+        format!("{}/{}", current, target)
+    };
+    let logical = normalize_logical_path(&joined);
+
+    let new_pwd = if env::set_current_dir(glob::raw_path(&logical)).is_ok() {
+        logical
+    } else {
+        env::set_current_dir(glob::raw_path(target))?;
+        glob::name_to_string(env::current_dir()?.as_os_str())
+    };
+    env::set_var("OLDPWD", &current);
+    env::set_var("PWD", &new_pwd);
+    Ok(())
+}
+
+/// 現在の論理カレントディレクトリを返す。
+///
+/// `$PWD` が実際のカレントディレクトリを指している場合のみ信用し、
+/// それ以外（未設定・古い値）は物理パスを返す。
+fn logical_cwd() -> std::io::Result<String> {
+    let physical = env::current_dir()?;
+    match env::var("PWD") {
+        Ok(pwd) if glob::raw_path(&pwd).canonicalize().ok() == physical.canonicalize().ok() => Ok(pwd),
+        _ => Ok(glob::name_to_string(physical.as_os_str())),
+    }
+}
+
+/// 絶対パスの `.` / `..` / 重複スラッシュ / 末尾スラッシュを字句的に解決する。
+fn normalize_logical_path(path: &str) -> String {
+    let mut parts: Vec<&str> = Vec::new();
+    for comp in path.split('/') {
+        match comp {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            _ => parts.push(comp),
+        }
+    }
+    format!("/{}", parts.join("/"))
+}
+
 /// `pwd` — カレントディレクトリを出力する。
 fn builtin_pwd(stdout: &mut dyn Write) -> i32 {
     match env::current_dir() {
         Ok(path) => {
-            let _ = writeln!(stdout, "{}", path.display());
+            use std::os::unix::ffi::OsStrExt;
+            let _ = stdout.write_all(path.as_os_str().as_bytes());
+            let _ = writeln!(stdout);
             0
         }
         Err(e) => {
@@ -169,19 +272,47 @@ fn builtin_pwd(stdout: &mut dyn Write) -> i32 {
     }
 }
 
-/// `echo [-n] args...` — 引数をスペース区切りで出力する。`-n` で改行抑制。
+/// `times` — シェル自身と子プロセスの累積 user/sys CPU 時間を 2 行で出力する。
+fn builtin_times(stdout: &mut dyn Write) -> i32 {
+    for who in [libc::RUSAGE_SELF, libc::RUSAGE_CHILDREN] {
+        let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+        unsafe { libc::getrusage(who, &mut usage) };
+        let _ = writeln!(stdout, "{} {}", format_cpu_time(&usage.ru_utime), format_cpu_time(&usage.ru_stime));
+    }
+    0
+}
+
+/// `timeval` を `XmY.YYYs` 形式に整形する。
+fn format_cpu_time(tv: &libc::timeval) -> String {
+    let ms = tv.tv_sec as u64 * 1000 + tv.tv_usec as u64 / 1000;
+    format!("{}m{}.{:03}s", ms / 60_000, ms / 1000 % 60, ms % 1000)
+}
+
+/// `echo [-n] [--] args...` — 引数をスペース区切りで出力する。`-n` で改行抑制。
+///
+/// 先頭の `-n` / `-nn` 等をオプションとして読み、それ以外の引数（後ろの `-n` を含む）はそのまま出力する。
+/// 最初の引数が `--` ならオプション解析をせずに残りを出力する。
 fn builtin_echo(args: &[&str], stdout: &mut dyn Write) -> i32 {
-    let (no_newline, words) = if args.len() > 1 && args[1] == "-n" {
-        (true, &args[2..])
+    let mut no_newline = false;
+    let mut words = &args[1..];
+    if words.first() == Some(&"--") {
+        words = &words[1..];
     } else {
-        (false, &args[1..])
-    };
+        while let Some(opt) = words.first() {
+            if opt.len() < 2 || !opt.starts_with('-') || !opt[1..].bytes().all(|b| b == b'n') {
+                break;
+            }
+            no_newline = true;
+            words = &words[1..];
+        }
+    }
 
     for (i, word) in words.iter().enumerate() {
         if i > 0 {
             let _ = write!(stdout, " ");
         }
-        let _ = write!(stdout, "{}", word);
+        // 非 UTF-8 ファイル名から退避した文字は元のバイトに戻して出力する
+        let _ = stdout.write_all(&glob::raw_bytes(word));
     }
 
     if !no_newline {
@@ -191,78 +322,349 @@ fn builtin_echo(args: &[&str], stdout: &mut dyn Write) -> i32 {
     0
 }
 
-/// `export [VAR=val...]` — 環境変数を設定する。引数なしなら全変数をソート済みで一覧表示。
-fn builtin_export(args: &[&str], stdout: &mut dyn Write) -> i32 {
-    if args.len() <= 1 {
+/// `export [-n] [-p] [VAR[=val]...]` — 環境変数を設定する。
+///
+/// 引数なし・`-p` なら export 属性を持つ全変数を `declare -x` 形式でソート済み一覧表示する。
+/// `-n` は値を残したまま export 属性だけを外す（[`Shell::unexported`] に記録）。
+/// 値なしの `export VAR` は既存変数の export 属性を付け直す。`export a=1 b=2` のように複数指定でき、
+/// 不正な識別子はエラーを表示して残りの引数の処理を続ける（終了ステータス 1）。
+/// 変数ストアはプロセス環境そのものなので、`-n` の変数は子プロセスの環境を作るときに取り除く。
+fn builtin_export(shell: &mut Shell, args: &[&str], stdout: &mut dyn Write) -> i32 {
+    let mut unexport = false;
+    let mut idx = 1;
+    while idx < args.len() {
+        let arg = args[idx];
+        if arg == "--" {
+            idx += 1;
+            break;
+        }
+        if !arg.starts_with('-') || arg == "-" {
+            break;
+        }
+        for c in arg[1..].chars() {
+            match c {
+                'n' => unexport = true,
+                'p' => {}
+                _ => {
+                    eprintln!("rush: export: -{}: invalid option", c);
+                    eprintln!("export: usage: export [-n] [name[=value] ...] or export -p");
+                    return 2;
+                }
+            }
+        }
+        idx += 1;
+    }
+
+    if idx >= args.len() {
         // 全変数を一覧表示（ソート済み）
-        let mut vars: Vec<(String, String)> = env::vars().collect();
+        let mut vars: Vec<(String, String)> = env::vars()
+            .filter(|(key, _)| !shell.unexported.contains(key))
+            .collect();
         vars.sort_by(|a, b| a.0.cmp(&b.0));
         for (key, value) in &vars {
-            let _ = writeln!(stdout, "declare -x {}=\"{}\"", key, value);
+            let _ = writeln!(stdout, "declare -x {}=\"{}\"", key, quote_declare_value(value));
         }
         return 0;
     }
 
-    for arg in &args[1..] {
-        if let Some(eq_pos) = arg.find('=') {
-            let key = &arg[..eq_pos];
-            let value = &arg[eq_pos + 1..];
+    let mut status = 0;
+    for arg in &args[idx..] {
+        let (key, value) = match arg.find('=') {
+            Some(eq_pos) => (&arg[..eq_pos], Some(&arg[eq_pos + 1..])),
+            None => (*arg, None),
+        };
+        if !is_valid_identifier(key) {
+            eprintln!("rush: export: `{}': not a valid identifier", arg);
+            status = 1;
+            continue;
+        }
+        if let Some(value) = value {
             env::set_var(key, value);
+        }
+        if unexport {
+            shell.unexported.insert(key.to_string());
         } else {
-            // 引数に `=` がない場合は無視（bash互換: export VAR は既存変数をexportする）
+            // `export VAR` は既存変数に export 属性を付け直す（bash互換）
+            shell.unexported.remove(key);
         }
     }
 
-    0
+    status
+}
+
+/// `declare -x` 一覧用に値中の `"` `\` `$` `` ` `` をバックスラッシュでエスケープする。
+fn quote_declare_value(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | '$' | '`') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
 }
 
-/// `unset VAR...` — 環境変数を削除する。
+/// `unset [-f | -v] name...` — 変数または関数を削除する。
+///
+/// - `-v`: 変数（環境変数・配列・`arr[N]` 要素）のみを削除
+/// - `-f`: 関数のみを削除
+/// - 指定なし: 変数を削除し、同名の変数がなければ関数を削除（bash 互換）
+///
+/// 存在しない名前の指定はエラーにならない。`1` や `@` など識別子でない名前
+/// （位置パラメータ・特殊パラメータ）は削除できずエラーとする。
+/// rush には readonly 属性がないため、readonly 変数の保護は行わない。
 fn builtin_unset(shell: &mut Shell, args: &[&str]) -> i32 {
-    if args.len() > 1 && args[1] == "-f" {
-        // unset -f: 関数の削除
-        for arg in &args[2..] {
-            shell.functions.remove(*arg);
+    let mut functions_only = false;
+    let mut variables_only = false;
+    let mut idx = 1;
+    while idx < args.len() && args[idx].starts_with('-') && args[idx].len() > 1 {
+        if args[idx] == "--" {
+            idx += 1;
+            break;
         }
-    } else {
-        for arg in &args[1..] {
-            // arr[N] パターン: 単一要素の削除
-            if let (Some(bs), Some(be)) = (arg.find('['), arg.find(']')) {
-                if be > bs {
-                    let name = &arg[..bs];
-                    let idx_str = &arg[bs + 1..be];
-                    if let Ok(idx) = idx_str.parse::<usize>() {
-                        if let Some(arr) = shell.arrays.get_mut(name) {
-                            arr.remove(&idx);
-                        }
-                        continue;
+        for c in args[idx][1..].chars() {
+            match c {
+                'f' => functions_only = true,
+                'v' => variables_only = true,
+                _ => {
+                    eprintln!("rush: unset: -{}: invalid option", c);
+                    eprintln!("unset: usage: unset [-f] [-v] [name ...]");
+                    return 2;
+                }
+            }
+        }
+        idx += 1;
+    }
+    if functions_only && variables_only {
+        eprintln!("rush: unset: cannot simultaneously unset a function and a variable");
+        return 1;
+    }
+
+    let mut status = 0;
+    for &arg in &args[idx..] {
+        if functions_only {
+            shell.functions.remove(arg);
+            continue;
+        }
+        // arr[N] パターン: 単一要素の削除
+        if let (Some(bs), Some(be)) = (arg.find('['), arg.find(']')) {
+            if be > bs {
+                let name = &arg[..bs];
+                let idx_str = &arg[bs + 1..be];
+                if let Ok(idx) = idx_str.parse::<usize>() {
+                    if let Some(arr) = shell.arrays.get_mut(name) {
+                        arr.remove(&idx);
                     }
+                    continue;
                 }
             }
+        }
+        if !is_valid_identifier(arg) {
+            eprintln!("rush: unset: `{}': not a valid identifier", arg);
+            status = 1;
+            continue;
+        }
+        let is_var = env::var_os(arg).is_some() || shell.arrays.contains_key(arg);
+        if is_var || variables_only {
             env::remove_var(arg);
-            shell.arrays.remove(*arg);
+            shell.arrays.remove(arg);
+            shell.unexported.remove(arg);
+        } else {
+            shell.functions.remove(arg);
         }
     }
-    0
+    status
+}
+
+/// 変数名として有効な識別子（英字または `_` で始まり、英数字と `_` のみ）か判定する。
+fn is_valid_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// ── help ビルトイン ──────────────────────────────────────────────────
+
+/// `help [name ...]` — 引数なしでビルトイン名を段組みで一覧表示し、
+/// `name` 指定時はその書式を `name: synopsis` 形式で表示する。
+fn builtin_help(args: &[&str], stdout: &mut dyn Write) -> i32 {
+    if args.len() < 2 {
+        let width = BUILTIN_HELP.iter().map(|(name, _)| name.len()).max().unwrap_or(0) + 2;
+        let columns = (80 / width).max(1);
+        let _ = writeln!(stdout, "rush builtins (help NAME for usage):");
+        for row in BUILTIN_HELP.chunks(columns) {
+            let line: String = row.iter().map(|(name, _)| format!("{:<width$}", name)).collect();
+            let _ = writeln!(stdout, "{}", line.trim_end());
+        }
+        return 0;
+    }
+
+    let mut status = 0;
+    for &name in &args[1..] {
+        match BUILTIN_HELP.iter().find(|(n, _)| *n == name) {
+            Some((_, synopsis)) => {
+                let _ = writeln!(stdout, "{}: {}", name, synopsis);
+            }
+            None => {
+                eprintln!("rush: help: no help topics match `{}'", name);
+                status = 1;
+            }
+        }
+    }
+    status
 }
 
 // ── type ビルトイン ──────────────────────────────────────────────────
 
-/// `type name [name ...]` — コマンドの所在を表示する。ビルトインか外部コマンドかを判定。
+/// `type` が `keyword` として報告するシェル予約語。
+const SHELL_KEYWORDS: &[&str] = &[
+    "!", "case", "do", "done", "elif", "else", "esac", "fi", "for", "function", "if", "in",
+    "select", "then", "time", "until", "while", "{", "}", "[[", "]]",
+];
+
+/// `type [-afpt] name [name ...]` — コマンドの所在を表示する。
+///
+/// エイリアス → キーワード → 関数 → ビルトイン → PATH の順に解決する。
+/// - `-t`: `alias`/`keyword`/`function`/`builtin`/`file` の種別のみ表示
+/// - `-p`: ファイルのパスのみ表示（ファイル以外に解決される名前は何も表示しない）
+/// - `-a`: 最初の一致で止めず、全ての一致（PATH 上の全ヒットを含む）を表示
+/// - `-f`: シェル関数を検索しない
 fn builtin_type(shell: &Shell, args: &[&str], stdout: &mut dyn Write) -> i32 {
-    if args.len() <= 1 {
-        let _ = writeln!(stdout, "type: usage: type name [name ...]");
+    let mut all = false;
+    let mut type_only = false;
+    let mut path_only = false;
+    let mut no_functions = false;
+    let mut idx = 1;
+    while idx < args.len() && args[idx].starts_with('-') && args[idx].len() > 1 {
+        if args[idx] == "--" {
+            idx += 1;
+            break;
+        }
+        for c in args[idx][1..].chars() {
+            match c {
+                'a' => all = true,
+                't' => type_only = true,
+                'p' => path_only = true,
+                'f' => no_functions = true,
+                _ => {
+                    eprintln!("rush: type: -{}: invalid option", c);
+                    eprintln!("type: usage: type [-afpt] name [name ...]");
+                    return 2;
+                }
+            }
+        }
+        idx += 1;
+    }
+    if idx >= args.len() {
+        let _ = writeln!(stdout, "type: usage: type [-afpt] name [name ...]");
         return 1;
     }
+
     let mut status = 0;
-    for &name in &args[1..] {
-        if shell.functions.contains_key(name) {
-            let _ = writeln!(stdout, "{} is a function", name);
-        } else if is_builtin(name) {
-            let _ = writeln!(stdout, "{} is a shell builtin", name);
-        } else if let Some(path) = find_in_path(name) {
-            let _ = writeln!(stdout, "{} is {}", name, path);
-        } else {
-            let _ = writeln!(stdout, "rush: type: {}: not found", name);
+    for &name in &args[idx..] {
+        // (種別, 通常表示) の一覧を解決順に集める。ファイルの場合は表示にパスを含める
+        let mut matches: Vec<(&str, String)> = Vec::new();
+        if let Some(value) = shell.aliases.get(name) {
+            matches.push(("alias", format!("{} is aliased to `{}'", name, value)));
+        }
+        if SHELL_KEYWORDS.contains(&name) {
+            matches.push(("keyword", format!("{} is a shell keyword", name)));
+        }
+        if !no_functions && shell.functions.contains_key(name) {
+            matches.push(("function", format!("{} is a function", name)));
+        }
+        if is_builtin(name) {
+            matches.push(("builtin", format!("{} is a shell builtin", name)));
+        }
+        if all || matches.is_empty() {
+            let paths = if all {
+                find_all_in_path(name)
+            } else {
+                find_in_path(name).into_iter().collect()
+            };
+            for path in paths {
+                matches.push(("file", path));
+            }
+        }
+        if !all {
+            matches.truncate(1);
+        }
+
+        if matches.is_empty() {
+            if !type_only && !path_only {
+                let _ = writeln!(stdout, "rush: type: {}: not found", name);
+            }
+            status = 1;
+            continue;
+        }
+        for (kind, desc) in matches {
+            if type_only {
+                let _ = writeln!(stdout, "{}", kind);
+            } else if kind == "file" {
+                if path_only {
+                    let _ = writeln!(stdout, "{}", desc);
+                } else {
+                    let _ = writeln!(stdout, "{} is {}", name, desc);
+                }
+            } else if !path_only {
+                let _ = writeln!(stdout, "{}", desc);
+            }
+        }
+    }
+    status
+}
+
+// ── hash ビルトイン ──────────────────────────────────────────────────
+
+/// `hash [-r] [-d] [name ...]` — コマンド位置キャッシュを表示・操作する。
+///
+/// - 引数なし: キャッシュ済みエントリを `hits\tcommand` 形式で一覧表示（bash 互換）
+/// - `-r`: キャッシュを全破棄
+/// - `-d name`: 指定エントリのみ破棄
+/// - `name`: PATH を再検索してキャッシュに登録（ヒット数は 0 から）
+fn builtin_hash(shell: &mut Shell, args: &[&str], stdout: &mut dyn Write) -> i32 {
+    let mut delete = false;
+    let mut names: Vec<&str> = Vec::new();
+    for &arg in &args[1..] {
+        match arg {
+            "-r" => shell.command_hash.clear(),
+            "-d" => delete = true,
+            _ if arg.starts_with('-') => {
+                eprintln!("rush: hash: {}: invalid option", arg);
+                eprintln!("hash: usage: hash [-r] [-d] [name ...]");
+                return 2;
+            }
+            _ => names.push(arg),
+        }
+    }
+
+    if args.len() == 1 {
+        if shell.command_hash.is_empty() {
+            let _ = writeln!(stdout, "hash: hash table empty");
+            return 0;
+        }
+        let _ = writeln!(stdout, "hits\tcommand");
+        for (_, entry) in shell.command_hash.entries() {
+            let _ = writeln!(stdout, "{:>4}\t{}", entry.hits, entry.path);
+        }
+        return 0;
+    }
+
+    let mut status = 0;
+    for name in names {
+        if delete {
+            if !shell.command_hash.remove(name) {
+                eprintln!("rush: hash: {}: not found", name);
+                status = 1;
+            }
+        } else if name.contains('/') || is_builtin(name) || shell.functions.contains_key(name) {
+            // パス指定・ビルトイン・関数はキャッシュ対象外
+            continue;
+        } else if shell.command_hash.rehash(name).is_none() {
+            eprintln!("rush: hash: {}: not found", name);
             status = 1;
         }
     }
@@ -271,34 +673,41 @@ fn builtin_type(shell: &Shell, args: &[&str], stdout: &mut dyn Write) -> i32 {
 
 /// `$PATH` 内でコマンド名を検索し、最初に見つかった実行可能ファイルのフルパスを返す。
 fn find_in_path(name: &str) -> Option<String> {
+    find_all_in_path(name).into_iter().next()
+}
+
+/// `$PATH` 内でコマンド名を検索し、見つかった全ての実行可能ファイルのフルパスを PATH 順に返す。
+fn find_all_in_path(name: &str) -> Vec<String> {
     use std::os::unix::fs::PermissionsExt;
-    let path_var = env::var("PATH").ok()?;
+    let path_var = match env::var("PATH") {
+        Ok(v) => v,
+        Err(_) => return Vec::new(),
+    };
+    let mut found = Vec::new();
     for dir in path_var.split(':') {
         let full = format!("{}/{}", dir, name);
         let p = Path::new(&full);
         if let Ok(meta) = p.metadata() {
             if meta.is_file() && meta.permissions().mode() & 0o111 != 0 {
-                return Some(full);
+                found.push(full);
             }
         }
     }
-    None
+    found
 }
 
 // ── ジョブコントロールビルトイン ─────────────────────────────────────
 
 /// `fg` / `bg` の引数を解析してジョブ ID を返す。
 ///
-/// - `%N` → ジョブ番号 N
-/// - 数値のみ → ジョブ番号として解釈
-/// - 省略時 → [`JobTable::current_job_id`](crate::job::JobTable::current_job_id) で最新の非 Done ジョブを選択
+/// - ジョブ指定 → [`resolve_job_spec`] で解決
+/// - 省略時 → [`JobTable::current_job_id`](crate::job::JobTable::current_job_id) でカレントジョブを選択
 ///
 /// 該当ジョブが見つからない場合はエラーメッセージを出力して `Err(1)` を返す。
 fn parse_job_arg(shell: &Shell, args: &[&str]) -> Result<usize, i32> {
     if args.len() > 1 {
         let arg = args[1];
-        let num_str = arg.strip_prefix('%').unwrap_or(arg);
-        num_str.parse::<usize>().map_err(|_| {
+        resolve_job_spec(&shell.jobs, arg).ok_or_else(|| {
             eprintln!("rush: {}: {}: no such job", args[0], arg);
             1
         })
@@ -310,17 +719,103 @@ fn parse_job_arg(shell: &Shell, args: &[&str]) -> Result<usize, i32> {
     }
 }
 
-/// `jobs` — 全ジョブを `[N]   Running/Stopped/Done   command` 形式で一覧表示する。
-fn builtin_jobs(shell: &Shell, stdout: &mut dyn Write) -> i32 {
-    for job in shell.jobs.iter() {
+/// ジョブ指定（jobspec）をジョブ ID に解決する。
+///
+/// - `%N` / `N` → ジョブ番号 N
+/// - `%%` / `%+` / `%` → カレントジョブ
+/// - `%-` → 1 つ前のジョブ
+/// - `%string` → コマンドが string で始まる最新のジョブ
+/// - `%?string` → コマンドに string を含む最新のジョブ
+fn resolve_job_spec(jobs: &JobTable, spec: &str) -> Option<usize> {
+    let Some(body) = spec.strip_prefix('%') else {
+        return spec.parse::<usize>().ok();
+    };
+    match body {
+        "" | "%" | "+" => jobs.current_job_id(),
+        "-" => jobs.previous_job_id(),
+        _ if body.bytes().all(|b| b.is_ascii_digit()) => body.parse::<usize>().ok(),
+        _ => {
+            let mut matching = jobs.iter().rev();
+            match body.strip_prefix('?') {
+                Some(needle) => matching.find(|j| j.command.contains(needle)),
+                None => matching.find(|j| j.command.starts_with(body)),
+            }
+            .map(|j| j.id)
+        }
+    }
+}
+
+/// `jobs [-l|-p] [jobspec ...]` — ジョブを `[N]+  Running/Stopped/Done   command` 形式で一覧表示する。
+///
+/// ジョブ番号の直後にカレントジョブは `+`、直前のジョブは `-` を付ける。
+/// - `-l`: ジョブ ID の後に PID 列を追加する。パイプラインの後続プロセスは次行以降に PID のみ表示。
+/// - `-p`: 各ジョブのプロセスグループ ID のみを1行ずつ表示する。
+///
+/// jobspec を指定すると [`resolve_job_spec`] で解決したジョブだけを引数の順に表示する。
+/// 見つからない jobspec があればエラーを表示し、残りを表示したうえで 1 を返す。
+fn builtin_jobs(shell: &Shell, args: &[&str], stdout: &mut dyn Write) -> i32 {
+    let mut long = false;
+    let mut pgid_only = false;
+    let mut specs = &args[1..];
+    while let Some(arg) = specs.first() {
+        match *arg {
+            "-l" => long = true,
+            "-p" => pgid_only = true,
+            "-lp" | "-pl" => {
+                long = true;
+                pgid_only = true;
+            }
+            "--" => {
+                specs = &specs[1..];
+                break;
+            }
+            _ if arg.starts_with('-') && arg.len() > 1 => {
+                eprintln!("rush: jobs: {}: invalid option", arg);
+                return 2;
+            }
+            _ => break,
+        }
+        specs = &specs[1..];
+    }
+
+    let mut status = 0;
+    let selected: Vec<&Job> = if specs.is_empty() {
+        shell.jobs.iter().collect()
+    } else {
+        specs
+            .iter()
+            .filter_map(|spec| {
+                let job = resolve_job_spec(&shell.jobs, spec).and_then(|id| shell.jobs.get(id));
+                if job.is_none() {
+                    eprintln!("rush: jobs: {}: no such job", spec);
+                    status = 1;
+                }
+                job
+            })
+            .collect()
+    };
+    for job in selected {
+        if pgid_only {
+            let _ = writeln!(stdout, "{}", job.pgid);
+            continue;
+        }
         let status_str = match job.status() {
             JobStatus::Running => "Running",
             JobStatus::Stopped => "Stopped",
             JobStatus::Done(_) => "Done",
         };
-        let _ = writeln!(stdout, "[{}]   {}   {}", job.id, status_str, job.command);
+        let marker = shell.jobs.marker(job.id);
+        if long {
+            let first_pid = job.processes.first().map(|p| p.pid).unwrap_or(job.pgid);
+            let _ = writeln!(stdout, "[{}]{}  {}   {}   {}", job.id, marker, first_pid, status_str, job.command);
+            for proc in job.processes.iter().skip(1) {
+                let _ = writeln!(stdout, "      {}", proc.pid);
+            }
+        } else {
+            let _ = writeln!(stdout, "[{}]{}  {}   {}", job.id, marker, status_str, job.command);
+        }
     }
-    0
+    status
 }
 
 /// `fg [%N]` — ジョブをフォアグラウンドに復帰させる。
@@ -346,6 +841,7 @@ fn builtin_fg(shell: &mut Shell, args: &[&str]) -> i32 {
     };
 
     eprintln!("{}", command);
+    shell.jobs.make_current(job_id);
 
     // ターミナル制御を渡す
     job::give_terminal_to(shell.terminal_fd, pgid);
@@ -424,75 +920,122 @@ fn builtin_bg(shell: &mut Shell, args: &[&str]) -> i32 {
         }
     }
 
+    shell.jobs.make_current(job_id);
     eprintln!("[{}]+ {} &", job_id, command);
     0
 }
 
 // ── wait ────────────────────────────────────────────────────────────
 
-/// `wait [%N]` — バックグラウンドジョブの完了を待機する。
-/// 引数なしなら全バックグラウンドジョブを待つ。`%N` で特定ジョブを待つ。
+/// `wait [-n] [id ...]` — バックグラウンドジョブの完了を待機する。
+/// 引数なしなら全バックグラウンドジョブを待って 0 を返す。`id`（ジョブ指定または PID）を
+/// 指定したら順に待ち、最後のジョブの終了ステータスを返す（シグナルで終了したら 128+N、
+/// 存在しなければ 127）。`-n` なら次に完了したいずれか1つのジョブを待ち、その終了ステータスを返す。
 fn builtin_wait(shell: &mut Shell, args: &[&str]) -> i32 {
+    if args.get(1) == Some(&"-n") {
+        return wait_any_job(shell);
+    }
     if args.len() > 1 {
-        // 特定ジョブを待機
-        let job_id = match parse_job_arg(shell, args) {
-            Ok(id) => id,
-            Err(status) => return status,
-        };
-        let pgid = match shell.jobs.get(job_id) {
-            Some(job) => job.pgid,
-            None => {
-                eprintln!("rush: wait: %{}: no such job", job_id);
-                return 127;
-            }
-        };
-        // waitpid で完了まで待機
-        loop {
-            let mut raw_status: i32 = 0;
-            let pid = unsafe { libc::waitpid(-pgid, &mut raw_status, libc::WUNTRACED) };
-            if pid <= 0 {
-                break;
-            }
-            shell.jobs.mark_pid(pid, raw_status);
-            if let Some(job) = shell.jobs.get(job_id) {
-                match job.status() {
-                    JobStatus::Done(code) => {
-                        if let Some(job) = shell.jobs.get_mut(job_id) {
-                            job.notified = true;
-                        }
-                        shell.jobs.remove_done();
-                        return code;
-                    }
-                    JobStatus::Stopped => return 148,
-                    JobStatus::Running => continue,
+        let mut status = 0;
+        for spec in &args[1..] {
+            status = wait_job(shell, spec);
+        }
+        return status;
+    }
+    // 全バックグラウンドジョブを待機
+    loop {
+        let mut raw_status: i32 = 0;
+        let pid = unsafe { libc::waitpid(-1, &mut raw_status, libc::WUNTRACED) };
+        if pid <= 0 {
+            break;
+        }
+        shell.jobs.mark_pid(pid, raw_status);
+    }
+    // 完了済みジョブを通知・削除
+    job::notify_and_clean(&mut shell.jobs);
+    0
+}
+
+/// `wait id` の 1 つ分。ジョブの完了を待ち、その終了ステータスを返す。
+///
+/// 数字だけの `id` はまずジョブ内のプロセスの PID（`wait $!`）として探し、なければジョブ番号とみなす。
+/// 既に完了していたジョブはそのステータスをすぐ返す。
+fn wait_job(shell: &mut Shell, spec: &str) -> i32 {
+    let by_pid = spec.parse::<libc::pid_t>().ok().and_then(|pid| {
+        shell.jobs.iter().find(|j| j.processes.iter().any(|p| p.pid == pid)).map(|j| j.id)
+    });
+    let Some((job_id, pgid)) = by_pid
+        .or_else(|| resolve_job_spec(&shell.jobs, spec))
+        .and_then(|id| shell.jobs.get(id))
+        .map(|job| (job.id, job.pgid))
+    else {
+        eprintln!("rush: wait: {}: no such job", spec);
+        return 127;
+    };
+    loop {
+        match shell.jobs.get(job_id).map(|job| job.status()) {
+            Some(JobStatus::Done(code)) => {
+                if let Some(job) = shell.jobs.get_mut(job_id) {
+                    job.notified = true;
                 }
-            } else {
-                break;
+                shell.jobs.remove_done();
+                return code;
             }
+            Some(JobStatus::Stopped) => return 128 + libc::SIGTSTP,
+            Some(JobStatus::Running) => {}
+            None => return 127,
         }
-        0
-    } else {
-        // 全バックグラウンドジョブを待機
-        loop {
-            let mut raw_status: i32 = 0;
-            let pid = unsafe { libc::waitpid(-1, &mut raw_status, libc::WUNTRACED) };
-            if pid <= 0 {
-                break;
+        let mut raw_status: i32 = 0;
+        let pid = unsafe { libc::waitpid(-pgid, &mut raw_status, libc::WUNTRACED) };
+        if pid <= 0 {
+            return 127;
+        }
+        shell.jobs.mark_pid(pid, raw_status);
+    }
+}
+
+/// `wait -n` の本体。`waitpid(-1)` で子を1つずつ reap し、ジョブが完了した時点で返る。
+///
+/// 完了しうる実行中のジョブがなければ 127（bash 互換）。停止中のジョブは完了しないので数えない。
+fn wait_any_job(shell: &mut Shell) -> i32 {
+    loop {
+        if !shell.jobs.iter().any(|j| j.status() == JobStatus::Running) {
+            return 127;
+        }
+        let mut raw_status: i32 = 0;
+        // 待機中に停止したジョブも数え直せるよう停止も報告させる
+        let pid = unsafe { libc::waitpid(-1, &mut raw_status, libc::WUNTRACED) };
+        if pid <= 0 {
+            return 127;
+        }
+        shell.jobs.mark_pid(pid, raw_status);
+        let done = shell
+            .jobs
+            .iter()
+            .find(|j| j.processes.iter().any(|p| p.pid == pid))
+            .and_then(|j| match j.status() {
+                JobStatus::Done(code) => Some((j.id, code)),
+                _ => None,
+            });
+        if let Some((job_id, code)) = done {
+            if let Some(job) = shell.jobs.get_mut(job_id) {
+                job.notified = true;
             }
-            shell.jobs.mark_pid(pid, raw_status);
+            shell.jobs.remove_done();
+            return code;
         }
-        // 完了済みジョブを通知・削除
-        job::notify_and_clean(&mut shell.jobs);
-        0
     }
 }
 
 // ── exec ────────────────────────────────────────────────────────────
 
 /// `exec cmd [args...]` — シェルプロセスを `execvp` で置換する。引数なしなら no-op。
-fn builtin_exec(args: &[&str]) -> i32 {
+///
+/// export 属性のないシェル変数は置換前に環境から取り除く。
+fn builtin_exec(shell: &Shell, args: &[&str]) -> i32 {
     if args.len() < 2 {
-        return 0; // 引数なし → no-op
+        // コマンドなし → no-op（リダイレクトのみの `exec > file` は executor が処理する）
+        return 0;
     }
     // シグナルハンドラを SIG_DFL に復元
     unsafe {
@@ -501,6 +1044,9 @@ fn builtin_exec(args: &[&str]) -> i32 {
         libc::signal(libc::SIGTTOU, libc::SIG_DFL);
         libc::signal(libc::SIGTTIN, libc::SIG_DFL);
     }
+    for name in &shell.unexported {
+        env::remove_var(name);
+    }
     let c_args: Vec<std::ffi::CString> = args[1..]
         .iter()
         .map(|s| std::ffi::CString::new(*s).unwrap_or_default())
@@ -554,8 +1100,7 @@ fn builtin_command(shell: &mut Shell, args: &[&str], stdout: &mut dyn Write) ->
         } else {
             // 外部コマンドとして実行 — executor に委ねるため 127 を返す
             // （実際には executor がこれを処理する）
-            eprintln!("rush: {}: command not found", sub_args[0]);
-            127
+            crate::spawn::SpawnError::not_found(sub_args[0]).report()
         }
     }
 }
@@ -667,7 +1212,8 @@ fn builtin_break(shell: &mut Shell, args: &[&str]) -> i32 {
     } else {
         1
     };
-    shell.break_level = n;
+    // 囲んでいるループ数を超える N は最も外側のループを指す（bash 互換）
+    shell.break_level = n.min(shell.loop_depth);
     0
 }
 
@@ -692,7 +1238,8 @@ fn builtin_continue(shell: &mut Shell, args: &[&str]) -> i32 {
     } else {
         1
     };
-    shell.continue_level = n;
+    // 囲んでいるループ数を超える N は最も外側のループを指す（bash 互換）
+    shell.continue_level = n.min(shell.loop_depth);
     0
 }
 
@@ -700,11 +1247,17 @@ fn builtin_continue(shell: &mut Shell, args: &[&str]) -> i32 {
 
 /// `source file` / `. file` — ファイルを現在のシェルコンテキストで行単位実行する。
 /// `return` による早期脱出をサポート。if/fi ブロック、for/while/until ループに対応。
+/// `exit` は読み込みを打ち切り、`should_exit` を立てたまま戻るのでシェル自体が終了する。
 fn builtin_source(shell: &mut Shell, args: &[&str]) -> i32 {
     if args.len() < 2 {
         eprintln!("rush: {}: filename argument required", args[0]);
         return 2;
     }
+    let limit = shell.nesting_limit();
+    if shell.source_depth >= limit {
+        eprintln!("rush: {}: maximum nesting level exceeded ({})", args[0], limit);
+        return 1;
+    }
     let path = args[1];
     let content = match std::fs::read_to_string(path) {
         Ok(c) => c,
@@ -715,8 +1268,10 @@ fn builtin_source(shell: &mut Shell, args: &[&str]) -> i32 {
     };
     shell.source_depth += 1;
     let lines: Vec<&str> = content.lines().collect();
+    let saved_lineno = parser::lineno();
     let mut i = 0;
     while i < lines.len() {
+        parser::set_lineno(i + 1);
         let trimmed = lines[i].trim();
         if trimmed.is_empty() || trimmed.starts_with('#') {
             i += 1;
@@ -726,9 +1281,9 @@ fn builtin_source(shell: &mut Shell, args: &[&str]) -> i32 {
         // if ブロック検出
         if executor::starts_with_if(trimmed) {
             let (block, next_i) = executor::collect_if_block(&lines, i);
-            shell.last_status = executor::execute_if_block(shell, &block);
+            shell.last_status = executor::execute_block(shell, &block);
             i = next_i;
-            if shell.should_return {
+            if shell.should_return || shell.should_exit {
                 shell.should_return = false;
                 break;
             }
@@ -741,14 +1296,9 @@ fn builtin_source(shell: &mut Shell, args: &[&str]) -> i32 {
             || executor::starts_with_until(trimmed)
         {
             let (block, next_i) = executor::collect_loop_block(&lines, i);
-            if executor::starts_with_for(trimmed) {
-                shell.last_status = executor::execute_for_block(shell, &block);
-            } else {
-                shell.last_status = executor::execute_while_block(
-                    shell, &block, executor::starts_with_until(trimmed));
-            }
+            shell.last_status = executor::execute_block(shell, &block);
             i = next_i;
-            if shell.should_return {
+            if shell.should_return || shell.should_exit {
                 shell.should_return = false;
                 break;
             }
@@ -758,9 +1308,9 @@ fn builtin_source(shell: &mut Shell, args: &[&str]) -> i32 {
         // case ブロック検出
         if executor::starts_with_case(trimmed) {
             let (block, next_i) = executor::collect_case_block(&lines, i);
-            shell.last_status = executor::execute_case_block(shell, &block);
+            shell.last_status = executor::execute_block(shell, &block);
             i = next_i;
-            if shell.should_return {
+            if shell.should_return || shell.should_exit {
                 shell.should_return = false;
                 break;
             }
@@ -775,7 +1325,7 @@ fn builtin_source(shell: &mut Shell, args: &[&str]) -> i32 {
             continue;
         }
 
-        match parser::parse(trimmed, shell.last_status, &shell.positional_args, shell.set_nounset, &shell.arrays) {
+        match parser::parse(trimmed, shell.last_status, &shell.shell_name, &shell.positional_args, shell.set_nounset, &shell.arrays) {
             Ok(Some(list)) => {
                 let cmd_text = trimmed.to_string();
                 shell.last_status = executor::execute(shell, &list, &cmd_text);
@@ -783,12 +1333,13 @@ fn builtin_source(shell: &mut Shell, args: &[&str]) -> i32 {
             Ok(None) => {}
             Err(e) => eprintln!("rush: {}: {}", path, e),
         }
-        if shell.should_return {
+        if shell.should_return || shell.should_exit {
             shell.should_return = false;
             break;
         }
         i += 1;
     }
+    parser::set_lineno(saved_lineno);
     shell.source_depth -= 1;
     shell.last_status
 }
@@ -876,11 +1427,46 @@ fn signal_name(sig: i32) -> Option<&'static str> {
 
 // ── pushd / popd / dirs ─────────────────────────────────────────────
 
-/// `pushd [dir]` — カレントディレクトリをスタックに積んで dir に移動する。
+/// カレントディレクトリを先頭（インデックス 0）とした、`dirs` 表示順のスタック全体を返す。
+///
+/// `shell.dir_stack` はスタックトップが末尾なので、逆順にして連結する。
+fn full_dir_stack(shell: &Shell) -> std::io::Result<Vec<String>> {
+    let mut full = vec![logical_cwd()?];
+    full.extend(shell.dir_stack.iter().rev().cloned());
+    Ok(full)
+}
+
+/// [`full_dir_stack`] 形式のスタックから先頭を除いた残りを `shell.dir_stack` に書き戻す。
+fn store_dir_stack(shell: &mut Shell, full: &[String]) {
+    shell.dir_stack = full[1..].iter().rev().cloned().collect();
+}
+
+/// `+N` / `-N` 形式のスタック指定を、長さ `len` のスタック上のインデックスに変換する。
+///
+/// `+N` は左（カレント）から、`-N` は右から数えた 0 始まりの位置。
+/// 数値でない引数は `None`、範囲外は `Some(Err(()))` を返す。
+fn parse_stack_index(arg: &str, len: usize) -> Option<Result<usize, ()>> {
+    let (from_left, digits) = if let Some(d) = arg.strip_prefix('+') {
+        (true, d)
+    } else if let Some(d) = arg.strip_prefix('-') {
+        (false, d)
+    } else {
+        return None;
+    };
+    let n = digits.parse::<usize>().ok()?;
+    if n >= len {
+        return Some(Err(()));
+    }
+    Some(Ok(if from_left { n } else { len - 1 - n }))
+}
+
+/// `pushd [dir | +N | -N]` — カレントディレクトリをスタックに積んで dir に移動する。
+///
 /// 引数なしならスタックトップとカレントを交換する。
+/// `+N` / `-N` はスタックを回転させ、N 番目のエントリを先頭にしてそこへ移動する。
 fn builtin_pushd(shell: &mut Shell, args: &[&str], stdout: &mut dyn Write) -> i32 {
-    let cwd = match env::current_dir() {
-        Ok(p) => p.to_string_lossy().to_string(),
+    let mut full = match full_dir_stack(shell) {
+        Ok(f) => f,
         Err(e) => {
             eprintln!("rush: pushd: {}", e);
             return 1;
@@ -888,65 +1474,152 @@ fn builtin_pushd(shell: &mut Shell, args: &[&str], stdout: &mut dyn Write) -> i3
     };
 
     if args.len() > 1 {
-        let target = args[1];
-        if let Err(e) = env::set_current_dir(Path::new(target)) {
-            eprintln!("rush: pushd: {}: {}", target, e);
-            return 1;
+        let arg = args[1];
+        match parse_stack_index(arg, full.len()) {
+            Some(Ok(idx)) => full.rotate_left(idx),
+            Some(Err(())) => {
+                eprintln!("rush: pushd: {}: directory stack index out of range", arg);
+                return 1;
+            }
+            None => {
+                let target = parser::expand_tilde(arg).into_owned();
+                if let Err(e) = change_directory(&target) {
+                    eprintln!("rush: pushd: {}: {}", target, e);
+                    return 1;
+                }
+                shell.dir_stack.push(full.swap_remove(0));
+                print_dir_stack(shell, false, stdout);
+                return 0;
+            }
         }
-        shell.dir_stack.push(cwd);
     } else {
         // 引数なし: スタックトップとカレントを交換
-        if let Some(top) = shell.dir_stack.pop() {
-            if let Err(e) = env::set_current_dir(Path::new(&top)) {
-                eprintln!("rush: pushd: {}: {}", top, e);
-                shell.dir_stack.push(top);
-                return 1;
-            }
-            shell.dir_stack.push(cwd);
-        } else {
+        if full.len() < 2 {
             eprintln!("rush: pushd: no other directory");
             return 1;
         }
+        full.swap(0, 1);
+    }
+
+    if let Err(e) = change_directory(&full[0]) {
+        eprintln!("rush: pushd: {}: {}", full[0], e);
+        return 1;
     }
-    // スタック表示
-    print_dir_stack(shell, stdout);
+    store_dir_stack(shell, &full);
+    print_dir_stack(shell, false, stdout);
     0
 }
 
-/// `popd` — スタックからディレクトリをポップして移動する。
-fn builtin_popd(shell: &mut Shell, _args: &[&str], stdout: &mut dyn Write) -> i32 {
-    match shell.dir_stack.pop() {
-        Some(dir) => {
-            if let Err(e) = env::set_current_dir(Path::new(&dir)) {
-                eprintln!("rush: popd: {}: {}", dir, e);
-                shell.dir_stack.push(dir);
+/// `popd [+N | -N]` — スタックからディレクトリをポップして移動する。
+///
+/// `+N` / `-N` は N 番目のエントリをスタックから削除する（先頭以外ならディレクトリは移動しない）。
+fn builtin_popd(shell: &mut Shell, args: &[&str], stdout: &mut dyn Write) -> i32 {
+    let mut full = match full_dir_stack(shell) {
+        Ok(f) => f,
+        Err(e) => {
+            eprintln!("rush: popd: {}", e);
+            return 1;
+        }
+    };
+    if full.len() < 2 {
+        eprintln!("rush: popd: directory stack empty");
+        return 1;
+    }
+
+    let idx = match args.get(1) {
+        None => 0,
+        Some(arg) => match parse_stack_index(arg, full.len()) {
+            Some(Ok(idx)) => idx,
+            Some(Err(())) => {
+                eprintln!("rush: popd: {}: directory stack index out of range", arg);
                 return 1;
             }
-            print_dir_stack(shell, stdout);
-            0
-        }
-        None => {
-            eprintln!("rush: popd: directory stack empty");
-            1
+            None => {
+                eprintln!("rush: popd: {}: invalid argument", arg);
+                eprintln!("popd: usage: popd [+N | -N]");
+                return 2;
+            }
+        },
+    };
+
+    full.remove(idx);
+    if idx == 0 {
+        if let Err(e) = change_directory(&full[0]) {
+            eprintln!("rush: popd: {}: {}", full[0], e);
+            return 1;
         }
     }
+    store_dir_stack(shell, &full);
+    print_dir_stack(shell, false, stdout);
+    0
 }
 
-/// `dirs` — ディレクトリスタックを表示する。
-fn builtin_dirs(shell: &Shell, stdout: &mut dyn Write) -> i32 {
-    print_dir_stack(shell, stdout);
+/// `dirs [-clpv]` — ディレクトリスタックを表示する。
+///
+/// - `-c`: スタックを空にする
+/// - `-l`: `~` に省略せずフルパスで表示
+/// - `-p`: 1 行に 1 エントリずつ表示
+/// - `-v`: 1 行に 1 エントリずつ、インデックス付きで表示
+fn builtin_dirs(shell: &mut Shell, args: &[&str], stdout: &mut dyn Write) -> i32 {
+    let mut long = false;
+    let mut per_line = false;
+    let mut verbose = false;
+    for &arg in &args[1..] {
+        if !arg.starts_with('-') || arg.len() < 2 {
+            eprintln!("rush: dirs: {}: invalid argument", arg);
+            eprintln!("dirs: usage: dirs [-clpv]");
+            return 2;
+        }
+        for c in arg[1..].chars() {
+            match c {
+                'c' => {
+                    shell.dir_stack.clear();
+                    return 0;
+                }
+                'l' => long = true,
+                'p' => per_line = true,
+                'v' => verbose = true,
+                _ => {
+                    eprintln!("rush: dirs: -{}: invalid option", c);
+                    eprintln!("dirs: usage: dirs [-clpv]");
+                    return 2;
+                }
+            }
+        }
+    }
+
+    if verbose || per_line {
+        if let Ok(full) = full_dir_stack(shell) {
+            for (i, dir) in full.iter().enumerate() {
+                let dir = format_stack_dir(dir, long);
+                if verbose {
+                    let _ = writeln!(stdout, "{:2}  {}", i, dir);
+                } else {
+                    let _ = writeln!(stdout, "{}", dir);
+                }
+            }
+        }
+    } else {
+        print_dir_stack(shell, long, stdout);
+    }
     0
 }
 
-/// ディレクトリスタックを表示する（カレントディレクトリ + スタック）。
-fn print_dir_stack(shell: &Shell, stdout: &mut dyn Write) {
-    if let Ok(cwd) = env::current_dir() {
-        let _ = write!(stdout, "{}", cwd.display());
+/// スタック表示用にディレクトリを整形する。`long` でなければホームを `~` / `~user` に省略する。
+fn format_stack_dir(dir: &str, long: bool) -> String {
+    if long {
+        dir.to_string()
+    } else {
+        parser::abbreviate_home(dir).into_owned()
     }
-    for dir in shell.dir_stack.iter().rev() {
-        let _ = write!(stdout, " {}", dir);
+}
+
+/// ディレクトリスタックを 1 行で表示する（カレントディレクトリ + スタック）。
+fn print_dir_stack(shell: &Shell, long: bool, stdout: &mut dyn Write) {
+    if let Ok(full) = full_dir_stack(shell) {
+        let line: Vec<String> = full.iter().map(|d| format_stack_dir(d, long)).collect();
+        let _ = writeln!(stdout, "{}", line.join(" "));
     }
-    let _ = writeln!(stdout);
 }
 
 // ── printf ──────────────────────────────────────────────────────────
@@ -955,16 +1628,29 @@ fn print_dir_stack(shell: &Shell, stdout: &mut dyn Write) {
 ///
 /// 対応フォーマット指定子: `%s`（文字列）, `%d`（整数）, `%x`（16進数）, `%o`（8進数）
 /// エスケープ: `\n`, `\t`, `\\`, `\0NNN`（8進数）
+///
+/// 引数が変換指定子より多ければ、引数を使い切るまでフォーマットを繰り返し適用する
+/// （`printf '[%s]' a b c` → `[a][b][c]`）。変換指定子がなければフォーマットを 1 回だけ出力する。
 fn builtin_printf(args: &[&str], stdout: &mut dyn Write) -> i32 {
     if args.len() < 2 {
         eprintln!("rush: printf: usage: printf format [arguments]");
         return 1;
     }
     let format = args[1];
-    let arguments = &args[2..];
-    let mut arg_idx = 0;
+    let mut arguments = &args[2..];
+    loop {
+        let used = printf_format(&glob::raw_bytes(format), arguments, stdout);
+        arguments = &arguments[used.min(arguments.len())..];
+        if used == 0 || arguments.is_empty() {
+            break;
+        }
+    }
+    0
+}
 
-    let bytes = format.as_bytes();
+/// フォーマットを 1 回分出力し、消費した引数の数を返す（足りない引数は空文字列として扱う）。
+fn printf_format(bytes: &[u8], arguments: &[&str], stdout: &mut dyn Write) -> usize {
+    let mut arg_idx = 0;
     let mut i = 0;
     while i < bytes.len() {
         if bytes[i] == b'\\' && i + 1 < bytes.len() {
@@ -1026,15 +1712,12 @@ fn builtin_printf(args: &[&str], stdout: &mut dyn Write) -> i32 {
 
             match bytes[i] {
                 b's' => {
-                    if let Some(w) = width {
-                        if left_align {
-                            let _ = write!(stdout, "{:<width$}", arg_val, width = w);
-                        } else {
-                            let _ = write!(stdout, "{:>width$}", arg_val, width = w);
-                        }
-                    } else {
-                        let _ = write!(stdout, "{}", arg_val);
-                    }
+                    let padded = match width {
+                        Some(w) if left_align => format!("{:<width$}", arg_val, width = w),
+                        Some(w) => format!("{:>width$}", arg_val, width = w),
+                        None => arg_val.to_string(),
+                    };
+                    let _ = stdout.write_all(&glob::raw_bytes(&padded));
                     arg_idx += 1;
                 }
                 b'd' => {
@@ -1093,7 +1776,7 @@ fn builtin_printf(args: &[&str], stdout: &mut dyn Write) -> i32 {
         }
     }
 
-    0
+    arg_idx
 }
 
 // ── test / [ ────────────────────────────────────────────────────────
@@ -1104,7 +1787,9 @@ fn builtin_printf(args: &[&str], stdout: &mut dyn Write) -> i32 {
 /// - 文字列: `-n STR`, `-z STR`, `STR = STR`, `STR != STR`
 /// - 整数: `-eq`, `-ne`, `-lt`, `-le`, `-gt`, `-ge`
 /// - ファイル: `-e`, `-f`, `-d`, `-r`, `-w`, `-x`, `-s`
-/// - 論理: `!`（否定）
+/// - 論理: `!`（否定）, `EXPR -a EXPR`（AND）, `EXPR -o EXPR`（OR）, `( EXPR )`（グループ化）
+///
+/// 構文エラーは終了ステータス 2。
 fn builtin_test(args: &[&str]) -> i32 {
     let is_bracket = args[0] == "[";
     let test_args = if is_bracket {
@@ -1118,52 +1803,147 @@ fn builtin_test(args: &[&str]) -> i32 {
         &args[1..]
     };
 
-    if eval_test(test_args) { 0 } else { 1 }
+    match eval_test(test_args) {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(msg) => {
+            eprintln!("rush: {}: {}", args[0], msg);
+            2
+        }
+    }
 }
 
-/// test の条件式を再帰的に評価する。
-fn eval_test(args: &[&str]) -> bool {
+/// test の条件式を評価する。
+///
+/// 引数が 4 個以下なら POSIX の引数個数による規則で解釈し（`[ ! = x ]` や `[ ( = ( ]` を
+/// 文字列比較として扱うため）、それ以外は [`TestParser`] で再帰下降に評価する。
+fn eval_test(args: &[&str]) -> Result<bool, String> {
     match args.len() {
-        0 => false,
-        1 => !args[0].is_empty(),
-        2 => eval_unary(args[0], args[1]),
-        3 => {
-            if args[0] == "!" {
-                return !eval_test(&args[1..]);
-            }
-            eval_binary(args[0], args[1], args[2])
-        }
-        4 => {
-            if args[0] == "!" {
-                !eval_test(&args[1..])
-            } else {
-                false
+        0 => Ok(false),
+        1 => Ok(!args[0].is_empty()),
+        2 if args[0] == "!" => Ok(args[1].is_empty()),
+        2 if is_unary_op(args[0]) => Ok(eval_unary(args[0], args[1])),
+        2 => Err(format!("{}: unary operator expected", args[0])),
+        3 if is_binary_op(args[1]) => Ok(eval_binary(args[0], args[1], args[2])),
+        3 if args[0] == "!" => Ok(!eval_test(&args[1..])?),
+        3 if args[0] == "(" && args[2] == ")" => Ok(!args[1].is_empty()),
+        4 if args[0] == "!" => Ok(!eval_test(&args[1..])?),
+        4 if args[0] == "(" && args[3] == ")" => eval_test(&args[1..3]),
+        _ => TestParser { args, pos: 0 }.parse(),
+    }
+}
+
+/// test 式の再帰下降パーサ。優先順位は `!` > `-a` > `-o`。
+///
+/// ```text
+/// expr    := and ( -o and )*
+/// and     := not ( -a not )*
+/// not     := ! not | primary
+/// primary := ( expr ) | STR BINOP STR | UNOP STR | STR
+/// ```
+struct TestParser<'a> {
+    args: &'a [&'a str],
+    pos: usize,
+}
+
+impl TestParser<'_> {
+    /// 式全体を評価する。末尾に余分な引数が残れば構文エラー。
+    fn parse(&mut self) -> Result<bool, String> {
+        let result = self.or_expr()?;
+        match self.args.get(self.pos) {
+            None => Ok(result),
+            Some(_) => Err("too many arguments".to_string()),
+        }
+    }
+
+    fn or_expr(&mut self) -> Result<bool, String> {
+        let mut result = self.and_expr()?;
+        while self.args.get(self.pos) == Some(&"-o") {
+            self.pos += 1;
+            // 短絡評価はしない（オペランドの構文チェックのため常に評価する）
+            let rhs = self.and_expr()?;
+            result = result || rhs;
+        }
+        Ok(result)
+    }
+
+    fn and_expr(&mut self) -> Result<bool, String> {
+        let mut result = self.not_expr()?;
+        while self.args.get(self.pos) == Some(&"-a") {
+            self.pos += 1;
+            let rhs = self.not_expr()?;
+            result = result && rhs;
+        }
+        Ok(result)
+    }
+
+    fn not_expr(&mut self) -> Result<bool, String> {
+        if self.args.get(self.pos) == Some(&"!") {
+            self.pos += 1;
+            return Ok(!self.not_expr()?);
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<bool, String> {
+        let rest = &self.args[self.pos..];
+        match rest {
+            [] => Err("argument expected".to_string()),
+            ["(", ..] => {
+                self.pos += 1;
+                let result = self.or_expr()?;
+                if self.args.get(self.pos) != Some(&")") {
+                    return Err("`)' expected".to_string());
+                }
+                self.pos += 1;
+                Ok(result)
+            }
+            // 二項演算子を単項演算子より優先する（`[ -f = -f ]` を比較として扱う、bash 互換）
+            [left, op, right, ..] if is_binary_op(op) => {
+                self.pos += 3;
+                Ok(eval_binary(left, op, right))
+            }
+            [op, operand, ..] if is_unary_op(op) => {
+                self.pos += 2;
+                Ok(eval_unary(op, operand))
+            }
+            [word, ..] => {
+                self.pos += 1;
+                Ok(!word.is_empty())
             }
         }
-        _ => false,
     }
 }
 
+/// `eval_unary` が扱う単項演算子か。
+fn is_unary_op(op: &str) -> bool {
+    matches!(op, "-n" | "-z" | "-e" | "-f" | "-d" | "-r" | "-w" | "-x" | "-s")
+}
+
+/// `eval_binary` が扱う二項演算子か。
+fn is_binary_op(op: &str) -> bool {
+    matches!(op, "=" | "==" | "!=" | "-eq" | "-ne" | "-lt" | "-le" | "-gt" | "-ge")
+}
+
 /// 単項演算子: `-n`, `-z`, `-e`, `-f`, `-d`, `-r`, `-w`, `-x`, `-s`
 fn eval_unary(op: &str, operand: &str) -> bool {
     match op {
         "-n" => !operand.is_empty(),
         "-z" => operand.is_empty(),
-        "-e" => Path::new(operand).exists(),
-        "-f" => Path::new(operand).is_file(),
-        "-d" => Path::new(operand).is_dir(),
+        "-e" => glob::raw_path(operand).exists(),
+        "-f" => glob::raw_path(operand).is_file(),
+        "-d" => glob::raw_path(operand).is_dir(),
         "-r" => check_access(operand, libc::R_OK),
         "-w" => check_access(operand, libc::W_OK),
         "-x" => check_access(operand, libc::X_OK),
-        "-s" => std::fs::metadata(operand).map(|m| m.len() > 0).unwrap_or(false),
-        "!" => operand.is_empty(), // `! STR` → true if STR is empty
+        "-s" => std::fs::metadata(glob::raw_path(operand)).map(|m| m.len() > 0).unwrap_or(false),
         _ => false,
     }
 }
 
 /// `access(2)` でファイルアクセス権をチェックする。
 fn check_access(path: &str, mode: i32) -> bool {
-    let c_path = match std::ffi::CString::new(path) {
+    let c_path = match std::ffi::CString::new(glob::raw_bytes(path).into_owned()) {
         Ok(p) => p,
         Err(_) => return false,
     };
@@ -1199,9 +1979,21 @@ fn eval_binary(left: &str, op: &str, right: &str) -> bool {
 /// bash 互換: 関数内でのみ意味を持つが、rush では簡易実装として
 /// `export` と同様に環境変数として設定する。関数から return した後に
 /// 呼び出し側で変数が見えなくなるような厳密なスコープは未実装。
-fn builtin_local(args: &[&str]) -> i32 {
+/// `local -` は現在の `set` オプションを退避し、関数から戻るときに復元させる。
+/// 関数の外では復元する時点がないので、bash と同じくエラーにする。
+fn builtin_local(shell: &mut Shell, args: &[&str]) -> i32 {
+    if shell.saved_set_options.is_empty() && args[1..].contains(&"-") {
+        eprintln!("rush: local: can only be used in a function");
+        return 1;
+    }
+    let options = (shell.set_errexit, shell.set_nounset, shell.set_pipefail);
     for arg in &args[1..] {
-        if let Some(eq) = arg.find('=') {
+        if *arg == "-" {
+            // 同じ関数内で 2 回目以降の `local -` は最初の退避を保つ
+            if let Some(frame) = shell.saved_set_options.last_mut() {
+                frame.get_or_insert(options);
+            }
+        } else if let Some(eq) = arg.find('=') {
             let (name, val) = arg.split_at(eq);
             let val = &val[1..]; // '=' をスキップ
             env::set_var(name, val);
@@ -1322,6 +2114,79 @@ fn builtin_set(shell: &mut Shell, args: &[&str], stdout: &mut dyn Write) -> i32
     0
 }
 
+/// `shopt [-pqsu] [optname ...]` — パス名展開オプション（[`glob::GlobOptions`](crate::glob::GlobOptions)）を切り替える。
+///
+/// - `shopt -s name ...` / `shopt -u name ...` — 有効化 / 無効化（名前なしなら有効 / 無効なものを一覧）
+/// - `shopt [name ...]` — 状態を一覧表示。`-p` なら `shopt -s name` 形式、`-q` なら表示せず
+///   すべて有効なら 0、そうでなければ 1 を返す
+fn builtin_shopt(shell: &mut Shell, args: &[&str], stdout: &mut dyn Write) -> i32 {
+    let mut set: Option<bool> = None;
+    let mut print = false;
+    let mut quiet = false;
+    let mut i = 1;
+    while i < args.len() && args[i].starts_with('-') && args[i].len() > 1 {
+        if args[i] == "--" {
+            i += 1;
+            break;
+        }
+        for ch in args[i][1..].chars() {
+            match ch {
+                's' => set = Some(true),
+                'u' => set = Some(false),
+                'p' => print = true,
+                'q' => quiet = true,
+                _ => {
+                    eprintln!("rush: shopt: -{}: invalid option", ch);
+                    eprintln!("shopt: usage: shopt [-pqsu] [optname ...]");
+                    return 2;
+                }
+            }
+        }
+        i += 1;
+    }
+    let names = &args[i..];
+    for name in names {
+        if shell.shopt.get(name).is_none() {
+            eprintln!("rush: shopt: {}: invalid shell option name", name);
+            return 1;
+        }
+    }
+
+    if let (Some(enable), false) = (set, names.is_empty()) {
+        for name in names {
+            if let Some(flag) = shell.shopt.flag_mut(name) {
+                *flag = enable;
+            }
+        }
+        return 0;
+    }
+
+    // 表示: 名前指定があればそれだけ、`-s` / `-u` のみなら該当する状態のものだけ
+    let listed: Vec<&str> = if names.is_empty() {
+        glob::GlobOptions::NAMES.iter().copied()
+            .filter(|name| set.is_none_or(|want| shell.shopt.get(name) == Some(want)))
+            .collect()
+    } else {
+        names.to_vec()
+    };
+    let mut status = 0;
+    for name in listed {
+        let on = shell.shopt.get(name) == Some(true);
+        if !on {
+            status = 1;
+        }
+        if quiet {
+            continue;
+        }
+        if print {
+            let _ = writeln!(stdout, "shopt {} {}", if on { "-s" } else { "-u" }, name);
+        } else {
+            let _ = writeln!(stdout, "{:<15}\t{}", name, if on { "on" } else { "off" });
+        }
+    }
+    if names.is_empty() && !quiet { 0 } else { status }
+}
+
 /// `declare [-a] [-p] name` — 配列変数の宣言・表示。
 ///
 /// - `declare -a name` → 空配列を作成
@@ -1430,18 +2295,39 @@ fn builtin_read_with_shell(shell: &mut Shell, args: &[&str]) -> i32 {
         eprint!("{}", p);
     }
 
-    // stdin から 1 行読み取り
-    let mut line = String::new();
-    match std::io::stdin().read_line(&mut line) {
-        Ok(0) => return 1, // EOF
-        Ok(_) => {}
-        Err(_) => return 1,
+    // stdin から 1 行読み取り。改行の先を読み過ぎないよう 1 バイトずつ読む
+    // （リダイレクト付きループの `read` が fd 0 を差し替え・復元しても読み残しが混ざらない）
+    let mut bytes = Vec::new();
+    let mut eof = true;
+    loop {
+        let mut byte = 0u8;
+        let n = unsafe { libc::read(0, &mut byte as *mut u8 as *mut libc::c_void, 1) };
+        if n < 0 && std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted {
+            continue;
+        }
+        if n <= 0 {
+            break;
+        }
+        eof = false;
+        if byte == b'\n' {
+            break;
+        }
+        bytes.push(byte);
+    }
+    if eof {
+        return 1;
     }
-    let line = line.trim_end_matches('\n').trim_end_matches('\r');
+    let line = {
+        use std::os::unix::ffi::OsStrExt;
+        glob::name_to_string(std::ffi::OsStr::from_bytes(&bytes))
+    };
+    let line = line.trim_end_matches('\r');
+
+    let ifs = parser::ifs_chars();
 
     // -a: 配列に代入
     if let Some(arr_name) = array_name {
-        let parts: Vec<&str> = line.split(|c: char| c == ' ' || c == '\t').collect();
+        let parts = split_read_fields(line, &ifs, 0);
         let mut btree = std::collections::BTreeMap::new();
         for (j, part) in parts.iter().enumerate() {
             btree.insert(j, part.to_string());
@@ -1455,21 +2341,50 @@ fn builtin_read_with_shell(shell: &mut Shell, args: &[&str]) -> i32 {
 
     if vars.is_empty() {
         env::set_var("REPLY", line);
-    } else if vars.len() == 1 {
-        env::set_var(vars[0], line);
     } else {
-        let parts: Vec<&str> = line.splitn(vars.len(), |c: char| c == ' ' || c == '\t').collect();
+        let parts = split_read_fields(line, &ifs, vars.len());
         for (j, var) in vars.iter().enumerate() {
-            if j < parts.len() {
-                env::set_var(var, parts[j]);
-            } else {
-                env::set_var(var, "");
-            }
+            env::set_var(var, parts.get(j).map_or("", |p| p.as_str()));
         }
     }
     0
 }
 
+/// `read` 用に 1 行を `ifs` の文字で分割する。
+///
+/// bash と同様に前後の IFS 空白（スペース・タブ・改行のうち IFS に含まれるもの）は捨て、
+/// 連続する IFS 空白は 1 つの区切りとみなす。空白以外の IFS 文字は 1 文字ごとに区切りになる
+/// （前後の IFS 空白はその区切りに含まれる）。`max` が 1 以上なら最後のフィールドに
+/// 残り全体（途中の区切り文字を含む）を入れる。`ifs` が空なら分割しない。
+fn split_read_fields(line: &str, ifs: &str, max: usize) -> Vec<String> {
+    if ifs.is_empty() {
+        return vec![line.to_string()];
+    }
+    let is_ws = |c: char| matches!(c, ' ' | '\t' | '\n') && ifs.contains(c);
+    let is_delim = |c: char| ifs.contains(c);
+
+    let mut fields = Vec::new();
+    let mut rest = line.trim_matches(is_ws);
+    while !rest.is_empty() {
+        if max > 0 && fields.len() + 1 == max {
+            fields.push(rest.to_string());
+            break;
+        }
+        let Some(pos) = rest.find(is_delim) else {
+            fields.push(rest.to_string());
+            break;
+        };
+        fields.push(rest[..pos].to_string());
+        // 区切り: IFS 空白の並び + 高々 1 つの非空白 IFS 文字 + IFS 空白の並び
+        let mut after = rest[pos..].trim_start_matches(is_ws);
+        if let Some(c) = after.chars().next().filter(|&c| !is_ws(c) && is_delim(c)) {
+            after = after[c.len_utf8()..].trim_start_matches(is_ws);
+        }
+        rest = after;
+    }
+    fields
+}
+
 // ── Tests ───────────────────────────────────────────────────────────
 
 #[cfg(test)]
@@ -1481,6 +2396,72 @@ mod tests {
     /// `set_current_dir` はプロセスグローバルなため、並列実行時の競合を防ぐ。
     static CWD_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
 
+    #[test]
+    fn read_fields_split_on_default_ifs() {
+        assert_eq!(split_read_fields("  a   b\tc  ", " \t\n", 0), vec!["a", "b", "c"]);
+        assert_eq!(split_read_fields("  a   b  c  ", " \t\n", 2), vec!["a", "b  c"]);
+        assert_eq!(split_read_fields("  only  ", " \t\n", 1), vec!["only"]);
+        assert!(split_read_fields("   ", " \t\n", 2).is_empty());
+    }
+
+    #[test]
+    fn read_fields_split_on_custom_ifs() {
+        assert_eq!(split_read_fields("a:b:c", ":", 2), vec!["a", "b:c"]);
+        assert_eq!(split_read_fields("a::c", ":", 0), vec!["a", "", "c"]);
+        // 空白は IFS に含まれないので保持される
+        assert_eq!(split_read_fields(" a b:c ", ":", 0), vec![" a b", "c "]);
+        // 非空白 IFS 文字の前後の IFS 空白は区切りの一部
+        assert_eq!(split_read_fields("a , b,c", " ,", 0), vec!["a", "b", "c"]);
+        assert_eq!(split_read_fields("a b", "", 2), vec!["a b"]);
+    }
+
+    #[test]
+    fn read_last_variable_gets_the_rest() {
+        // stdin を差し替えるため fork した子プロセス内で実行する
+        let (out, _) = crate::executor::run_forked(1, || {
+            let mut input = [0i32; 2];
+            unsafe {
+                libc::pipe(input.as_mut_ptr());
+                libc::write(input[1], b"a:b:c\n".as_ptr() as *const libc::c_void, 6);
+                libc::close(input[1]);
+                libc::dup2(input[0], 0);
+            }
+            env::set_var("IFS", ":");
+            let status = builtin_read_with_shell(&mut Shell::new(), &["read", "RUSH_READ_X", "RUSH_READ_Y"]);
+            let out = format!(
+                "{}|{}|{}",
+                status,
+                env::var("RUSH_READ_X").unwrap_or_default(),
+                env::var("RUSH_READ_Y").unwrap_or_default()
+            );
+            unsafe { libc::write(1, out.as_ptr() as *const libc::c_void, out.len()) };
+            0
+        });
+        assert_eq!(out, "0|a|b:c");
+    }
+
+    #[test]
+    fn shopt_toggles_and_lists_options() {
+        let mut shell = Shell::new();
+        assert_eq!(builtin_shopt(&mut shell, &["shopt", "-s", "nullglob", "dotglob"], &mut Vec::new()), 0);
+        assert!(shell.shopt.nullglob && shell.shopt.dotglob);
+        assert_eq!(builtin_shopt(&mut shell, &["shopt", "-q", "nullglob"], &mut Vec::new()), 0);
+        assert_eq!(builtin_shopt(&mut shell, &["shopt", "-u", "dotglob"], &mut Vec::new()), 0);
+        assert_eq!(builtin_shopt(&mut shell, &["shopt", "-q", "nullglob", "dotglob"], &mut Vec::new()), 1);
+
+        let mut buf = Vec::new();
+        builtin_shopt(&mut shell, &["shopt"], &mut buf);
+        let out = String::from_utf8(buf).unwrap();
+        assert_eq!(out.lines().count(), glob::GlobOptions::NAMES.len());
+        assert!(out.lines().any(|l| l == "nullglob       \ton"));
+        let mut buf = Vec::new();
+        builtin_shopt(&mut shell, &["shopt", "-p", "-s"], &mut buf);
+        assert_eq!(String::from_utf8(buf).unwrap(), "shopt -s nullglob\n");
+
+        assert_eq!(builtin_shopt(&mut shell, &["shopt", "-s", "bogus"], &mut Vec::new()), 1);
+        assert_eq!(builtin_shopt(&mut shell, &["shopt", "-x"], &mut Vec::new()), 2);
+    }
+
     #[test]
     fn pwd_outputs_current_dir() {
         let _lock = CWD_LOCK.lock().unwrap();
@@ -1513,6 +2494,22 @@ mod tests {
         assert_eq!(String::from_utf8(buf).unwrap(), "hello");
     }
 
+    #[test]
+    fn echo_double_dash_and_later_dash_n() {
+        let mut buf = Vec::new();
+        builtin_echo(&["echo", "--", "-n"], &mut buf);
+        assert_eq!(String::from_utf8(buf).unwrap(), "-n\n");
+        let mut buf = Vec::new();
+        builtin_echo(&["echo", "-n", "--"], &mut buf);
+        assert_eq!(String::from_utf8(buf).unwrap(), "--");
+        let mut buf = Vec::new();
+        builtin_echo(&["echo", "-nn", "-n", "a", "-n"], &mut buf);
+        assert_eq!(String::from_utf8(buf).unwrap(), "a -n");
+        let mut buf = Vec::new();
+        builtin_echo(&["echo", "-nx", "-"], &mut buf);
+        assert_eq!(String::from_utf8(buf).unwrap(), "-nx -\n");
+    }
+
     #[test]
     fn echo_dash_n_no_args() {
         let mut buf = Vec::new();
@@ -1523,7 +2520,7 @@ mod tests {
     #[test]
     fn export_set_and_get() {
         let mut buf = Vec::new();
-        builtin_export(&["export", "RUSH_TEST_EXPORT=hello123"], &mut buf);
+        builtin_export(&mut Shell::new(), &["export", "RUSH_TEST_EXPORT=hello123"], &mut buf);
         assert_eq!(env::var("RUSH_TEST_EXPORT").unwrap(), "hello123");
         env::remove_var("RUSH_TEST_EXPORT");
     }
@@ -1531,7 +2528,7 @@ mod tests {
     #[test]
     fn export_value_with_equals() {
         let mut buf = Vec::new();
-        builtin_export(&["export", "RUSH_TEST_EQ=A=B=C"], &mut buf);
+        builtin_export(&mut Shell::new(), &["export", "RUSH_TEST_EQ=A=B=C"], &mut buf);
         assert_eq!(env::var("RUSH_TEST_EQ").unwrap(), "A=B=C");
         env::remove_var("RUSH_TEST_EQ");
     }
@@ -1541,7 +2538,7 @@ mod tests {
         env::set_var("RUSH_TEST_Z", "z");
         env::set_var("RUSH_TEST_A", "a");
         let mut buf = Vec::new();
-        builtin_export(&["export"], &mut buf);
+        builtin_export(&mut Shell::new(), &["export"], &mut buf);
         let output = String::from_utf8(buf).unwrap();
         let a_pos = output.find("RUSH_TEST_A").unwrap();
         let z_pos = output.find("RUSH_TEST_Z").unwrap();
@@ -1550,6 +2547,62 @@ mod tests {
         env::remove_var("RUSH_TEST_A");
     }
 
+    #[test]
+    fn export_p_lists_declarations() {
+        env::set_var("RUSH_TEST_EXPORT_P", "say \"hi\" $x");
+        let mut shell = Shell::new();
+        let mut buf = Vec::new();
+        assert_eq!(builtin_export(&mut shell, &["export", "-p"], &mut buf), 0);
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.lines().any(|l| l == "declare -x RUSH_TEST_EXPORT_P=\"say \\\"hi\\\" \\$x\""));
+        env::remove_var("RUSH_TEST_EXPORT_P");
+        assert_eq!(builtin_export(&mut shell, &["export", "-z"], &mut Vec::new()), 2);
+    }
+
+    #[test]
+    fn export_n_removes_export_attribute() {
+        let mut shell = Shell::new();
+        builtin_export(&mut shell, &["export", "-n", "RUSH_TEST_EXPORT_N=kept"], &mut Vec::new());
+        assert_eq!(env::var("RUSH_TEST_EXPORT_N").unwrap(), "kept");
+        let mut buf = Vec::new();
+        builtin_export(&mut shell, &["export", "-p"], &mut buf);
+        assert!(!String::from_utf8(buf).unwrap().contains("RUSH_TEST_EXPORT_N"));
+
+        // export VAR で属性を付け直す
+        builtin_export(&mut shell, &["export", "RUSH_TEST_EXPORT_N"], &mut Vec::new());
+        let mut buf = Vec::new();
+        builtin_export(&mut shell, &["export"], &mut buf);
+        assert!(String::from_utf8(buf).unwrap().contains("declare -x RUSH_TEST_EXPORT_N=\"kept\""));
+        env::remove_var("RUSH_TEST_EXPORT_N");
+    }
+
+    #[test]
+    fn export_sets_multiple_and_rejects_invalid_names() {
+        let mut shell = Shell::new();
+        let status = builtin_export(
+            &mut shell,
+            &["export", "RUSH_TEST_EXPORT_M1=1", "1bad=x", "RUSH_TEST_EXPORT_M2=2"],
+            &mut Vec::new(),
+        );
+        assert_eq!(status, 1);
+        assert_eq!(env::var("RUSH_TEST_EXPORT_M1").unwrap(), "1");
+        assert_eq!(env::var("RUSH_TEST_EXPORT_M2").unwrap(), "2");
+        assert!(env::var("1bad").is_err());
+        env::remove_var("RUSH_TEST_EXPORT_M1");
+        env::remove_var("RUSH_TEST_EXPORT_M2");
+    }
+
+    #[test]
+    fn export_marks_existing_variable() {
+        let mut shell = Shell::new();
+        env::set_var("RUSH_TEST_EXPORT_MARK", "v");
+        shell.unexported.insert("RUSH_TEST_EXPORT_MARK".to_string());
+        assert_eq!(builtin_export(&mut shell, &["export", "RUSH_TEST_EXPORT_MARK"], &mut Vec::new()), 0);
+        assert!(!shell.unexported.contains("RUSH_TEST_EXPORT_MARK"));
+        assert_eq!(env::var("RUSH_TEST_EXPORT_MARK").unwrap(), "v");
+        env::remove_var("RUSH_TEST_EXPORT_MARK");
+    }
+
     #[test]
     fn unset_removes_var() {
         let mut shell = Shell::new();
@@ -1558,6 +2611,40 @@ mod tests {
         assert!(env::var("RUSH_TEST_UNSET").is_err());
     }
 
+    #[test]
+    fn unset_v_and_f_select_namespace() {
+        let mut shell = Shell::new();
+        env::set_var("RUSH_TEST_UNSET_VF", "value");
+        shell.functions.insert("RUSH_TEST_UNSET_VF".to_string(), "echo hi".to_string());
+
+        // -f は関数のみ、-v は変数のみを削除する
+        assert_eq!(builtin_unset(&mut shell, &["unset", "-f", "RUSH_TEST_UNSET_VF"]), 0);
+        assert!(!shell.functions.contains_key("RUSH_TEST_UNSET_VF"));
+        assert_eq!(env::var("RUSH_TEST_UNSET_VF").unwrap(), "value");
+
+        shell.functions.insert("RUSH_TEST_UNSET_VF".to_string(), "echo hi".to_string());
+        assert_eq!(builtin_unset(&mut shell, &["unset", "-v", "RUSH_TEST_UNSET_VF"]), 0);
+        assert!(env::var("RUSH_TEST_UNSET_VF").is_err());
+        assert!(shell.functions.contains_key("RUSH_TEST_UNSET_VF"));
+
+        // -v で変数がなければ関数は残り、指定なしなら関数を削除する
+        builtin_unset(&mut shell, &["unset", "-v", "RUSH_TEST_UNSET_VF"]);
+        assert!(shell.functions.contains_key("RUSH_TEST_UNSET_VF"));
+        builtin_unset(&mut shell, &["unset", "RUSH_TEST_UNSET_VF"]);
+        assert!(!shell.functions.contains_key("RUSH_TEST_UNSET_VF"));
+
+        assert_eq!(builtin_unset(&mut shell, &["unset", "-fv", "x"]), 1);
+    }
+
+    #[test]
+    fn unset_nonexistent_and_invalid_names() {
+        let mut shell = Shell::new();
+        assert_eq!(builtin_unset(&mut shell, &["unset", "RUSH_TEST_UNSET_NOPE"]), 0);
+        assert_eq!(builtin_unset(&mut shell, &["unset", "-v", "RUSH_TEST_UNSET_NOPE"]), 0);
+        assert_eq!(builtin_unset(&mut shell, &["unset", "1"]), 1);
+        assert_eq!(builtin_unset(&mut shell, &["unset", "@"]), 1);
+    }
+
     #[test]
     fn is_builtin_check() {
         assert!(is_builtin("exit"));
@@ -1570,6 +2657,7 @@ mod tests {
         assert!(is_builtin("fg"));
         assert!(is_builtin("bg"));
         assert!(is_builtin("type"));
+        assert!(is_builtin("hash"));
         assert!(!is_builtin("ls"));
         assert!(!is_builtin("grep"));
     }
@@ -1615,6 +2703,60 @@ mod tests {
         }
     }
 
+    #[test]
+    fn type_t_prints_kind() {
+        let mut shell = Shell::new();
+        shell.aliases.insert("ll".to_string(), "ls -l".to_string());
+        shell.functions.insert("myfn".to_string(), "echo hi".to_string());
+        let mut buf = Vec::new();
+        let status = try_exec(&mut shell, &["type", "-t", "echo", "if", "ll", "myfn"], &mut buf).unwrap();
+        assert_eq!(status, 0);
+        assert_eq!(String::from_utf8(buf).unwrap(), "builtin\nkeyword\nalias\nfunction\n");
+    }
+
+    #[test]
+    fn type_f_skips_functions() {
+        let mut shell = Shell::new();
+        shell.functions.insert("echo".to_string(), "printf hi".to_string());
+        let mut buf = Vec::new();
+        try_exec(&mut shell, &["type", "-t", "echo"], &mut buf).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), "function\n");
+        let mut buf = Vec::new();
+        let status = try_exec(&mut shell, &["type", "-ft", "echo"], &mut buf).unwrap();
+        assert_eq!(status, 0);
+        assert_eq!(String::from_utf8(buf).unwrap(), "builtin\n");
+    }
+
+    #[test]
+    fn type_p_prints_path_or_nothing() {
+        let mut shell = Shell::new();
+        let mut buf = Vec::new();
+        try_exec(&mut shell, &["type", "-p", "echo"], &mut buf).unwrap();
+        assert!(buf.is_empty());
+        let mut buf = Vec::new();
+        try_exec(&mut shell, &["type", "-p", "ls"], &mut buf).unwrap();
+        let expected = find_in_path("ls").map(|p| format!("{}\n", p)).unwrap_or_default();
+        assert_eq!(String::from_utf8(buf).unwrap(), expected);
+    }
+
+    #[test]
+    fn type_a_lists_every_path_hit() {
+        let mut shell = Shell::new();
+        let mut buf = Vec::new();
+        let status = try_exec(&mut shell, &["type", "-a", "ls"], &mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        let expected: Vec<String> = find_all_in_path("ls").iter().map(|p| format!("ls is {}", p)).collect();
+        assert_eq!(output.lines().map(String::from).collect::<Vec<_>>(), expected);
+        assert_eq!(status, if expected.is_empty() { 1 } else { 0 });
+
+        // -a は関数・ビルトインの後に PATH ヒットも列挙する
+        let mut buf = Vec::new();
+        try_exec(&mut shell, &["type", "-a", "-t", "echo"], &mut buf).unwrap();
+        let kinds = String::from_utf8(buf).unwrap();
+        assert!(kinds.starts_with("builtin\n"));
+        assert_eq!(kinds.lines().skip(1).count(), find_all_in_path("echo").len());
+    }
+
     #[test]
     fn try_exec_returns_none_for_external() {
         let mut shell = Shell::new();
@@ -1661,6 +2803,51 @@ mod tests {
         let _ = env::set_current_dir(&orig);
     }
 
+    #[test]
+    fn cd_updates_pwd_and_resolves_dotdot() {
+        let _lock = CWD_LOCK.lock().unwrap();
+        let orig = env::current_dir().unwrap();
+        let base = env::temp_dir().join(format!("rush_cd_pwd_{}", std::process::id()));
+        let sub = base.join("sub");
+        std::fs::create_dir_all(&sub).unwrap();
+        let mut buf = Vec::new();
+
+        assert_eq!(builtin_cd(&["cd", &format!("{}/", sub.display())], &mut buf), 0);
+        assert_eq!(env::var("PWD").unwrap(), sub.to_string_lossy());
+        assert_eq!(env::current_dir().unwrap().canonicalize().unwrap(), sub.canonicalize().unwrap());
+
+        assert_eq!(builtin_cd(&["cd", "../sub/./.."], &mut buf), 0);
+        assert_eq!(env::var("PWD").unwrap(), base.to_string_lossy());
+        assert_eq!(env::var("OLDPWD").unwrap(), sub.to_string_lossy());
+
+        let _ = env::set_current_dir(&orig);
+        let _ = std::fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn cd_tilde_goes_home() {
+        let _lock = CWD_LOCK.lock().unwrap();
+        let home = match env::var("HOME") {
+            Ok(h) if Path::new(&h).is_dir() => h,
+            _ => return,
+        };
+        let orig = env::current_dir().unwrap();
+        let mut buf = Vec::new();
+        assert_eq!(builtin_cd(&["cd", "~"], &mut buf), 0);
+        assert_eq!(
+            env::current_dir().unwrap().canonicalize().unwrap(),
+            Path::new(&home).canonicalize().unwrap()
+        );
+        let _ = env::set_current_dir(&orig);
+    }
+
+    #[test]
+    fn normalize_logical_path_cases() {
+        assert_eq!(normalize_logical_path("/a/b/../c/./"), "/a/c");
+        assert_eq!(normalize_logical_path("//a//b/"), "/a/b");
+        assert_eq!(normalize_logical_path("/.."), "/");
+    }
+
     #[test]
     fn true_returns_zero() {
         let mut shell = Shell::new();
@@ -1694,6 +2881,56 @@ mod tests {
         assert!(shell.should_return);
     }
 
+    #[test]
+    fn exit_inside_source_exits_shell() {
+        let path = env::temp_dir().join(format!("rush_source_exit_{}.sh", std::process::id()));
+        std::fs::write(&path, "exit 3\necho after > /dev/null\n").unwrap();
+        let mut shell = Shell::new();
+        let status = executor::run_command_string(&mut shell, &format!("source {}\necho unreachable", path.display()));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(status, 3);
+        assert_eq!(shell.last_status, 3);
+        assert!(shell.should_exit);
+    }
+
+    #[test]
+    fn self_sourcing_file_hits_nesting_limit() {
+        let path = env::temp_dir().join(format!("rush_source_self_{}.sh", std::process::id()));
+        std::fs::write(&path, format!("source {}\n", path.display())).unwrap();
+        let mut shell = Shell::new();
+        shell.max_nesting_depth = 20;
+        let status = executor::run_command_string(&mut shell, &format!("source {}", path.display()));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(status, 1);
+        assert_eq!(shell.source_depth, 0);
+    }
+
+    #[test]
+    fn recursive_function_hits_nesting_limit() {
+        let mut shell = Shell::new();
+        shell.max_nesting_depth = 20;
+        let status = executor::run_command_string(&mut shell, "rush_recurse() { rush_recurse; }\nrush_recurse");
+        assert_eq!(status, 1);
+        assert_eq!(shell.function_depth, 0);
+        assert_eq!(shell.source_depth, 0);
+        assert!(!shell.should_exit);
+    }
+
+    #[test]
+    fn funcnest_variable_sets_nesting_limit() {
+        // FUNCNEST は他のテストの関数呼び出しにも効くため fork した子プロセス内で設定する
+        let (_, status) = crate::executor::run_forked(1, || {
+            let mut shell = Shell::new();
+            let script = "FUNCNEST=3\nn=0\nrush_nest() { n=$((n+1)); rush_nest; }\nrush_nest\necho $n";
+            let (out, status) = executor::capture(&mut shell, script);
+            // 不正な値は既定の上限に戻す
+            std::env::set_var("FUNCNEST", "x");
+            let fallback = shell.nesting_limit();
+            if status == 0 && out == "3" && fallback == crate::shell::DEFAULT_MAX_NESTING_DEPTH { 0 } else { 1 }
+        });
+        assert_eq!(status, 0);
+    }
+
     #[test]
     fn test_string_nonempty() {
         assert_eq!(builtin_test(&["test", "hello"]), 0);
@@ -1750,6 +2987,35 @@ mod tests {
         assert_eq!(builtin_test(&["[", "a", "=", "a", "]"]), 0);
     }
 
+    #[test]
+    fn test_and_or_grouping() {
+        let args = ["[", "(", "1", "-eq", "1", "-o", "2", "-eq", "3", ")", "-a", "-f", "Cargo.toml", "]"];
+        assert_eq!(builtin_test(&args), 0);
+        assert_eq!(builtin_test(&["test", "(", "1", "-eq", "2", "-o", "2", "-eq", "3", ")", "-a", "-f", "Cargo.toml"]), 1);
+        // -a は -o より強く結合する
+        assert_eq!(builtin_test(&["test", "x", "-o", "", "-a", ""]), 0);
+        assert_eq!(builtin_test(&["test", "-n", "x", "-a", "-z", "x"]), 1);
+        assert_eq!(builtin_test(&["test", "!", "(", "a", "=", "b", ")", "-a", "!", "-d", "Cargo.toml"]), 0);
+        assert_eq!(builtin_test(&["test", "(", "(", "x", ")", ")"]), 0);
+    }
+
+    #[test]
+    fn test_posix_argument_count_rules() {
+        // 3 引数で中央が二項演算子なら、`!` や `(` も文字列として比較する
+        assert_eq!(builtin_test(&["test", "!", "=", "!"]), 0);
+        assert_eq!(builtin_test(&["test", "(", "=", ")"]), 1);
+        assert_eq!(builtin_test(&["test", "(", "", ")"]), 1);
+        assert_eq!(builtin_test(&["test", "!", "(", "x", ")"]), 1);
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        assert_eq!(builtin_test(&["test", "foo", "bar"]), 2);
+        assert_eq!(builtin_test(&["test", "(", "a", "-a", "b"]), 2);
+        assert_eq!(builtin_test(&["test", "a", "-a", "b", "-o"]), 2);
+        assert_eq!(builtin_test(&["test", "a", "-a", "b", "c"]), 2);
+    }
+
     #[test]
     fn test_bracket_missing_close() {
         assert_eq!(builtin_test(&["[", "hello"]), 2);
@@ -1797,6 +3063,24 @@ mod tests {
         assert_eq!(String::from_utf8(buf).unwrap(), "Name: Alice, Age: 30\n");
     }
 
+    #[test]
+    fn printf_reuses_format_for_extra_args() {
+        let mut buf = Vec::new();
+        builtin_printf(&["printf", "[%s]", "a", "b", "c"], &mut buf);
+        assert_eq!(String::from_utf8(buf).unwrap(), "[a][b][c]");
+        // 最後のパスで足りない引数は空文字列・0 になる
+        let mut buf = Vec::new();
+        builtin_printf(&["printf", "%s=%d;", "x", "1", "y"], &mut buf);
+        assert_eq!(String::from_utf8(buf).unwrap(), "x=1;y=0;");
+        // 変換指定子がないフォーマットは 1 回だけ出力する
+        let mut buf = Vec::new();
+        builtin_printf(&["printf", "hi\\n", "a", "b"], &mut buf);
+        assert_eq!(String::from_utf8(buf).unwrap(), "hi\n");
+        let mut buf = Vec::new();
+        builtin_printf(&["printf", "[%s]"], &mut buf);
+        assert_eq!(String::from_utf8(buf).unwrap(), "[]");
+    }
+
     #[test]
     fn printf_percent_literal() {
         let mut buf = Vec::new();
@@ -1827,6 +3111,94 @@ mod tests {
         let _ = env::set_current_dir(&orig);
     }
 
+    #[test]
+    fn pushd_rotates_with_index() {
+        let _lock = CWD_LOCK.lock().unwrap();
+        let orig = env::current_dir().unwrap();
+        let base = env::temp_dir().join(format!("rush_pushd_rot_{}", std::process::id()));
+        let dirs: Vec<String> = ["a", "b", "c"]
+            .iter()
+            .map(|d| {
+                let p = base.join(d);
+                std::fs::create_dir_all(&p).unwrap();
+                p.to_string_lossy().to_string()
+            })
+            .collect();
+        let mut shell = Shell::new();
+        let mut buf = Vec::new();
+        builtin_cd(&["cd", &dirs[0]], &mut buf);
+        builtin_pushd(&mut shell, &["pushd", &dirs[1]], &mut buf);
+        builtin_pushd(&mut shell, &["pushd", &dirs[2]], &mut buf);
+        // スタック: c b a
+        assert_eq!(full_dir_stack(&shell).unwrap(), vec![dirs[2].clone(), dirs[1].clone(), dirs[0].clone()]);
+
+        // +1 で b を先頭に回転: b a c
+        assert_eq!(builtin_pushd(&mut shell, &["pushd", "+1"], &mut buf), 0);
+        assert_eq!(full_dir_stack(&shell).unwrap(), vec![dirs[1].clone(), dirs[0].clone(), dirs[2].clone()]);
+        assert_eq!(env::var("PWD").unwrap(), dirs[1]);
+
+        // -0 で右端の c を先頭に回転: c b a
+        assert_eq!(builtin_pushd(&mut shell, &["pushd", "-0"], &mut buf), 0);
+        assert_eq!(full_dir_stack(&shell).unwrap(), vec![dirs[2].clone(), dirs[1].clone(), dirs[0].clone()]);
+
+        assert_eq!(builtin_pushd(&mut shell, &["pushd", "+3"], &mut buf), 1);
+
+        let _ = env::set_current_dir(&orig);
+        let _ = std::fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn popd_removes_indexed_entry() {
+        let _lock = CWD_LOCK.lock().unwrap();
+        let orig = env::current_dir().unwrap();
+        let cwd = logical_cwd().unwrap();
+        let mut shell = Shell::new();
+        shell.dir_stack = vec!["/z".to_string(), "/y".to_string(), "/x".to_string()];
+        // スタック: cwd /x /y /z — +1 は /x、-0 は /z を削除（移動しない）
+        let mut buf = Vec::new();
+        assert_eq!(builtin_popd(&mut shell, &["popd", "+1"], &mut buf), 0);
+        assert_eq!(shell.dir_stack, vec!["/z".to_string(), "/y".to_string()]);
+        assert_eq!(builtin_popd(&mut shell, &["popd", "-0"], &mut buf), 0);
+        assert_eq!(shell.dir_stack, vec!["/y".to_string()]);
+        assert_eq!(logical_cwd().unwrap(), cwd);
+        assert_eq!(builtin_popd(&mut shell, &["popd", "+2"], &mut buf), 1);
+        let _ = env::set_current_dir(&orig);
+    }
+
+    #[test]
+    fn dirs_v_l_and_c() {
+        let _lock = CWD_LOCK.lock().unwrap();
+        let cwd = logical_cwd().unwrap();
+        let mut shell = Shell::new();
+        shell.dir_stack = vec!["/b".to_string(), "/a".to_string()];
+        let mut buf = Vec::new();
+        assert_eq!(builtin_dirs(&mut shell, &["dirs", "-v", "-l"], &mut buf), 0);
+        assert_eq!(String::from_utf8(buf).unwrap(), format!(" 0  {}\n 1  /a\n 2  /b\n", cwd));
+        assert_eq!(builtin_dirs(&mut shell, &["dirs", "-c"], &mut Vec::new()), 0);
+        assert!(shell.dir_stack.is_empty());
+    }
+
+    #[test]
+    fn dirs_abbreviates_home() {
+        let _lock = CWD_LOCK.lock().unwrap();
+        let home = env::var("HOME").unwrap_or_default();
+        let home = home.trim_end_matches('/');
+        if home.is_empty() {
+            return;
+        }
+        let mut shell = Shell::new();
+        shell.dir_stack = vec![format!("{}/rush_dirs_sub", home)];
+        let mut buf = Vec::new();
+        builtin_dirs(&mut shell, &["dirs", "-p"], &mut buf);
+        let output = String::from_utf8(buf).unwrap();
+        assert_eq!(output.lines().nth(1), Some("~/rush_dirs_sub"));
+
+        let mut buf = Vec::new();
+        builtin_dirs(&mut shell, &["dirs", "-l", "-p"], &mut buf);
+        let expected = format!("{}/rush_dirs_sub", home);
+        assert_eq!(String::from_utf8(buf).unwrap().lines().nth(1), Some(expected.as_str()));
+    }
+
     #[test]
     fn popd_empty_stack() {
         let mut shell = Shell::new();
@@ -1874,9 +3246,9 @@ mod tests {
     #[test]
     fn dirs_shows_current() {
         let _lock = CWD_LOCK.lock().unwrap();
-        let shell = Shell::new();
+        let mut shell = Shell::new();
         let mut buf = Vec::new();
-        let status = builtin_dirs(&shell, &mut buf);
+        let status = builtin_dirs(&mut shell, &["dirs"], &mut buf);
         assert_eq!(status, 0);
         let output = String::from_utf8(buf).unwrap();
         assert!(!output.trim().is_empty());
@@ -1923,6 +3295,30 @@ mod tests {
         assert!(!shell.set_pipefail);
     }
 
+    #[test]
+    fn local_dash_restores_set_options_on_return() {
+        let mut shell = Shell::new();
+        crate::executor::run_command_string(&mut shell, "scoped() { local -; set -e -o pipefail; }\nleaky() { set -u; }");
+        crate::executor::run_command_string(&mut shell, "scoped");
+        assert!(!shell.set_errexit);
+        assert!(!shell.set_pipefail);
+        // local - のない関数の set は呼び出し元に残る
+        crate::executor::run_command_string(&mut shell, "leaky");
+        assert!(shell.set_nounset);
+        // 呼び出し時点のオプションに戻す（return で抜けても同じ）
+        crate::executor::run_command_string(&mut shell, "early() { local -; set +u; return 1; }\nearly");
+        assert!(shell.set_nounset);
+        assert!(shell.saved_set_options.is_empty());
+    }
+
+    #[test]
+    fn local_dash_outside_function_fails() {
+        let mut shell = Shell::new();
+        assert_eq!(builtin_local(&mut shell, &["local", "-", "RUSH_LOCAL_DASH_X=1"]), 1);
+        assert!(std::env::var("RUSH_LOCAL_DASH_X").is_err());
+        assert!(shell.saved_set_options.is_empty());
+    }
+
     #[test]
     fn set_display_options() {
         let mut shell = Shell::new();
@@ -2028,4 +3424,314 @@ mod tests {
         builtin_unset(&mut shell, &["unset", "arr"]);
         assert!(!shell.arrays.contains_key("arr"));
     }
+
+    // ── jobs -l / -p ──
+
+    #[test]
+    fn jobs_p_prints_pgids() {
+        let mut shell = Shell::new();
+        shell.jobs.insert(4321, "sleep 10".to_string(), vec![4321]);
+        shell.jobs.insert(5432, "cat | wc".to_string(), vec![5432, 5433]);
+
+        let mut buf = Vec::new();
+        assert_eq!(builtin_jobs(&shell, &["jobs", "-p"], &mut buf), 0);
+        let output = String::from_utf8(buf).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines, vec!["4321", "5432"]);
+        assert!(lines.iter().all(|l| l.parse::<i32>().is_ok()));
+    }
+
+    #[test]
+    fn jobs_l_includes_pid_column() {
+        let mut shell = Shell::new();
+        shell.jobs.insert(5432, "cat | wc".to_string(), vec![5432, 5433]);
+
+        let mut buf = Vec::new();
+        assert_eq!(builtin_jobs(&shell, &["jobs", "-l"], &mut buf), 0);
+        let output = String::from_utf8(buf).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[0], "[1]+  5432   Running   cat | wc");
+        assert_eq!(lines[1].trim(), "5433");
+    }
+
+    #[test]
+    fn jobs_marks_current_and_previous() {
+        let mut shell = Shell::new();
+        shell.jobs.insert(4321, "sleep 10".to_string(), vec![4321]);
+        shell.jobs.insert(5432, "sleep 20".to_string(), vec![5432]);
+        shell.jobs.insert(6543, "sleep 30".to_string(), vec![6543]);
+
+        let mut buf = Vec::new();
+        assert_eq!(builtin_jobs(&shell, &["jobs"], &mut buf), 0);
+        let output = String::from_utf8(buf).unwrap();
+        assert_eq!(
+            output.lines().collect::<Vec<_>>(),
+            vec!["[1]   Running   sleep 10", "[2]-  Running   sleep 20", "[3]+  Running   sleep 30"]
+        );
+    }
+
+    #[test]
+    fn jobs_lists_only_given_jobspecs() {
+        let mut shell = Shell::new();
+        shell.jobs.insert(4321, "sleep 10".to_string(), vec![4321]);
+        shell.jobs.insert(5432, "sleep 20".to_string(), vec![5432]);
+        shell.jobs.insert(6543, "cat foo".to_string(), vec![6543]);
+
+        let mut buf = Vec::new();
+        assert_eq!(builtin_jobs(&shell, &["jobs", "%cat", "%1"], &mut buf), 0);
+        let output = String::from_utf8(buf).unwrap();
+        assert_eq!(output.lines().collect::<Vec<_>>(), vec!["[3]+  Running   cat foo", "[1]   Running   sleep 10"]);
+
+        let mut buf = Vec::new();
+        assert_eq!(builtin_jobs(&shell, &["jobs", "-p", "%-"], &mut buf), 0);
+        assert_eq!(String::from_utf8(buf).unwrap(), "5432\n");
+
+        // 見つからない jobspec はエラーだが残りは表示する
+        let mut buf = Vec::new();
+        assert_eq!(builtin_jobs(&shell, &["jobs", "%9", "%2"], &mut buf), 1);
+        assert_eq!(String::from_utf8(buf).unwrap(), "[2]-  Running   sleep 20\n");
+    }
+
+    #[test]
+    fn jobs_invalid_option() {
+        let shell = Shell::new();
+        let mut buf = Vec::new();
+        assert_eq!(builtin_jobs(&shell, &["jobs", "-x"], &mut buf), 2);
+        assert!(buf.is_empty());
+    }
+
+    // ── ジョブ指定 ──
+
+    #[test]
+    fn job_spec_resolves_numbers_and_strings() {
+        let mut shell = Shell::new();
+        shell.jobs.insert(4321, "sleep 10".to_string(), vec![4321]);
+        shell.jobs.insert(5432, "cat foo | wc -l".to_string(), vec![5432, 5433]);
+        shell.jobs.insert(6543, "sleep 20".to_string(), vec![6543]);
+
+        assert_eq!(resolve_job_spec(&shell.jobs, "%2"), Some(2));
+        assert_eq!(resolve_job_spec(&shell.jobs, "3"), Some(3));
+        // 前方一致は最新のジョブを選ぶ
+        assert_eq!(resolve_job_spec(&shell.jobs, "%sl"), Some(3));
+        assert_eq!(resolve_job_spec(&shell.jobs, "%cat"), Some(2));
+        assert_eq!(resolve_job_spec(&shell.jobs, "%?wc"), Some(2));
+        assert_eq!(resolve_job_spec(&shell.jobs, "%?10"), Some(1));
+        assert_eq!(resolve_job_spec(&shell.jobs, "%vim"), None);
+        assert_eq!(resolve_job_spec(&shell.jobs, "%?zzz"), None);
+        assert_eq!(resolve_job_spec(&shell.jobs, "sleep"), None);
+    }
+
+    #[test]
+    fn job_spec_current_and_previous() {
+        let mut shell = Shell::new();
+        assert_eq!(resolve_job_spec(&shell.jobs, "%+"), None);
+        assert_eq!(resolve_job_spec(&shell.jobs, "%-"), None);
+        shell.jobs.insert(4321, "sleep 10".to_string(), vec![4321]);
+        assert_eq!(resolve_job_spec(&shell.jobs, "%-"), None);
+        shell.jobs.insert(5432, "sleep 20".to_string(), vec![5432]);
+        assert_eq!(resolve_job_spec(&shell.jobs, "%+"), Some(2));
+        assert_eq!(resolve_job_spec(&shell.jobs, "%%"), Some(2));
+        assert_eq!(resolve_job_spec(&shell.jobs, "%"), Some(2));
+        assert_eq!(resolve_job_spec(&shell.jobs, "%-"), Some(1));
+        assert_eq!(parse_job_arg(&shell, &["fg", "%-"]), Ok(1));
+        assert_eq!(parse_job_arg(&shell, &["fg", "%nope"]), Err(1));
+    }
+
+    // ── wait ──
+
+    #[test]
+    fn wait_unknown_job_returns_127() {
+        // 引数なしの wait が他テストの子プロセスを reap しないよう、fork した子プロセス内で検証する
+        let (_, status) = crate::executor::run_forked(1, || {
+            let mut shell = Shell::new();
+            let ok = builtin_wait(&mut shell, &["wait", "%9"]) == 127
+                && builtin_wait(&mut shell, &["wait", "%nope"]) == 127
+                && builtin_wait(&mut shell, &["wait", "999999"]) == 127
+                && builtin_wait(&mut shell, &["wait"]) == 0;
+            if ok { 0 } else { 1 }
+        });
+        assert_eq!(status, 0);
+    }
+
+    #[test]
+    fn wait_reports_exit_and_signal_status() {
+        // waitpid が他テストの子プロセスを reap しないよう、fork した子プロセス内で検証する
+        let (_, status) = crate::executor::run_forked(1, || {
+            let mut shell = Shell::new();
+            crate::executor::run_command_string(&mut shell, "sh -c 'exit 3' &");
+            let exited = builtin_wait(&mut shell, &["wait", "%1"]);
+            crate::executor::run_command_string(&mut shell, "sleep 5 &");
+            let pgid = shell.jobs.iter().next().map(|j| j.pgid).unwrap_or(0);
+            unsafe { libc::kill(-pgid, libc::SIGTERM); }
+            let bg_pid = shell.last_bg_pid.to_string();
+            let by_pid = builtin_wait(&mut shell, &["wait", &bg_pid]);
+            let ok = exited == 3 && by_pid == 128 + libc::SIGTERM && shell.jobs.iter().count() == 0;
+            if ok { 0 } else { 1 }
+        });
+        assert_eq!(status, 0);
+    }
+
+    // ── wait -n ──
+
+    #[test]
+    fn wait_n_without_jobs_returns_127() {
+        let mut shell = Shell::new();
+        assert_eq!(builtin_wait(&mut shell, &["wait", "-n"]), 127);
+    }
+
+    #[test]
+    fn wait_n_with_only_stopped_jobs_returns_127() {
+        let mut shell = Shell::new();
+        shell.jobs.insert(4321, "vim".to_string(), vec![4321]);
+        for proc in &mut shell.jobs.get_mut(1).unwrap().processes {
+            proc.stopped = true;
+        }
+        // 停止中のジョブは完了しないので待たずに返る
+        assert_eq!(builtin_wait(&mut shell, &["wait", "-n"]), 127);
+        assert_eq!(shell.jobs.iter().count(), 1);
+    }
+
+    #[test]
+    fn wait_n_returns_after_first_job() {
+        // waitpid(-1) が他テストの子プロセスを reap しないよう、fork した子プロセス内で検証する
+        let (_, status) = crate::executor::run_forked(1, || {
+            let mut shell = Shell::new();
+            crate::executor::run_command_string(&mut shell, "sh -c 'sleep 0.1; exit 3' &");
+            crate::executor::run_command_string(&mut shell, "sleep 5 &");
+            let start = std::time::Instant::now();
+            let status = builtin_wait(&mut shell, &["wait", "-n"]);
+            let elapsed = start.elapsed();
+            let remaining = shell.jobs.iter().count();
+            for job in shell.jobs.iter() {
+                unsafe { libc::kill(-job.pgid, libc::SIGKILL); }
+            }
+            let ok = status == 3 && elapsed < std::time::Duration::from_secs(3) && remaining == 1;
+            if ok { 0 } else { 1 }
+        });
+        assert_eq!(status, 0);
+    }
+
+    // ── help ──
+
+    #[test]
+    fn help_lists_builtins() {
+        let mut buf = Vec::new();
+        assert_eq!(builtin_help(&["help"], &mut buf), 0);
+        let out = String::from_utf8(buf).unwrap();
+        assert!(out.split_whitespace().any(|w| w == "cd"));
+        assert!(out.split_whitespace().any(|w| w == "wait"));
+    }
+
+    #[test]
+    fn help_name_prints_usage() {
+        let mut buf = Vec::new();
+        assert_eq!(builtin_help(&["help", "echo"], &mut buf), 0);
+        assert_eq!(String::from_utf8(buf).unwrap(), "echo: echo [-n] [--] [arg ...]\n");
+        let mut buf = Vec::new();
+        assert_eq!(builtin_help(&["help", "nosuchbuiltin"], &mut buf), 1);
+    }
+
+    #[test]
+    fn help_table_matches_is_builtin() {
+        for (name, _) in BUILTIN_HELP {
+            assert!(is_builtin(name), "{} is not a builtin", name);
+        }
+        // 補完候補の全ビルトインにも書式がある
+        for name in crate::complete::BUILTINS {
+            assert!(BUILTIN_HELP.iter().any(|(n, _)| n == name), "{} has no help entry", name);
+        }
+    }
+
+    // ── times ──
+
+    #[test]
+    fn times_prints_two_lines() {
+        let mut buf = Vec::new();
+        assert_eq!(builtin_times(&mut buf), 0);
+        let out = String::from_utf8(buf).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 2);
+        for line in lines {
+            let fields: Vec<&str> = line.split(' ').collect();
+            assert_eq!(fields.len(), 2);
+            assert!(fields.iter().all(|f| f.contains('m') && f.ends_with('s')), "{}", line);
+        }
+    }
+
+    #[test]
+    fn format_cpu_time_minutes_and_millis() {
+        let tv = libc::timeval { tv_sec: 61, tv_usec: 234_567 };
+        assert_eq!(format_cpu_time(&tv), "1m1.234s");
+    }
+
+    // ── hash ──
+
+    #[test]
+    fn hash_empty_table() {
+        let mut shell = Shell::new();
+        let mut buf = Vec::new();
+        assert_eq!(builtin_hash(&mut shell, &["hash"], &mut buf), 0);
+        assert_eq!(String::from_utf8(buf).unwrap(), "hash: hash table empty\n");
+    }
+
+    #[test]
+    fn hash_lists_and_clears_executor_lookups() {
+        let mut shell = Shell::new();
+        let path = shell.command_hash.lookup("sh").expect("sh should be in PATH");
+        shell.command_hash.lookup("sh");
+
+        let mut buf = Vec::new();
+        builtin_hash(&mut shell, &["hash"], &mut buf);
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.starts_with("hits\tcommand\n"));
+        assert!(output.contains(&format!("   2\t{}", path)));
+
+        assert_eq!(builtin_hash(&mut shell, &["hash", "-r"], &mut Vec::new()), 0);
+        assert!(shell.command_hash.is_empty());
+    }
+
+    #[test]
+    fn hash_name_registers_with_zero_hits() {
+        let mut shell = Shell::new();
+        assert_eq!(builtin_hash(&mut shell, &["hash", "sh"], &mut Vec::new()), 0);
+        let entries = shell.command_hash.entries();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].0, "sh");
+        assert_eq!(entries[0].1.hits, 0);
+
+        let mut buf = Vec::new();
+        builtin_hash(&mut shell, &["hash"], &mut buf);
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains(&format!("   0\t{}", entries_path(&shell, "sh"))));
+    }
+
+    #[test]
+    fn hash_name_not_found() {
+        let mut shell = Shell::new();
+        assert_eq!(builtin_hash(&mut shell, &["hash", "rush_no_such_cmd_xyz"], &mut Vec::new()), 1);
+        assert!(shell.command_hash.is_empty());
+    }
+
+    #[test]
+    fn hash_d_drops_single_entry() {
+        let mut shell = Shell::new();
+        builtin_hash(&mut shell, &["hash", "sh", "ls"], &mut Vec::new());
+        assert_eq!(shell.command_hash.entries().len(), 2);
+
+        assert_eq!(builtin_hash(&mut shell, &["hash", "-d", "sh"], &mut Vec::new()), 0);
+        let names: Vec<&str> = shell.command_hash.entries().iter().map(|(n, _)| *n).collect();
+        assert_eq!(names, vec!["ls"]);
+
+        assert_eq!(builtin_hash(&mut shell, &["hash", "-d", "sh"], &mut Vec::new()), 1);
+    }
+
+    fn entries_path(shell: &Shell, name: &str) -> String {
+        shell
+            .command_hash
+            .entries()
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, e)| e.path.clone())
+            .unwrap()
+    }
 }
@@ -7,7 +7,12 @@
 //!
 //! - **コマンド名補完**（行頭 or `|`/`&&`/`||`/`;` の後の最初の単語）:
 //!   ビルトイン一覧 + `$PATH` 内の実行可能ファイルから候補を収集
-//! - **ファイル名補完**（それ以外の位置）:
+//! - **オプション補完**（`-` で始まる単語で、コマンドに [`OptionSpecs`] の定義がある場合）:
+//!   `~/.rush_completions` に登録したフラグ一覧から候補を収集
+//! - **ホスト名補完**（`ssh`/`scp`/`sftp` の `user@hos` のように `@` を含む単語）:
+//!   `~/.ssh/known_hosts` と `~/.ssh/config` の `Host` からホスト名を収集。
+//!   対象コマンドは `$RUSH_HOST_COMMANDS`（空白区切り）で変更できる
+//! - **ファイル名補完**（それ以外の位置。`>`/`>>`/`<`/`2>` 等のリダイレクト先は常にこちら）:
 //!   カレントディレクトリまたは指定ディレクトリのファイル名から候補を収集。
 //!   `~/` プレフィックスはチルダ展開してディレクトリを検索し、
 //!   表示用にはオリジナルの `~` プレフィックスを維持する。
@@ -18,13 +23,15 @@
 //! - 候補 1 件 → 単語を置換 + 末尾にスペース（ディレクトリなら `/`）
 //! - 候補複数 → 共通接頭辞まで補完 + 候補一覧を表示
 
+use std::collections::HashMap;
+
 use crate::highlight::PathCache;
 use crate::parser;
 
 /// コマンド名補完に使うビルトイン一覧（アルファベット順）。
 ///
 /// [`builtins::is_builtin`](crate::builtins::is_builtin) と同期させること。
-const BUILTINS: &[&str] = &[".", ":", "[", "alias", "bg", "builtin", "cd", "command", "declare", "dirs", "echo", "eval", "exec", "exit", "export", "false", "fg", "history", "jobs", "popd", "printf", "pushd", "pwd", "read", "return", "set", "source", "test", "trap", "true", "type", "unalias", "unset", "wait"];
+pub const BUILTINS: &[&str] = &[".", ":", "[", "alias", "bg", "builtin", "cd", "command", "declare", "dirs", "echo", "eval", "exec", "exit", "export", "false", "fg", "hash", "help", "history", "jobs", "popd", "printf", "pushd", "pwd", "read", "return", "set", "shopt", "source", "test", "times", "trap", "true", "type", "unalias", "unset", "wait"];
 
 /// Tab 補完の結果。候補リストと補完対象の単語位置を持つ。
 pub struct CompletionResult {
@@ -36,12 +43,87 @@ pub struct CompletionResult {
     pub word_end: usize,
 }
 
+/// コマンドごとのオプション（フラグ）一覧。`~/.rush_completions` から読み込む。
+///
+/// 1 行に 1 コマンドを `command: -a -b --long` の形式で書く。空行と `#` で始まる行は無視し、
+/// `-` で始まらない語は読み飛ばす。同じコマンドを複数行に書けばフラグを追加する。
+#[derive(Debug, Default)]
+pub struct OptionSpecs {
+    flags: HashMap<String, Vec<String>>,
+}
+
+impl OptionSpecs {
+    /// `$HOME/.rush_completions` を読み込む。ファイルがなければ空。
+    pub fn load() -> Self {
+        let path = std::env::var("HOME")
+            .map(std::path::PathBuf::from)
+            .unwrap_or_else(|_| std::path::PathBuf::from("/tmp"))
+            .join(".rush_completions");
+        std::fs::read_to_string(path).map(|text| Self::parse(&text)).unwrap_or_default()
+    }
+
+    /// 定義ファイルの内容を解析する。
+    pub fn parse(text: &str) -> Self {
+        let mut specs = Self::default();
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((command, flags)) = line.split_once(':') else { continue };
+            let command = command.trim();
+            if command.is_empty() {
+                continue;
+            }
+            let entry = specs.flags.entry(command.to_string()).or_default();
+            entry.extend(flags.split_whitespace().filter(|f| f.starts_with('-')).map(str::to_string));
+        }
+        specs
+    }
+
+    /// `command` のフラグのうち `prefix` で始まるものを返す。定義がなければ `None`。
+    ///
+    /// `/usr/bin/git` のようなパス指定はベース名でも引く。
+    pub fn flags_with_prefix(&self, command: &str, prefix: &str) -> Option<Vec<String>> {
+        let base = command.rsplit('/').next().unwrap_or(command);
+        let flags = self.flags.get(command).or_else(|| self.flags.get(base))?;
+        let mut results: Vec<String> = flags.iter().filter(|f| f.starts_with(prefix)).cloned().collect();
+        results.sort();
+        results.dedup();
+        Some(results)
+    }
+}
+
 /// カーソル位置の単語に対する補完候補を返す。
-pub fn complete(buf: &str, cursor: usize, cache: &PathCache) -> CompletionResult {
-    let (word_start, word, is_command) = current_word(buf, cursor);
+pub fn complete(buf: &str, cursor: usize, cache: &PathCache, specs: &OptionSpecs) -> CompletionResult {
+    let (mut word_start, mut word, is_command) = current_word(buf, cursor);
 
-    let candidates = if is_command {
+    // リダイレクト先はコマンドに関係なくファイル名補完（`>/et` のように演算子が付いていれば外す）
+    let after_redirect = match redirect_operator_len(word) {
+        Some(n) => {
+            word_start += n;
+            word = &word[n..];
+            true
+        }
+        None => buf[..word_start]
+            .split_whitespace()
+            .next_back()
+            .is_some_and(|prev| redirect_operator_len(prev) == Some(prev.len())),
+    };
+
+    let candidates = if after_redirect {
+        find_files(word)
+    } else if is_command {
         find_commands(word, cache)
+    } else if word.contains('@') && segment_command(buf, word_start).is_some_and(is_host_command) {
+        find_hosts(word, &ssh_hosts())
+    } else if let Some(flags) = word
+        .starts_with('-')
+        .then(|| segment_command(buf, word_start))
+        .flatten()
+        .and_then(|cmd| specs.flags_with_prefix(cmd, word))
+    {
+        flags
     } else {
         find_files(word)
     };
@@ -74,6 +156,130 @@ fn current_word(buf: &str, cursor: usize) -> (usize, &str, bool) {
     (word_start, word, is_command)
 }
 
+/// ホスト名補完の対象コマンドか判定する。`$RUSH_HOST_COMMANDS` がなければ `ssh`/`scp`/`sftp`。
+fn is_host_command(command: &str) -> bool {
+    let base = command.rsplit('/').next().unwrap_or(command);
+    match std::env::var("RUSH_HOST_COMMANDS") {
+        Ok(list) => list.split_whitespace().any(|c| c == base),
+        Err(_) => matches!(base, "ssh" | "scp" | "sftp"),
+    }
+}
+
+/// `user@hos` の `@` 以降に一致するホストを `user@host` の形で返す。
+/// scp の `host:path` のように `:` 以降を入力中ならホスト名補完はしない。
+fn find_hosts(word: &str, hosts: &[String]) -> Vec<String> {
+    let Some((user, host_prefix)) = word.rsplit_once('@') else { return Vec::new() };
+    if host_prefix.contains(':') {
+        return Vec::new();
+    }
+    hosts
+        .iter()
+        .filter(|h| h.starts_with(host_prefix))
+        .map(|h| format!("{}@{}", user, h))
+        .collect()
+}
+
+/// `~/.ssh/known_hosts` と `~/.ssh/config` のホスト名を集め、ソート・重複除去して返す。
+fn ssh_hosts() -> Vec<String> {
+    let ssh_dir = std::env::var("HOME")
+        .map(std::path::PathBuf::from)
+        .unwrap_or_else(|_| std::path::PathBuf::from("/tmp"))
+        .join(".ssh");
+    let mut hosts = Vec::new();
+    if let Ok(text) = std::fs::read_to_string(ssh_dir.join("known_hosts")) {
+        hosts.extend(parse_known_hosts(&text));
+    }
+    if let Ok(text) = std::fs::read_to_string(ssh_dir.join("config")) {
+        hosts.extend(parse_ssh_config(&text));
+    }
+    hosts.sort();
+    hosts.dedup();
+    hosts
+}
+
+/// ホスト名がワイルドカードや否定を含むパターンでないか。
+fn is_plain_host(host: &str) -> bool {
+    !host.is_empty() && !host.contains(['*', '?', '!'])
+}
+
+/// `known_hosts` の各行の先頭フィールド（カンマ区切りのホスト名）を返す。
+///
+/// `@cert-authority` 等のマーカーは読み飛ばし、`[host]:port` は `host` にする。
+/// ハッシュ化されたエントリ（`|1|...`）とワイルドカードは含めない。
+pub fn parse_known_hosts(text: &str) -> Vec<String> {
+    let mut hosts = Vec::new();
+    for line in text.lines() {
+        let mut fields = line.split_whitespace();
+        let Some(mut field) = fields.next() else { continue };
+        if field.starts_with('#') {
+            continue;
+        }
+        if field.starts_with('@') {
+            match fields.next() {
+                Some(f) => field = f,
+                None => continue,
+            }
+        }
+        for host in field.split(',') {
+            if host.starts_with('|') {
+                continue;
+            }
+            let host = match host.strip_prefix('[').and_then(|h| h.split_once(']')) {
+                Some((name, _port)) => name,
+                None => host,
+            };
+            if is_plain_host(host) {
+                hosts.push(host.to_string());
+            }
+        }
+    }
+    hosts
+}
+
+/// ssh の `config` の `Host` 行に書かれたホスト名（エイリアス）を返す。ワイルドカードは含めない。
+pub fn parse_ssh_config(text: &str) -> Vec<String> {
+    let mut hosts = Vec::new();
+    for line in text.lines() {
+        let line = line.trim();
+        // `Host a b` と `Host=a` の両方の書き方がある
+        let (keyword, rest) = match line.find(|c: char| c.is_whitespace() || c == '=') {
+            Some(i) => (&line[..i], line[i + 1..].trim_start_matches(|c: char| c.is_whitespace() || c == '=')),
+            None => continue,
+        };
+        if keyword.eq_ignore_ascii_case("host") {
+            hosts.extend(rest.split_whitespace().filter(|h| is_plain_host(h)).map(str::to_string));
+        }
+    }
+    hosts
+}
+
+/// `s` の先頭がファイル名を取るリダイレクト演算子（`>`, `>>`, `>|`, `<`, `&>`, `&>>` と
+/// `2>` のような fd 番号付き）ならその長さを返す。ヒアドキュメント `<<` や fd 複製 `>&` は対象外。
+fn redirect_operator_len(s: &str) -> Option<usize> {
+    let digits = s.bytes().take_while(u8::is_ascii_digit).count();
+    let rest = &s[digits..];
+    let op_len = if digits == 0 && (rest.starts_with("&>>") || rest.starts_with("&>")) {
+        if rest.starts_with("&>>") { 3 } else { 2 }
+    } else if rest.starts_with(">>") || rest.starts_with(">|") {
+        2
+    } else if rest.starts_with('>') || rest.starts_with('<') {
+        1
+    } else {
+        return None;
+    };
+    match rest[op_len..].chars().next() {
+        Some('<') | Some('&') => None,
+        _ => Some(digits + op_len),
+    }
+}
+
+/// `word_start` より前で、同じパイプライン要素 / コマンドリスト要素の先頭の単語（コマンド名）を返す。
+fn segment_command(buf: &str, word_start: usize) -> Option<&str> {
+    let prefix = &buf[..word_start];
+    let segment = prefix.rsplit(['|', ';', '&']).next().unwrap_or(prefix);
+    segment.split_whitespace().next()
+}
+
 /// ビルトイン + PATH コマンドから prefix に一致するものを返す。
 fn find_commands(prefix: &str, cache: &PathCache) -> Vec<String> {
     let mut results: Vec<String> = BUILTINS
@@ -125,25 +331,24 @@ fn find_files(prefix: &str) -> Vec<String> {
     };
 
     let mut results = Vec::new();
-    if let Ok(entries) = std::fs::read_dir(search_dir) {
+    if let Ok(entries) = std::fs::read_dir(crate::glob::raw_path(search_dir)) {
         for entry in entries.flatten() {
-            if let Ok(name) = entry.file_name().into_string() {
-                // 隠しファイルは prefix が '.' で始まる場合のみ表示
-                if !name.starts_with(file_prefix.as_str()) {
-                    continue;
-                }
-                if name.starts_with('.') && !file_prefix.starts_with('.') {
-                    continue;
-                }
-                let is_dir = entry.file_type().map_or(false, |t| t.is_dir());
-                let candidate = format!(
-                    "{}{}{}",
-                    display_dir,
-                    name,
-                    if is_dir { "/" } else { "" }
-                );
-                results.push(candidate);
+            let name = crate::glob::name_to_string(&entry.file_name());
+            // 隠しファイルは prefix が '.' で始まる場合のみ表示
+            if !name.starts_with(file_prefix.as_str()) {
+                continue;
             }
+            if name.starts_with('.') && !file_prefix.starts_with('.') {
+                continue;
+            }
+            let is_dir = entry.file_type().map_or(false, |t| t.is_dir());
+            let candidate = format!(
+                "{}{}{}",
+                display_dir,
+                name,
+                if is_dir { "/" } else { "" }
+            );
+            results.push(candidate);
         }
     }
 
@@ -234,4 +439,98 @@ mod tests {
         let results = find_commands("ech", &cache);
         assert!(results.contains(&"echo".to_string()));
     }
+
+    #[test]
+    fn option_specs_parse_and_filter_flags() {
+        let specs = OptionSpecs::parse(
+            "# comment\n\ngit: --version --help -C\nls: -l -a --all not-a-flag\nls: --color\nbroken line\n",
+        );
+        assert_eq!(specs.flags_with_prefix("ls", "--"), Some(vec!["--all".to_string(), "--color".to_string()]));
+        assert_eq!(specs.flags_with_prefix("ls", "-").unwrap().len(), 4);
+        assert_eq!(specs.flags_with_prefix("/usr/bin/git", "--h"), Some(vec!["--help".to_string()]));
+        assert_eq!(specs.flags_with_prefix("cat", "-"), None);
+    }
+
+    #[test]
+    fn complete_offers_flags_for_known_commands() {
+        let cache = PathCache::new();
+        let specs = OptionSpecs::parse("git: --version --verbose\n");
+        let buf = "echo x | git --ver";
+        let result = complete(buf, buf.len(), &cache, &specs);
+        assert_eq!(result.candidates, ["--verbose", "--version"]);
+        assert_eq!(result.word_start, 13);
+        // 定義のないコマンドや `-` で始まらない単語はファイル名補完のまま
+        assert_eq!(segment_command("ls -l /tm", 6), Some("ls"));
+        let result = complete("cat --nosuch_rush_flag", 22, &cache, &specs);
+        assert!(result.candidates.is_empty());
+    }
+
+    #[test]
+    fn redirect_operator_len_recognizes_file_redirects() {
+        assert_eq!(redirect_operator_len(">"), Some(1));
+        assert_eq!(redirect_operator_len(">>/tmp"), Some(2));
+        assert_eq!(redirect_operator_len("2>err"), Some(2));
+        assert_eq!(redirect_operator_len("&>out"), Some(2));
+        assert_eq!(redirect_operator_len(">|f"), Some(2));
+        assert_eq!(redirect_operator_len("<in"), Some(1));
+        assert_eq!(redirect_operator_len("<<EOF"), None);
+        assert_eq!(redirect_operator_len("2>&1"), None);
+        assert_eq!(redirect_operator_len("42"), None);
+        assert_eq!(redirect_operator_len("file"), None);
+    }
+
+    #[test]
+    fn complete_after_redirect_offers_files() {
+        let dir = std::env::temp_dir().join(format!("rush_complete_redir_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("output.txt"), "").unwrap();
+        let d = dir.display().to_string();
+        let cache = PathCache::new();
+        let specs = OptionSpecs::default();
+        let expected = vec![format!("{}/output.txt", d)];
+
+        let buf = format!("cat > {}/out", d);
+        assert_eq!(complete(&buf, buf.len(), &cache, &specs).candidates, expected);
+        // 演算子が単語に付いていても、コマンド位置（`>|` の `|`）でも同じ
+        for op in [">>", "2>", "<", ">|"] {
+            let buf = format!("echo x {}{}/out", op, d);
+            let result = complete(&buf, buf.len(), &cache, &specs);
+            assert_eq!(result.candidates, expected, "{}", op);
+            assert_eq!(result.word_start, 7 + op.len());
+            let buf = format!("echo x {} {}/out", op, d);
+            assert_eq!(complete(&buf, buf.len(), &cache, &specs).candidates, expected, "{}", op);
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn ssh_host_sources_parse_known_hosts_and_config() {
+        let known_hosts = "\
+github.com,140.82.112.3 ssh-ed25519 AAAA
+[git.example.org]:2222 ssh-rsa AAAA
+|1|abc=|def= ssh-rsa AAAA
+@cert-authority *.corp.example ssh-rsa AAAA
+@revoked old.example.net ssh-rsa AAAA
+# comment
+
+";
+        assert_eq!(
+            parse_known_hosts(known_hosts),
+            ["github.com", "140.82.112.3", "git.example.org", "old.example.net"]
+        );
+
+        let config = "\
+Host dev devbox
+    HostName 10.0.0.5
+    User alice
+host=bastion
+Host *.internal !skip
+Match host foo
+";
+        assert_eq!(parse_ssh_config(config), ["dev", "devbox", "bastion"]);
+
+        let hosts = vec!["dev".to_string(), "devbox".to_string(), "github.com".to_string()];
+        assert_eq!(find_hosts("alice@de", &hosts), ["alice@dev", "alice@devbox"]);
+        assert!(find_hosts("alice@dev:/tmp", &hosts).is_empty());
+    }
 }
@@ -6,8 +6,8 @@
 //! ## 主な機能
 //!
 //! - カーソル移動（←→、Home/End、Ctrl+A/E、Alt+F/B 単語単位移動）
-//! - 編集操作（Ctrl+K/U/W/Alt+D: 削除 → キルリング保存、Ctrl+Y: ヤンク）
-//! - 履歴ナビゲーション（↑↓キー）
+//! - 編集操作（Ctrl+K/U/W/Alt+D: 削除 → キルリング保存、Ctrl+Y: ヤンク、Ctrl+T: 文字の入れ替え、Ctrl+_: アンドゥ）
+//! - 履歴ナビゲーション（↑↓キー。カーソルより前の文字列があればそれで始まるエントリだけを辿る）
 //! - Ctrl+R 逆方向インクリメンタル検索
 //! - Tab 補完（コマンド名 + ファイル名）
 //! - シンタックスハイライト
@@ -49,8 +49,8 @@
 //! 1 回の `write(2)` で出力し、フリッカーを防止する。
 //! カーソル位置は raw バッファの文字数で計算し、ANSI エスケープシーケンスのバイト数を含めない。
 
-use crate::complete;
-use crate::highlight::{self, PathCache};
+use crate::complete::{self, OptionSpecs};
+use crate::highlight::{self, ColorTable, PathCache};
 use crate::history::History;
 
 // ── RawMode ガード ────────────────────────────────────────────────
@@ -133,7 +133,7 @@ pub enum Key {
     Tab,
     /// Ctrl+A（`0x01`）— 行頭へ移動。
     CtrlA,
-    /// Ctrl+C（`0x03`）— 現在の入力を破棄して新プロンプト。
+    /// Ctrl+C（`0x03`）— 現在の入力を破棄し、空行を返して新プロンプト。
     CtrlC,
     /// Ctrl+D（`0x04`）— 空バッファなら EOF、それ以外は無視。
     CtrlD,
@@ -143,6 +143,8 @@ pub enum Key {
     CtrlK,
     /// Ctrl+L（`0x0c`）— 画面クリア + 再描画。
     CtrlL,
+    /// Ctrl+T（`0x14`）— カーソル前後の 2 文字を入れ替える。
+    CtrlT,
     /// Ctrl+U（`0x15`）— 行頭からカーソルまで削除。
     CtrlU,
     /// Ctrl+R（`0x12`）— 逆方向インクリメンタル検索。
@@ -151,6 +153,10 @@ pub enum Key {
     CtrlW,
     /// Ctrl+Y（`0x19`）— キルリングからヤンク（貼り付け）。
     CtrlY,
+    /// Ctrl+Z（`0x1a`）— プロンプトでは何もしない（シェルは SIGTSTP を無視している）。
+    CtrlZ,
+    /// Ctrl+_（`0x1f`）— 直前の編集を取り消す。
+    CtrlUnderscore,
     /// Alt+F（`ESC f`）— 次の単語末尾へ移動。
     AltF,
     /// Alt+B（`ESC b`）— 前の単語先頭へ移動。
@@ -255,7 +261,7 @@ fn read_utf8(fd: i32, first: u8, expected_len: usize) -> Key {
 /// - `\r` / `\n` → Enter
 /// - `0x7f` / `0x08` → Backspace
 /// - `0x1b` → [`read_escape_seq`] でエスケープシーケンスを解析
-/// - `0x01`〜`0x17` → 各種 Ctrl キー
+/// - `0x01`〜`0x1a`, `0x1f` → 各種 Ctrl キー
 /// - `0x20`〜`0x7e` → ASCII 印字可能文字
 /// - `0xC0`〜`0xF7` → [`read_utf8`] で UTF-8 マルチバイト文字を読み取り
 fn read_key(fd: i32) -> Key {
@@ -276,9 +282,12 @@ fn read_key(fd: i32) -> Key {
         11 => Key::CtrlK,
         12 => Key::CtrlL,
         18 => Key::CtrlR,
+        20 => Key::CtrlT,
         21 => Key::CtrlU,
         23 => Key::CtrlW,
         25 => Key::CtrlY,
+        26 => Key::CtrlZ,
+        31 => Key::CtrlUnderscore,
         b if b >= 32 && b < 127 => Key::Char(b as char),
         // UTF-8 マルチバイト
         b if b & 0xE0 == 0xC0 => read_utf8(fd, b, 2),
@@ -314,12 +323,23 @@ pub struct LineEditor {
     path_cache: PathCache,
     /// キルリング。Ctrl+K/U/W/Alt+D で削除したテキストを保持し、Ctrl+Y で貼り付ける。
     kill_ring: String,
+    /// ハイライトの色テーブル。起動時に `RUSH_COLORS` から構築する。
+    colors: ColorTable,
+    /// Ctrl+_ 用のアンドゥスタック。編集操作の直前の `(buf, cursor)` を積む。
+    undo_stack: Vec<(String, usize)>,
+    /// 直前の編集が 1 文字挿入だったか。連続する挿入は 1 つのアンドゥ単位にまとめる。
+    last_edit_insert: bool,
+    /// 入力行の右端に表示するプロンプト（`RPROMPT`）。空なら表示しない。
+    rprompt: String,
+    /// オプション補完の定義。起動時に `~/.rush_completions` から読み込む。
+    option_specs: OptionSpecs,
 }
 
 impl LineEditor {
     /// 新しい `LineEditor` を作成する。
     ///
-    /// `~/.rush_history` から履歴を読み込み、`$PATH` キャッシュを初期化する。
+    /// `~/.rush_history` から履歴、`~/.rush_completions` からオプション補完の定義を読み込み、
+    /// `$PATH` キャッシュと色テーブルを初期化する。
     pub fn new() -> Self {
         Self {
             buf: String::new(),
@@ -328,9 +348,19 @@ impl LineEditor {
             fd: libc::STDIN_FILENO,
             path_cache: PathCache::new(),
             kill_ring: String::new(),
+            colors: ColorTable::from_env(),
+            undo_stack: Vec::new(),
+            last_edit_insert: false,
+            rprompt: String::new(),
+            option_specs: OptionSpecs::load(),
         }
     }
 
+    /// 入力行の右端に表示するプロンプトを設定する。空文字列で非表示。
+    pub fn set_right_prompt(&mut self, rprompt: String) {
+        self.rprompt = rprompt;
+    }
+
     /// コマンド履歴にエントリを追加する。空行・直前と同一のコマンドはスキップ。
     pub fn add_history(&mut self, line: &str) {
         self.history.add(line);
@@ -347,10 +377,12 @@ impl LineEditor {
     }
 
     /// プロンプトを表示し、1 行読み取る。
-    /// Enter → `Some(line)`, Ctrl+D (空バッファ) → `None` (EOF)。
+    /// Enter → `Some(line)`, Ctrl+C → `Some("")`（入力を破棄）, Ctrl+D (空バッファ) → `None` (EOF)。
     pub fn read_line(&mut self, prompt: &str) -> Option<String> {
         self.buf.clear();
         self.cursor = 0;
+        self.undo_stack.clear();
+        self.last_edit_insert = false;
         self.history.reset_nav();
         self.path_cache.refresh();
 
@@ -359,6 +391,13 @@ impl LineEditor {
 
         loop {
             let key = read_key(self.fd);
+            // バッファを書き換えるキーはアンドゥ用に直前の状態を保存する
+            match key {
+                Key::Char(_) => self.push_undo(true),
+                Key::Backspace | Key::Delete | Key::Up | Key::Down | Key::Tab | Key::CtrlK | Key::CtrlT
+                | Key::CtrlU | Key::CtrlW | Key::CtrlY | Key::AltD => self.push_undo(false),
+                _ => self.last_edit_insert = false,
+            }
             match key {
                 Key::Enter => {
                     write_all("\n");
@@ -370,12 +409,10 @@ impl LineEditor {
                     }
                 }
                 Key::CtrlC => {
+                    // シェルは SIGINT を無視しているので、ここで入力だけを取り消す
                     write_all("^C\n");
-                    self.buf.clear();
-                    self.cursor = 0;
-                    self.history.reset_nav();
-                    self.refresh_line(prompt);
-                    continue;
+                    self.cancel_line();
+                    return Some(String::new());
                 }
                 Key::Char(ch) => self.insert_char(ch),
                 Key::Backspace => self.delete_char_before(),
@@ -395,6 +432,8 @@ impl LineEditor {
                     continue;
                 }
                 Key::CtrlK => self.kill_to_end(),
+                Key::CtrlT => self.transpose_chars(),
+                Key::CtrlUnderscore => self.undo(),
                 Key::CtrlU => self.kill_to_start(),
                 Key::CtrlW => self.kill_word_back(),
                 Key::CtrlY => self.yank(),
@@ -405,7 +444,7 @@ impl LineEditor {
                     self.clear_screen(prompt);
                     continue;
                 }
-                Key::Unknown => continue,
+                Key::CtrlZ | Key::Unknown => continue,
             }
             self.refresh_line(prompt);
         }
@@ -413,6 +452,42 @@ impl LineEditor {
 
     // ── バッファ操作 ──────────────────────────────────────────────
 
+    /// 編集操作の直前に現在の `(buf, cursor)` をアンドゥスタックに積む。
+    ///
+    /// `insert` が `true` で直前の編集も挿入なら積まない（連続入力を 1 ステップにまとめる）。
+    /// スタック先頭と同じ状態も積まない（行頭での Backspace など変化のない操作用）。
+    fn push_undo(&mut self, insert: bool) {
+        let coalesce = insert && self.last_edit_insert;
+        self.last_edit_insert = insert;
+        if coalesce {
+            return;
+        }
+        if self.undo_stack.last().is_some_and(|(buf, cursor)| *buf == self.buf && *cursor == self.cursor) {
+            return;
+        }
+        self.undo_stack.push((self.buf.clone(), self.cursor));
+    }
+
+    /// Ctrl+_: 直前の編集の前の状態に戻す。スタックが空なら何もしない。
+    fn undo(&mut self) {
+        // 変化のなかった操作で積まれた状態は読み飛ばす
+        while let Some((buf, cursor)) = self.undo_stack.pop() {
+            if buf != self.buf {
+                self.buf = buf;
+                self.cursor = cursor;
+                break;
+            }
+        }
+        self.last_edit_insert = false;
+    }
+
+    /// Ctrl+C: 入力バッファとカーソルを破棄し、履歴ナビゲーションを末尾に戻す。
+    fn cancel_line(&mut self) {
+        self.buf.clear();
+        self.cursor = 0;
+        self.history.reset_nav();
+    }
+
     /// カーソル位置に 1 文字挿入し、カーソルをその文字の直後に進める。
     fn insert_char(&mut self, ch: char) {
         self.buf.insert(self.cursor, ch);
@@ -468,6 +543,25 @@ impl LineEditor {
         self.cursor = self.buf.len();
     }
 
+    /// Ctrl+T: カーソル直前の文字とカーソル位置の文字を入れ替え、カーソルを 1 文字進める。
+    /// 行末では末尾の 2 文字を入れ替える。行頭や 1 文字以下のバッファでは何もしない。
+    fn transpose_chars(&mut self) {
+        if self.cursor == 0 || self.buf.chars().nth(1).is_none() {
+            return;
+        }
+        if self.cursor == self.buf.len() {
+            self.move_left();
+        }
+        let right_len = self.buf[self.cursor..].chars().next().unwrap().len_utf8();
+        let end = self.cursor + right_len;
+        self.move_left();
+        let start = self.cursor;
+        let left: String = self.buf[start..].chars().take(1).collect();
+        let right = self.buf[start + left.len()..end].to_string();
+        self.buf.replace_range(start..end, &format!("{}{}", right, left));
+        self.cursor = end;
+    }
+
     /// Ctrl+K: カーソルから行末まで削除。削除テキストをキルリングに保存。
     fn kill_to_end(&mut self) {
         self.kill_ring = self.buf[self.cursor..].to_string();
@@ -586,25 +680,51 @@ impl LineEditor {
     // ── 履歴ナビゲーション ────────────────────────────────────────
 
     /// ↑: 履歴を一つ遡る。初回は現在のバッファを保存する。
+    ///
+    /// 初回 Up の時点でカーソルより前に文字があれば、それで始まるエントリだけを辿る
+    /// （カーソルは接頭辞の直後に置いたまま）。空なら時系列にすべてのエントリを辿る。
     fn history_prev(&mut self) {
         if self.history.at_end() {
             let buf = self.buf.clone();
             self.history.save_current(&buf);
+            self.history.set_prefix(&buf[..self.cursor]);
         }
         if let Some(entry) = self.history.prev().map(|s| s.to_string()) {
-            self.buf = entry;
-            self.cursor = self.buf.len();
+            self.show_history_entry(entry);
         }
     }
 
     /// ↓: 履歴を一つ進む。末尾到達時は保存しておいたバッファを復元する。
     fn history_next(&mut self) {
         if let Some(entry) = self.history.next().map(|s| s.to_string()) {
-            self.buf = entry;
-            self.cursor = self.buf.len();
+            self.show_history_entry(entry);
         }
     }
 
+    /// 履歴エントリをバッファに置く。接頭辞検索中はカーソルを接頭辞の直後、それ以外は行末に置く。
+    fn show_history_entry(&mut self, entry: String) {
+        let prefix_len = self.history.prefix().len();
+        self.buf = entry;
+        self.cursor = if prefix_len > 0 && prefix_len <= self.buf.len() {
+            prefix_len
+        } else {
+            self.buf.len()
+        };
+    }
+
+    /// 履歴の `index` 番目のエントリ `line` をバッファに置き、ナビゲーション位置もそこへ移す。
+    ///
+    /// 履歴に入る前なら入力中の行を保存するので、↓で末尾まで戻ると元の入力が復元される。
+    fn select_history_entry(&mut self, index: usize, line: String) {
+        if self.history.at_end() {
+            let buf = self.buf.clone();
+            self.history.save_current(&buf);
+        }
+        self.history.set_nav(index);
+        self.buf = line;
+        self.cursor = self.buf.len();
+    }
+
     // ── Ctrl+R 逆方向検索 ─────────────────────────────────────────
 
     /// Ctrl+R: 逆方向インクリメンタル検索。
@@ -634,8 +754,7 @@ impl LineEditor {
                 Key::Enter => {
                     // 確定: マッチした行をバッファにセット
                     if let Some(line) = matched_line {
-                        self.buf = line;
-                        self.cursor = self.buf.len();
+                        self.select_history_entry(search_pos, line);
                     }
                     write_all("\n");
                     // 通常プロンプトに戻す
@@ -690,8 +809,7 @@ impl LineEditor {
                 _ => {
                     // その他のキーで検索終了、マッチした行をバッファにセット
                     if let Some(line) = matched_line {
-                        self.buf = line;
-                        self.cursor = self.buf.len();
+                        self.select_history_entry(search_pos, line);
                     }
                     self.refresh_line(prompt);
                     return;
@@ -708,7 +826,7 @@ impl LineEditor {
     /// - 候補 1 件 → 単語を候補で置換し、末尾にスペース（ディレクトリなら `/`）を付加
     /// - 候補複数 → 共通接頭辞まで補完し、候補一覧を表示
     fn do_complete(&mut self, prompt: &str) {
-        let result = complete::complete(&self.buf, self.cursor, &self.path_cache);
+        let result = complete::complete(&self.buf, self.cursor, &self.path_cache, &self.option_specs);
 
         match result.candidates.len() {
             0 => {
@@ -732,13 +850,23 @@ impl LineEditor {
                         .replace_range(result.word_start..result.word_end, &common);
                     self.cursor = result.word_start + common.len();
                 }
-                // 候補一覧を表示
+                // 候補一覧を表示（端末幅を超える手前で折り返す）
+                let (cols, _) = crate::shell::terminal_size();
                 let mut display = String::from("\n");
+                let mut line_len = 0;
                 for (i, candidate) in result.candidates.iter().enumerate() {
+                    let len = candidate.chars().count();
                     if i > 0 {
-                        display.push_str("  ");
+                        if line_len + 2 + len > cols as usize {
+                            display.push('\n');
+                            line_len = 0;
+                        } else {
+                            display.push_str("  ");
+                            line_len += 2;
+                        }
                     }
                     display.push_str(candidate);
+                    line_len += len;
                 }
                 display.push('\n');
                 write_all(&display);
@@ -756,9 +884,10 @@ impl LineEditor {
     /// 2. プロンプトを出力
     /// 3. [`highlight::highlight`] でハイライト済みバッファを出力
     /// 4. `\x1b[K` で行末までクリア（前回より短い入力のゴミを消す）
-    /// 5. `\x1b[{N}D` でカーソルを正しい位置に戻す
+    /// 5. 右プロンプトがあり入力と重ならなければ、`\x1b[{N}G` で右端に寄せて出力する
+    /// 6. カーソルを正しい位置に戻す
     fn refresh_line(&self, prompt: &str) {
-        let highlighted = highlight::highlight(&self.buf, &self.path_cache);
+        let highlighted = highlight::highlight(&self.buf, &self.path_cache, &self.colors);
 
         let buf_chars = self.buf.chars().count();
         let cursor_chars = self.buf[..self.cursor].chars().count();
@@ -769,7 +898,19 @@ impl LineEditor {
         out.push_str(prompt);
         out.push_str(&highlighted);
         out.push_str("\x1b[K"); // 行末までクリア
-        if move_back > 0 {
+
+        let prompt_width = display_width(prompt);
+        let rprompt_col = if self.rprompt.is_empty() {
+            None
+        } else {
+            let cols = crate::shell::terminal_size().0 as usize;
+            rprompt_column(cols, prompt_width + buf_chars, display_width(&self.rprompt))
+        };
+        if let Some(col) = rprompt_col {
+            // 右プロンプトを描いたあと、カーソルを絶対位置で戻す
+            out.push_str(&format!("\x1b[{}G{}", col + 1, self.rprompt));
+            out.push_str(&format!("\x1b[{}G", prompt_width + cursor_chars + 1));
+        } else if move_back > 0 {
             out.push_str(&format!("\x1b[{}D", move_back));
         }
 
@@ -777,6 +918,36 @@ impl LineEditor {
     }
 }
 
+/// 右プロンプトを右端から 1 桁空けて置くときの、開始桁（0 始まり）を返す。
+///
+/// `left_width` はプロンプトと入力を合わせた表示幅。入力との間に 1 桁以上の隙間が取れなければ
+/// （行が右プロンプトの位置まで伸びた、端末が狭い）`None` を返し、右プロンプトは表示しない。
+pub fn rprompt_column(cols: usize, left_width: usize, rprompt_width: usize) -> Option<usize> {
+    // 最終桁に書くと端末が自動改行することがあるので、右端は 1 桁空ける
+    let start = cols.checked_sub(rprompt_width + 1)?;
+    (left_width < start).then_some(start)
+}
+
+/// 端末上の表示幅（文字数）を返す。`\x1b[...m` などの CSI エスケープ列は数えない。
+fn display_width(s: &str) -> usize {
+    let mut width = 0;
+    let mut chars = s.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch == '\x1b' && chars.peek() == Some(&'[') {
+            chars.next();
+            // パラメータ・中間バイトを読み飛ばし、終端バイト（0x40–0x7E）で終わる
+            for c in chars.by_ref() {
+                if ('\x40'..='\x7e').contains(&c) {
+                    break;
+                }
+            }
+        } else {
+            width += 1;
+        }
+    }
+    width
+}
+
 /// libc::write で直接出力する（Rust の stdout バッファをバイパス）。
 fn write_all(s: &str) {
     let bytes = s.as_bytes();
@@ -811,7 +982,221 @@ mod tests {
             fd: libc::STDIN_FILENO,
             path_cache: PathCache::new(),
             kill_ring: String::new(),
+            colors: ColorTable::default(),
+            undo_stack: Vec::new(),
+            last_edit_insert: false,
+            rprompt: String::new(),
+            option_specs: OptionSpecs::default(),
+        }
+    }
+
+    /// `bytes` をパイプに書き込み、[`read_key`] で 1 キー読み取る。
+    fn decode_key(bytes: &[u8]) -> Key {
+        let mut fds = [0i32; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        unsafe {
+            libc::write(fds[1], bytes.as_ptr() as *const libc::c_void, bytes.len());
+            libc::close(fds[1]);
+        }
+        let key = read_key(fds[0]);
+        unsafe { libc::close(fds[0]); }
+        key
+    }
+
+    #[test]
+    fn rprompt_column_aligns_right_and_hides_on_overlap() {
+        // 80 桁で幅 5 の右プロンプト → 74 桁目から（右端 1 桁空け）
+        assert_eq!(rprompt_column(80, 10, 5), Some(74));
+        assert_eq!(rprompt_column(80, 73, 5), Some(74));
+        // 入力が右プロンプトに接したら隠す
+        assert_eq!(rprompt_column(80, 74, 5), None);
+        assert_eq!(rprompt_column(80, 100, 5), None);
+        // 端末より広い右プロンプトは表示しない
+        assert_eq!(rprompt_column(4, 0, 5), None);
+        assert_eq!(rprompt_column(6, 0, 5), None);
+        assert_eq!(rprompt_column(7, 0, 5), Some(1));
+    }
+
+    #[test]
+    fn display_width_skips_escape_sequences() {
+        assert_eq!(display_width("rush$ "), 6);
+        assert_eq!(display_width("\x1b[1;32m~/src\x1b[0m $ "), 8);
+        assert_eq!(display_width("日本 "), 3);
+    }
+
+    #[test]
+    fn ctrl_z_decodes_to_ignored_key() {
+        assert!(matches!(decode_key(&[0x1a]), Key::CtrlZ));
+        assert!(matches!(decode_key(&[0x03]), Key::CtrlC));
+        assert!(matches!(decode_key("é".as_bytes()), Key::Char('é')));
+    }
+
+    #[test]
+    fn history_navigation_restores_in_progress_line() {
+        let mut ed = test_editor();
+        ed.history = History::with_entries(&["ls", "echo old"]);
+        ed.buf = "git sta".to_string();
+        ed.cursor = 0;
+        ed.history_prev();
+        ed.history_prev();
+        assert_eq!(ed.buf, "ls");
+        ed.history_next();
+        ed.history_next();
+        assert_eq!(ed.buf, "git sta");
+        assert_eq!(ed.cursor, ed.buf.len());
+        assert!(ed.history.at_end());
+        // 末尾でさらに↓しても入力は変わらない
+        ed.history_next();
+        assert_eq!(ed.buf, "git sta");
+
+        // Ctrl+R で選んだエントリからも↓で入力中の行に戻れる
+        ed.select_history_entry(0, "ls".to_string());
+        assert_eq!(ed.buf, "ls");
+        ed.history_next();
+        assert_eq!(ed.buf, "echo old");
+        ed.history_next();
+        assert_eq!(ed.buf, "git sta");
+    }
+
+    #[test]
+    fn up_with_text_before_cursor_searches_by_prefix() {
+        let mut ed = test_editor();
+        ed.history = History::with_entries(&["git status", "ls", "git log", "make"]);
+        ed.buf = "git ".to_string();
+        ed.cursor = ed.buf.len();
+        ed.history_prev();
+        assert_eq!((ed.buf.as_str(), ed.cursor), ("git log", 4));
+        ed.history_prev();
+        assert_eq!((ed.buf.as_str(), ed.cursor), ("git status", 4));
+        ed.history_prev();
+        assert_eq!(ed.buf, "git status");
+        ed.history_next();
+        ed.history_next();
+        assert_eq!((ed.buf.as_str(), ed.cursor), ("git ", 4));
+
+        // 空のバッファからは時系列
+        ed.history.reset_nav();
+        ed.buf.clear();
+        ed.cursor = 0;
+        ed.history_prev();
+        ed.history_prev();
+        assert_eq!((ed.buf.as_str(), ed.cursor), ("git log", 7));
+    }
+
+    #[test]
+    fn cancel_line_discards_buffer_and_history_nav() {
+        let mut ed = test_editor();
+        ed.history = History::with_entries(&["echo old"]);
+        ed.buf = "echo new".to_string();
+        ed.cursor = 4;
+        ed.history_prev();
+        assert_eq!(ed.buf, "echo old");
+        assert!(!ed.history.at_end());
+
+        ed.cancel_line();
+        assert_eq!(ed.buf, "");
+        assert_eq!(ed.cursor, 0);
+        assert!(ed.history.at_end());
+        // 保存していた入力も破棄され、↓ で復元されない
+        ed.history_next();
+        assert_eq!(ed.buf, "");
+    }
+
+    #[test]
+    fn undo_restores_previous_edit() {
+        let mut ed = test_editor();
+        ed.buf = "echo hello".to_string();
+        ed.cursor = ed.buf.len();
+        ed.push_undo(false);
+        ed.kill_word_back();
+        assert_eq!(ed.buf, "echo ");
+        ed.undo();
+        assert_eq!(ed.buf, "echo hello");
+        assert_eq!(ed.cursor, 10);
+        // 空のスタックでは何もしない
+        ed.undo();
+        assert_eq!(ed.buf, "echo hello");
+    }
+
+    #[test]
+    fn undo_coalesces_consecutive_inserts() {
+        let mut ed = test_editor();
+        for ch in "ls".chars() {
+            ed.push_undo(true);
+            ed.insert_char(ch);
+        }
+        ed.push_undo(false);
+        ed.delete_char_before();
+        for ch in " -la".chars() {
+            ed.push_undo(true);
+            ed.insert_char(ch);
         }
+        assert_eq!(ed.undo_stack.len(), 3);
+        ed.undo();
+        assert_eq!(ed.buf, "l");
+        ed.undo();
+        assert_eq!(ed.buf, "ls");
+        ed.undo();
+        assert_eq!((ed.buf.as_str(), ed.cursor), ("", 0));
+    }
+
+    #[test]
+    fn undo_skips_edits_that_changed_nothing() {
+        let mut ed = test_editor();
+        ed.push_undo(true);
+        ed.insert_char('a');
+        ed.move_home();
+        ed.last_edit_insert = false;
+        // 行頭での Backspace は変化がない
+        ed.push_undo(false);
+        ed.delete_char_before();
+        ed.undo();
+        assert_eq!(ed.buf, "");
+        assert!(matches!(decode_key(&[0x1f]), Key::CtrlUnderscore));
+    }
+
+    #[test]
+    fn transpose_chars_mid_line() {
+        let mut ed = test_editor();
+        ed.buf = "abcd".to_string();
+        ed.cursor = 2;
+        ed.transpose_chars();
+        assert_eq!(ed.buf, "acbd");
+        assert_eq!(ed.cursor, 3);
+    }
+
+    #[test]
+    fn transpose_chars_at_end_of_line() {
+        let mut ed = test_editor();
+        ed.buf = "abcd".to_string();
+        ed.cursor = 4;
+        ed.transpose_chars();
+        assert_eq!(ed.buf, "abdc");
+        assert_eq!(ed.cursor, 4);
+    }
+
+    #[test]
+    fn transpose_chars_respects_utf8_and_edges() {
+        let mut ed = test_editor();
+        ed.buf = "aあい".to_string();
+        ed.cursor = 1;
+        ed.transpose_chars();
+        assert_eq!(ed.buf, "あaい");
+        assert_eq!(ed.cursor, "あa".len());
+        ed.move_end();
+        ed.transpose_chars();
+        assert_eq!(ed.buf, "あいa");
+        assert_eq!(ed.cursor, ed.buf.len());
+
+        // 行頭・1 文字のバッファでは何もしない
+        ed.cursor = 0;
+        ed.transpose_chars();
+        assert_eq!(ed.buf, "あいa");
+        assert_eq!(ed.cursor, 0);
+        ed.buf = "x".to_string();
+        ed.cursor = 1;
+        ed.transpose_chars();
+        assert_eq!((ed.buf.as_str(), ed.cursor), ("x", 1));
     }
 
     #[test]
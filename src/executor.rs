@@ -14,13 +14,15 @@
 //!   - fd 複製（`2>&1` 等）は `extra_dup2s` で spawn に渡す
 //!   - foreground: `tcsetpgrp` でターミナル制御を渡し、`waitpid(WUNTRACED)` で待機
 //!   - background: ジョブテーブルに登録して即座に返る
+//! - `time` 予約語付きパイプライン: 実行後に real/user/sys を stderr に報告（`TIMEFORMAT` 対応）
 //!
 //! ## 複合コマンド (`if`/`then`/`elif`/`else`/`fi`)
 //!
-//! テキストベースのアプローチで実装。既存の AST を変更せず、
-//! if ブロック全体を文字列として収集してから専用関数で解釈・実行する。
+//! パーサが [`parser::Compound`] ノードとして構造化し、
+//! [`execute_compound`] が条件・本体のテキストを再帰的にパース・実行する
+//! （変数展開はパース時に行われるため、本体は実行の都度パースする）。
 //!
-//! - [`execute_if_block`]: if ブロックのエントリポイント。テキストをセクション分割し条件評価
+//! - [`execute_block`]: 行ベースで収集した if/ループ/case ブロックのエントリポイント
 //! - [`collect_if_block`]: 行配列から `if`〜`fi` の範囲を収集（ネスト深さ追跡）
 //! - [`starts_with_if`]: 行が `if` キーワードで始まるかの判定
 //!
@@ -29,15 +31,13 @@
 //! if command; then body; fi
 //! if command; then body; else body; fi
 //! if command; then body; elif command; then body; else body; fi
-//! # ネスト・複数行にも対応
+//! # ネスト・複数行・`&&` 等との組み合わせにも対応
 //! ```
 //!
 //! ## ループ (`for`/`while`/`until`/`do`/`done`)
 //!
-//! if と同じテキストベースアプローチで実装。
+//! if と同じく [`parser::Compound`] として実行する。
 //!
-//! - [`execute_for_block`]: `for VAR in WORDS; do BODY; done` を実行
-//! - [`execute_while_block`]: `while COND; do BODY; done` / `until COND; do BODY; done` を実行
 //! - [`collect_loop_block`]: 行配列から `for`/`while`/`until`〜`done` の範囲を収集
 //! - [`starts_with_for`], [`starts_with_while`], [`starts_with_until`]: キーワード判定
 //!
@@ -49,9 +49,16 @@
 //! # ネスト・break・continue 対応
 //! ```
 //!
+//! `fi` / `done` / `esac` の後のリダイレクトはブロック全体に適用する（`for ...; done > out`）。
+//! 入出力ともシェル自身の fd を差し替えて現在のシェルで実行するので、ブロック内の代入は呼び出し元に残る。
+//! パイプラインの要素になった複合コマンド（`... | while read x; do ...; done`）は
+//! fork した子プロセスで実行するため、本体での代入は呼び出し元に残らない（bash の既定と同じ）。
+//!
 //! ## case 文 (`case`/`in`/`)`/`;;`/`esac`)
 //!
-//! - [`execute_case_block`]: `case WORD in PATTERN) BODY;; ... esac` を実行
+//! パーサは `case`〜`esac` のテキストを [`parser::Compound::Case`] として切り出し、
+//! 節の解釈は行単位で行う。
+//!
 //! - [`collect_case_block`]: 行配列から `case`〜`esac` の範囲を収集
 //! - [`starts_with_case`]: 行が `case` キーワードで始まるかの判定
 //!
@@ -79,6 +86,7 @@
 //! name arg1 arg2           # 呼び出し（$1, $2 で参照）
 //! ```
 
+use std::collections::HashSet;
 use std::fs::{File, OpenOptions};
 use std::io;
 use std::os::unix::io::IntoRawFd;
@@ -88,7 +96,6 @@ use crate::glob;
 use crate::job;
 use crate::parser::{self, CommandList, Connector, Pipeline, RedirectKind};
 use crate::shell::Shell;
-use crate::spawn;
 
 /// コマンド置換 + チルダ展開 + ブレース展開 + glob 展開を統一的に適用する。
 fn expand_args_full(args: &[std::borrow::Cow<'_, str>], shell: &mut Shell) -> Vec<String> {
@@ -109,21 +116,68 @@ fn expand_args_full(args: &[std::borrow::Cow<'_, str>], shell: &mut Shell) -> Ve
         let tilde_expanded = parser::expand_tilde(&sub_expanded);
         // 3. ブレース展開
         let brace_expanded = expand_braces(&tilde_expanded);
-        // 4. glob 展開
+        // 4. ワード分割（"$@" / ${arr[@]} / 非クォート展開の IFS 分割: \x1F をセパレータとして分割）
+        //    → 5. glob 展開
         for word in &brace_expanded {
-            if glob::has_glob_chars(word) {
-                result.extend(glob::expand(word));
-            } else if word.contains('\x1F') {
-                // 配列 ${arr[@]} のワード分割: \x1F をセパレータとして分割
-                result.extend(word.split('\x1F').filter(|s| !s.is_empty()).map(String::from));
-            } else {
-                result.push(word.clone());
+            for field in word.split('\x1F').filter(|s| !s.is_empty()) {
+                if glob::has_glob_chars(field) {
+                    match glob::expand_with(field, &shell.shopt) {
+                        Some(paths) => result.extend(paths),
+                        None => {
+                            // failglob: 呼び出し側がコマンドを実行せずに失敗させる
+                            eprintln!("rush: no match: {}", glob::strip_quoted(field));
+                            shell.glob_failed = true;
+                        }
+                    }
+                } else {
+                    result.push(glob::strip_quoted(field).into_owned());
+                }
             }
         }
     }
     result
 }
 
+/// ファイルリダイレクトのターゲットにコマンド置換・チルダ・glob 展開とワード分割を適用する。
+///
+/// 展開結果がちょうど 1 ワードでなければ `ambiguous redirect` を表示して `Err(1)` を返す
+/// （`V="a b"; echo x > $V` など）。ヒアドキュメント・ヒアストリング・fd 複製・プロセス置換はそのまま。
+fn expand_redirect_targets<'a>(
+    redirects: &[parser::Redirect<'a>],
+    shell: &mut Shell,
+) -> Result<Vec<parser::Redirect<'a>>, i32> {
+    let mut result = Vec::with_capacity(redirects.len());
+    for r in redirects {
+        let is_file = matches!(
+            r.kind,
+            RedirectKind::Output | RedirectKind::Append | RedirectKind::Input
+                | RedirectKind::Stderr | RedirectKind::StderrAppend | RedirectKind::FdOutput { .. }
+        );
+        if !is_file || r.target.starts_with("\x1E") {
+            result.push(r.clone());
+            continue;
+        }
+        let words = expand_args_full(std::slice::from_ref(&r.target), shell);
+        if std::mem::take(&mut shell.glob_failed) {
+            return Err(1);
+        }
+        match <[String; 1]>::try_from(words) {
+            Ok([target]) => result.push(parser::Redirect { kind: r.kind, target: std::borrow::Cow::Owned(target) }),
+            Err(_) => {
+                // 元のワードはパース時に展開済みなので、展開後のワードを表示する
+                let shown: Vec<&str> = r.target.split('\x1F').filter(|w| !w.is_empty()).collect();
+                if shown.is_empty() {
+                    eprintln!("rush: ambiguous redirect");
+                } else {
+                    eprintln!("rush: {}: ambiguous redirect", shown.join(" ").replace(parser::UNQUOTED_SUB, ""));
+                }
+                return Err(1);
+            }
+        }
+    }
+    Ok(result)
+}
+
 // ── プロセス置換 ───────────────────────────────────────────────────
 
 /// プロセス置換で生成された子プロセスの情報。
@@ -414,7 +468,7 @@ fn execute_subshell(shell: &mut Shell, body: &str, redirects: &[parser::Redirect
             let target = r.target.as_ref();
             match r.kind {
                 parser::RedirectKind::Output => {
-                    match std::fs::File::create(target) {
+                    match std::fs::File::create(glob::raw_path(target)) {
                         Ok(f) => {
                             use std::os::unix::io::IntoRawFd;
                             let fd = f.into_raw_fd();
@@ -424,7 +478,7 @@ fn execute_subshell(shell: &mut Shell, body: &str, redirects: &[parser::Redirect
                     }
                 }
                 parser::RedirectKind::Append => {
-                    match std::fs::OpenOptions::new().create(true).append(true).open(target) {
+                    match std::fs::OpenOptions::new().create(true).append(true).open(glob::raw_path(target)) {
                         Ok(f) => {
                             use std::os::unix::io::IntoRawFd;
                             let fd = f.into_raw_fd();
@@ -434,7 +488,7 @@ fn execute_subshell(shell: &mut Shell, body: &str, redirects: &[parser::Redirect
                     }
                 }
                 parser::RedirectKind::Input => {
-                    match std::fs::File::open(target) {
+                    match std::fs::File::open(glob::raw_path(target)) {
                         Ok(f) => {
                             use std::os::unix::io::IntoRawFd;
                             let fd = f.into_raw_fd();
@@ -445,9 +499,9 @@ fn execute_subshell(shell: &mut Shell, body: &str, redirects: &[parser::Redirect
                 }
                 parser::RedirectKind::Stderr | parser::RedirectKind::StderrAppend => {
                     let f = if matches!(r.kind, parser::RedirectKind::StderrAppend) {
-                        std::fs::OpenOptions::new().create(true).append(true).open(target)
+                        std::fs::OpenOptions::new().create(true).append(true).open(glob::raw_path(target))
                     } else {
-                        std::fs::File::create(target).map(|f| f)
+                        std::fs::File::create(glob::raw_path(target)).map(|f| f)
                     };
                     match f {
                         Ok(f) => {
@@ -474,17 +528,23 @@ fn execute_subshell(shell: &mut Shell, body: &str, redirects: &[parser::Redirect
     }
 }
 
-/// コマンド文字列を実行して stdout の出力を取得する（コマンド置換用）。
-fn execute_capture(cmd_str: &str, shell: &mut Shell) -> String {
+/// コマンド文字列を fork した子プロセスで実行し、stdout の出力と終了ステータスを返す。
+///
+/// コマンド置換 `$(...)` / `` `...` `` の実装本体で、ライブラリ利用者にも公開する。
+/// 子の終了ステータスを `shell.last_status` に反映する（`x=$(false)` の `$?` 用）。
+/// 出力末尾の改行の連続は取り除く。
+pub fn capture(shell: &mut Shell, cmd_str: &str) -> (String, i32) {
     let mut pipefd = [0i32; 2];
     if unsafe { libc::pipe(pipefd.as_mut_ptr()) } != 0 {
-        return String::new();
+        eprintln!("rush: pipe: {}", std::io::Error::last_os_error());
+        return (String::new(), 1);
     }
 
     let pid = unsafe { libc::fork() };
     if pid < 0 {
+        eprintln!("rush: fork: {}", std::io::Error::last_os_error());
         unsafe { libc::close(pipefd[0]); libc::close(pipefd[1]); }
-        return String::new();
+        return (String::new(), 1);
     }
 
     if pid == 0 {
@@ -496,13 +556,8 @@ fn execute_capture(cmd_str: &str, shell: &mut Shell) -> String {
             libc::signal(libc::SIGINT, libc::SIG_DFL);
             libc::signal(libc::SIGTSTP, libc::SIG_DFL);
         }
-        match parser::parse(cmd_str, shell.last_status, &shell.positional_args, shell.set_nounset, &shell.arrays) {
-            Ok(Some(list)) => {
-                let status = execute(shell, &list, cmd_str);
-                std::process::exit(status);
-            }
-            _ => std::process::exit(1),
-        }
+        let status = run_command_string(shell, cmd_str);
+        std::process::exit(status);
     }
 
     // 親プロセス: パイプから出力を読み取り
@@ -517,58 +572,122 @@ fn execute_capture(cmd_str: &str, shell: &mut Shell) -> String {
         output.extend_from_slice(&buf[..n as usize]);
     }
     unsafe { libc::close(pipefd[0]); }
-    let mut status = 0i32;
-    unsafe { libc::waitpid(pid, &mut status, 0); }
+    let mut raw_status = 0i32;
+    unsafe { libc::waitpid(pid, &mut raw_status, 0); }
+    let status = if libc::WIFEXITED(raw_status) {
+        libc::WEXITSTATUS(raw_status)
+    } else if libc::WIFSIGNALED(raw_status) {
+        128 + libc::WTERMSIG(raw_status)
+    } else {
+        1
+    };
+    shell.last_status = status;
+
+    let text = String::from_utf8_lossy(&output).trim_end_matches('\n').to_string();
+    (text, status)
+}
 
-    String::from_utf8_lossy(&output).trim_end_matches('\n').to_string()
+/// テスト用: `f` を fork した子プロセスで実行し、fd `fd` に書かれた出力と `f` の戻り値を返す。
+///
+/// シェル自身の fd・cwd・環境変数の差し替えや `waitpid(-1)` など、
+/// プロセス全体の状態に触れるテストを他のテストから隔離する。
+/// 子プロセスでの panic は終了ステータス 101 として返す。
+#[cfg(test)]
+pub(crate) fn run_forked(fd: i32, f: impl FnOnce() -> i32) -> (String, i32) {
+    let mut fds = [0i32; 2];
+    assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+    let pid = unsafe { libc::fork() };
+    assert!(pid >= 0);
+    if pid == 0 {
+        unsafe {
+            libc::close(fds[0]);
+            libc::dup2(fds[1], fd);
+            libc::close(fds[1]);
+        }
+        let status = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)).unwrap_or(101);
+        let _ = io::Write::flush(&mut io::stdout());
+        unsafe { libc::_exit(status) };
+    }
+    unsafe { libc::close(fds[1]) };
+    let mut output = Vec::new();
+    {
+        use std::io::Read;
+        use std::os::unix::io::FromRawFd;
+        let mut f = unsafe { File::from_raw_fd(fds[0]) };
+        f.read_to_end(&mut output).unwrap();
+    }
+    let mut raw_status: i32 = 0;
+    unsafe { libc::waitpid(pid, &mut raw_status, 0) };
+    let status = if libc::WIFEXITED(raw_status) {
+        libc::WEXITSTATUS(raw_status)
+    } else {
+        128 + libc::WTERMSIG(raw_status)
+    };
+    (String::from_utf8_lossy(&output).into_owned(), status)
 }
 
 /// 文字列内の $(...) と `...` を展開する。
+///
+/// [`parser::UNQUOTED_SUB`] マーカーの付いた（非クォートの）置換の出力は IFS 文字を `\x1F` に
+/// 置き換え、[`expand_args_full`] で個別の引数に分割させる。
 fn expand_command_subs(s: &str, shell: &mut Shell) -> String {
     let bytes = s.as_bytes();
     let len = bytes.len();
     let mut result = String::new();
     let mut pos = 0;
+    let mut split = false;
 
     while pos < len {
+        if s[pos..].starts_with(parser::UNQUOTED_SUB) {
+            split = true;
+            pos += parser::UNQUOTED_SUB.len_utf8();
+            continue;
+        }
+        let ifs = if std::mem::take(&mut split) { parser::ifs_chars() } else { String::new() };
         if bytes[pos] == b'$' && pos + 1 < len && bytes[pos + 1] == b'(' {
-            pos += 2;
-            let start = pos;
-            let mut depth = 1;
-            while pos < len && depth > 0 {
-                match bytes[pos] {
-                    b'(' => depth += 1,
-                    b')' => {
-                        depth -= 1;
-                        if depth == 0 { break; }
-                    }
-                    b'\'' => { pos += 1; while pos < len && bytes[pos] != b'\'' { pos += 1; } }
-                    b'"' => { pos += 1; while pos < len && bytes[pos] != b'"' {
-                        if bytes[pos] == b'\\' { pos += 1; }
-                        pos += 1;
-                    }}
-                    _ => {}
-                }
-                pos += 1;
-            }
-            let inner = &s[start..pos];
-            if pos < len { pos += 1; } // skip ')'
-            result.push_str(&execute_capture(inner, shell));
+            // 内側のコマンドは capture → run_command_string で再度パース・展開されるので、
+            // ネストした $(...) もそこで再帰的に展開される
+            let start = pos + 2;
+            let end = parser::command_sub_end(bytes, start).unwrap_or(len);
+            let inner = &s[start..end];
+            pos = (end + 1).min(len);
+            parser::push_split(&mut result, &capture(shell, inner).0, &ifs);
         } else if bytes[pos] == b'`' {
             pos += 1;
             let start = pos;
             while pos < len && bytes[pos] != b'`' { pos += 1; }
             let inner = &s[start..pos];
             if pos < len { pos += 1; }
-            result.push_str(&execute_capture(inner, shell));
+            parser::push_split(&mut result, &capture(shell, inner).0, &ifs);
         } else {
-            result.push(bytes[pos] as char);
-            pos += 1;
+            // マルチバイト文字を壊さないよう文字単位でコピーする
+            let ch = s[pos..].chars().next().unwrap();
+            result.push(ch);
+            pos += ch.len_utf8();
         }
     }
     result
 }
 
+/// 代入値のコマンド置換を展開する（変数・算術展開はパース時に済んでいる）。
+///
+/// 代入値はフィールド分割しないので、出力の内部の改行や空白はそのまま残る。
+fn expand_assignment_value(value: &str, shell: &mut Shell) -> String {
+    if value.contains("$(") || value.contains('`') {
+        expand_command_subs(value, shell)
+    } else {
+        value.to_string()
+    }
+}
+
+/// `VAR=value` の一覧の各値に [`expand_assignment_value`] を適用する。
+fn expand_assignments(assignments: &[(String, String)], shell: &mut Shell) -> Vec<(String, String)> {
+    assignments
+        .iter()
+        .map(|(k, v)| (k.clone(), expand_assignment_value(v, shell)))
+        .collect()
+}
+
 /// コマンドリスト全体を実行し、終了ステータスを返す。
 ///
 /// `cmd_text` は元のコマンド文字列で、ジョブテーブルの表示用に使用される。
@@ -577,6 +696,8 @@ fn expand_command_subs(s: &str, shell: &mut Shell) -> String {
 pub fn execute(shell: &mut Shell, list: &CommandList<'_>, cmd_text: &str) -> i32 {
     // バックグラウンドジョブを reap
     job::reap_jobs(&mut shell.jobs);
+    // パース時の `${var:=val}` で作られた変数は export しない
+    shell.unexported.extend(list.default_assigned.iter().cloned());
 
     let mut last_status = 0;
     let mut in_cond_chain = false;
@@ -597,7 +718,11 @@ pub fn execute(shell: &mut Shell, list: &CommandList<'_>, cmd_text: &str) -> i32
             }
         }
 
-        last_status = execute_pipeline(shell, &item.pipeline, cmd_text);
+        last_status = if item.pipeline.timed {
+            execute_timed_pipeline(shell, &item.pipeline, cmd_text)
+        } else {
+            execute_pipeline(shell, &item.pipeline, cmd_text)
+        };
 
         // errexit チェック
         if shell.set_errexit && last_status != 0 && shell.in_condition == 0 {
@@ -608,21 +733,148 @@ pub fn execute(shell: &mut Shell, list: &CommandList<'_>, cmd_text: &str) -> i32
             }
         }
         in_cond_chain = matches!(item.connector, Connector::And | Connector::Or);
+
+        // return / exit / break / continue は同じ行の後続コマンドも飛ばす
+        if shell.should_return || shell.should_exit || shell.break_level > 0 || shell.continue_level > 0 {
+            break;
+        }
     }
 
     last_status
 }
 
+// ── time 予約語 ──────────────────────────────────────────────────────
+
+/// `TIMEFORMAT` 未設定時の出力書式（bash と同じ）。
+const DEFAULT_TIMEFORMAT: &str = "\nreal\t%3lR\nuser\t%3lU\nsys\t%3lS";
+
+/// `time pipeline`: パイプラインを実行し、経過時間と CPU 時間を stderr に報告する。
+///
+/// user/sys はシェル自身（ビルトイン）と wait 済み子プロセスの `getrusage` 差分の合計。
+/// 書式は `TIMEFORMAT`（空文字なら報告しない）に従う。
+fn execute_timed_pipeline(shell: &mut Shell, pipeline: &Pipeline<'_>, cmd_text: &str) -> i32 {
+    let start = std::time::Instant::now();
+    let (user_start, sys_start) = cpu_times();
+
+    let status = execute_pipeline(shell, pipeline, cmd_text);
+
+    let real = start.elapsed().as_secs_f64();
+    let (user_end, sys_end) = cpu_times();
+    let format = std::env::var("TIMEFORMAT").unwrap_or_else(|_| DEFAULT_TIMEFORMAT.to_string());
+    if !format.is_empty() {
+        use std::io::Write;
+        let report = format_time_report(&format, real, user_end - user_start, sys_end - sys_start);
+        let _ = writeln!(io::stderr(), "{}", report);
+    }
+    status
+}
+
+/// シェル自身と子プロセスの累積 (user, sys) CPU 秒。
+fn cpu_times() -> (f64, f64) {
+    let mut total = (0.0, 0.0);
+    for who in [libc::RUSAGE_SELF, libc::RUSAGE_CHILDREN] {
+        let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+        if unsafe { libc::getrusage(who, &mut usage) } == 0 {
+            total.0 += usage.ru_utime.tv_sec as f64 + usage.ru_utime.tv_usec as f64 / 1e6;
+            total.1 += usage.ru_stime.tv_sec as f64 + usage.ru_stime.tv_usec as f64 / 1e6;
+        }
+    }
+    total
+}
+
+/// `TIMEFORMAT` 書式を展開する。
+///
+/// - `%[p][l]R` / `U` / `S` — 経過・user・sys 秒。`p` は小数桁数（0〜3、既定 3）、
+///   `l` は `XmY.YYYs` 形式
+/// - `%P` — CPU 使用率（(user + sys) / real × 100）
+/// - `%%` — リテラル `%`
+fn format_time_report(format: &str, real: f64, user: f64, sys: f64) -> String {
+    let mut out = String::new();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        let mut precision = 3;
+        let mut long = false;
+        if let Some(d) = chars.peek().and_then(|c| c.to_digit(10)) {
+            precision = d.min(3) as usize;
+            chars.next();
+        }
+        if chars.peek() == Some(&'l') {
+            long = true;
+            chars.next();
+        }
+        let secs = match chars.next() {
+            Some('R') => real,
+            Some('U') => user,
+            Some('S') => sys,
+            Some('P') => {
+                let pct = if real > 0.0 { (user + sys) / real * 100.0 } else { 0.0 };
+                out.push_str(&format!("{:.2}", pct));
+                continue;
+            }
+            Some('%') => {
+                out.push('%');
+                continue;
+            }
+            Some(other) => {
+                out.push('%');
+                out.push(other);
+                continue;
+            }
+            None => {
+                out.push('%');
+                break;
+            }
+        };
+        if long {
+            let minutes = (secs / 60.0).floor();
+            out.push_str(&format!("{}m{:.*}s", minutes as u64, precision, secs - minutes * 60.0));
+        } else {
+            out.push_str(&format!("{:.*}", precision, secs));
+        }
+    }
+    out
+}
+
 /// 単一パイプラインを実行し、終了ステータスを返す。
 ///
 /// ディスパッチ:
 /// 1. 単一ビルトイン（非 background） → [`execute_builtin`]（fork なし高速パス）
 /// 2. それ以外（外部コマンド、パイプライン、ビルトイン + `&`） → [`execute_job`]
 fn execute_pipeline(shell: &mut Shell, pipeline: &Pipeline<'_>, cmd_text: &str) -> i32 {
+    // パース時に記録した算術展開の代入（`$((i++))` 等）は実行するときに反映する
+    for cmd in &pipeline.commands {
+        for (name, value) in &cmd.arith_assignments {
+            std::env::set_var(name, value);
+        }
+    }
     // 単一サブシェル（非 background）→ fork で実行
     if pipeline.commands.len() == 1 && !pipeline.background {
         if let Some(ref body) = pipeline.commands[0].subshell_body {
-            return execute_subshell(shell, body, &pipeline.commands[0].redirects);
+            let redirects = match expand_redirect_targets(&pipeline.commands[0].redirects, shell) {
+                Ok(redirects) => redirects,
+                Err(status) => return status,
+            };
+            return execute_subshell(shell, body, &redirects);
+        }
+    }
+
+    // 単一の複合コマンド（非 background）→ 現在のシェルで実行
+    // リダイレクトはシェル自身の fd を差し替えてブロック全体に適用する。
+    // プロセス置換を含む場合は execute_job で fork した子プロセス内で実行する
+    if pipeline.commands.len() == 1 && !pipeline.background {
+        let cmd = &pipeline.commands[0];
+        if let Some(ref compound) = cmd.compound {
+            if cmd.redirects.is_empty() {
+                return execute_compound(shell, compound);
+            }
+            let forks = cmd.redirects.iter().any(|r| r.target.starts_with("\x1E"));
+            if !forks {
+                return execute_compound_redirected(shell, compound, &cmd.redirects);
+            }
         }
     }
 
@@ -634,7 +886,12 @@ fn execute_pipeline(shell: &mut Shell, pipeline: &Pipeline<'_>, cmd_text: &str)
         if cmd.args.is_empty() && (!cmd.assignments.is_empty()
             || !cmd.array_assignments.is_empty() || !cmd.array_appends.is_empty() || !cmd.indexed_assignments.is_empty())
         {
-            for (name, value) in &cmd.assignments {
+            // 終了ステータスは最後のコマンド置換のもの（置換がなければ 0）
+            shell.last_status = 0;
+            for (name, value) in expand_assignments(&cmd.assignments, shell) {
+                if name == "RANDOM" {
+                    parser::seed_random(&value);
+                }
                 std::env::set_var(name, value);
             }
             // 配列代入
@@ -662,32 +919,37 @@ fn execute_pipeline(shell: &mut Shell, pipeline: &Pipeline<'_>, cmd_text: &str)
             }
             // インデックス代入
             for (name, idx, val) in &cmd.indexed_assignments {
+                let val = expand_assignment_value(val, shell);
                 let arr = shell.arrays.entry(name.clone()).or_default();
                 arr.insert(*idx, val.clone());
                 if *idx == 0 { std::env::set_var(name, val); }
             }
-            return 0;
+            return shell.last_status;
         }
 
-        // FdDup またはプロセス置換があれば spawn パスにフォールバック
+        // プロセス置換があれば spawn パスにフォールバック（関数は FdDup でもフォールバック）
         let has_fd_dup = cmd.redirects.iter().any(|r| matches!(r.kind, RedirectKind::FdDup { .. }));
         let has_proc_sub = cmd.args.iter().any(|a| a.starts_with("\x1E"))
             || cmd.redirects.iter().any(|r| r.target.starts_with("\x1E"));
-        if !has_fd_dup && !has_proc_sub {
+        if !has_proc_sub {
             let expanded = expand_args_full(&cmd.args, shell);
+            if std::mem::take(&mut shell.glob_failed) {
+                return 1;
+            }
             let args: Vec<&str> = expanded.iter().map(|s| s.as_str()).collect();
             // ユーザー定義関数の呼び出しチェック（ビルトインより優先）
-            if !args.is_empty() {
+            if !args.is_empty() && !has_fd_dup {
                 if let Some(body) = shell.functions.get(args[0]).cloned() {
                     // 代入を一時的にシェル環境に設定し、実行後に復元
-                    let saved: Vec<(String, Option<String>)> = cmd.assignments.iter()
+                    let saved: Vec<(String, Option<String>)> = expand_assignments(&cmd.assignments, shell)
+                        .into_iter()
                         .map(|(k, v)| {
-                            let old = std::env::var(k).ok();
-                            std::env::set_var(k, v);
-                            (k.clone(), old)
+                            let old = std::env::var(&k).ok();
+                            std::env::set_var(&k, v);
+                            (k, old)
                         })
                         .collect();
-                    let status = execute_function(shell, &body, &args[1..]);
+                    let status = execute_function(shell, args[0], &body, &args[1..]);
                     for (k, old) in saved {
                         match old {
                             Some(v) => std::env::set_var(&k, &v),
@@ -699,11 +961,12 @@ fn execute_pipeline(shell: &mut Shell, pipeline: &Pipeline<'_>, cmd_text: &str)
             }
             if !args.is_empty() && builtins::is_builtin(args[0]) {
                 // ビルトイン: 代入を一時的にシェル環境に設定し、実行後に復元
-                let saved: Vec<(String, Option<String>)> = cmd.assignments.iter()
+                let saved: Vec<(String, Option<String>)> = expand_assignments(&cmd.assignments, shell)
+                    .into_iter()
                     .map(|(k, v)| {
-                        let old = std::env::var(k).ok();
-                        std::env::set_var(k, v);
-                        (k.clone(), old)
+                        let old = std::env::var(&k).ok();
+                        std::env::set_var(&k, v);
+                        (k, old)
                     })
                     .collect();
                 let status = execute_builtin(shell, cmd, &expanded);
@@ -725,17 +988,154 @@ fn execute_pipeline(shell: &mut Shell, pipeline: &Pipeline<'_>, cmd_text: &str)
 
 /// 単一ビルトインを fork なしで実行する。
 ///
-/// stdout リダイレクトがあればファイルを開いてから実行する。
-/// `&` 付きビルトインはこのパスを通らず [`execute_job`] で外部コマンドとして spawn される。
+/// stdout リダイレクトだけならファイルを開いて出力先として渡す。
+/// stderr リダイレクトや fd 複製（`2>err`, `1>&2`）を含む場合は、
+/// シェル自身の fd を一時的に `dup2` で差し替えて実行し、終了後に元へ戻す。
+/// `&` 付きビルトインはこのパスを通らず [`execute_job`] で fork した子プロセス内で実行される。
 fn execute_builtin(shell: &mut Shell, cmd: &parser::Command<'_>, expanded_args: &[String]) -> i32 {
     let args: Vec<&str> = expanded_args.iter().map(|s| s.as_str()).collect();
-    match open_builtin_stdout(&cmd.redirects) {
+    let redirects = match expand_redirect_targets(&cmd.redirects, shell) {
+        Ok(redirects) => redirects,
+        Err(status) => return status,
+    };
+    if args == ["exec"] {
+        return apply_exec_redirects(&redirects);
+    }
+    let needs_fd_swap = redirects.iter().any(|r| {
+        matches!(
+            r.kind,
+            RedirectKind::Stderr | RedirectKind::StderrAppend | RedirectKind::FdDup { .. } | RedirectKind::FdOutput { .. }
+        )
+    });
+    if needs_fd_swap {
+        use std::io::Write;
+        let _ = io::stdout().flush();
+        let saved = match redirect_builtin_fds(&redirects) {
+            Ok(saved) => saved,
+            Err(status) => return status,
+        };
+        let status = builtins::try_exec(shell, &args, &mut io::stdout()).unwrap();
+        let _ = io::stdout().flush();
+        restore_builtin_fds(saved);
+        return status;
+    }
+    match open_builtin_stdout(&redirects) {
         Ok(Some(mut file)) => builtins::try_exec(shell, &args, &mut file).unwrap(),
         Ok(None) => builtins::try_exec(shell, &args, &mut io::stdout()).unwrap(),
         Err(status) => status,
     }
 }
 
+/// ビルトイン実行用に出力系リダイレクトをシェル自身の fd へ左から順に適用する。
+///
+/// 差し替えた fd ごとに退避用の複製を作り、`(fd, 退避 fd)` の一覧を返す（退避 fd が -1 なら元は閉じていた）。
+/// 入力系リダイレクトは対象外。失敗時は適用済みの分を戻してから `Err(1)` を返す。
+fn redirect_builtin_fds(redirects: &[parser::Redirect<'_>]) -> Result<Vec<(i32, i32)>, i32> {
+    fn save(fd: i32, saved: &mut Vec<(i32, i32)>) {
+        if !saved.iter().any(|&(f, _)| f == fd) {
+            // 退避 fd はユーザが使う低い番号と衝突しないよう 10 以上に置く
+            let backup = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 10) };
+            saved.push((fd, backup));
+        }
+    }
+
+    let mut saved: Vec<(i32, i32)> = Vec::new();
+    for r in redirects {
+        let (target_fd, file) = match r.kind {
+            RedirectKind::Output => (1, File::create(glob::raw_path(&r.target))),
+            RedirectKind::Append => (1, OpenOptions::new().create(true).append(true).open(glob::raw_path(&r.target))),
+            RedirectKind::Stderr => (2, File::create(glob::raw_path(&r.target))),
+            RedirectKind::StderrAppend => (2, OpenOptions::new().create(true).append(true).open(glob::raw_path(&r.target))),
+            RedirectKind::FdOutput { fd, append: false } => (fd, File::create(glob::raw_path(&r.target))),
+            RedirectKind::FdOutput { fd, append: true } => (fd, OpenOptions::new().create(true).append(true).open(glob::raw_path(&r.target))),
+            RedirectKind::FdDup { src_fd, dst_fd } => {
+                save(src_fd, &mut saved);
+                if unsafe { libc::dup2(dst_fd, src_fd) } < 0 {
+                    restore_builtin_fds(saved);
+                    eprintln!("rush: {}: Bad file descriptor", dst_fd);
+                    return Err(1);
+                }
+                continue;
+            }
+            _ => continue,
+        };
+        match file {
+            Ok(f) => {
+                save(target_fd, &mut saved);
+                let raw = f.into_raw_fd();
+                // 対象 fd が閉じていると open がちょうどその番号を返すことがある
+                if raw != target_fd {
+                    unsafe {
+                        libc::dup2(raw, target_fd);
+                        libc::close(raw);
+                    }
+                }
+            }
+            Err(e) => {
+                restore_builtin_fds(saved);
+                eprintln!("rush: {}: {}", r.target, e);
+                return Err(1);
+            }
+        }
+    }
+    Ok(saved)
+}
+
+/// コマンドなしの `exec`（`exec > log`, `exec 3>file`, `exec 2>&1`）のリダイレクトを
+/// シェル自身の fd に恒久的に適用する。以降のコマンドはすべて差し替え後の fd を継承する。
+fn apply_exec_redirects(redirects: &[parser::Redirect<'_>]) -> i32 {
+    use std::io::Write;
+    let _ = io::stdout().flush();
+    let saved = match redirect_builtin_fds(redirects) {
+        Ok(saved) => saved,
+        Err(status) => return status,
+    };
+    // 元に戻さないので退避 fd は捨てる。open が対象 fd をそのまま返した場合は dup2 を経ておらず
+    // FD_CLOEXEC が付いたままなので、外部コマンドにも継承されるよう外す
+    for (fd, backup) in saved {
+        unsafe {
+            let flags = libc::fcntl(fd, libc::F_GETFD);
+            if flags >= 0 {
+                libc::fcntl(fd, libc::F_SETFD, flags & !libc::FD_CLOEXEC);
+            }
+            if backup >= 0 {
+                libc::close(backup);
+            }
+        }
+    }
+    // 入力リダイレクト（`exec < file`）は出力系と別に stdin へ適用する
+    for r in redirects.iter().filter(|r| r.kind == RedirectKind::Input) {
+        match File::open(glob::raw_path(&r.target)) {
+            Ok(f) => {
+                let raw = f.into_raw_fd();
+                unsafe {
+                    libc::dup2(raw, libc::STDIN_FILENO);
+                    libc::close(raw);
+                }
+            }
+            Err(e) => {
+                eprintln!("rush: {}: {}", r.target, e);
+                return 1;
+            }
+        }
+    }
+    0
+}
+
+/// [`redirect_builtin_fds`] で差し替えた fd を退避 fd から元に戻す。
+fn restore_builtin_fds(saved: Vec<(i32, i32)>) {
+    for (fd, backup) in saved.into_iter().rev() {
+        unsafe {
+            if backup >= 0 {
+                libc::dup2(backup, fd);
+                libc::close(backup);
+            } else {
+                libc::close(fd);
+            }
+        }
+    }
+}
+
 /// ビルトイン用の stdout リダイレクト先ファイルを開く。
 ///
 /// `>` / `>>` があればファイルを開いて `Ok(Some(File))` を返す。
@@ -746,7 +1146,7 @@ fn open_builtin_stdout(redirects: &[parser::Redirect<'_>]) -> Result<Option<File
     for r in redirects.iter().rev() {
         match r.kind {
             RedirectKind::Output => {
-                let f = File::create(r.target.as_ref()).map_err(|e| {
+                let f = File::create(glob::raw_path(&r.target)).map_err(|e| {
                     eprintln!("rush: {}: {}", r.target, e);
                     1
                 })?;
@@ -756,7 +1156,7 @@ fn open_builtin_stdout(redirects: &[parser::Redirect<'_>]) -> Result<Option<File
                 let f = OpenOptions::new()
                     .create(true)
                     .append(true)
-                    .open(r.target.as_ref())
+                    .open(glob::raw_path(&r.target))
                     .map_err(|e| {
                         eprintln!("rush: {}: {}", r.target, e);
                         1
@@ -777,7 +1177,9 @@ struct RedirectFds {
     stdout_fd: Option<i32>,
     stderr_fd: Option<i32>,
     dup_actions: Vec<(i32, i32)>, // (src_fd, dst_fd) — spawn で適用
+    extra_fds: Vec<i32>,          // `N>file` で開いた fd（dup_actions の複製元、spawn 後に close）
     proc_subs: Vec<ProcSubInfo>,  // プロセス置換の子プロセス情報
+    writers: Vec<libc::pid_t>,    // ヒアドキュメントの書き込み子プロセス（コマンド終了後に waitpid）
 }
 
 /// リダイレクト先ファイルを開き、raw fd を返す。
@@ -789,7 +1191,9 @@ fn open_redirect_fds(redirects: &[parser::Redirect<'_>], shell: &mut Shell) -> R
         stdout_fd: None,
         stderr_fd: None,
         dup_actions: Vec::new(),
+        extra_fds: Vec::new(),
         proc_subs: Vec::new(),
+        writers: Vec::new(),
     };
 
     for r in redirects {
@@ -808,7 +1212,7 @@ fn open_redirect_fds(redirects: &[parser::Redirect<'_>], shell: &mut Shell) -> R
                         return Err(1);
                     }
                 } else {
-                    let f = File::create(target).map_err(|e| {
+                    let f = File::create(glob::raw_path(target)).map_err(|e| {
                         eprintln!("rush: {}: {}", target, e);
                         1
                     })?;
@@ -822,7 +1226,7 @@ fn open_redirect_fds(redirects: &[parser::Redirect<'_>], shell: &mut Shell) -> R
                 let f = OpenOptions::new()
                     .create(true)
                     .append(true)
-                    .open(target)
+                    .open(glob::raw_path(target))
                     .map_err(|e| {
                         eprintln!("rush: {}: {}", target, e);
                         1
@@ -842,7 +1246,7 @@ fn open_redirect_fds(redirects: &[parser::Redirect<'_>], shell: &mut Shell) -> R
                         return Err(1);
                     }
                 } else {
-                    let f = File::open(target).map_err(|e| {
+                    let f = File::open(glob::raw_path(target)).map_err(|e| {
                         eprintln!("rush: {}: {}", target, e);
                         1
                     })?;
@@ -853,7 +1257,7 @@ fn open_redirect_fds(redirects: &[parser::Redirect<'_>], shell: &mut Shell) -> R
                 if let Some(old) = fds.stderr_fd {
                     unsafe { libc::close(old); }
                 }
-                let f = File::create(target).map_err(|e| {
+                let f = File::create(glob::raw_path(target)).map_err(|e| {
                     eprintln!("rush: {}: {}", target, e);
                     1
                 })?;
@@ -863,7 +1267,7 @@ fn open_redirect_fds(redirects: &[parser::Redirect<'_>], shell: &mut Shell) -> R
                 if let Some(old) = fds.stderr_fd {
                     unsafe { libc::close(old); }
                 }
-                let f = OpenOptions::new().create(true).append(true).open(target).map_err(|e| {
+                let f = OpenOptions::new().create(true).append(true).open(glob::raw_path(target)).map_err(|e| {
                     eprintln!("rush: {}: {}", target, e);
                     1
                 })?;
@@ -872,24 +1276,53 @@ fn open_redirect_fds(redirects: &[parser::Redirect<'_>], shell: &mut Shell) -> R
             RedirectKind::FdDup { src_fd, dst_fd } => {
                 fds.dup_actions.push((src_fd, dst_fd));
             }
-            RedirectKind::HereDoc => {
+            RedirectKind::FdOutput { fd, append } => {
+                let f = if append {
+                    OpenOptions::new().create(true).append(true).open(glob::raw_path(target))
+                } else {
+                    File::create(glob::raw_path(target))
+                };
+                let opened = f.map_err(|e| {
+                    eprintln!("rush: {}: {}", target, e);
+                    1
+                })?.into_raw_fd();
+                // 閉じている N をそのまま掴むと dup2(N, N) で CLOEXEC が外れないため 10 以上へ逃がす
+                let raw = unsafe { libc::fcntl(opened, libc::F_DUPFD_CLOEXEC, 10) };
+                unsafe { libc::close(opened); }
+                // fd を開いたファイルの複製にする（他の fd 複製との順序を保つため dup_actions に積む）
+                fds.dup_actions.push((fd, raw));
+                fds.extra_fds.push(raw);
+            }
+            RedirectKind::HereDoc | RedirectKind::HereDocLiteral => {
                 // <<DELIM — target にはデリミタ文字列が入っている
                 // REPL の継続行入力で本体が蓄積されているはずだが、
                 // 非インタラクティブ実行時は target に本体テキストが入る
                 if let Some(old) = fds.stdin_fd {
                     unsafe { libc::close(old); }
                 }
-                let fd = create_pipe_from_string(target);
+                let (fd, writer) = if r.kind == RedirectKind::HereDoc {
+                    create_pipe_from_string(&expand_heredoc_body(target, shell)?)
+                } else {
+                    create_pipe_from_string(target)
+                };
                 fds.stdin_fd = Some(fd);
+                fds.writers.extend(writer);
             }
-            RedirectKind::HereString => {
-                // <<<word — word + 改行を stdin に供給
+            RedirectKind::HereString | RedirectKind::HereStringLiteral => {
+                // <<<word — word + 改行を stdin に供給（変数はパース時に展開済み）。
+                // クォートされた置換（HereStringLiteral）は実行しない
                 if let Some(old) = fds.stdin_fd {
                     unsafe { libc::close(old); }
                 }
-                let content = format!("{}\n", target);
-                let fd = create_pipe_from_string(&content);
+                let word = if r.kind == RedirectKind::HereString && (target.contains("$(") || target.contains('`')) {
+                    std::borrow::Cow::Owned(expand_command_subs(target, shell))
+                } else {
+                    std::borrow::Cow::Borrowed(target)
+                };
+                let content = format!("{}\n", word);
+                let (fd, writer) = create_pipe_from_string(&content);
                 fds.stdin_fd = Some(fd);
+                fds.writers.extend(writer);
             }
         }
     }
@@ -897,19 +1330,236 @@ fn open_redirect_fds(redirects: &[parser::Redirect<'_>], shell: &mut Shell) -> R
     Ok(fds)
 }
 
-/// 文字列をパイプの書き込み側に書き込み、読み取り側の fd を返す。
+/// 非クォートのヒアドキュメント本文に変数・算術展開とコマンド置換を適用する。
+///
+/// 本文中のクォートは特別扱いせずリテラルのまま残す（bash 互換）。
+/// バックスラッシュは `\$`・`` \` ``・`\\` をリテラルにし、`\` + 改行を行継続として除く。
+/// それ以外のバックスラッシュはそのまま残す。
+/// `set -u` 違反や算術エラーはメッセージを表示して `Err(1)` を返す。
+fn expand_heredoc_body(body: &str, shell: &mut Shell) -> Result<String, i32> {
+    // エスケープされた `$` と `` ` `` は展開後に戻す私用領域の文字に退避する
+    const ESCAPED_DOLLAR: char = '\u{E000}';
+    const ESCAPED_BACKQUOTE: char = '\u{E001}';
+
+    let mut unescaped = String::with_capacity(body.len());
+    let mut chars = body.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            unescaped.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('$') => unescaped.push(ESCAPED_DOLLAR),
+            Some('`') => unescaped.push(ESCAPED_BACKQUOTE),
+            Some('\\') => unescaped.push('\\'),
+            Some('\n') => {}
+            Some(other) => {
+                unescaped.push('\\');
+                unescaped.push(other);
+            }
+            None => unescaped.push('\\'),
+        }
+    }
+
+    let (expanded, created) = parser::expand_text(
+        &unescaped, shell.last_status, &shell.shell_name, &shell.positional_args, shell.set_nounset, &shell.arrays,
+    )
+    .map_err(|e| {
+        eprintln!("rush: {}", e);
+        1
+    })?;
+    shell.unexported.extend(created);
+    let expanded = if expanded.contains("$(") || expanded.contains('`') {
+        expand_command_subs(&expanded, shell)
+    } else {
+        expanded
+    };
+    Ok(expanded.replace(ESCAPED_DOLLAR, "$").replace(ESCAPED_BACKQUOTE, "`"))
+}
+
+/// 文字列をパイプの書き込み側に書き込み、読み取り側の fd と書き込み子プロセスの PID を返す。
 /// ヒアドキュメント・ヒアストリング用。
-fn create_pipe_from_string(content: &str) -> i32 {
+///
+/// `PIPE_BUF` 以下ならその場で書き込む（空のパイプへの書き込みはブロックしない）。
+/// それより大きい本文はパイプ容量を超えると読み手の接続前に `write` が詰まるため、
+/// fork した書き込み専用の子プロセスに供給させる。呼び出し側はコマンド終了後にその PID を
+/// [`wait_writers`] で回収する。
+fn create_pipe_from_string(content: &str) -> (i32, Option<libc::pid_t>) {
     let mut pipe_fds: [i32; 2] = [0; 2];
     unsafe { libc::pipe(pipe_fds.as_mut_ptr()); }
     let read_fd = pipe_fds[0];
     let write_fd = pipe_fds[1];
     let bytes = content.as_bytes();
-    unsafe {
-        libc::write(write_fd, bytes.as_ptr() as *const libc::c_void, bytes.len());
-        libc::close(write_fd);
+    if bytes.len() <= libc::PIPE_BUF {
+        unsafe {
+            libc::write(write_fd, bytes.as_ptr() as *const libc::c_void, bytes.len());
+            libc::close(write_fd);
+        }
+        return (read_fd, None);
+    }
+
+    let pid = unsafe { libc::fork() };
+    if pid == 0 {
+        // 書き込み子プロセス: 読み手が先に終了したら SIGPIPE で静かに終わる。
+        // 親から継承したパイプラインのパイプ端を握ったままだと読み手側が EOF を受け取れないので、
+        // write_fd 以外はすべて閉じる。
+        close_fds_except(write_fd);
+        unsafe { libc::signal(libc::SIGPIPE, libc::SIG_DFL); }
+        let mut written = 0;
+        while written < bytes.len() {
+            let n = unsafe {
+                libc::write(
+                    write_fd,
+                    bytes[written..].as_ptr() as *const libc::c_void,
+                    bytes.len() - written,
+                )
+            };
+            if n < 0 {
+                if std::io::Error::last_os_error().raw_os_error() == Some(libc::EINTR) {
+                    continue;
+                }
+                break;
+            }
+            written += n as usize;
+        }
+        unsafe { libc::_exit(0) };
     }
-    read_fd
+    unsafe { libc::close(write_fd); }
+    if pid < 0 {
+        eprintln!("rush: fork: {}", std::io::Error::last_os_error());
+        return (read_fd, None);
+    }
+    (read_fd, Some(pid))
+}
+
+/// `keep` 以外の開いている fd をすべて閉じる（fork 直後の子プロセス用）。
+///
+/// `/proc/self/fd` が読めればそこに並ぶ fd だけを、読めなければ `_SC_OPEN_MAX` までを閉じる。
+fn close_fds_except(keep: i32) {
+    let fds: Vec<i32> = match std::fs::read_dir("/proc/self/fd") {
+        Ok(dir) => dir
+            .filter_map(|e| e.ok()?.file_name().to_str()?.parse().ok())
+            .collect(),
+        Err(_) => {
+            let max = unsafe { libc::sysconf(libc::_SC_OPEN_MAX) };
+            (0..if max > 0 { max as i32 } else { 1024 }).collect()
+        }
+    };
+    for fd in fds {
+        if fd != keep {
+            unsafe { libc::close(fd); }
+        }
+    }
+}
+
+/// [`create_pipe_from_string`] の書き込み子プロセスを回収する。
+///
+/// 読み手が終了してパイプが閉じていれば書き込み側も SIGPIPE で終わっているので、ブロックしない。
+fn wait_writers(writers: &[libc::pid_t]) {
+    for &pid in writers {
+        unsafe { libc::waitpid(pid, std::ptr::null_mut(), 0); }
+    }
+}
+
+/// `ENOEXEC` で起動できなかったファイルを fork した子プロセスで rush スクリプトとして実行する。
+///
+/// 先頭行に NUL バイトを含むファイルはバイナリとみなし、
+/// "cannot execute binary file" を表示して 126 を返す。
+/// `stdio` は `[stdin, stdout, stderr]` に接続する fd（`None` なら継承）。
+/// `assignments` は前置代入で、子プロセスの中でだけ環境に設定する。
+#[allow(clippy::too_many_arguments)]
+fn spawn_script(
+    shell: &mut Shell,
+    program: &str,
+    args: &[&str],
+    assignments: &[(String, String)],
+    pgid: libc::pid_t,
+    stdio: [Option<i32>; 3],
+    fds_to_close: &[i32],
+    dup_actions: &[(i32, i32)],
+) -> Result<libc::pid_t, i32> {
+    let content = match std::fs::read(program) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("rush: {}: {}", args[0], e);
+            return Err(126);
+        }
+    };
+    let first_line = content.split(|&b| b == b'\n').next().unwrap_or(&[]);
+    if first_line.contains(&0) {
+        eprintln!("rush: {}: cannot execute binary file: Exec format error", args[0]);
+        return Err(126);
+    }
+    let script = String::from_utf8_lossy(&content).into_owned();
+
+    let child_pid = unsafe { libc::fork() };
+    if child_pid < 0 {
+        eprintln!("rush: fork: {}", std::io::Error::last_os_error());
+        return Err(1);
+    }
+    if child_pid == 0 {
+        unsafe {
+            for (target, fd) in stdio.iter().enumerate() {
+                if let Some(fd) = *fd {
+                    libc::dup2(fd, target as i32);
+                    if fd != target as i32 { libc::close(fd); }
+                }
+            }
+            for &(src, dst) in dup_actions {
+                libc::dup2(dst, src);
+            }
+            for &fd in fds_to_close {
+                if !stdio.contains(&Some(fd)) { libc::close(fd); }
+            }
+            libc::signal(libc::SIGINT, libc::SIG_DFL);
+            libc::signal(libc::SIGTSTP, libc::SIG_DFL);
+            libc::setpgid(0, pgid);
+        }
+        // スクリプトは新しいシェルとして動くので、export していないシェル変数は見せない
+        for name in std::mem::take(&mut shell.unexported) {
+            std::env::remove_var(name);
+        }
+        for (k, v) in assignments {
+            std::env::set_var(k, v);
+        }
+        shell.positional_args = args[1..].iter().map(|s| s.to_string()).collect();
+        let status = run_command_string(shell, &script);
+        std::process::exit(status);
+    }
+    Ok(child_pid)
+}
+
+/// コマンド名を実行ファイルの絶対パスに解決する。
+///
+/// 前置代入に `PATH=...` があればその値で検索する（`PATH=/opt/bin:$PATH cmd`）。
+/// シェル自身の `$PATH` とは異なるので CommandHash は使わない。
+fn resolve_command(shell: &mut Shell, name: &str, assignments: &[(String, String)]) -> Option<String> {
+    match assignments.iter().rev().find(|(k, _)| k == "PATH") {
+        Some((_, path)) => crate::shell::search_path(name, path),
+        None => shell.command_hash.lookup(name),
+    }
+}
+
+/// PATH に見つからないコマンドの代わりに呼ぶ関数の名前（bash 互換）。
+/// 元のコマンド名と引数を位置パラメータとして、fork した子プロセスで実行する。
+const COMMAND_NOT_FOUND_HANDLE: &str = "command_not_found_handle";
+
+/// 外部コマンドに渡す環境（`KEY=VALUE` の列）を、シェルの環境にインライン代入を重ねて作る。
+///
+/// export 属性のない変数（`unexported`）は除く。インライン代入も非 export 変数もなければ
+/// `None`（`environ` をそのまま継承する）。
+/// 非 UTF-8 の名前・値は [`glob::name_to_string`] で退避し、spawn で元のバイト列に戻る。
+fn child_environment(assignments: &[(String, String)], unexported: &HashSet<String>) -> Option<Vec<String>> {
+    if assignments.is_empty() && unexported.is_empty() {
+        return None;
+    }
+    let mut env: Vec<String> = std::env::vars_os()
+        .map(|(k, v)| (glob::name_to_string(&k), glob::name_to_string(&v)))
+        .filter(|(k, _)| !unexported.contains(k) && !assignments.iter().any(|(name, _)| name == k))
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect();
+    env.extend(assignments.iter().map(|(k, v)| format!("{}={}", k, v)));
+    Some(env)
 }
 
 /// パイプライン（単一 or 複数コマンド）を子プロセスとして実行する。
@@ -964,6 +1614,7 @@ fn execute_job(shell: &mut Shell, pipeline: &Pipeline<'_>, cmd_text: &str) -> i3
     let mut spawn_error = false;
     let mut error_status = 1i32;
     let mut all_proc_subs: Vec<ProcSubInfo> = Vec::new();
+    let mut all_writers: Vec<libc::pid_t> = Vec::new();
 
     // ── close 対象 fd 収集用スタック配列 ──
     let mut close_fds_buf: [i32; 16] = [-1; 16];
@@ -971,17 +1622,16 @@ fn execute_job(shell: &mut Shell, pipeline: &Pipeline<'_>, cmd_text: &str) -> i3
     for i in 0..n {
         let cmd = &pipeline.commands[i];
 
-        // インライン代入を環境変数に設定（子プロセスに継承される）
-        let saved_env: Vec<(String, Option<String>)> = cmd.assignments.iter()
-            .map(|(k, v)| {
-                let old = std::env::var(k).ok();
-                std::env::set_var(k, v);
-                (k.clone(), old)
-            })
-            .collect();
+        // インライン代入はシェルの環境を書き換えず、子プロセスの環境にだけ渡す
+        let assignments = expand_assignments(&cmd.assignments, shell);
 
         // コマンド置換 + チルダ + glob 展開
         let mut expanded = expand_args_full(&cmd.args, shell);
+        if std::mem::take(&mut shell.glob_failed) {
+            error_status = 1;
+            spawn_error = true;
+            break;
+        }
 
         // プロセス置換の展開（引数中の \x1E を /dev/fd/N に置換）
         let proc_subs = expand_proc_subs(&mut expanded, shell);
@@ -1000,7 +1650,9 @@ fn execute_job(shell: &mut Shell, pipeline: &Pipeline<'_>, cmd_text: &str) -> i3
         }
 
         // リダイレクトの fd を開く（プロセス置換のリダイレクトを含む）
-        let redir_fds = match open_redirect_fds(&cmd.redirects, shell) {
+        let redir_fds = match expand_redirect_targets(&cmd.redirects, shell)
+            .and_then(|redirects| open_redirect_fds(&redirects, shell))
+        {
             Ok(fds) => fds,
             Err(status) => {
                 cleanup_proc_subs(&proc_subs);
@@ -1040,8 +1692,33 @@ fn execute_job(shell: &mut Shell, pipeline: &Pipeline<'_>, cmd_text: &str) -> i3
             }
         }
 
-        if let Some(ref body) = cmd.subshell_body {
-            // サブシェルをパイプライン要素として fork 実行
+        let function_body = match cmd.subshell_body {
+            None if !args.is_empty() => shell.functions.get(args[0]).cloned(),
+            _ => None,
+        };
+        let is_builtin_stage = cmd.subshell_body.is_none()
+            && !args.is_empty()
+            && builtins::is_builtin(args[0]);
+        // PATH に見つからない外部コマンドは、command_not_found_handle 関数があればそれに委ねる
+        let not_found_handler = match shell.functions.get(COMMAND_NOT_FOUND_HANDLE) {
+            Some(handler)
+                if cmd.subshell_body.is_none()
+                    && cmd.compound.is_none()
+                    && function_body.is_none()
+                    && !is_builtin_stage
+                    && !args.is_empty()
+                    && !args[0].contains('/') =>
+            {
+                let handler = handler.clone();
+                resolve_command(shell, args[0], &assignments).is_none().then_some(handler)
+            }
+            _ => None,
+        };
+        if cmd.subshell_body.is_some() || cmd.compound.is_some() || function_body.is_some() || is_builtin_stage
+            || not_found_handler.is_some()
+        {
+            // サブシェル・複合コマンド・関数・ビルトインをパイプライン要素として fork 実行
+            // （外部コマンドとして spawn せず、子プロセス内で本体の実行や try_exec をする）
             let child_pid = unsafe { libc::fork() };
             if child_pid < 0 {
                 eprintln!("rush: fork: {}", std::io::Error::last_os_error());
@@ -1064,6 +1741,9 @@ fn execute_job(shell: &mut Shell, pipeline: &Pipeline<'_>, cmd_text: &str) -> i3
                         libc::dup2(fd, libc::STDERR_FILENO);
                         libc::close(fd);
                     }
+                    for &(src_fd, dst_fd) in &redir_fds.dup_actions {
+                        libc::dup2(dst_fd, src_fd);
+                    }
                     // 他のパイプ fd をすべて close
                     for j in 0..pipe_count {
                         if pipes[j][0] >= 0 { libc::close(pipes[j][0]); }
@@ -1071,9 +1751,28 @@ fn execute_job(shell: &mut Shell, pipeline: &Pipeline<'_>, cmd_text: &str) -> i3
                     }
                     libc::signal(libc::SIGINT, libc::SIG_DFL);
                     libc::signal(libc::SIGTSTP, libc::SIG_DFL);
-                    if pgid != 0 { libc::setpgid(0, pgid); }
+                    // pgid == 0 なら自分がリーダー。親の setpgid より先に終了しても wait できるよう子側でも設定する
+                    libc::setpgid(0, pgid);
                 }
-                let status = run_command_string(shell, body);
+                // 子プロセスの中なのでインライン代入はそのまま環境に設定してよい
+                for (k, v) in &assignments {
+                    std::env::set_var(k, v);
+                }
+                let status = if let Some(compound) = &cmd.compound {
+                    execute_compound(shell, compound)
+                } else if let Some(handler) = &not_found_handler {
+                    // ハンドラ内の見つからないコマンドで再びハンドラを呼ばない
+                    shell.functions.remove(COMMAND_NOT_FOUND_HANDLE);
+                    execute_function(shell, COMMAND_NOT_FOUND_HANDLE, handler, &args)
+                } else {
+                    match (&cmd.subshell_body, &function_body) {
+                        (Some(body), _) => run_command_string(shell, body),
+                        (None, Some(body)) => execute_function(shell, args[0], body, &args[1..]),
+                        (None, None) => builtins::try_exec(shell, &args, &mut io::stdout()).unwrap_or(127),
+                    }
+                };
+                use std::io::Write;
+                let _ = io::stdout().flush();
                 std::process::exit(status);
             }
             // 親プロセス
@@ -1082,8 +1781,19 @@ fn execute_job(shell: &mut Shell, pipeline: &Pipeline<'_>, cmd_text: &str) -> i3
             pids[pid_count] = child_pid;
             pid_count += 1;
         } else {
-            match spawn::spawn(
+            // `/` を含まないコマンド名は CommandHash で絶対パスに解決してから起動する
+            let resolved = if args[0].contains('/') {
+                None
+            } else {
+                resolve_command(shell, args[0], &assignments)
+            };
+            let program = resolved.as_deref().unwrap_or(args[0]);
+            let env = child_environment(&assignments, &shell.unexported);
+            let spawned = match shell.spawn_ctx.spawn_program(
+                program,
                 &args,
+                env.as_deref(),
+                None,
                 pgid,
                 stdin_fd,
                 stdout_fd,
@@ -1091,6 +1801,21 @@ fn execute_job(shell: &mut Shell, pipeline: &Pipeline<'_>, cmd_text: &str) -> i3
                 &close_fds_buf[..close_count],
                 &redir_fds.dup_actions,
             ) {
+                Ok(child_pid) => Ok(child_pid),
+                // シバンなしのテキストファイル → rush スクリプトとして実行（bash 互換）
+                Err(e) if e.errno == libc::ENOEXEC => spawn_script(
+                    shell,
+                    program,
+                    &args,
+                    &assignments,
+                    pgid,
+                    [stdin_fd, stdout_fd, redir_fds.stderr_fd],
+                    &close_fds_buf[..close_count],
+                    &redir_fds.dup_actions,
+                ),
+                Err(e) => Err(e.report()),
+            };
+            match spawned {
                 Ok(child_pid) => {
                     // 親側でもプロセスグループを設定（レースコンディション防止）
                     if pgid == 0 {
@@ -1103,9 +1828,8 @@ fn execute_job(shell: &mut Shell, pipeline: &Pipeline<'_>, cmd_text: &str) -> i3
                     pids[pid_count] = child_pid;
                     pid_count += 1;
                 }
-                Err(e) => {
-                    eprintln!("{}", e);
-                    error_status = e.exit_status();
+                Err(status) => {
+                    error_status = status;
                     spawn_error = true;
                     break;
                 }
@@ -1132,7 +1856,10 @@ fn execute_job(shell: &mut Shell, pipeline: &Pipeline<'_>, cmd_text: &str) -> i3
         if let Some(fd) = redir_fds.stderr_fd {
             unsafe { libc::close(fd); }
         }
-
+        for &fd in &redir_fds.extra_fds {
+            unsafe { libc::close(fd); }
+        }
+
         // プロセス置換の fd を親側で close（spawn した子が fd を継承した後）
         // リダイレクト経由のプロセス置換 fd も close
         for ps in &redir_fds.proc_subs {
@@ -1144,14 +1871,7 @@ fn execute_job(shell: &mut Shell, pipeline: &Pipeline<'_>, cmd_text: &str) -> i3
         // ProcSubInfo を全体リストに移動（waitpid 用）
         all_proc_subs.extend(proc_subs);
         all_proc_subs.extend(redir_fds.proc_subs);
-
-        // インライン代入を復元（子プロセスにのみ影響させるため）
-        for (k, old) in saved_env {
-            match old {
-                Some(v) => std::env::set_var(&k, &v),
-                None => std::env::remove_var(&k),
-            }
-        }
+        all_writers.extend(redir_fds.writers);
     }
 
     // 未消費のパイプ fd を close
@@ -1169,10 +1889,11 @@ fn execute_job(shell: &mut Shell, pipeline: &Pipeline<'_>, cmd_text: &str) -> i3
                 libc::waitpid(pid, std::ptr::null_mut(), 0);
             }
         }
-        // プロセス置換の子プロセスも待機
+        // プロセス置換・ヒアドキュメントの子プロセスも待機
         for ps in &all_proc_subs {
             unsafe { libc::waitpid(ps.child_pid, std::ptr::null_mut(), 0); }
         }
+        wait_writers(&all_writers);
         return error_status;
     }
 
@@ -1187,6 +1908,8 @@ fn execute_job(shell: &mut Shell, pipeline: &Pipeline<'_>, cmd_text: &str) -> i3
             .jobs
             .insert(pgid, display_cmd.to_string(), active_pids.to_vec());
         eprintln!("[{}] {}", job_id, pgid);
+        // ヒアドキュメントの書き込み子プロセスはジョブが読み終えるまで終わらないので、
+        // ここでは待たず `reap_jobs` に回収させる
         // プロセス置換の子プロセスを待機
         for ps in &all_proc_subs {
             unsafe { libc::waitpid(ps.child_pid, std::ptr::null_mut(), 0); }
@@ -1196,7 +1919,7 @@ fn execute_job(shell: &mut Shell, pipeline: &Pipeline<'_>, cmd_text: &str) -> i3
         // フォアグラウンド: ジョブテーブルに一時登録して待機
         let job_id = shell
             .jobs
-            .insert(pgid, display_cmd.to_string(), active_pids.to_vec());
+            .insert_foreground(pgid, display_cmd.to_string(), active_pids.to_vec());
 
         job::give_terminal_to(shell.terminal_fd, pgid);
 
@@ -1210,6 +1933,11 @@ fn execute_job(shell: &mut Shell, pipeline: &Pipeline<'_>, cmd_text: &str) -> i3
             unsafe { libc::waitpid(ps.child_pid, std::ptr::null_mut(), 0); }
         }
 
+        // 停止したジョブはまだヒアドキュメントを読むので、書き込み子プロセスは reap_jobs に任せる
+        if !stopped {
+            wait_writers(&all_writers);
+        }
+
         if stopped {
             // Ctrl+Z で停止: ジョブテーブルに残す。停止状態をマーク
             if let Some(job) = shell.jobs.get_mut(job_id) {
@@ -1217,6 +1945,7 @@ fn execute_job(shell: &mut Shell, pipeline: &Pipeline<'_>, cmd_text: &str) -> i3
                     proc.stopped = true;
                 }
             }
+            shell.jobs.make_current(job_id);
             eprintln!("\n[{}]+  Stopped   {}", job_id, display_cmd);
             status
         } else {
@@ -1234,326 +1963,212 @@ fn execute_job(shell: &mut Shell, pipeline: &Pipeline<'_>, cmd_text: &str) -> i3
     }
 }
 
-// ── if/then/elif/else/fi 複合コマンド ────────────────────────────────
+// ── if/for/while/until 複合コマンド ─────────────────────────────────
 
-/// テキストベースで if ブロック全体を解釈・実行する。
+/// 複合コマンドを含むブロックテキストを [`parser::parse`] で AST にして実行する。
 ///
-/// 処理フロー:
-/// 1. トークン列を `if` / `then` / `elif` / `else` / `fi` で分割
-/// 2. 条件部分をパース＆実行し、ステータス 0 なら本体を実行
-/// 3. elif チェーン、else 部分を順に評価
-/// 4. 最終ステータスを返す（どの分岐も実行されなければ 0）
-pub fn execute_if_block(shell: &mut Shell, block: &str) -> i32 {
-    // if ブロックをセクションに分割
-    let sections = match parse_if_sections(block) {
-        Ok(s) => s,
-        Err(msg) => {
-            eprintln!("rush: {}", msg);
-            return 2;
+/// `collect_if_block` / `collect_loop_block` / `collect_case_block` で集めた複数行のブロック
+/// （`if`〜`fi`、`for`/`while`/`until`〜`done`、`case`〜`esac`）を受け取る。
+/// 閉じ予約語の後に続くコマンド（`fi; echo done` 等）も同じリストとして実行する。
+pub fn execute_block(shell: &mut Shell, block: &str) -> i32 {
+    match parser::parse(block, shell.last_status, &shell.shell_name, &shell.positional_args, shell.set_nounset, &shell.arrays) {
+        Ok(Some(list)) => execute(shell, &list, block.trim()),
+        Ok(None) => 0,
+        Err(e) => {
+            eprintln!("rush: {}", e);
+            2
         }
-    };
-
-    // if 条件を評価（errexit 免除）
-    shell.in_condition += 1;
-    let cond_status = run_command_string(shell, &sections.condition);
-    shell.in_condition -= 1;
-    shell.errexit_pending = false;
-    if cond_status == 0 {
-        return run_command_string(shell, &sections.then_body);
     }
+}
 
-    // elif チェーン
-    for (elif_cond, elif_body) in &sections.elif_parts {
-        shell.in_condition += 1;
-        let s = run_command_string(shell, elif_cond);
-        shell.in_condition -= 1;
-        shell.errexit_pending = false;
-        if s == 0 {
-            return run_command_string(shell, elif_body);
+/// 複合コマンドの AST を現在のシェルで実行する。
+///
+/// 実行開始時の `$LINENO` をパース入力の先頭行とみなし、各節の行オフセットを足して本体に引き継ぐ。
+pub fn execute_compound(shell: &mut Shell, compound: &parser::Compound) -> i32 {
+    let base = parser::lineno();
+    match compound {
+        parser::Compound::If { clauses, else_body } => execute_if(shell, base, clauses, else_body.as_ref()),
+        parser::Compound::For { var, words, body } => execute_for(shell, base, var, words.as_deref(), body),
+        parser::Compound::While { condition, body, until } => execute_while(shell, base, condition, body, *until),
+        parser::Compound::Case { block, line } => {
+            parser::set_lineno(base + line);
+            execute_case(shell, block)
         }
     }
-
-    // else
-    if let Some(ref else_body) = sections.else_body {
-        return run_command_string(shell, else_body);
-    }
-
-    0
 }
 
-// ── for/while/until ループ ─────────────────────────────────────────
+/// 条件・本体の節を、その開始行を `$LINENO` にして実行する。
+fn run_clause(shell: &mut Shell, base: usize, clause: &parser::Clause) -> i32 {
+    parser::set_lineno(base + clause.line);
+    run_command_string(shell, &clause.text)
+}
 
-/// `for VAR in WORDS...; do BODY; done` ブロックを解釈・実行する。
+/// リダイレクト付きの複合コマンド（`for ...; done > out`、`while read l; do ...; done < in` 等）を
+/// 現在のシェルで実行する。
 ///
-/// 処理フロー:
-/// 1. `for VAR [in WORDS...]` と `do ... done` の間を分割
-/// 2. `in` がなければ `"$@"` 相当（現在は空リスト）
-/// 3. WORDS を展開し、各要素で VAR に代入して BODY を実行
-/// 4. `break`/`continue` を適切にハンドリング
-pub fn execute_for_block(shell: &mut Shell, block: &str) -> i32 {
-    let tokens = tokenize_block(block);
-
-    // for VAR in words... ; do body ; done を解析
-    let mut var_name = String::new();
-    let mut word_tokens: Vec<String> = Vec::new();
-    let mut body_tokens: Vec<String> = Vec::new();
-    let mut depth = 0i32;
-
-    #[derive(PartialEq)]
-    enum State { BeforeFor, InHeader, InBody }
-    let mut state = State::BeforeFor;
-
-    for token in &tokens {
-        let trimmed = token.trim();
-        if trimmed.is_empty() {
-            continue;
+/// [`execute_builtin`] と同じくシェル自身の fd を `dup2` で差し替え、ブロック終了後に元へ戻す。
+/// 入力系（`<`・ヒアドキュメント・ヒアストリング）は stdin を差し替える（`read` は 1 バイトずつ読むので
+/// 読み残しは出ない）。ブロック内の代入や `cd` は呼び出し元に残る。
+fn execute_compound_redirected(
+    shell: &mut Shell,
+    compound: &parser::Compound,
+    redirects: &[parser::Redirect<'_>],
+) -> i32 {
+    use std::io::Write;
+    let redirects = match expand_redirect_targets(redirects, shell) {
+        Ok(redirects) => redirects,
+        Err(status) => return status,
+    };
+    let is_input = |r: &&parser::Redirect<'_>| {
+        matches!(
+            r.kind,
+            RedirectKind::Input
+                | RedirectKind::HereDoc
+                | RedirectKind::HereDocLiteral
+                | RedirectKind::HereString
+                | RedirectKind::HereStringLiteral
+        )
+    };
+    let inputs: Vec<parser::Redirect<'_>> = redirects.iter().filter(is_input).cloned().collect();
+    let (stdin_fd, writers) = if inputs.is_empty() {
+        (None, Vec::new())
+    } else {
+        match open_redirect_fds(&inputs, shell) {
+            Ok(fds) => (fds.stdin_fd, fds.writers),
+            Err(status) => return status,
         }
-        let kw = extract_keyword(trimmed);
-
-        match state {
-            State::BeforeFor => {
-                if let Some("for") = kw {
-                    state = State::InHeader;
-                    let after = trimmed.strip_prefix("for").unwrap().trim();
-                    if !after.is_empty() {
-                        // "for VAR in a b c" or "for VAR"
-                        let parts: Vec<&str> = after.splitn(2, char::is_whitespace).collect();
-                        var_name = parts[0].to_string();
-                        if parts.len() > 1 {
-                            let rest = parts[1].trim();
-                            if let Some(after_in) = rest.strip_prefix("in") {
-                                let words_str = after_in.trim();
-                                if !words_str.is_empty() {
-                                    for w in words_str.split_whitespace() {
-                                        word_tokens.push(w.to_string());
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-            State::InHeader => {
-                if let Some("do") = kw {
-                    state = State::InBody;
-                    let after_do = trimmed.strip_prefix("do").unwrap().trim();
-                    if !after_do.is_empty() {
-                        body_tokens.push(after_do.to_string());
-                    }
-                } else {
-                    // 変数名 or in words
-                    if var_name.is_empty() {
-                        let parts: Vec<&str> = trimmed.splitn(2, char::is_whitespace).collect();
-                        var_name = parts[0].to_string();
-                        if parts.len() > 1 {
-                            let rest = parts[1].trim();
-                            if let Some(after_in) = rest.strip_prefix("in") {
-                                for w in after_in.trim().split_whitespace() {
-                                    word_tokens.push(w.to_string());
-                                }
-                            }
-                        }
-                    } else if let Some(after_in) = trimmed.strip_prefix("in") {
-                        for w in after_in.trim().split_whitespace() {
-                            word_tokens.push(w.to_string());
-                        }
-                    } else {
-                        for w in trimmed.split_whitespace() {
-                            word_tokens.push(w.to_string());
-                        }
-                    }
-                }
-            }
-            State::InBody => {
-                match kw {
-                    Some("for") | Some("while") | Some("until") => {
-                        depth += 1;
-                        body_tokens.push(trimmed.to_string());
-                    }
-                    Some("done") if depth > 0 => {
-                        depth -= 1;
-                        body_tokens.push(trimmed.to_string());
-                    }
-                    Some("done") => {
-                        break;
-                    }
-                    _ => {
-                        body_tokens.push(trimmed.to_string());
-                    }
-                }
+    };
+    let _ = io::stdout().flush();
+    let mut saved = match redirect_builtin_fds(&redirects) {
+        Ok(saved) => saved,
+        Err(status) => {
+            if let Some(fd) = stdin_fd {
+                unsafe { libc::close(fd); }
             }
+            wait_writers(&writers);
+            return status;
         }
-    }
-
-    if var_name.is_empty() {
-        eprintln!("rush: syntax error: missing variable name in `for`");
-        return 2;
-    }
-
-    let body = body_tokens.join("\n");
-
-    // word_tokens を展開（変数展開 → コマンド置換、チルダ、ブレース、glob）
-    let expanded_words: Vec<String> = if word_tokens.is_empty() {
-        Vec::new()
-    } else {
-        // まず変数展開（$VAR, ${arr[@]} 等）
-        let cow_words: Vec<std::borrow::Cow<'_, str>> = word_tokens.iter()
-            .map(|s| {
-                match parser::expand_variables(s, shell.last_status, &shell.positional_args, shell.set_nounset, &shell.arrays) {
-                    Ok(expanded) => std::borrow::Cow::Owned(expanded.into_owned()),
-                    Err(_) => std::borrow::Cow::Owned(s.clone()),
-                }
-            })
-            .collect();
-        expand_args_full(&cow_words, shell)
     };
+    if let Some(fd) = stdin_fd {
+        unsafe {
+            saved.push((0, libc::fcntl(0, libc::F_DUPFD_CLOEXEC, 10)));
+            libc::dup2(fd, 0);
+            libc::close(fd);
+        }
+    }
+    let status = execute_compound(shell, compound);
+    let _ = io::stdout().flush();
+    restore_builtin_fds(saved);
+    wait_writers(&writers);
+    status
+}
 
-    let mut last_status = 0;
-    shell.loop_depth += 1;
-
-    for word in &expanded_words {
-        std::env::set_var(&var_name, word);
-        last_status = run_command_string(shell, &body);
-        shell.last_status = last_status;
+/// 条件部分を errexit 免除で実行し、終了ステータスを返す。
+fn run_condition(shell: &mut Shell, base: usize, condition: &parser::Clause) -> i32 {
+    shell.in_condition += 1;
+    let status = run_clause(shell, base, condition);
+    shell.in_condition -= 1;
+    shell.errexit_pending = false;
+    status
+}
 
-        // break チェック
-        if shell.break_level > 0 {
-            shell.break_level -= 1;
-            break;
-        }
-        // continue チェック
-        if shell.continue_level > 0 {
-            shell.continue_level -= 1;
-            if shell.continue_level > 0 {
-                // 外側ループの continue
-                break;
-            }
-            continue;
+/// if / elif の条件を順に評価し、最初に成功した節の本体を実行する。
+///
+/// どの分岐も実行されなければ 0 を返す。
+fn execute_if(
+    shell: &mut Shell,
+    base: usize,
+    clauses: &[(parser::Clause, parser::Clause)],
+    else_body: Option<&parser::Clause>,
+) -> i32 {
+    for (condition, body) in clauses {
+        if run_condition(shell, base, condition) == 0 {
+            return run_clause(shell, base, body);
         }
         if shell.should_return || shell.should_exit {
-            break;
+            return shell.last_status;
         }
     }
+    match else_body {
+        Some(body) => run_clause(shell, base, body),
+        None => 0,
+    }
+}
 
-    shell.loop_depth -= 1;
-    last_status
+/// ループ本体の実行後に `break` / `continue` / `return` を処理する。ループを抜けるなら `true`。
+fn loop_should_stop(shell: &mut Shell) -> bool {
+    if shell.break_level > 0 {
+        shell.break_level -= 1;
+        return true;
+    }
+    if shell.continue_level > 0 {
+        shell.continue_level -= 1;
+        // 外側ループの continue なら、このループは抜ける
+        return shell.continue_level > 0;
+    }
+    shell.should_return || shell.should_exit
 }
 
-/// `while COND; do BODY; done` / `until COND; do BODY; done` ブロックを解釈・実行する。
+/// `for` ループ: ワードを展開し、各要素を `var` に代入して本体を実行する。
 ///
-/// `is_until=true` のとき until ループ（条件が偽の間ループ継続）。
-/// `is_until=false` のとき while ループ（条件が真の間ループ継続）。
-pub fn execute_while_block(shell: &mut Shell, block: &str, is_until: bool) -> i32 {
-    let tokens = tokenize_block(block);
-
-    // while/until COND; do BODY; done を解析
-    let mut cond_tokens: Vec<String> = Vec::new();
-    let mut body_tokens: Vec<String> = Vec::new();
-    let mut depth = 0i32;
-
-    #[derive(PartialEq)]
-    enum State { BeforeKw, InCond, InBody }
-    let mut state = State::BeforeKw;
-
-    for token in &tokens {
-        let trimmed = token.trim();
-        if trimmed.is_empty() {
-            continue;
-        }
-        let kw = extract_keyword(trimmed);
-
-        match state {
-            State::BeforeKw => {
-                if let Some("while") | Some("until") = kw {
-                    state = State::InCond;
-                    let prefix = if trimmed.starts_with("while") { "while" } else { "until" };
-                    let after = trimmed.strip_prefix(prefix).unwrap().trim();
-                    if !after.is_empty() {
-                        cond_tokens.push(after.to_string());
-                    }
-                }
-            }
-            State::InCond => {
-                if let Some("do") = kw {
-                    state = State::InBody;
-                    let after_do = trimmed.strip_prefix("do").unwrap().trim();
-                    if !after_do.is_empty() {
-                        body_tokens.push(after_do.to_string());
-                    }
-                } else {
-                    cond_tokens.push(trimmed.to_string());
+/// `words` が `None`（`in` なし）なら位置パラメータを順に使う。
+/// ワードは通常のコマンド引数と同じトークナイザを通すため、`"a b"` は 1 要素になる。
+fn execute_for(shell: &mut Shell, base: usize, var: &str, words: Option<&[String]>, body: &parser::Clause) -> i32 {
+    let items: Vec<String> = match words {
+        None => shell.positional_args.clone(),
+        Some([]) => Vec::new(),
+        Some(words) => {
+            // クォート除去・変数展開 → コマンド置換、チルダ、ブレース、glob
+            let text = words.join(" ");
+            match parser::expand_words(&text, shell.last_status, &shell.shell_name, &shell.positional_args, shell.set_nounset, &shell.arrays) {
+                Ok((args, created)) => {
+                    shell.unexported.extend(created);
+                    expand_args_full(&args, shell)
                 }
-            }
-            State::InBody => {
-                match kw {
-                    Some("for") | Some("while") | Some("until") => {
-                        depth += 1;
-                        body_tokens.push(trimmed.to_string());
-                    }
-                    Some("done") if depth > 0 => {
-                        depth -= 1;
-                        body_tokens.push(trimmed.to_string());
-                    }
-                    Some("done") => {
-                        break;
-                    }
-                    _ => {
-                        body_tokens.push(trimmed.to_string());
-                    }
+                Err(e) => {
+                    eprintln!("rush: {}", e);
+                    return 1;
                 }
             }
         }
-    }
-
-    let cond = cond_tokens.join("\n");
-    let body = body_tokens.join("\n");
+    };
 
-    if cond.is_empty() {
-        eprintln!("rush: syntax error: missing condition in `{}`",
-            if is_until { "until" } else { "while" });
-        return 2;
+    let mut last_status = 0;
+    shell.loop_depth += 1;
+    for item in &items {
+        std::env::set_var(var, item);
+        last_status = run_clause(shell, base, body);
+        shell.last_status = last_status;
+        if loop_should_stop(shell) {
+            break;
+        }
     }
+    shell.loop_depth -= 1;
+    last_status
+}
 
+/// `while` / `until` ループ: 条件が成功（until なら失敗）する間、本体を実行する。
+fn execute_while(shell: &mut Shell, base: usize, condition: &parser::Clause, body: &parser::Clause, until: bool) -> i32 {
     let mut last_status = 0;
     shell.loop_depth += 1;
-
     loop {
-        // 条件評価は errexit 免除
-        shell.in_condition += 1;
-        let cond_status = run_command_string(shell, &cond);
-        shell.in_condition -= 1;
-        shell.errexit_pending = false;
-        let should_run = if is_until { cond_status != 0 } else { cond_status == 0 };
-        if !should_run {
+        let cond_status = run_condition(shell, base, condition);
+        if shell.should_return || shell.should_exit || (cond_status == 0) == until {
             break;
         }
-
-        last_status = run_command_string(shell, &body);
+        last_status = run_clause(shell, base, body);
         shell.last_status = last_status;
-
-        if shell.break_level > 0 {
-            shell.break_level -= 1;
-            break;
-        }
-        if shell.continue_level > 0 {
-            shell.continue_level -= 1;
-            if shell.continue_level > 0 {
-                break;
-            }
-            continue;
-        }
-        if shell.should_return || shell.should_exit {
+        if loop_should_stop(shell) {
             break;
         }
     }
-
     shell.loop_depth -= 1;
     last_status
 }
 
 // ── case/in/esac 文 ──────────────────────────────────────────────────
 
-/// `case WORD in PATTERN) BODY ;; ... esac` ブロックを解釈・実行する。
+/// `case WORD in PATTERN) BODY ;; ... esac` を行単位で解釈・実行する。
 ///
 /// 処理フロー:
 /// 1. `case WORD in` のヘッダから WORD を抽出
@@ -1561,13 +2176,26 @@ pub fn execute_while_block(shell: &mut Shell, block: &str, is_until: bool) -> i3
 /// 3. WORD がパターンにマッチしたら BODY を実行して終了
 /// 4. `*` はワイルドカード（デフォルト節）
 /// 5. `|` で複数パターンを OR 結合可能
-pub fn execute_case_block(shell: &mut Shell, block: &str) -> i32 {
-    // case ブロックは `;;` をクロージャ区切りとして使うため、
-    // tokenize_block（`;` で分割）ではなく行ベースで解析する。
-    let lines: Vec<&str> = block.lines().collect();
+/// 6. 節末尾が `;&` なら次の節の BODY も無条件に実行し、
+///    `;;&` なら後続の節のパターン評価を続ける
+fn execute_case(shell: &mut Shell, block: &str) -> i32 {
+    // case ブロックは `;;` をクロージャ区切りとして使うため、行ベースで解析する。
+    // ヘッダ行の `in` の後に節が続く場合（ワンライナー）は節を次の行として扱う
+    let mut lines: Vec<&str> = block.lines().collect();
+    let mut spliced = false;
+    if let Some(first) = lines.first().copied() {
+        let tokens = shell_tokens(first.trim());
+        if tokens.len() > 3 && tokens[0] == "case" && tokens[2] == "in" {
+            let rest_at = tokens[3].as_ptr() as usize - first.as_ptr() as usize;
+            lines.splice(0..1, [&first[..rest_at], &first[rest_at..]]);
+            spliced = true;
+        }
+    }
 
+    let base = parser::lineno();
     let mut word = String::new();
-    let mut clauses: Vec<(Vec<String>, String)> = Vec::new(); // (patterns, body)
+    // (patterns, body, terminator, ブロック内での本体の開始行)
+    let mut clauses: Vec<(Vec<String>, String, CaseTerm, usize)> = Vec::new();
     let mut depth = 0i32;
 
     #[derive(PartialEq)]
@@ -1575,15 +2203,17 @@ pub fn execute_case_block(shell: &mut Shell, block: &str) -> i32 {
     let mut state = State::BeforeCase;
     let mut current_body = String::new();
     let mut current_patterns: Vec<String> = Vec::new();
+    let mut body_row = None;
 
-    for line in &lines {
-        // 行内を `;;\n` で区切る必要がある: ワンライナー対応
-        // まず ;; で分割し、各セグメントを処理
+    for (n, line) in lines.iter().enumerate() {
+        let row = if spliced && n > 0 { n - 1 } else { n };
+        // 行内を `;;` / `;&` / `;;&` で区切る必要がある: ワンライナー対応
+        // まず終端子で分割し、各セグメントを処理
         let segments = split_case_segments(line);
 
-        for seg in &segments {
+        for &(seg, term) in &segments {
             let trimmed = seg.trim();
-            if trimmed.is_empty() {
+            if trimmed.is_empty() && term.is_none() {
                 continue;
             }
 
@@ -1630,7 +2260,8 @@ pub fn execute_case_block(shell: &mut Shell, block: &str) -> i32 {
                             current_body.push_str(trimmed);
                         } else {
                             if !current_patterns.is_empty() {
-                                clauses.push((current_patterns.clone(), current_body.trim().to_string()));
+                                let body_row = body_row.unwrap_or(row);
+                                clauses.push((current_patterns.clone(), current_body.trim().to_string(), CaseTerm::Break, body_row));
                             }
                             break;
                         }
@@ -1638,12 +2269,12 @@ pub fn execute_case_block(shell: &mut Shell, block: &str) -> i32 {
                         depth += 1;
                         if !current_body.is_empty() { current_body.push('\n'); }
                         current_body.push_str(trimmed);
-                    } else if depth == 0 && trimmed.contains(')') && current_patterns.is_empty() {
+                    } else if depth == 0 && current_patterns.is_empty() && find_case_pattern_end(trimmed).is_some() {
                         // パターン行: "pattern)" or "pat1|pat2) body"
-                        let paren_pos = trimmed.find(')').unwrap();
+                        let paren_pos = find_case_pattern_end(trimmed).unwrap();
                         let pattern_str = trimmed[..paren_pos].trim();
                         let pattern_str = pattern_str.strip_prefix('(').unwrap_or(pattern_str).trim();
-                        current_patterns = pattern_str.split('|').map(|p| p.trim().to_string()).collect();
+                        current_patterns = split_case_patterns(pattern_str);
                         let after = trimmed[paren_pos + 1..].trim();
                         if !after.is_empty() {
                             current_body = after.to_string();
@@ -1651,20 +2282,29 @@ pub fn execute_case_block(shell: &mut Shell, block: &str) -> i32 {
                     } else if depth == 0 && !current_patterns.is_empty() {
                         if !current_body.is_empty() { current_body.push('\n'); }
                         current_body.push_str(trimmed);
-                    } else {
+                    } else if !trimmed.is_empty() {
                         if !current_body.is_empty() { current_body.push('\n'); }
                         current_body.push_str(trimmed);
                     }
+
+                    if body_row.is_none() && !current_body.is_empty() {
+                        body_row = Some(row);
+                    }
+
+                    // 終端子の後にクロージャ確定。ネストした case 内の終端子は本文に残す。
+                    if let Some(term) = term {
+                        if depth > 0 {
+                            current_body.push_str(term.as_str());
+                        } else if !current_patterns.is_empty() {
+                            let row = body_row.take().unwrap_or(row);
+                            clauses.push((current_patterns.clone(), current_body.trim().to_string(), term, row));
+                            current_patterns.clear();
+                            current_body.clear();
+                        }
+                    }
                 }
             }
         }
-
-        // `;;\n` の後にクロージャ確定
-        if state == State::InClauses && depth == 0 && line.contains(";;") && !current_patterns.is_empty() {
-            clauses.push((current_patterns.clone(), current_body.trim().to_string()));
-            current_patterns.clear();
-            current_body.clear();
-        }
     }
 
     if word.is_empty() {
@@ -1676,15 +2316,46 @@ pub fn execute_case_block(shell: &mut Shell, block: &str) -> i32 {
     let word_val = expand_case_word(&word, shell);
 
     // Match against clauses
-    for (patterns, body) in &clauses {
-        for pattern in patterns {
-            if case_pattern_match(&word_val, pattern) {
-                return run_command_string(shell, body);
-            }
+    let mut status = 0;
+    let mut fall_through = false;
+    for (patterns, body, term, row) in &clauses {
+        if !fall_through && !patterns.iter().any(|p| case_pattern_match(&word_val, &expand_case_pattern(p, shell))) {
+            continue;
+        }
+        parser::set_lineno(base + row);
+        status = run_command_string(shell, body);
+        if shell.should_return || shell.should_exit || shell.break_level > 0 || shell.continue_level > 0 {
+            return status;
+        }
+        match term {
+            CaseTerm::Break => return status,
+            CaseTerm::FallThrough => fall_through = true,
+            CaseTerm::Continue => fall_through = false,
         }
     }
 
-    0
+    status
+}
+
+/// case 節の終端子。
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum CaseTerm {
+    /// `;;` — case 文を抜ける。
+    Break,
+    /// `;&` — 次の節の本文を無条件に実行する。
+    FallThrough,
+    /// `;;&` — 後続の節のパターン評価を続ける。
+    Continue,
+}
+
+impl CaseTerm {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Break => ";;",
+            Self::FallThrough => ";&",
+            Self::Continue => ";;&",
+        }
+    }
 }
 
 /// case 文の WORD を展開する。
@@ -1692,25 +2363,22 @@ pub fn execute_case_block(shell: &mut Shell, block: &str) -> i32 {
 /// `parser::parse` で変数展開（`$VAR`, `${VAR}`）を行い、
 /// さらに `expand_args_full` でコマンド置換・チルダ・glob を適用する。
 fn expand_case_word(word: &str, shell: &mut Shell) -> String {
-    // パーサーで変数展開を行う（echo WORD をパースして展開済み引数を取得）
-    let synthetic = format!("echo {}", word);
-    if let Ok(Some(list)) = parser::parse(&synthetic, shell.last_status, &shell.positional_args, shell.set_nounset, &shell.arrays) {
-        if let Some(cmd) = list.items.first() {
-            if let Some(arg) = cmd.pipeline.commands[0].args.get(1) {
-                let cow_args = vec![arg.clone()];
-                let expanded = expand_args_full(&cow_args, shell);
-                return expanded.into_iter().next().unwrap_or_default();
-            }
+    if let Ok((mut words, created)) = parser::expand_words(word, shell.last_status, &shell.shell_name, &shell.positional_args, shell.set_nounset, &shell.arrays) {
+        shell.unexported.extend(created);
+        if !words.is_empty() {
+            words.truncate(1);
+            // case の WORD はフィールド分割しないため、分割結果をスペースで戻す
+            return expand_args_full(&words, shell).join(" ");
         }
     }
     word.to_string()
 }
 
-/// case ブロックの行を `;;` で分割する。
+/// case ブロックの行を終端子（`;;` / `;&` / `;;&`）で分割する。
 ///
-/// `;;` はクロージャ区切りとして特別扱いする。クォート内の `;;` は無視する。
-/// 各セグメントは `;;` を含まない文字列。
-fn split_case_segments(line: &str) -> Vec<&str> {
+/// 終端子はクロージャ区切りとして特別扱いする。クォート内の終端子は無視する。
+/// 各セグメントは終端子を含まない文字列と、その直後の終端子（行末なら `None`）の組。
+fn split_case_segments(line: &str) -> Vec<(&str, Option<CaseTerm>)> {
     let bytes = line.as_bytes();
     let len = bytes.len();
     let mut segments = Vec::new();
@@ -1732,20 +2400,107 @@ fn split_case_segments(line: &str) -> Vec<&str> {
                 }
                 if i < len { i += 1; }
             }
-            b';' if i + 1 < len && bytes[i + 1] == b';' => {
-                segments.push(&line[start..i]);
-                i += 2; // skip ;;
+            b';' if i + 1 < len && (bytes[i + 1] == b';' || bytes[i + 1] == b'&') => {
+                let term = if bytes[i + 1] == b'&' {
+                    CaseTerm::FallThrough
+                } else if i + 2 < len && bytes[i + 2] == b'&' {
+                    CaseTerm::Continue
+                } else {
+                    CaseTerm::Break
+                };
+                segments.push((&line[start..i], Some(term)));
+                i += term.as_str().len();
                 start = i;
             }
             _ => { i += 1; }
         }
     }
 
-    segments.push(&line[start..]);
+    segments.push((&line[start..], None));
     segments
 }
 
+/// パターン行で節のパターンを閉じる `)` の位置を返す。
+///
+/// 先頭の `(` と `[...]` 内・クォート内の `)` は閉じ括弧として扱わない。
+fn find_case_pattern_end(s: &str) -> Option<usize> {
+    let bytes = s.as_bytes();
+    let mut i = if bytes.first() == Some(&b'(') { 1 } else { 0 };
+    let mut bracket = false;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'\'' | b'"' if !bracket => {
+                let q = bytes[i];
+                i += 1;
+                while i < bytes.len() && bytes[i] != q { i += 1; }
+            }
+            b'[' => bracket = true,
+            b']' => bracket = false,
+            b')' if !bracket => return Some(i),
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// 節のパターン文字列をトップレベルの `|` で分割する。
+///
+/// `[a|b]` のような文字クラス内やクォート内の `|` では分割しない。
+fn split_case_patterns(s: &str) -> Vec<String> {
+    let bytes = s.as_bytes();
+    let mut patterns = Vec::new();
+    let mut start = 0;
+    let mut bracket = false;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'\'' | b'"' if !bracket => {
+                let q = bytes[i];
+                i += 1;
+                while i < bytes.len() && bytes[i] != q { i += 1; }
+            }
+            b'[' => bracket = true,
+            b']' => bracket = false,
+            b'|' if !bracket => {
+                patterns.push(s[start..i].trim().to_string());
+                start = i + 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    patterns.push(s[start..].trim().to_string());
+    patterns
+}
+
+/// case のパターンにクォート除去・変数展開・コマンド置換を適用する。
+///
+/// クォート・エスケープされたグロブ文字には [`glob::QUOTED_GLOB`] が付き、照合ではリテラルになる。
+/// パターンはフィールド分割もパス名展開もしない。
+fn expand_case_pattern(pattern: &str, shell: &mut Shell) -> String {
+    let words = match parser::expand_words(pattern, shell.last_status, &shell.shell_name, &shell.positional_args, shell.set_nounset, &shell.arrays) {
+        Ok((words, created)) => {
+            shell.unexported.extend(created);
+            words
+        }
+        Err(_) => return pattern.to_string(),
+    };
+    // `"a"*` のようにクォートで区切られた部分は別ワードになるので連結して 1 つのパターンに戻す
+    let joined: String = words.iter().map(|w| w.as_ref()).collect();
+    let joined = if joined.contains("$(") || joined.contains('`') {
+        expand_command_subs(&joined, shell)
+    } else {
+        joined
+    };
+    joined.replace(parser::UNQUOTED_SUB, "").replace('\x1F', " ")
+}
+
 /// case 文のパターンマッチ。`*` と `?` によるグロブマッチをサポート。
+///
+/// `pattern` は [`expand_case_pattern`] で展開済みのもの（クォートされたグロブ文字はリテラル）。
 fn case_pattern_match(word: &str, pattern: &str) -> bool {
     if pattern == "*" {
         return true;
@@ -1755,17 +2510,37 @@ fn case_pattern_match(word: &str, pattern: &str) -> bool {
 
 // ── 関数定義・実行 ──────────────────────────────────────────────────
 
-/// 行が関数定義 (`name() {` または `name () {`) かどうかを判定する。
+/// 行が関数定義 (`name() {`, `name () {`, `function name {`, `function name() {`) かどうかを判定する。
 ///
 /// 関数定義と認識されたら `Some((name, body_start))` を返す。
 /// `body_start` は `{` の後の残りテキスト（あれば）。
 pub fn parse_function_def(line: &str) -> Option<(String, String)> {
-    let trimmed = line.trim();
+    let (name, after) = split_function_header(line.trim())?;
+    // `{` で始まるはず
+    after.strip_prefix('{').map(|rest| (name.to_string(), rest.trim().to_string()))
+}
 
-    // パターン: name() { body } または name () { body }
-    // `()` を探す
-    let paren_pos = trimmed.find("()")?;
-    let name = trimmed[..paren_pos].trim();
+/// `{` を次の行に置いた関数定義の頭部（`name()` / `function name`）だけの行か。
+pub fn is_function_header(line: &str) -> bool {
+    matches!(split_function_header(line.trim()), Some((_, "")))
+}
+
+/// 関数定義の頭部を関数名と `()` の後の残りに分ける。関数名が不正なら `None`。
+fn split_function_header(trimmed: &str) -> Option<(&str, &str)> {
+    let (name, after) = match trimmed.strip_prefix("function") {
+        // `function name [()]` 形式: `()` は省略可能
+        Some(rest) if rest.starts_with([' ', '\t']) => {
+            let rest = rest.trim_start();
+            let end = rest.find(|c: char| c.is_whitespace() || c == '(' || c == '{').unwrap_or(rest.len());
+            let after = rest[end..].trim_start();
+            (&rest[..end], after.strip_prefix("()").unwrap_or(after).trim_start())
+        }
+        // パターン: name() { body } または name () { body }
+        _ => {
+            let paren_pos = trimmed.find("()")?;
+            (trimmed[..paren_pos].trim(), trimmed[paren_pos + 2..].trim())
+        }
+    };
 
     // 名前が有効な識別子かチェック
     if name.is_empty() || name.contains(' ') || name.contains('\t') {
@@ -1783,15 +2558,7 @@ pub fn parse_function_def(line: &str) -> Option<(String, String)> {
         | "until" | "do" | "done" | "case" | "esac" | "in") {
         return None;
     }
-
-    let after_paren = trimmed[paren_pos + 2..].trim();
-
-    // `{` で始まるはず
-    if let Some(rest) = after_paren.strip_prefix('{') {
-        Some((name.to_string(), rest.trim().to_string()))
-    } else {
-        None
-    }
+    Some((name, after))
 }
 
 /// 関数定義ブロック（`name() { ... }`）を行配列から収集する。
@@ -1847,293 +2614,46 @@ pub fn collect_function_body(lines: &[&str], start: usize, initial_rest: &str) -
     (body.trim().to_string(), i)
 }
 
-/// ユーザー定義関数を実行する。
+/// ユーザー定義関数 `name` を実行する。
 ///
 /// 位置パラメータ（`$1`〜`$N`, `$@`, `$*`, `$#`）を設定し、
 /// 関数本体を `run_command_string` で実行する。
 /// `return` による早期脱出をサポート。
-pub fn execute_function(shell: &mut Shell, body: &str, args: &[&str]) -> i32 {
-    // 位置パラメータを保存
-    let saved_positional = shell.positional_args.clone();
-
-    // 新しい位置パラメータを設定
-    shell.positional_args = args.iter().map(|s| s.to_string()).collect();
-
-    // source_depth を上げて return を有効にする
-    shell.source_depth += 1;
-    let status = run_command_string(shell, body);
-    shell.source_depth -= 1;
-
-    // should_return をリセット（関数内の return はここで消化される）
-    shell.should_return = false;
-
-    // 位置パラメータを復元
-    shell.positional_args = saved_positional;
-
-    status
-}
-
-/// if ブロックの各セクションを保持する構造体。
-///
-/// [`parse_if_sections`] が if ブロックテキストを解析した結果を格納する。
-/// 各フィールドは実行可能なコマンド文字列（改行区切り）。
-struct IfSections {
-    /// `if` と `then` の間の条件コマンド。
-    condition: String,
-    /// `then` 以降（`elif`/`else`/`fi` の前）の本体コマンド。
-    then_body: String,
-    /// `elif` 部分のリスト。各要素は `(条件, 本体)` のペア。
-    elif_parts: Vec<(String, String)>,
-    /// `else` 部分。存在しない場合は `None`。
-    else_body: Option<String>,
-}
-
-/// if ブロックのテキストを解析し、各セクション（condition, then, elif, else）に分割する。
-///
-/// 入力は `if ... fi` の完全なブロック。ネストした if/fi も正しく追跡する。
-fn parse_if_sections(block: &str) -> Result<IfSections, String> {
-    let tokens = tokenize_block(block);
-
-    let mut depth = 0i32;
-    let mut state = IfParseState::BeforeIf;
-    let mut condition = String::new();
-    let mut then_body = String::new();
-    let mut elif_parts: Vec<(String, String)> = Vec::new();
-    let mut else_body: Option<String> = None;
-    let mut current_elif_cond = String::new();
-
-    for token in &tokens {
-        let trimmed = token.trim();
-        if trimmed.is_empty() {
-            continue;
-        }
-
-        let keyword = extract_keyword(trimmed);
-
-        match keyword {
-            Some("if") if matches!(state, IfParseState::BeforeIf) => {
-                state = IfParseState::InCondition;
-                let after_if = trimmed.strip_prefix("if").unwrap().trim();
-                if !after_if.is_empty() {
-                    append_to_section(&mut state, after_if, &mut condition, &mut then_body,
-                        &mut elif_parts, &mut else_body, &mut current_elif_cond);
-                }
-            }
-            Some("if") => {
-                // ネストした if
-                depth += 1;
-                append_to_section(&mut state, trimmed, &mut condition, &mut then_body,
-                    &mut elif_parts, &mut else_body, &mut current_elif_cond);
-            }
-            Some("fi") if depth > 0 => {
-                depth -= 1;
-                append_to_section(&mut state, trimmed, &mut condition, &mut then_body,
-                    &mut elif_parts, &mut else_body, &mut current_elif_cond);
-            }
-            Some("fi") => {
-                // ブロック終了
-                break;
-            }
-            Some("then") if depth == 0 => {
-                match state {
-                    IfParseState::InCondition => {
-                        state = IfParseState::InThenBody;
-                        let after_then = trimmed.strip_prefix("then").unwrap().trim();
-                        if !after_then.is_empty() {
-                            append_to_section(&mut state, after_then, &mut condition, &mut then_body,
-                                &mut elif_parts, &mut else_body, &mut current_elif_cond);
-                        }
-                    }
-                    IfParseState::InElifCond => {
-                        state = IfParseState::InElifBody;
-                        elif_parts.push((current_elif_cond.clone(), String::new()));
-                        current_elif_cond.clear();
-                        let after_then = trimmed.strip_prefix("then").unwrap().trim();
-                        if !after_then.is_empty() {
-                            append_to_section(&mut state, after_then, &mut condition, &mut then_body,
-                                &mut elif_parts, &mut else_body, &mut current_elif_cond);
-                        }
-                    }
-                    _ => {
-                        append_to_section(&mut state, trimmed, &mut condition, &mut then_body,
-                            &mut elif_parts, &mut else_body, &mut current_elif_cond);
-                    }
-                }
-            }
-            Some("elif") if depth == 0 => {
-                state = IfParseState::InElifCond;
-                let after_elif = trimmed.strip_prefix("elif").unwrap().trim();
-                if !after_elif.is_empty() {
-                    append_to_section(&mut state, after_elif, &mut condition, &mut then_body,
-                        &mut elif_parts, &mut else_body, &mut current_elif_cond);
-                }
-            }
-            Some("else") if depth == 0 => {
-                state = IfParseState::InElseBody;
-                else_body = Some(String::new());
-                let after_else = trimmed.strip_prefix("else").unwrap().trim();
-                if !after_else.is_empty() {
-                    append_to_section(&mut state, after_else, &mut condition, &mut then_body,
-                        &mut elif_parts, &mut else_body, &mut current_elif_cond);
-                }
-            }
-            _ => {
-                append_to_section(&mut state, trimmed, &mut condition, &mut then_body,
-                    &mut elif_parts, &mut else_body, &mut current_elif_cond);
-            }
-        }
-    }
-
-    if condition.is_empty() {
-        return Err("syntax error: missing condition after `if`".to_string());
-    }
-    if matches!(state, IfParseState::InCondition | IfParseState::BeforeIf) {
-        return Err("syntax error: missing `then` keyword".to_string());
-    }
-
-    Ok(IfSections {
-        condition,
-        then_body,
-        elif_parts,
-        else_body,
-    })
-}
-
-/// [`parse_if_sections`] の状態遷移マシン。
-///
-/// トークンを走査しながら以下の順で遷移する:
-/// `BeforeIf` → `InCondition` → `InThenBody` → (`InElifCond` → `InElifBody`)* → `InElseBody`?
-enum IfParseState {
-    /// `if` キーワード出現前の初期状態。
-    BeforeIf,
-    /// `if` と `then` の間（条件部分を蓄積中）。
-    InCondition,
-    /// `then` 以降の本体を蓄積中。
-    InThenBody,
-    /// `elif` と次の `then` の間（elif 条件を蓄積中）。
-    InElifCond,
-    /// `elif ... then` 以降の本体を蓄積中。
-    InElifBody,
-    /// `else` 以降の本体を蓄積中。
-    InElseBody,
-}
-
-/// 現在の解析状態に応じて、適切なセクション文字列の末尾にテキストを追加する。
-///
-/// 複数行のセクション内容は `\n` で連結される。
-fn append_to_section(
-    state: &mut IfParseState,
-    text: &str,
-    condition: &mut String,
-    then_body: &mut String,
-    elif_parts: &mut Vec<(String, String)>,
-    else_body: &mut Option<String>,
-    current_elif_cond: &mut String,
-) {
-    let target = match state {
-        IfParseState::BeforeIf => return,
-        IfParseState::InCondition => condition,
-        IfParseState::InThenBody => then_body,
-        IfParseState::InElifCond => current_elif_cond,
-        IfParseState::InElifBody => {
-            if let Some((_, ref mut body)) = elif_parts.last_mut() {
-                body
-            } else {
-                return;
-            }
-        }
-        IfParseState::InElseBody => {
-            if let Some(ref mut body) = else_body {
-                body
-            } else {
-                return;
-            }
-        }
-    };
-    if !target.is_empty() {
-        target.push('\n');
+pub fn execute_function(shell: &mut Shell, name: &str, body: &str, args: &[&str]) -> i32 {
+    let limit = shell.nesting_limit();
+    if shell.function_depth >= limit {
+        eprintln!("rush: {}: maximum nesting level exceeded ({})", name, limit);
+        return 1;
     }
-    target.push_str(text);
-}
-
-/// if ブロックテキストをステートメント単位のトークン列に分割する。
-///
-/// `;` と改行 (`\n`) を区切りとして使い、各トークンは 1 つのステートメント
-/// （キーワード付き or コマンド）に対応する。
-/// シングル/ダブルクォート内の `;` や改行は区切りとして扱わない。
-///
-/// 例: `"if true; then echo yes; fi"` → `["if true", "then echo yes", "fi"]`
-fn tokenize_block(block: &str) -> Vec<String> {
-    let mut tokens = Vec::new();
-    let mut current = String::new();
-    let bytes = block.as_bytes();
-    let len = bytes.len();
-    let mut i = 0;
 
-    while i < len {
-        match bytes[i] {
-            b'\'' => {
-                current.push('\'');
-                i += 1;
-                while i < len && bytes[i] != b'\'' {
-                    current.push(bytes[i] as char);
-                    i += 1;
-                }
-                if i < len {
-                    current.push('\'');
-                    i += 1;
-                }
-            }
-            b'"' => {
-                current.push('"');
-                i += 1;
-                while i < len && bytes[i] != b'"' {
-                    if bytes[i] == b'\\' && i + 1 < len {
-                        current.push(bytes[i] as char);
-                        i += 1;
-                        current.push(bytes[i] as char);
-                        i += 1;
-                    } else {
-                        current.push(bytes[i] as char);
-                        i += 1;
-                    }
-                }
-                if i < len {
-                    current.push('"');
-                    i += 1;
-                }
-            }
-            b';' if i + 1 < len && bytes[i + 1] == b';' => {
-                // `;;` は case 文のクロージャ区切り → 現在のトークンに付加して分割
-                current.push_str(";;");
-                let trimmed = current.trim().to_string();
-                if !trimmed.is_empty() {
-                    tokens.push(trimmed);
-                }
-                current.clear();
-                i += 2;
-            }
-            b'\n' | b';' => {
-                let trimmed = current.trim().to_string();
-                if !trimmed.is_empty() {
-                    tokens.push(trimmed);
-                }
-                current.clear();
-                i += 1;
-            }
-            _ => {
-                current.push(bytes[i] as char);
-                i += 1;
-            }
-        }
-    }
+    // 位置パラメータを保存
+    let saved_positional = shell.positional_args.clone();
 
-    let trimmed = current.trim().to_string();
-    if !trimmed.is_empty() {
-        tokens.push(trimmed);
+    // 新しい位置パラメータを設定
+    shell.positional_args = args.iter().map(|s| s.to_string()).collect();
+
+    // source_depth を上げて return を有効にする
+    shell.source_depth += 1;
+    shell.function_depth += 1;
+    shell.saved_set_options.push(None);
+    let status = run_command_string(shell, body);
+    shell.function_depth -= 1;
+    shell.source_depth -= 1;
+
+    // `local -` で退避した set オプションを復元
+    if let Some(Some((errexit, nounset, pipefail))) = shell.saved_set_options.pop() {
+        shell.set_errexit = errexit;
+        shell.set_nounset = nounset;
+        shell.set_pipefail = pipefail;
     }
 
-    tokens
+    // should_return をリセット（関数内の return はここで消化される）
+    shell.should_return = false;
+
+    // 位置パラメータを復元
+    shell.positional_args = saved_positional;
+
+    status
 }
 
 /// トークンの先頭がシェルキーワード (`if`, `then`, `elif`, `else`, `fi`) かを判定する。
@@ -2161,13 +2681,23 @@ fn extract_keyword(token: &str) -> Option<&'static str> {
 /// if ブロックの条件部分・本体部分を実行するために使用する。
 /// 複数行入力・ネストした if ブロックにも対応し、各行を
 /// [`parser::parse`] → [`execute`] で順次実行する。
+/// 呼び出し時の `$LINENO` を先頭行として行ごとに進め、終了時に元へ戻す。
 /// 最後に実行されたコマンドの終了ステータスを返す。
 pub fn run_command_string(shell: &mut Shell, input: &str) -> i32 {
+    let base = parser::lineno();
+    let status = run_lines(shell, input, base);
+    parser::set_lineno(base);
+    status
+}
+
+/// [`run_command_string`] の本体。`input` の各行を `$LINENO` = `base` + 行番号で実行する。
+fn run_lines(shell: &mut Shell, input: &str, base: usize) -> i32 {
     let lines: Vec<&str> = input.lines().collect();
     let mut last_status = 0;
     let mut i = 0;
 
     while i < lines.len() {
+        parser::set_lineno(base + i);
         let trimmed = lines[i].trim();
         if trimmed.is_empty() || trimmed.starts_with('#') {
             i += 1;
@@ -2177,7 +2707,7 @@ pub fn run_command_string(shell: &mut Shell, input: &str) -> i32 {
         // ネストした if ブロックを検出
         if starts_with_if(trimmed) {
             let (block, next_i) = collect_if_block(&lines, i);
-            last_status = execute_if_block(shell, &block);
+            last_status = execute_block(shell, &block);
             shell.last_status = last_status;
             i = next_i;
             if shell.should_return || shell.should_exit || shell.errexit_pending
@@ -2191,11 +2721,7 @@ pub fn run_command_string(shell: &mut Shell, input: &str) -> i32 {
         // ネストした for/while/until ブロックを検出
         if starts_with_for(trimmed) || starts_with_while(trimmed) || starts_with_until(trimmed) {
             let (block, next_i) = collect_loop_block(&lines, i);
-            if starts_with_for(trimmed) {
-                last_status = execute_for_block(shell, &block);
-            } else {
-                last_status = execute_while_block(shell, &block, starts_with_until(trimmed));
-            }
+            last_status = execute_block(shell, &block);
             shell.last_status = last_status;
             i = next_i;
             if shell.should_return || shell.should_exit || shell.errexit_pending
@@ -2209,7 +2735,7 @@ pub fn run_command_string(shell: &mut Shell, input: &str) -> i32 {
         // ネストした case ブロックを検出
         if starts_with_case(trimmed) {
             let (block, next_i) = collect_case_block(&lines, i);
-            last_status = execute_case_block(shell, &block);
+            last_status = execute_block(shell, &block);
             shell.last_status = last_status;
             i = next_i;
             if shell.should_return || shell.should_exit || shell.errexit_pending
@@ -2228,7 +2754,7 @@ pub fn run_command_string(shell: &mut Shell, input: &str) -> i32 {
             continue;
         }
 
-        match parser::parse(trimmed, shell.last_status, &shell.positional_args, shell.set_nounset, &shell.arrays) {
+        match parser::parse(trimmed, shell.last_status, &shell.shell_name, &shell.positional_args, shell.set_nounset, &shell.arrays) {
             Ok(Some(list)) => {
                 let cmd_text = trimmed.to_string();
                 last_status = execute(shell, &list, &cmd_text);
@@ -2618,90 +3144,121 @@ mod tests {
     }
 
     #[test]
-    fn parse_if_sections_basic() {
-        let sections = parse_if_sections("if true; then echo yes; fi").unwrap();
-        assert_eq!(sections.condition.trim(), "true");
-        assert_eq!(sections.then_body.trim(), "echo yes");
-        assert!(sections.elif_parts.is_empty());
-        assert!(sections.else_body.is_none());
+    fn execute_if_block_true() {
+        let mut shell = Shell::new();
+        let status = execute_block(&mut shell, "if true; then echo yes; fi");
+        assert_eq!(status, 0);
+    }
+
+    #[test]
+    fn execute_if_block_false_with_else() {
+        let mut shell = Shell::new();
+        let status = execute_block(&mut shell, "if false; then echo no; else true; fi");
+        assert_eq!(status, 0);
     }
 
     #[test]
-    fn parse_if_sections_else() {
-        let sections = parse_if_sections("if false; then echo no; else echo yes; fi").unwrap();
-        assert_eq!(sections.condition.trim(), "false");
-        assert_eq!(sections.then_body.trim(), "echo no");
-        assert!(sections.elif_parts.is_empty());
-        assert_eq!(sections.else_body.as_deref().unwrap().trim(), "echo yes");
+    fn execute_if_block_false_no_else() {
+        let mut shell = Shell::new();
+        let status = execute_block(&mut shell, "if false; then echo no; fi");
+        assert_eq!(status, 0); // no branch executed → 0
     }
 
     #[test]
-    fn parse_if_sections_elif() {
-        let sections = parse_if_sections(
-            "if false; then echo first; elif true; then echo second; else echo third; fi"
-        ).unwrap();
-        assert_eq!(sections.condition.trim(), "false");
-        assert_eq!(sections.then_body.trim(), "echo first");
-        assert_eq!(sections.elif_parts.len(), 1);
-        assert_eq!(sections.elif_parts[0].0.trim(), "true");
-        assert_eq!(sections.elif_parts[0].1.trim(), "echo second");
-        assert_eq!(sections.else_body.as_deref().unwrap().trim(), "echo third");
+    fn execute_if_block_elif() {
+        let mut shell = Shell::new();
+        let status = execute_block(&mut shell,
+            "if false; then false; elif true; then true; fi");
+        assert_eq!(status, 0);
     }
 
     #[test]
-    fn parse_if_sections_multiline() {
-        let block = "if true\nthen\necho hello\necho world\nfi";
-        let sections = parse_if_sections(block).unwrap();
-        assert_eq!(sections.condition.trim(), "true");
-        assert!(sections.then_body.contains("echo hello"));
-        assert!(sections.then_body.contains("echo world"));
+    fn if_condition_keeps_semicolons_in_substitutions_and_quotes() {
+        let mut shell = Shell::new();
+        let (out, _) = capture(&mut shell, "if [ \"$(echo a; echo b)\" = \"$(printf 'a\\nb')\" ]; then echo yes; else echo no; fi");
+        assert_eq!(out, "yes");
+        assert_eq!(capture(&mut shell, "if echo 'x; then'; then echo ok; fi").0, "x; then\nok");
+        assert_eq!(capture(&mut shell, "if [ \"a;fi\" = 'a;fi' ]; then echo same; fi").0, "same");
+        // 閉じ予約語の後のコマンドも実行する
+        assert_eq!(capture(&mut shell, "if true; then echo a; fi; echo b").0, "a\nb");
+        assert_eq!(capture(&mut shell, "true && if false; then echo no; elif true; then echo elif; fi").0, "elif");
+        // 同じ行の後続コマンドは continue で飛ばす
+        assert_eq!(capture(&mut shell, "for x in 1 2 3; do if [ $x = 2 ]; then continue; fi; echo $x; done").0, "1\n3");
+        assert_eq!(capture(&mut shell, "for x in a b; do echo $x; done | sort -r").0, "b\na");
+        assert_eq!(capture(&mut shell, "if true; then echo a; done").1, 2);
     }
 
     #[test]
-    fn execute_if_block_true() {
+    fn compound_commands_as_pipeline_stages() {
         let mut shell = Shell::new();
-        let status = execute_if_block(&mut shell, "if true; then echo yes; fi");
-        assert_eq!(status, 0);
+        // 複合コマンドの出力をパイプへ
+        assert_eq!(capture(&mut shell, "for x in b c a; do echo $x; done | sort").0, "a\nb\nc");
+        assert_eq!(capture(&mut shell, "case a in a) echo q; echo p;; esac | sort").0, "p\nq");
+        assert_eq!(capture(&mut shell, "if true; then echo b; echo a; fi | sort | tr a-z A-Z").0, "A\nB");
+        // パイプから複合コマンドへ（本体はサブシェルなので代入は残らない）
+        assert_eq!(
+            capture(&mut shell, "n=0\nprintf '1\\n2\\n' | while read x; do n=$x; echo got $x; done\necho n=$n").0,
+            "got 1\ngot 2\nn=0"
+        );
+        assert_eq!(capture(&mut shell, "echo zz | case z in z) cat;; esac").0, "zz");
+        assert_eq!(capture(&mut shell, "echo hi | if read v; then echo v=$v; false; fi").1, 1);
     }
 
     #[test]
-    fn execute_if_block_false_with_else() {
+    fn command_not_found_returns_127() {
         let mut shell = Shell::new();
-        let status = execute_if_block(&mut shell, "if false; then echo no; else true; fi");
-        assert_eq!(status, 0);
+        assert_eq!(capture(&mut shell, "rush_no_such_cmd_xyz\necho $?").0, "127");
+        assert_eq!(capture(&mut shell, "command rush_no_such_cmd_xyz").1, 127);
     }
 
     #[test]
-    fn execute_if_block_false_no_else() {
+    fn prefix_path_assignment_resolves_command() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = std::env::temp_dir().join(format!("rush_prefix_path_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let probe = dir.join("rush_path_probe");
+        std::fs::write(&probe, "#!/bin/sh\necho found\n").unwrap();
+        std::fs::set_permissions(&probe, std::fs::Permissions::from_mode(0o755)).unwrap();
         let mut shell = Shell::new();
-        let status = execute_if_block(&mut shell, "if false; then echo no; fi");
-        assert_eq!(status, 0); // no branch executed → 0
+        let cmd = format!("PATH={}:$PATH rush_path_probe", dir.display());
+        assert_eq!(capture(&mut shell, &cmd), ("found".to_string(), 0));
+        // シェル自身の PATH には影響しない
+        assert_eq!(capture(&mut shell, "rush_path_probe").1, 127);
+        let _ = std::fs::remove_dir_all(&dir);
     }
 
     #[test]
-    fn execute_if_block_elif() {
+    fn command_not_found_handle_receives_argv() {
         let mut shell = Shell::new();
-        let status = execute_if_block(&mut shell,
-            "if false; then false; elif true; then true; fi");
-        assert_eq!(status, 0);
+        let script = "command_not_found_handle() { echo \"missing: $1 ($#)\"; rush_inner_missing; return 42; }\n\
+                      rush_no_such_cmd_xyz a b\necho $?";
+        assert_eq!(capture(&mut shell, script).0, "missing: rush_no_such_cmd_xyz (3)\n42");
+        // パス付きのコマンドにはハンドラを使わない
+        assert_eq!(capture(&mut shell, "./rush_no_such_cmd_xyz").1, 127);
     }
 
     #[test]
-    fn tokenize_block_basic() {
-        let tokens = tokenize_block("if true; then echo yes; fi");
-        assert_eq!(tokens, vec!["if true", "then echo yes", "fi"]);
+    fn bracket_test_with_escaped_parens() {
+        let mut shell = Shell::new();
+        assert_eq!(capture(&mut shell, "[ \\( 1 -eq 1 -o 2 -eq 3 \\) -a -f Cargo.toml ]").1, 0);
+        assert_eq!(capture(&mut shell, "[ \\( 1 -eq 2 -o 2 -eq 3 \\) -a -f Cargo.toml ]").1, 1);
+        assert_eq!(capture(&mut shell, "if [ ! \\( -d Cargo.toml \\) ]; then echo ok; fi").0, "ok");
     }
 
     #[test]
-    fn tokenize_block_multiline() {
-        let tokens = tokenize_block("if true\nthen\necho yes\nfi");
-        assert_eq!(tokens, vec!["if true", "then", "echo yes", "fi"]);
+    fn if_empty_bodies_are_noops() {
+        let mut shell = Shell::new();
+        assert_eq!(capture(&mut shell, "false\nif true; then; fi\necho $?"), ("0".to_string(), 0));
+        assert_eq!(capture(&mut shell, "if false; then echo no; else; fi\necho $?"), ("0".to_string(), 0));
+        assert_eq!(capture(&mut shell, "if true; then\nfi\necho done"), ("done".to_string(), 0));
     }
 
     #[test]
-    fn tokenize_block_quoted_semicolons() {
-        let tokens = tokenize_block("echo 'hello;world'; echo done");
-        assert_eq!(tokens, vec!["echo 'hello;world'", "echo done"]);
+    fn if_malformed_elif_is_syntax_error() {
+        let mut shell = Shell::new();
+        assert_eq!(run_command_string(&mut shell, "if false; then echo no; elif true; fi"), 2);
+        assert_eq!(run_command_string(&mut shell, "if false; then :; elif; then :; fi"), 2);
+        assert_eq!(capture(&mut shell, "if false; then echo no; elif true; fi\necho next"), ("next".to_string(), 0));
     }
 
     #[test]
@@ -2787,7 +3344,7 @@ mod tests {
     #[test]
     fn execute_for_block_basic() {
         let mut shell = Shell::new();
-        let status = execute_for_block(&mut shell, "for x in a b c; do true; done");
+        let status = execute_block(&mut shell, "for x in a b c; do true; done");
         assert_eq!(status, 0);
         // x should be set to the last value
         assert_eq!(std::env::var("x").unwrap_or_default(), "c");
@@ -2796,16 +3353,57 @@ mod tests {
     #[test]
     fn execute_for_block_empty_list() {
         let mut shell = Shell::new();
-        let status = execute_for_block(&mut shell, "for x in; do echo $x; done");
+        let status = execute_block(&mut shell, "for x in; do echo $x; done");
         assert_eq!(status, 0); // no iterations
     }
 
+    #[test]
+    fn for_quoted_words_are_single_items() {
+        let mut shell = Shell::new();
+        let (out, _) = capture(&mut shell, "for x in \"a b\" c 'd  e' '$HOME'; do echo \"[$x]\"; done");
+        assert_eq!(out, "[a b]\n[c]\n[d  e]\n[$HOME]");
+        shell.positional_args = vec!["1 2".to_string(), "3".to_string()];
+        assert_eq!(capture(&mut shell, "for x in \"$@\"; do echo \"[$x]\"; done").0, "[1 2]\n[3]");
+    }
+
+    #[test]
+    fn for_words_get_brace_expansion() {
+        let mut shell = Shell::new();
+        assert_eq!(capture(&mut shell, "for i in {1..3}; do echo $i; done").0, "1\n2\n3");
+        assert_eq!(capture(&mut shell, "for i in x{a,b} {3..1}; do echo $i; done").0, "xa\nxb\n3\n2\n1");
+    }
+
+    #[test]
+    fn for_words_get_glob_expansion() {
+        let dir = std::env::temp_dir().join(format!("rush_for_glob_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["b.rs", "a.rs", "c.txt"] {
+            std::fs::write(dir.join(name), "").unwrap();
+        }
+        let mut shell = Shell::new();
+        // cd は fork した子プロセス内でのみ効く
+        let script = format!("cd {}\nfor f in *.rs; do echo $f; done", dir.display());
+        assert_eq!(capture(&mut shell, &script).0, "a.rs\nb.rs");
+        let script = format!("for f in {}/*.txt; do echo $f; done", dir.display());
+        assert_eq!(capture(&mut shell, &script).0, dir.join("c.txt").display().to_string());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn for_header_in_on_separate_line() {
+        let mut shell = Shell::new();
+        let (out, _) = capture(&mut shell, "for x\nin a \"b c\"\ndo\necho \"[$x]\"\ndone");
+        assert_eq!(out, "[a]\n[b c]");
+        let (out, _) = capture(&mut shell, "for x\nin 1 2; do echo $x; done");
+        assert_eq!(out, "1\n2");
+    }
+
     #[test]
     fn execute_while_block_basic() {
         let mut shell = Shell::new();
         std::env::set_var("RUSH_WHILE_TEST", "3");
         let block = "while [ $RUSH_WHILE_TEST -gt 0 ]; do\nexport RUSH_WHILE_TEST=$(( RUSH_WHILE_TEST - 1 ))\ndone";
-        let status = execute_while_block(&mut shell, block, false);
+        let status = execute_block(&mut shell, block);
         assert_eq!(status, 0);
         assert_eq!(std::env::var("RUSH_WHILE_TEST").unwrap(), "0");
         std::env::remove_var("RUSH_WHILE_TEST");
@@ -2816,7 +3414,7 @@ mod tests {
         let mut shell = Shell::new();
         std::env::set_var("RUSH_UNTIL_TEST", "0");
         let block = "until [ $RUSH_UNTIL_TEST -eq 3 ]; do\nexport RUSH_UNTIL_TEST=$(( RUSH_UNTIL_TEST + 1 ))\ndone";
-        let status = execute_while_block(&mut shell, block, true);
+        let status = execute_block(&mut shell, block);
         assert_eq!(status, 0);
         assert_eq!(std::env::var("RUSH_UNTIL_TEST").unwrap(), "3");
         std::env::remove_var("RUSH_UNTIL_TEST");
@@ -2827,11 +3425,37 @@ mod tests {
         let mut shell = Shell::new();
         // break after first iteration
         let block = "for x in a b c; do\nbreak\ndone";
-        let status = execute_for_block(&mut shell, block);
+        let status = execute_block(&mut shell, block);
         assert_eq!(status, 0);
         assert_eq!(std::env::var("x").unwrap_or_default(), "a");
     }
 
+    #[test]
+    fn nested_for_break_2_leaves_both_loops() {
+        let mut shell = Shell::new();
+        let script = "for i in 1 2 3; do\nfor j in a b c; do\nif [ $j = b ]; then break 2; fi\necho $i$j\ndone\necho end$i\ndone\necho out";
+        assert_eq!(capture(&mut shell, script), ("1a\nout".to_string(), 0));
+    }
+
+    #[test]
+    fn nested_for_continue_2_resumes_outer_loop() {
+        let mut shell = Shell::new();
+        let script = "for i in 1 2 3; do\nfor j in a b c; do\nif [ $j = b ]; then continue 2; fi\necho $i$j\ndone\necho end$i\ndone\necho out";
+        assert_eq!(capture(&mut shell, script), ("1a\n2a\n3a\nout".to_string(), 0));
+        // 1 行に書いた場合も同じ
+        let one_line = "for i in 1 2; do for j in a b; do for k in x y; do [ $k = y ] && continue 3; echo $i$j$k; done; done; done";
+        assert_eq!(capture(&mut shell, one_line).0, "1ax\n2ax");
+    }
+
+    #[test]
+    fn break_level_beyond_nesting_is_clamped() {
+        let mut shell = Shell::new();
+        let script = "for i in 1 2; do\nfor j in a b; do\nbreak 5\ndone\necho no\ndone\necho after";
+        assert_eq!(capture(&mut shell, script), ("after".to_string(), 0));
+        let script = "for i in 1 2; do\nfor j in a b; do\ncontinue 9\ndone\necho no\ndone\necho after";
+        assert_eq!(capture(&mut shell, script), ("after".to_string(), 0));
+    }
+
     // ── case/esac テスト ──────────────────────────────────────────────
 
     #[test]
@@ -2855,7 +3479,7 @@ mod tests {
         let mut shell = Shell::new();
         std::env::set_var("RUSH_CASE_TEST", "hello");
         let block = "case $RUSH_CASE_TEST in\nhello) true ;;\n*) false ;;\nesac";
-        let status = execute_case_block(&mut shell, block);
+        let status = execute_block(&mut shell, block);
         assert_eq!(status, 0);
         std::env::remove_var("RUSH_CASE_TEST");
     }
@@ -2865,7 +3489,7 @@ mod tests {
         let mut shell = Shell::new();
         std::env::set_var("RUSH_CASE_TEST2", "xyz");
         let block = "case $RUSH_CASE_TEST2 in\nhello) true ;;\n*) false ;;\nesac";
-        let status = execute_case_block(&mut shell, block);
+        let status = execute_block(&mut shell, block);
         assert_eq!(status, 1);
         std::env::remove_var("RUSH_CASE_TEST2");
     }
@@ -2875,7 +3499,7 @@ mod tests {
         let mut shell = Shell::new();
         std::env::set_var("RUSH_CASE_TEST3", "yes");
         let block = "case $RUSH_CASE_TEST3 in\nyes|true|1) true ;;\n*) false ;;\nesac";
-        let status = execute_case_block(&mut shell, block);
+        let status = execute_block(&mut shell, block);
         assert_eq!(status, 0);
         std::env::remove_var("RUSH_CASE_TEST3");
     }
@@ -2885,7 +3509,7 @@ mod tests {
         let mut shell = Shell::new();
         std::env::set_var("RUSH_CASE_TEST4", "file.rs");
         let block = "case $RUSH_CASE_TEST4 in\n*.rs) true ;;\n*.txt) false ;;\nesac";
-        let status = execute_case_block(&mut shell, block);
+        let status = execute_block(&mut shell, block);
         assert_eq!(status, 0);
         std::env::remove_var("RUSH_CASE_TEST4");
     }
@@ -2895,23 +3519,78 @@ mod tests {
         let mut shell = Shell::new();
         std::env::set_var("RUSH_CASE_TEST5", "none");
         let block = "case $RUSH_CASE_TEST5 in\nhello) true ;;\nworld) false ;;\nesac";
-        let status = execute_case_block(&mut shell, block);
+        let status = execute_block(&mut shell, block);
         assert_eq!(status, 0); // no match → 0
         std::env::remove_var("RUSH_CASE_TEST5");
     }
 
-    #[test]
-    fn tokenize_block_preserves_double_semicolon() {
-        let tokens = tokenize_block("echo a ;; echo b");
-        assert_eq!(tokens, vec!["echo a ;;", "echo b"]);
-    }
-
     #[test]
     fn split_case_segments_basic() {
         let segs = split_case_segments("a) echo hello ;; b) echo world ;;");
         assert_eq!(segs.len(), 3); // before first ;;, between, after last ;;
     }
 
+    #[test]
+    fn split_case_patterns_top_level_only() {
+        assert_eq!(split_case_patterns("a*|x*"), vec!["a*", "x*"]);
+        assert_eq!(split_case_patterns("[|]x|y"), vec!["[|]x", "y"]);
+        assert_eq!(split_case_patterns("'a|b'|c"), vec!["'a|b'", "c"]);
+        assert_eq!(find_case_pattern_end("[)]*) echo"), Some(4));
+    }
+
+    #[test]
+    fn execute_case_block_alternation_and_class() {
+        let mut shell = Shell::new();
+        let block = "case abc in\nx*|a*) true ;;\n*) false ;;\nesac";
+        assert_eq!(execute_block(&mut shell, block), 0);
+        let block = "case 42abc in\n[a-z]*) false ;;\n[0-9]*) true ;;\n*) false ;;\nesac";
+        assert_eq!(execute_block(&mut shell, block), 0);
+        let block = "case z in\n[!a-y]) true ;;\n*) false ;;\nesac";
+        assert_eq!(execute_block(&mut shell, block), 0);
+    }
+
+    #[test]
+    fn execute_case_block_quoted_patterns() {
+        let mut shell = Shell::new();
+        let block = "case a in\n\"a\"|b) true ;;\n*) false ;;\nesac";
+        assert_eq!(execute_block(&mut shell, block), 0);
+        let block = "case b in\na|'b') true ;;\n*) false ;;\nesac";
+        assert_eq!(execute_block(&mut shell, block), 0);
+        // クォートしたグロブ文字はリテラル、クォート外はパターン
+        let block = "case ab in\n\"a*\") false ;;\n\"a\"*) true ;;\n*) false ;;\nesac";
+        assert_eq!(execute_block(&mut shell, block), 0);
+        let block = "case 'a*' in\na\\*) true ;;\n*) false ;;\nesac";
+        assert_eq!(execute_block(&mut shell, block), 0);
+    }
+
+    #[test]
+    fn split_case_segments_terminators() {
+        let segs = split_case_segments("a) x ;& b) y ;;& c) z ;;");
+        let terms: Vec<_> = segs.iter().map(|(_, t)| *t).collect();
+        assert_eq!(terms, vec![Some(CaseTerm::FallThrough), Some(CaseTerm::Continue), Some(CaseTerm::Break), None]);
+        assert_eq!(segs[1].0.trim(), "b) y");
+    }
+
+    #[test]
+    fn execute_case_block_fall_through() {
+        let mut shell = Shell::new();
+        std::env::remove_var("RUSH_CASE_FT");
+        let block = "case a in\na) export RUSH_CASE_FT=a ;&\nb) export RUSH_CASE_FT=${RUSH_CASE_FT}b ;;\nc) export RUSH_CASE_FT=c ;;\nesac";
+        execute_block(&mut shell, block);
+        assert_eq!(std::env::var("RUSH_CASE_FT").unwrap(), "ab");
+        std::env::remove_var("RUSH_CASE_FT");
+    }
+
+    #[test]
+    fn execute_case_block_continue_testing() {
+        let mut shell = Shell::new();
+        std::env::remove_var("RUSH_CASE_CT");
+        let block = "case abc in\na*) export RUSH_CASE_CT=1 ;;&\nx*) export RUSH_CASE_CT=bad ;;\n*c) export RUSH_CASE_CT=${RUSH_CASE_CT}2 ;;\n*) export RUSH_CASE_CT=bad ;;\nesac";
+        execute_block(&mut shell, block);
+        assert_eq!(std::env::var("RUSH_CASE_CT").unwrap(), "12");
+        std::env::remove_var("RUSH_CASE_CT");
+    }
+
     // ── 関数定義・実行テスト ──────────────────────────────────────────
 
     #[test]
@@ -2938,6 +3617,18 @@ mod tests {
         assert!(parse_function_def("for() { echo test; }").is_none());
     }
 
+    #[test]
+    fn parse_function_def_keyword_form_and_header() {
+        assert_eq!(parse_function_def("function greet { echo hi; }").unwrap().0, "greet");
+        assert_eq!(parse_function_def("function greet() {").unwrap(), ("greet".to_string(), String::new()));
+        assert!(is_function_header("greet()"));
+        assert!(is_function_header("function greet"));
+        assert!(is_function_header("function greet ()"));
+        assert!(!is_function_header("greet() {"));
+        assert!(!is_function_header("function"));
+        assert!(!is_function_header("echo hi"));
+    }
+
     #[test]
     fn parse_function_def_not_func() {
         assert!(parse_function_def("echo hello").is_none());
@@ -2948,7 +3639,7 @@ mod tests {
     fn execute_function_basic() {
         let mut shell = Shell::new();
         shell.functions.insert("myfn".to_string(), "export RUSH_FN_TEST=hello".to_string());
-        let status = execute_function(&mut shell, "export RUSH_FN_TEST=hello", &[]);
+        let status = execute_function(&mut shell, "f", "export RUSH_FN_TEST=hello", &[]);
         assert_eq!(status, 0);
         assert_eq!(std::env::var("RUSH_FN_TEST").unwrap(), "hello");
         std::env::remove_var("RUSH_FN_TEST");
@@ -2958,7 +3649,7 @@ mod tests {
     fn execute_function_with_args() {
         let mut shell = Shell::new();
         let body = "export RUSH_FN_ARG=$1";
-        let status = execute_function(&mut shell, body, &["world"]);
+        let status = execute_function(&mut shell, "f", body, &["world"]);
         assert_eq!(status, 0);
         assert_eq!(std::env::var("RUSH_FN_ARG").unwrap(), "world");
         std::env::remove_var("RUSH_FN_ARG");
@@ -2968,7 +3659,7 @@ mod tests {
     fn execute_function_return() {
         let mut shell = Shell::new();
         let body = "return 42";
-        let status = execute_function(&mut shell, body, &[]);
+        let status = execute_function(&mut shell, "f", body, &[]);
         assert_eq!(status, 42);
         assert!(!shell.should_return); // should_return is consumed
     }
@@ -2979,7 +3670,7 @@ mod tests {
         shell.positional_args = vec!["outer1".to_string()];
 
         let body = "export RUSH_FN_POS=$1";
-        execute_function(&mut shell, body, &["inner1"]);
+        execute_function(&mut shell, "f", body, &["inner1"]);
 
         // After function, positional args should be restored
         assert_eq!(shell.positional_args, vec!["outer1".to_string()]);
@@ -3018,7 +3709,7 @@ mod tests {
         let mut shell = Shell::new();
         shell.set_errexit = true;
         // `false` は終了ステータス 1 を返す → errexit 発動
-        let list = crate::parser::parse("false", 0, &[], false, &std::collections::HashMap::new()).unwrap().unwrap();
+        let list = crate::parser::parse("false", 0, "rush", &[], false, &std::collections::HashMap::new()).unwrap().unwrap();
         let status = execute(&mut shell, &list, "false");
         assert_eq!(status, 1);
         assert!(shell.errexit_pending);
@@ -3029,7 +3720,7 @@ mod tests {
         let mut shell = Shell::new();
         shell.set_errexit = true;
         // `false && true` — && チェーン内では errexit 免除
-        let list = crate::parser::parse("false && true", 0, &[], false, &std::collections::HashMap::new()).unwrap().unwrap();
+        let list = crate::parser::parse("false && true", 0, "rush", &[], false, &std::collections::HashMap::new()).unwrap().unwrap();
         let status = execute(&mut shell, &list, "false && true");
         assert_eq!(status, 1);
         assert!(!shell.errexit_pending);
@@ -3040,7 +3731,7 @@ mod tests {
         let mut shell = Shell::new();
         shell.set_errexit = true;
         // `false || true` — || チェーン内では errexit 免除
-        let list = crate::parser::parse("false || true", 0, &[], false, &std::collections::HashMap::new()).unwrap().unwrap();
+        let list = crate::parser::parse("false || true", 0, "rush", &[], false, &std::collections::HashMap::new()).unwrap().unwrap();
         let status = execute(&mut shell, &list, "false || true");
         assert_eq!(status, 0);
         assert!(!shell.errexit_pending);
@@ -3052,7 +3743,7 @@ mod tests {
         shell.set_errexit = true;
         // if 条件内の false は errexit 免除
         let block = "if false; then\necho yes\nelse\necho no\nfi";
-        let status = execute_if_block(&mut shell, block);
+        let status = execute_block(&mut shell, block);
         assert!(!shell.errexit_pending);
         // else 分岐が実行され、echo no のステータス 0
         assert_eq!(status, 0);
@@ -3064,7 +3755,7 @@ mod tests {
         shell.set_errexit = true;
         // while 条件内の false は errexit 免除、ループに入らない
         let block = "while false; do\necho body\ndone";
-        let status = execute_while_block(&mut shell, block, false);
+        let status = execute_block(&mut shell, block);
         assert!(!shell.errexit_pending);
         assert_eq!(status, 0);
     }
@@ -3077,7 +3768,7 @@ mod tests {
         let mut shell = Shell::new();
         std::env::remove_var("RUSH_SUBSHELL_ENVTEST");
         let input = "(export RUSH_SUBSHELL_ENVTEST=hello)";
-        match parser::parse(input, 0, &[], false, &std::collections::HashMap::new()) {
+        match parser::parse(input, 0, "rush", &[], false, &std::collections::HashMap::new()) {
             Ok(Some(list)) => { execute(&mut shell, &list, input); }
             _ => panic!("parse failed"),
         }
@@ -3088,7 +3779,7 @@ mod tests {
     fn subshell_exit_status_false() {
         let mut shell = Shell::new();
         let input = "(false)";
-        match parser::parse(input, 0, &[], false, &std::collections::HashMap::new()) {
+        match parser::parse(input, 0, "rush", &[], false, &std::collections::HashMap::new()) {
             Ok(Some(list)) => {
                 let status = execute(&mut shell, &list, input);
                 assert_ne!(status, 0);
@@ -3101,7 +3792,7 @@ mod tests {
     fn subshell_last_command_status() {
         let mut shell = Shell::new();
         let input = "(false; true)";
-        match parser::parse(input, 0, &[], false, &std::collections::HashMap::new()) {
+        match parser::parse(input, 0, "rush", &[], false, &std::collections::HashMap::new()) {
             Ok(Some(list)) => {
                 let status = execute(&mut shell, &list, input);
                 assert_eq!(status, 0);
@@ -3244,4 +3935,502 @@ mod tests {
         let status = run_command_string(&mut shell, "echo hello | tee >(cat > /dev/null)");
         assert_eq!(status, 0);
     }
+
+    // ── ENOEXEC フォールバック ──
+
+    /// 実行ビット付きの一時ファイルを作成してパスを返す。
+    fn write_exec_file(tag: &str, content: &[u8]) -> String {
+        use std::os::unix::fs::PermissionsExt;
+        let path = std::env::temp_dir().join(format!("rush_noexec_{}_{}", tag, std::process::id()));
+        std::fs::write(&path, content).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn shebangless_script_runs_as_rush_script() {
+        let path = write_exec_file("script", b"true\nexit $1\n");
+        let mut shell = Shell::new();
+        let status = run_command_string(&mut shell, &format!("{} 5", path));
+        assert_eq!(status, 5);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn shebangless_script_does_not_see_unexported_variables() {
+        let path = write_exec_file("unexported", b"echo \"[$RUSH_NOSHEBANG_NV]\"\n");
+        let mut shell = Shell::new();
+        let out = capture(&mut shell, &format!(": ${{RUSH_NOSHEBANG_NV:=1}}\n{0}\nRUSH_NOSHEBANG_NV=2 {0}", path)).0;
+        std::fs::remove_file(&path).unwrap();
+        // 前置代入はそのコマンドに export される
+        assert_eq!(out, "[]\n[2]");
+    }
+
+    #[test]
+    fn shebangless_binary_is_rejected() {
+        let path = write_exec_file("binary", b"\x7fXYZ\0\0\0garbage");
+        let mut shell = Shell::new();
+        let status = run_command_string(&mut shell, &path);
+        assert_eq!(status, 126);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    // ── capture ──
+
+    #[test]
+    fn unquoted_variable_is_split_into_args() {
+        let mut shell = Shell::new();
+        std::env::set_var("RUSH_EXEC_SPLIT", "a b  c");
+        run_command_string(&mut shell, "rush_count() { echo $#; }");
+        assert_eq!(capture(&mut shell, "rush_count $RUSH_EXEC_SPLIT").0, "3");
+        assert_eq!(capture(&mut shell, "rush_count \"$RUSH_EXEC_SPLIT\"").0, "1");
+        // 未定義変数の非クォート展開は引数ごと消える
+        assert_eq!(capture(&mut shell, "rush_count $RUSH_EXEC_SPLIT_UNDEF x").0, "1");
+        std::env::remove_var("RUSH_EXEC_SPLIT");
+    }
+
+    #[test]
+    fn non_whitespace_ifs_keeps_empty_fields() {
+        // IFS を変えるため fork した子プロセス内で実行する
+        let (out, _) = run_forked(1, || {
+            let mut shell = Shell::new();
+            run_command_string(&mut shell, "IFS=:\nx=a::b\nprintf '[%s]' $x :c\nx=:a:\nprintf '[%s]' $x");
+            0
+        });
+        assert_eq!(out, "[a][][b][:c][][a]");
+    }
+
+    #[test]
+    fn format_time_report_default_layout() {
+        let report = format_time_report(DEFAULT_TIMEFORMAT, 61.5, 0.25, 0.0);
+        assert_eq!(report, "\nreal\t1m1.500s\nuser\t0m0.250s\nsys\t0m0.000s");
+        assert_eq!(format_time_report("%R %1U %0lS %% %P", 2.0, 0.5, 0.5), "2.000 0.5 0m0s % 50.00");
+    }
+
+    #[test]
+    fn time_true_reports_to_stderr() {
+        // stderr を差し替えるため fork した子プロセス内で実行する
+        let (stderr, status) = run_forked(2, || {
+            std::env::remove_var("TIMEFORMAT");
+            let mut shell = Shell::new();
+            let status = run_command_string(&mut shell, "time true");
+            let status2 = run_command_string(&mut shell, "time false");
+            if status == 0 && status2 == 1 { 0 } else { 1 }
+        });
+        assert_eq!(status, 0);
+        let lines: Vec<&str> = stderr.lines().filter(|l| !l.is_empty()).collect();
+        assert_eq!(lines.len(), 6, "stderr: {:?}", stderr);
+        assert!(lines[0].starts_with("real\t0m"));
+        assert!(lines[1].starts_with("user\t"));
+        assert!(lines[2].starts_with("sys\t"));
+    }
+
+    #[test]
+    fn builtin_honors_stderr_and_fd_dup_redirects() {
+        let dir = std::env::temp_dir().join(format!("rush_builtin_fd_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let err_file = dir.join("err");
+        let script = format!(
+            "echo hi 2>{0} 1>&2; printf 'x%s\\n' y 2>>{0} >&2; pwd 2>/dev/null; echo after",
+            err_file.display()
+        );
+        // シェル自身の fd 1 を差し替えるため fork した子プロセス内で実行する
+        let (stdout, status) = run_forked(1, || run_command_string(&mut Shell::new(), &script));
+        assert_eq!(status, 0);
+        // pwd の出力は stdout に残り、リダイレクト後は fd が元に戻っている
+        let lines: Vec<&str> = stdout.lines().collect();
+        assert_eq!(lines.len(), 2, "stdout: {:?}", stdout);
+        assert_eq!(lines[1], "after");
+        assert_eq!(std::fs::read_to_string(&err_file).unwrap(), "hi\nxy\n");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn compound_redirect_applies_to_whole_block() {
+        let dir = std::env::temp_dir().join(format!("rush_compound_redir_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let out = dir.join("out");
+        let script = format!(
+            "for x in a b; do y=$x; echo $x; done > {0}\n\
+             case $y in b) echo matched;; esac >> {0}\n\
+             if true; then echo $y; fi 2>/dev/null >> {0}",
+            out.display()
+        );
+        // シェル自身の fd 1 を差し替えるため fork した子プロセス内で実行する
+        assert_eq!(run_forked(1, || run_command_string(&mut Shell::new(), &script)).1, 0);
+        // ループ内の代入は現在のシェルに残る
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), "a\nb\nmatched\nb\n");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn input_redirected_loop_keeps_assignments() {
+        let dir = std::env::temp_dir().join(format!("rush_compound_input_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in");
+        std::fs::write(&input, "a\nb\nc\n").unwrap();
+        let script = format!(
+            "n=0\nwhile read l; do n=$((n+1)); last=$l; done < {}\n\
+             echo \"$n $last\"\n\
+             while read w; do word=$w; done <<< here\n\
+             echo $word\n\
+             read outer\n\
+             echo $outer",
+            input.display()
+        );
+        // シェル自身の stdin を差し替えるため fork した子プロセス内で実行する
+        let (stdout, status) = run_forked(1, || {
+            let mut fds = [0i32; 2];
+            unsafe {
+                libc::pipe(fds.as_mut_ptr());
+                libc::write(fds[1], b"outer\n".as_ptr() as *const libc::c_void, 6);
+                libc::close(fds[1]);
+                libc::dup2(fds[0], 0);
+                libc::close(fds[0]);
+            }
+            run_command_string(&mut Shell::new(), &script)
+        });
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(status, 0);
+        // ループ内の代入が残り、ループ後の stdin は元に戻っている
+        assert_eq!(stdout, "3 c\nhere\nouter\n");
+    }
+
+    #[test]
+    fn large_here_string_writers_are_reaped() {
+        // 子プロセスが残っていないことを waitpid(-1) で確かめるため fork した子プロセス内で実行する
+        let (_, status) = run_forked(1, || {
+            std::env::set_var("RUSH_LARGE_HERE", "x".repeat(64 * 1024));
+            let mut shell = Shell::new();
+            let read_all = run_command_string(&mut shell, "cat <<< \"$RUSH_LARGE_HERE\" > /dev/null");
+            let left = unsafe { libc::waitpid(-1, std::ptr::null_mut(), libc::WNOHANG) };
+            // 読まずに終わるコマンドでも書き込み側は SIGPIPE で終わって回収される
+            let unread = run_command_string(&mut shell, "true <<< \"$RUSH_LARGE_HERE\"");
+            let left_unread = unsafe { libc::waitpid(-1, std::ptr::null_mut(), libc::WNOHANG) };
+            if read_all == 0 && unread == 0 && left == -1 && left_unread == -1 { 0 } else { 1 }
+        });
+        assert_eq!(status, 0);
+    }
+
+    #[test]
+    fn exec_without_command_redirects_shell_fds() {
+        let dir = std::env::temp_dir().join(format!("rush_exec_redir_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let out = dir.join("out");
+        let log = dir.join("log");
+        let script = format!(
+            "exec 3>{}\nexec > {}\necho hi\necho to-log >&3\nsh -c 'echo ext'",
+            log.display(),
+            out.display()
+        );
+        // シェル自身の fd を恒久的に差し替えるため fork した子プロセス内で実行する
+        assert_eq!(run_forked(1, || run_command_string(&mut Shell::new(), &script)).1, 0);
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), "hi\next\n");
+        assert_eq!(std::fs::read_to_string(&log).unwrap(), "to-log\n");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn exec_fd_opened_at_its_own_number_reaches_external_commands() {
+        let log = std::env::temp_dir().join(format!("rush_exec_fd3_{}", std::process::id()));
+        let script = format!("exec 3>{}\nsh -c 'echo ext >&3'", log.display());
+        let (_, status) = run_forked(1, || {
+            // fd 3 を空けておき、open がちょうど 3 を返す場合を確かめる
+            unsafe { libc::close(3); }
+            run_command_string(&mut Shell::new(), &script)
+        });
+        let written = std::fs::read_to_string(&log).unwrap_or_default();
+        let _ = std::fs::remove_file(&log);
+        assert_eq!(status, 0);
+        assert_eq!(written, "ext\n");
+    }
+
+    #[test]
+    fn builtin_in_pipeline_runs_without_external_binary() {
+        // PATH を grep だけのディレクトリに差し替えるため fork した子プロセス内で実行する
+        let grep = match std::env::var("PATH").unwrap_or_default().split(':')
+            .map(|d| std::path::Path::new(d).join("grep"))
+            .find(|p| p.is_file())
+        {
+            Some(p) => p,
+            None => return,
+        };
+        let dir = std::env::temp_dir().join(format!("rush_pipe_builtin_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let _ = std::fs::remove_file(dir.join("grep"));
+        std::os::unix::fs::symlink(&grep, dir.join("grep")).unwrap();
+
+        let (stdout, status) = run_forked(1, || {
+            std::env::set_var("PATH", &dir);
+            run_command_string(&mut Shell::new(), "printf 'a\\nb\\n' | grep a")
+        });
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(status, 0);
+        assert_eq!(stdout, "a\n");
+    }
+
+    #[test]
+    fn shopt_nullglob_and_failglob_change_unmatched_words() {
+        let mut shell = Shell::new();
+        let words = [std::borrow::Cow::Borrowed("/nonexistent_rush_dir/*"), std::borrow::Cow::Borrowed("x")];
+        assert_eq!(expand_args_full(&words, &mut shell), vec!["/nonexistent_rush_dir/*", "x"]);
+        shell.shopt.nullglob = true;
+        assert_eq!(expand_args_full(&words, &mut shell), vec!["x"]);
+        shell.shopt.nullglob = false;
+
+        // failglob: コマンドは実行されずに 1 で失敗する
+        shell.shopt.failglob = true;
+        let status = run_command_string(&mut shell, "export RUSH_FAILGLOB_RAN=/nonexistent_rush_dir/*");
+        assert_eq!(status, 1);
+        assert!(std::env::var("RUSH_FAILGLOB_RAN").is_err());
+        assert!(!shell.glob_failed);
+    }
+
+    #[test]
+    fn functions_run_in_pipelines_and_with_fd_dups() {
+        // 外部コマンドとして spawn されないことを確かめるため PATH を空にした子プロセス内で実行する
+        let (stdout, status) = run_forked(1, || {
+            std::env::set_var("PATH", "/nonexistent");
+            run_command_string(
+                &mut Shell::new(),
+                "f() { echo $1; return 3; }\nf hi\nf dup 2>&1\necho \"dup $?\"\nf piped | read v\necho \"got $?\"",
+            )
+        });
+        assert_eq!(stdout, "hi\ndup\ndup 3\ngot 0\n");
+        assert_eq!(status, 0);
+    }
+
+    #[test]
+    fn here_string_expands_unless_single_quoted() {
+        let mut shell = Shell::new();
+        let home = std::env::var("HOME").unwrap_or_default();
+        assert_eq!(capture(&mut shell, "cat <<<\"$HOME\"").0, home);
+        assert_eq!(capture(&mut shell, "cat <<<'$HOME'").0, "$HOME");
+        assert_eq!(capture(&mut shell, "cat <<<$(echo hi)").0, "hi");
+        assert_eq!(capture(&mut shell, "cat <<<\"$(echo hi)\"").0, "hi");
+        // クォートされた置換は実行しない
+        assert_eq!(capture(&mut shell, "cat <<<'$(echo hi)'").0, "$(echo hi)");
+        assert_eq!(capture(&mut shell, "cat <<<'`echo hi`'").0, "`echo hi`");
+    }
+
+    #[test]
+    fn heredoc_body_handles_backslash_escapes() {
+        let mut shell = Shell::new();
+        std::env::set_var("RUSH_HEREDOC_ESC", "v");
+        let expand = |shell: &mut Shell, body: &str| expand_heredoc_body(body, shell).unwrap();
+        assert_eq!(expand(&mut shell, "\\$RUSH_HEREDOC_ESC $RUSH_HEREDOC_ESC\n"), "$RUSH_HEREDOC_ESC v\n");
+        assert_eq!(expand(&mut shell, "\\`echo hi\\` `echo hi`\n"), "`echo hi` hi\n");
+        assert_eq!(expand(&mut shell, "a\\\\$RUSH_HEREDOC_ESC\n"), "a\\v\n");
+        assert_eq!(expand(&mut shell, "one \\\ntwo\n"), "one two\n");
+        // それ以外のバックスラッシュは残る
+        assert_eq!(expand(&mut shell, "\\n \\\"x\\\"\n"), "\\n \\\"x\\\"\n");
+        assert_eq!(expand(&mut shell, "$(echo \\$RUSH_HEREDOC_ESC)\n"), "$RUSH_HEREDOC_ESC\n");
+        std::env::remove_var("RUSH_HEREDOC_ESC");
+    }
+
+    #[test]
+    fn capture_returns_output_and_status() {
+        let mut shell = Shell::new();
+        assert_eq!(capture(&mut shell, "echo hi"), ("hi".to_string(), 0));
+        assert_eq!(shell.last_status, 0);
+    }
+
+    #[test]
+    fn capture_failing_command_sets_last_status() {
+        let mut shell = Shell::new();
+        let (out, status) = capture(&mut shell, "echo partial; false");
+        assert_eq!(out, "partial");
+        assert_eq!(status, 1);
+        assert_eq!(shell.last_status, 1);
+        assert_eq!(capture(&mut shell, "exit 3").1, 3);
+    }
+
+    #[test]
+    fn capture_strips_trailing_newline_run_only() {
+        let mut shell = Shell::new();
+        let (out, _) = capture(&mut shell, "printf 'a\\n\\nb\\n\\n\\n'");
+        assert_eq!(out, "a\n\nb");
+    }
+
+    #[test]
+    fn nested_command_substitution_expands_recursively() {
+        let mut shell = Shell::new();
+        assert_eq!(capture(&mut shell, "echo $(echo $(echo hi))").0, "hi");
+        assert_eq!(capture(&mut shell, "echo \"$(echo \"$(echo a b)\")\"").0, "a b");
+        assert_eq!(capture(&mut shell, "echo x$(echo é$(echo ü))y").0, "xéüy");
+    }
+
+    #[test]
+    fn redirect_target_expanding_to_several_words_is_ambiguous() {
+        let dir = std::env::temp_dir().join(format!("rush_ambig_redir_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("f1"), "").unwrap();
+        std::fs::write(dir.join("f2"), "").unwrap();
+        std::fs::write(dir.join("g1"), "").unwrap();
+        let d = dir.display();
+        let mut shell = Shell::new();
+        run_command_string(&mut shell, &format!("V={0}/a\\ {0}/b", d));
+        // ビルトイン・外部コマンド・サブシェルのいずれでも失敗し、ファイルを作らない
+        assert_eq!(run_command_string(&mut shell, "echo x > $V"), 1);
+        assert_eq!(run_command_string(&mut shell, "cat /dev/null > $V"), 1);
+        assert_eq!(run_command_string(&mut shell, "(echo x) > $V"), 1);
+        assert_eq!(run_command_string(&mut shell, &format!("echo x > {}/f*", d)), 1);
+        assert_eq!(run_command_string(&mut shell, "echo x > $RUSH_AMBIG_REDIR_UNSET"), 1);
+        assert!(!dir.join("a").exists() && !dir.join("a b").exists());
+        // 1 ワードに展開されるならそのまま使う
+        assert_eq!(run_command_string(&mut shell, &format!("echo x > {}/g*", d)), 0);
+        assert_eq!(std::fs::read_to_string(dir.join("g1")).unwrap(), "x\n");
+        assert_eq!(run_command_string(&mut shell, &format!("echo y > $(echo {}/h)", d)), 0);
+        assert_eq!(std::fs::read_to_string(dir.join("h")).unwrap(), "y\n");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn quoted_glob_chars_are_not_expanded() {
+        let dir = std::env::temp_dir().join(format!("rush_quoted_glob_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("f1"), "").unwrap();
+        std::fs::write(dir.join("f2"), "").unwrap();
+        let d = dir.display();
+        let mut shell = Shell::new();
+        // クォートされたリダイレクト先はファイル名そのもの
+        assert_eq!(run_command_string(&mut shell, &format!("echo x > \"{}/f*\"", d)), 0);
+        assert_eq!(std::fs::read_to_string(dir.join("f*")).unwrap(), "x\n");
+        assert_eq!(run_command_string(&mut shell, &format!("echo y >> '{}/f?'", d)), 0);
+        assert_eq!(std::fs::read_to_string(dir.join("f?")).unwrap(), "y\n");
+        // 引数も同様（`\*` のエスケープを含む）
+        assert_eq!(capture(&mut shell, &format!("echo \"{0}/f*\" {0}/f\\[12]", d)).0, format!("{0}/f* {0}/f[12]", d));
+        assert_eq!(capture(&mut shell, &format!("ls {}/f[12]", d)).0, format!("{0}/f1\n{0}/f2", d));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn glob_keeps_non_utf8_filenames_openable() {
+        use std::os::unix::ffi::OsStrExt;
+        let dir = std::env::temp_dir().join(format!("rush_raw_name_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(std::ffi::OsStr::from_bytes(b"caf\xe9.txt")), "latin1\n").unwrap();
+        let d = dir.display();
+        let mut shell = Shell::new();
+        // 外部コマンドの引数とリダイレクト先の両方で元のバイト列のファイルを開ける
+        assert_eq!(capture(&mut shell, &format!("cat {}/caf*.txt", d)), ("latin1".to_string(), 0));
+        assert_eq!(capture(&mut shell, &format!("cat < {}/caf*", d)).0, "latin1");
+        assert_eq!(run_command_string(&mut shell, &format!("echo x >> {}/*.txt", d)), 0);
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn builtins_decode_non_utf8_filenames() {
+        use std::os::unix::ffi::OsStrExt;
+        let dir = std::env::temp_dir().join(format!("rush_raw_builtin_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join(std::ffi::OsStr::from_bytes(b"sub\xe9"))).unwrap();
+        std::fs::write(dir.join(std::ffi::OsStr::from_bytes(b"caf\xe9.txt")), "x").unwrap();
+        let d = dir.display();
+        let mut shell = Shell::new();
+        // echo / printf は元のバイト列を書き、test のファイル判定も元の名前を見る
+        let script = format!(
+            "for f in {d}/caf*; do [ -f \"$f\" ] && echo \"$f\" > {d}/echo.out; printf '%s\\n' \"$f\" > {d}/printf.out; done"
+        );
+        assert_eq!(capture(&mut shell, &script).1, 0);
+        let expected = [dir.as_os_str().as_bytes(), b"/caf\xe9.txt\n"].concat();
+        assert_eq!(std::fs::read(dir.join("echo.out")).unwrap(), expected);
+        assert_eq!(std::fs::read(dir.join("printf.out")).unwrap(), expected);
+        // cd で非 UTF-8 のディレクトリに移動できる
+        assert_eq!(capture(&mut shell, &format!("cd {d}/sub*\npwd > {d}/pwd.out")).1, 0);
+        let pwd = std::fs::read(dir.join("pwd.out")).unwrap();
+        assert!(pwd.ends_with(b"/sub\xe9\n"));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn arith_assignment_applies_only_when_command_runs() {
+        let mut shell = Shell::new();
+        assert_eq!(capture(&mut shell, "y=0\nfalse && : $((y++))\necho $y").0, "0");
+        assert_eq!(capture(&mut shell, "y=0\ntrue || : $((y += 5))\necho $y").0, "0");
+        assert_eq!(capture(&mut shell, "y=0\ntrue && : $((y++)) $((++y))\necho $y").0, "2");
+        // 同じコマンドの後続の参照には代入後の値が見える
+        assert_eq!(capture(&mut shell, "y=3\necho $((y++)) $y").0, "3 4");
+        // 実行されなかったコマンドの代入は同じ行の後続コマンドにも見えない
+        assert_eq!(capture(&mut shell, "y=0\nfalse && : $((y=1)); echo \"y=$y\"").0, "y=0");
+    }
+
+    #[test]
+    fn assignment_values_expand_command_substitution_and_arithmetic() {
+        let mut shell = Shell::new();
+        assert_eq!(capture(&mut shell, "x=$((2*3))\necho $x").0, "6");
+        assert_eq!(capture(&mut shell, "d=$(echo hi)\necho $d").0, "hi");
+        assert_eq!(capture(&mut shell, "d=`printf 'a  b'`\necho \"[$d]\"").0, "[a  b]");
+        // 前置代入もコマンドに渡す前に展開する
+        assert_eq!(capture(&mut shell, "RUSH_ASSIGN_SUB=$(echo q) printenv RUSH_ASSIGN_SUB").0, "q");
+        // 代入のみのコマンドの終了ステータスは最後のコマンド置換のもの
+        assert_eq!(capture(&mut shell, "x=$(false)\necho $?").0, "1");
+        assert_eq!(capture(&mut shell, "false\nx=1\necho $?").0, "0");
+    }
+
+    #[test]
+    fn prefix_assignments_reach_child_without_touching_shell_env() {
+        let mut shell = Shell::new();
+        assert_eq!(capture(&mut shell, "RUSH_ENVP_FOO=bar printenv RUSH_ENVP_FOO"), ("bar".to_string(), 0));
+        // fork せずに spawn するので、シェル自身の環境には残らない
+        assert_eq!(run_command_string(&mut shell, "RUSH_ENVP_FOO=bar true"), 0);
+        assert!(std::env::var("RUSH_ENVP_FOO").is_err());
+        // 既存の変数の上書きも子にだけ見える
+        std::env::set_var("RUSH_ENVP_OLD", "old");
+        assert_eq!(capture(&mut shell, "RUSH_ENVP_OLD=new printenv RUSH_ENVP_OLD").0, "new");
+        assert_eq!(run_command_string(&mut shell, "RUSH_ENVP_OLD=new true"), 0);
+        assert_eq!(std::env::var("RUSH_ENVP_OLD").as_deref(), Ok("old"));
+        std::env::remove_var("RUSH_ENVP_OLD");
+    }
+
+    #[test]
+    fn command_substitution_splits_only_when_unquoted() {
+        let mut shell = Shell::new();
+        // 末尾の改行だけを取り除き、内部の改行・空白は保持する
+        assert_eq!(capture(&mut shell, "echo \"[$(printf 'a\\nb  c\\n\\n')]\"").0, "[a\nb  c]");
+        assert_eq!(capture(&mut shell, "echo [$(printf 'a\\nb  c\\n')]").0, "[a b c]");
+        assert_eq!(capture(&mut shell, "echo x`printf 'a  b'`y").0, "xa by");
+        assert_eq!(capture(&mut shell, "echo \"x`printf 'a  b'`y\"").0, "xa  by");
+        // 非クォートの置換が空なら引数ごと消える
+        run_command_string(&mut shell, "rush_count() { echo $#; }");
+        assert_eq!(capture(&mut shell, "rush_count $(true) z").0, "1");
+        assert_eq!(capture(&mut shell, "rush_count $(printf 'a\\nb') \"$(printf 'a\\nb')\"").0, "3");
+    }
+
+    #[test]
+    fn colon_expands_arguments_for_side_effects() {
+        let mut shell = Shell::new();
+        std::env::remove_var("RUSH_COLON_UNSET");
+        assert_eq!(capture(&mut shell, ": ${RUSH_COLON_UNSET:=hi}; echo $RUSH_COLON_UNSET"), ("hi".to_string(), 0));
+        assert_eq!(run_command_string(&mut shell, ": ${RUSH_COLON_UNSET:=hi}"), 0);
+        assert_eq!(std::env::var("RUSH_COLON_UNSET").as_deref(), Ok("hi"));
+        // 設定済みなら上書きしない。出力は捨てるがコマンド置換は実行する
+        assert_eq!(capture(&mut shell, ": ${RUSH_COLON_UNSET:=bye} $(echo out)\necho $RUSH_COLON_UNSET").0, "hi");
+        assert_eq!(capture(&mut shell, ": $(echo err >&2; exit 3)\necho $?").0, "0");
+        std::env::remove_var("RUSH_COLON_UNSET");
+    }
+
+    #[test]
+    fn default_assignment_creates_unexported_variable() {
+        let mut shell = Shell::new();
+        // 未設定の変数はシェル変数になり、子プロセスの環境には現れない
+        assert_eq!(
+            capture(&mut shell, ": ${RUSH_DEFAULT_NEW:=1}\nprintenv RUSH_DEFAULT_NEW\necho \"[$RUSH_DEFAULT_NEW]\"").0,
+            "[1]"
+        );
+        assert_eq!(capture(&mut shell, ": ${RUSH_DEFAULT_NEW:=1}\nexport RUSH_DEFAULT_NEW\nprintenv RUSH_DEFAULT_NEW").0, "1");
+        // export 済みの変数は export されたまま
+        assert_eq!(capture(&mut shell, "export RUSH_DEFAULT_OLD=\n: ${RUSH_DEFAULT_OLD:=2}\nprintenv RUSH_DEFAULT_OLD").0, "2");
+        // export -n も子プロセスへの継承を止める
+        assert_eq!(capture(&mut shell, "export RUSH_DEFAULT_OLD=3\nexport -n RUSH_DEFAULT_OLD\nprintenv RUSH_DEFAULT_OLD").1, 1);
+    }
+
+    #[test]
+    fn prefix_name_listing_yields_separate_words() {
+        let mut shell = Shell::new();
+        assert_eq!(
+            capture(&mut shell, "rush_p_1=a\nrush_p_2=b\nfor n in ${!rush_p_@}; do echo $n; done\necho \"${!rush_p_*}\"").0,
+            "rush_p_1\nrush_p_2\nrush_p_1 rush_p_2"
+        );
+    }
 }
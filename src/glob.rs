@@ -9,78 +9,378 @@
 //! - `[!...]` / `[^...]` — 否定文字クラス（マッチしない文字にマッチ）
 //!
 //! `.` で始まるファイルはパターンが `.` で始まる場合のみマッチ（bash 互換）。
+//!
+//! `/etc/*.conf` のような絶対パス、`src/*/mod.rs` のような複数要素のパターン、
+//! 末尾 `/` でディレクトリだけに絞る `*/` にも対応する。各要素で読むのはそのディレクトリ 1 段だけで
+//! 再帰的に辿らないため、`/proc` のような深い木でも展開が止まらなくなることはない。
+//! 読めないディレクトリ（権限がない、途中がファイル等）は黙ってスキップする。
+//!
+//! UTF-8 として不正なバイトを含むファイル名は [`name_to_string`] で私用領域の文字に退避して返し、
+//! 外部コマンドの argv やリダイレクト先を開くときに [`raw_bytes`] / [`raw_path`] で元のバイト列に戻す。
+//!
+//! ## shopt オプション
+//!
+//! [`GlobOptions`] で `shopt` の `dotglob` / `nullglob` / `failglob` / `nocaseglob` の挙動を切り替える
+//! （[`expand_with`] / [`Pattern::with_options`]）。`globstar` と `extglob` は設定値の保持のみ。
+
+/// `shopt` で切り替えるパス名展開・パターン照合のオプション。[`Shell`](crate::shell::Shell) が所有する。
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GlobOptions {
+    /// `.` で始まるファイルも `*` などにマッチさせる。
+    pub dotglob: bool,
+    /// `**` による再帰マッチ（未実装、値の保持のみ）。
+    pub globstar: bool,
+    /// マッチしないパターンを空のワード列に展開する。
+    pub nullglob: bool,
+    /// マッチしないパターンをエラーにする（[`expand_with`] が `None` を返す）。
+    pub failglob: bool,
+    /// `?(...)` などの拡張パターン（未実装、値の保持のみ）。
+    pub extglob: bool,
+    /// ファイル名の照合で大文字・小文字を区別しない。
+    pub nocaseglob: bool,
+}
 
-/// パターンにグロブ文字（`*`, `?`）が含まれるか判定する。
+impl GlobOptions {
+    /// `shopt` の一覧表示順に並べたオプション名。
+    pub const NAMES: [&'static str; 6] = ["dotglob", "extglob", "failglob", "globstar", "nocaseglob", "nullglob"];
+
+    /// 名前でオプションへの可変参照を返す。未知の名前なら `None`。
+    pub fn flag_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "dotglob" => Some(&mut self.dotglob),
+            "extglob" => Some(&mut self.extglob),
+            "failglob" => Some(&mut self.failglob),
+            "globstar" => Some(&mut self.globstar),
+            "nocaseglob" => Some(&mut self.nocaseglob),
+            "nullglob" => Some(&mut self.nullglob),
+            _ => None,
+        }
+    }
+
+    /// 名前でオプションの値を返す。未知の名前なら `None`。
+    pub fn get(&self, name: &str) -> Option<bool> {
+        let mut copy = *self;
+        copy.flag_mut(name).map(|f| *f)
+    }
+}
+
+/// クォート・エスケープされたグロブ文字（`"*"`, `'?'`, `\[`）の直前に置くマーカー。
+///
+/// パーサーが付け、パターンの照合ではマーカーの次の文字をリテラルとして扱う。
+/// 展開結果のワードからは [`strip_quoted`] で取り除く。
+pub const QUOTED_GLOB: char = '\x1C';
+
+/// パターンにクォートされていないグロブ文字（`*`, `?`, `[`）が含まれるか判定する。
 pub fn has_glob_chars(s: &str) -> bool {
-    s.bytes().any(|b| b == b'*' || b == b'?' || b == b'[')
+    let mut quoted = false;
+    for b in s.bytes() {
+        match b {
+            _ if quoted => quoted = false,
+            b'\x1C' => quoted = true,
+            b'*' | b'?' | b'[' => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// クォートされた文字列中のグロブ文字に [`QUOTED_GLOB`] を付ける。グロブ文字がなければそのまま返す。
+pub fn quote_glob_chars(s: std::borrow::Cow<'_, str>) -> std::borrow::Cow<'_, str> {
+    if !s.contains(['*', '?', '[']) {
+        return s;
+    }
+    let mut out = String::with_capacity(s.len() + 2);
+    for c in s.chars() {
+        if matches!(c, '*' | '?' | '[') {
+            out.push(QUOTED_GLOB);
+        }
+        out.push(c);
+    }
+    std::borrow::Cow::Owned(out)
+}
+
+/// [`QUOTED_GLOB`] マーカーを取り除く。
+pub fn strip_quoted(s: &str) -> std::borrow::Cow<'_, str> {
+    if s.contains(QUOTED_GLOB) {
+        std::borrow::Cow::Owned(s.replace(QUOTED_GLOB, ""))
+    } else {
+        std::borrow::Cow::Borrowed(s)
+    }
 }
 
 /// パターンを展開し、マッチするファイルパスをソート済みで返す。
 /// マッチなし → 元のパターンを含む Vec を返す。
+#[allow(dead_code)]
 pub fn expand(pattern: &str) -> Vec<String> {
-    let results = if let Some(slash_pos) = pattern.rfind('/') {
-        // パターンに `/` が含まれる場合
-        let dir_part = &pattern[..slash_pos];
-        let file_part = &pattern[slash_pos + 1..];
-
-        if has_glob_chars(dir_part) {
-            // ディレクトリ部分にもグロブがある → 再帰的に展開
-            let dir_candidates = expand(dir_part);
-            let mut matches = Vec::new();
-            for dir in &dir_candidates {
-                if let Ok(meta) = std::fs::metadata(dir) {
-                    if meta.is_dir() {
-                        matches.extend(expand_in_dir(dir, file_part));
-                    }
-                }
-            }
-            matches
-        } else {
-            // ディレクトリ部分にグロブなし
-            let dir = if dir_part.is_empty() { "/" } else { dir_part };
-            expand_in_dir(dir, file_part)
-        }
+    expand_with(pattern, &GlobOptions::default()).unwrap_or_default()
+}
+
+/// マッチするパスを遅延的に返すイテレータ版の [`expand`]。マッチがなければ何も返さない。
+///
+/// 途中の要素までのディレクトリ集合だけを先に求め、最後の要素はディレクトリ 1 つずつ読んで返すので、
+/// 結果全体を一度にメモリに載せない。順序は [`expand`] と同じ（各要素ごとの名前順）。
+#[allow(dead_code)]
+pub fn expand_iter(pattern: &str) -> impl Iterator<Item = String> {
+    expand_iter_with(pattern, &GlobOptions::default())
+}
+
+/// `opts` に従ってパターンを展開する。
+///
+/// マッチなしの場合、`nullglob` なら空の Vec、`failglob` なら `None`、どちらでもなければ
+/// 元のパターンを含む Vec を返す（`nullglob` が優先）。
+pub fn expand_with(pattern: &str, opts: &GlobOptions) -> Option<Vec<String>> {
+    let results: Vec<String> = expand_iter_with(pattern, opts).collect();
+    if !results.is_empty() {
+        Some(results)
+    } else if opts.nullglob {
+        Some(Vec::new())
+    } else if opts.failglob {
+        None
     } else {
-        // パターンに `/` がない → カレントディレクトリ
-        expand_in_dir(".", pattern)
+        Some(vec![strip_quoted(pattern).into_owned()])
+    }
+}
+
+/// `opts` に従ってマッチするパスを遅延的に返す（[`expand_iter`] の本体）。
+pub fn expand_iter_with(pattern: &str, opts: &GlobOptions) -> impl Iterator<Item = String> {
+    let opts = *opts;
+    // 末尾の `/` → ディレクトリだけを残し、`/` を付けて返す
+    let (pattern, dirs_only) = match pattern.strip_suffix('/') {
+        Some(dir_pattern) => (dir_pattern, true),
+        None => (pattern, false),
     };
 
-    if results.is_empty() {
-        vec![pattern.to_string()]
+    // 先頭から 1 要素ずつ、これまでに得たディレクトリの集合に対して展開していく
+    let (mut dirs, rest) = match pattern.strip_prefix('/') {
+        Some(rest) => (vec!["/".to_string()], rest),
+        None => (vec![String::new()], pattern),
+    };
+    // 連続する `/` は 1 つとみなす
+    let mut components: Vec<&str> = rest.split('/').filter(|c| !c.is_empty()).collect();
+    let last = components.pop().map(str::to_string);
+    for component in components {
+        if dirs.is_empty() {
+            break;
+        }
+        dirs = expand_component(&dirs, component, &opts);
+    }
+
+    dirs.into_iter()
+        .flat_map(move |dir| match &last {
+            Some(last) => expand_component(std::slice::from_ref(&dir), last, &opts),
+            None => Vec::new(),
+        })
+        .filter(move |p| !dirs_only || std::fs::metadata(raw_path(p)).is_ok_and(|m| m.is_dir()))
+        .map(move |p| if dirs_only { p + "/" } else { p })
+}
+
+/// パターンの 1 要素を各ディレクトリに対して展開する。リテラルの要素は存在するものだけ残す。
+fn expand_component(dirs: &[String], component: &str, opts: &GlobOptions) -> Vec<String> {
+    if has_glob_chars(component) {
+        dirs.iter().flat_map(|dir| expand_in_dir(dir, component, opts)).collect()
     } else {
-        results
+        let component = strip_quoted(component);
+        dirs.iter()
+            .map(|dir| join_path(dir, &component))
+            .filter(|p| std::fs::symlink_metadata(raw_path(p)).is_ok())
+            .collect()
     }
 }
 
-/// 指定ディレクトリ内でファイル名パターンにマッチするエントリを返す。
-fn expand_in_dir(dir: &str, file_pattern: &str) -> Vec<String> {
-    let entries = match std::fs::read_dir(dir) {
+/// ディレクトリ `dir`（空ならカレントディレクトリ）と名前を連結する。
+fn join_path(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        name.to_string()
+    } else if dir.ends_with('/') {
+        format!("{}{}", dir, name)
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+/// 指定ディレクトリ内でファイル名パターンにマッチするエントリを返す。`dir` が空ならカレントディレクトリ。
+///
+/// ディレクトリが読めなければ（権限なし・存在しない・ファイル）空を返す。
+fn expand_in_dir(dir: &str, file_pattern: &str, opts: &GlobOptions) -> Vec<String> {
+    let entries = match std::fs::read_dir(raw_path(if dir.is_empty() { "." } else { dir })) {
         Ok(e) => e,
         Err(_) => return Vec::new(),
     };
 
+    let pattern = Pattern::with_options(file_pattern, opts);
     let mut matches = Vec::new();
     for entry in entries.flatten() {
-        if let Ok(name) = entry.file_name().into_string() {
-            // `.` で始まるファイルはパターンが `.` で始まる場合のみマッチ
-            if name.starts_with('.') && !file_pattern.starts_with('.') {
-                continue;
+        let name = name_to_string(&entry.file_name());
+        // `.` で始まるファイルはパターンが `.` で始まる場合のみマッチ（dotglob なら常に対象）
+        if name.starts_with('.') && !file_pattern.starts_with('.') && !opts.dotglob {
+            continue;
+        }
+        if pattern.matches(&name) {
+            matches.push(join_path(dir, &name));
+        }
+    }
+    matches.sort();
+    matches
+}
+
+/// 事前にコンパイルしたグロブパターン。
+///
+/// ループ内で同じパターンを何度も照合する場合（ディレクトリの各エントリ、`${var#pat}` の各位置など）に、
+/// パターン文字列を毎回解釈し直さずに済むよう、トークン列に変換しておく。
+/// 照合結果は [`matches_pattern`] と同じ（バイト単位、閉じていない `[` は何にもマッチしない）。
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    tokens: Vec<Token>,
+    /// `nocaseglob`: パターンは小文字化済みで、照合時に名前も小文字化する。
+    nocase: bool,
+}
+
+/// [`Pattern`] を構成するトークン。
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// 1 バイトのリテラル。
+    Literal(u8),
+    /// `*`（連続する `*` は 1 つにまとめる）。
+    Star,
+    /// `?`。
+    Question,
+    /// `[...]`。`ranges` は `(lo, hi)` の組で、単独の文字は `(c, c)`。
+    Class { negate: bool, ranges: Vec<(u8, u8)> },
+    /// 閉じ括弧のない `[`。以降は何にもマッチしない。
+    Unclosed,
+}
+
+impl Pattern {
+    /// パターン文字列をコンパイルする。
+    pub fn new(pattern: &str) -> Self {
+        Pattern {
+            tokens: compile(pattern.as_bytes()),
+            nocase: false,
+        }
+    }
+
+    /// `opts` を反映してコンパイルする。`nocaseglob` なら大文字・小文字を区別しない。
+    pub fn with_options(pattern: &str, opts: &GlobOptions) -> Self {
+        if opts.nocaseglob {
+            Pattern {
+                tokens: compile(pattern.to_lowercase().as_bytes()),
+                nocase: true,
+            }
+        } else {
+            Pattern::new(pattern)
+        }
+    }
+
+    /// 名前がパターン全体にマッチするか判定する。
+    pub fn matches(&self, name: &str) -> bool {
+        if self.nocase {
+            matches_tokens(&self.tokens, name.to_lowercase().as_bytes())
+        } else {
+            matches_tokens(&self.tokens, name.as_bytes())
+        }
+    }
+}
+
+/// パターンのバイト列をトークン列に変換する。文字クラスの解釈は [`matches_recursive`] と同じ。
+fn compile(pat: &[u8]) -> Vec<Token> {
+    let plen = pat.len();
+    let mut tokens = Vec::new();
+    let mut pi = 0;
+    while pi < plen {
+        match pat[pi] {
+            // クォートされた文字はリテラル
+            0x1C if pi + 1 < plen => {
+                tokens.push(Token::Literal(pat[pi + 1]));
+                pi += 2;
+            }
+            b'*' => {
+                while pi < plen && pat[pi] == b'*' {
+                    pi += 1;
+                }
+                tokens.push(Token::Star);
+            }
+            b'?' => {
+                tokens.push(Token::Question);
+                pi += 1;
             }
-            if matches_pattern(file_pattern, &name) {
-                if dir == "." {
-                    matches.push(name);
+            b'[' => {
+                pi += 1;
+                let negate = pi < plen && (pat[pi] == b'!' || pat[pi] == b'^');
+                if negate {
+                    pi += 1;
+                }
+                let mut ranges = Vec::new();
+                // `]` を文字クラスの最初に置ける（bash 互換）
+                let mut first = true;
+                while pi < plen && (pat[pi] != b']' || first) {
+                    first = false;
+                    if pi + 2 < plen && pat[pi + 1] == b'-' && pat[pi + 2] != b']' {
+                        let (lo, hi) = (pat[pi], pat[pi + 2]);
+                        ranges.push((lo.min(hi), lo.max(hi)));
+                        pi += 3;
+                    } else {
+                        ranges.push((pat[pi], pat[pi]));
+                        pi += 1;
+                    }
+                }
+                if pi < plen {
+                    tokens.push(Token::Class { negate, ranges });
+                    pi += 1; // skip ']'
                 } else {
-                    matches.push(format!("{}/{}", dir, name));
+                    // 閉じ括弧がない → 以降はマッチしない
+                    tokens.push(Token::Unclosed);
                 }
             }
+            ch => {
+                tokens.push(Token::Literal(ch));
+                pi += 1;
+            }
         }
     }
-    matches.sort();
-    matches
+    tokens
+}
+
+/// トークン列と名前を照合する。`*` はバックトラックで残りの全接尾辞を試す。
+fn matches_tokens(tokens: &[Token], nam: &[u8]) -> bool {
+    let mut ti = 0;
+    let mut ni = 0;
+    while ti < tokens.len() {
+        match &tokens[ti] {
+            Token::Star => {
+                // パターン末尾が * → 残り全部マッチ
+                if ti + 1 == tokens.len() {
+                    return true;
+                }
+                return (ni..=nam.len()).any(|start| matches_tokens(&tokens[ti + 1..], &nam[start..]));
+            }
+            Token::Question => {
+                if ni >= nam.len() {
+                    return false;
+                }
+            }
+            Token::Class { negate, ranges } => {
+                let Some(&ch) = nam.get(ni) else { return false };
+                if ranges.iter().any(|&(lo, hi)| lo <= ch && ch <= hi) == *negate {
+                    return false;
+                }
+            }
+            Token::Unclosed => return false,
+            Token::Literal(ch) => {
+                if nam.get(ni) != Some(ch) {
+                    return false;
+                }
+            }
+        }
+        ti += 1;
+        ni += 1;
+    }
+    ni == nam.len()
 }
 
 /// パターン文字列とファイル名を照合する。
 /// `*` は 0 文字以上、`?` は任意の 1 文字にマッチ。
+///
+/// 呼び出しごとにパターンを解釈するので、同じパターンを繰り返し使うなら [`Pattern`] を使う。
 pub fn matches_pattern(pattern: &str, name: &str) -> bool {
     let pat = pattern.as_bytes();
     let nam = name.as_bytes();
@@ -96,6 +396,14 @@ fn matches_recursive(pat: &[u8], pi: usize, nam: &[u8], ni: usize) -> bool {
 
     while pi < plen {
         match pat[pi] {
+            // クォートされた文字はリテラル
+            0x1C if pi + 1 < plen => {
+                if ni >= nlen || nam[ni] != pat[pi + 1] {
+                    return false;
+                }
+                pi += 2;
+                ni += 1;
+            }
             b'*' => {
                 // 連続する * をスキップ
                 while pi < plen && pat[pi] == b'*' {
@@ -179,10 +487,74 @@ fn matches_recursive(pat: &[u8], pi: usize, nam: &[u8], ni: usize) -> bool {
     ni == nlen
 }
 
+// ── 非 UTF-8 ファイル名 ──────────────────────────────────────────────
+
+/// UTF-8 として不正なバイトを退避する私用領域の基点。バイト `b` を `U+10FE00 + b` の文字で表す。
+const RAW_BYTE_BASE: u32 = 0x10FE00;
+
+/// ファイル名を `String` に変換する。UTF-8 として不正なバイトは私用領域の文字に退避し、
+/// [`raw_bytes`] で元のバイト列に戻せるようにする（`to_string_lossy` のように `U+FFFD` で潰さない）。
+pub fn name_to_string(name: &std::ffi::OsStr) -> String {
+    use std::os::unix::ffi::OsStrExt;
+    let mut bytes = name.as_bytes();
+    let mut out = String::with_capacity(bytes.len());
+    loop {
+        match std::str::from_utf8(bytes) {
+            Ok(valid) => {
+                out.push_str(valid);
+                return out;
+            }
+            Err(e) => {
+                let (valid, rest) = bytes.split_at(e.valid_up_to());
+                out.push_str(std::str::from_utf8(valid).unwrap_or_default());
+                let bad = e.error_len().unwrap_or(rest.len());
+                for &b in &rest[..bad] {
+                    out.extend(char::from_u32(RAW_BYTE_BASE + b as u32));
+                }
+                bytes = &rest[bad..];
+            }
+        }
+    }
+}
+
+/// [`name_to_string`] で退避したバイトを元に戻したバイト列を返す。`spawn` の argv やファイルのオープンに使う。
+pub fn raw_bytes(s: &str) -> std::borrow::Cow<'_, [u8]> {
+    let is_raw = |c: char| (RAW_BYTE_BASE + 0x80..=RAW_BYTE_BASE + 0xFF).contains(&(c as u32));
+    if s.is_ascii() || !s.chars().any(is_raw) {
+        return std::borrow::Cow::Borrowed(s.as_bytes());
+    }
+    let mut out = Vec::with_capacity(s.len());
+    for c in s.chars() {
+        if is_raw(c) {
+            out.push((c as u32 - RAW_BYTE_BASE) as u8);
+        } else {
+            out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+        }
+    }
+    std::borrow::Cow::Owned(out)
+}
+
+/// [`raw_bytes`] で元のバイト列に戻したパス。
+pub fn raw_path(s: &str) -> std::path::PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    std::path::PathBuf::from(std::ffi::OsStr::from_bytes(&raw_bytes(s)))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn quoted_glob_chars_match_literally() {
+        let pattern = Pattern::new("a\x1C*\x1C?");
+        assert!(pattern.matches("a*?"));
+        assert!(!pattern.matches("ab?"));
+        assert!(matches_pattern("a\x1C*\x1C?", "a*?"));
+        assert!(!matches_pattern("a\x1C*\x1C?", "ab?"));
+        assert_eq!(quote_glob_chars("x*".into()), "x\x1C*");
+        assert_eq!(strip_quoted("x\x1C*"), "x*");
+    }
+
     #[test]
     fn pattern_star_suffix() {
         assert!(matches_pattern("*.txt", "hello.txt"));
@@ -236,6 +608,7 @@ mod tests {
 
     #[test]
     fn has_glob_chars_false() {
+        assert!(!has_glob_chars("f\x1C*"));
         assert!(!has_glob_chars("hello"));
         assert!(!has_glob_chars(""));
         assert!(!has_glob_chars("path/to/file.txt"));
@@ -247,6 +620,137 @@ mod tests {
         assert_eq!(result, vec!["nosuch_xyz_pattern_*.qqqq"]);
     }
 
+    #[test]
+    fn shopt_options_change_expansion() {
+        let dir = std::env::temp_dir().join(format!("rush_glob_opts_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("Visible.txt"), "").unwrap();
+        std::fs::write(dir.join(".hidden.txt"), "").unwrap();
+        let d = dir.display();
+
+        let none = format!("{}/*.nomatch", d);
+        let mut opts = GlobOptions::default();
+        assert_eq!(expand_with(&none, &opts), Some(vec![none.clone()]));
+        opts.failglob = true;
+        assert_eq!(expand_with(&none, &opts), None);
+        opts.nullglob = true;
+        assert_eq!(expand_with(&none, &opts), Some(Vec::new()));
+
+        let mut opts = GlobOptions::default();
+        assert_eq!(expand_with(&format!("{}/*.txt", d), &opts).unwrap(), vec![format!("{}/Visible.txt", d)]);
+        opts.dotglob = true;
+        assert_eq!(expand_with(&format!("{}/*.txt", d), &opts).unwrap().len(), 2);
+        assert_eq!(expand_with(&format!("{}/v*", d), &opts), Some(vec![format!("{}/v*", d)]));
+        opts.nocaseglob = true;
+        assert_eq!(expand_with(&format!("{}/v*", d), &opts).unwrap(), vec![format!("{}/Visible.txt", d)]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn expand_multi_component_and_absolute_patterns() {
+        let dir = std::env::temp_dir().join(format!("rush_glob_tree_{}", std::process::id()));
+        for sub in ["a", "b", "c"] {
+            std::fs::create_dir_all(dir.join("src").join(sub)).unwrap();
+        }
+        std::fs::write(dir.join("src/a/mod.rs"), "").unwrap();
+        std::fs::write(dir.join("src/b/mod.rs"), "").unwrap();
+        std::fs::write(dir.join("src/top.rs"), "").unwrap();
+        let d = dir.display();
+
+        assert_eq!(
+            expand(&format!("{}/src/*/mod.rs", d)),
+            vec![format!("{}/src/a/mod.rs", d), format!("{}/src/b/mod.rs", d)],
+        );
+        assert_eq!(
+            expand(&format!("{}/s*/*/", d)),
+            vec![format!("{}/src/a/", d), format!("{}/src/b/", d), format!("{}/src/c/", d)],
+        );
+        // ファイルを途中の要素として辿ろうとしても失敗しない
+        assert_eq!(expand(&format!("{}/src/top.rs/*", d)), vec![format!("{}/src/top.rs/*", d)]);
+        // ルート直下のパターンに `//` が付かない
+        assert!(expand("/*").iter().all(|p| p.starts_with('/') && !p.starts_with("//")));
+
+        // 相対パターンは cwd を変えるため fork した子プロセス内で確かめる
+        let (_, status) = crate::executor::run_forked(1, || {
+            let ok = std::env::set_current_dir(&dir).is_ok()
+                && expand("src/*/mod.rs") == ["src/a/mod.rs", "src/b/mod.rs"]
+                && expand("./src/*.rs") == ["./src/top.rs"]
+                && expand("*") == ["src"];
+            if ok { 0 } else { 1 }
+        });
+        assert_eq!(status, 0);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn expand_walks_wildcard_components() {
+        let dir = std::env::temp_dir().join(format!("rush_glob_walk_{}", std::process::id()));
+        for sub in ["z", "m", "a/deep", "b"] {
+            std::fs::create_dir_all(dir.join(sub)).unwrap();
+        }
+        for file in ["z/a.txt", "m/a.txt", "a/a.txt", "a/deep/a.txt", "b/other.txt"] {
+            std::fs::write(dir.join(file), "").unwrap();
+        }
+        let d = dir.display();
+
+        // 中間要素のワイルドカードを複数のサブディレクトリに対して展開し、ソート順を保つ
+        assert_eq!(
+            expand(&format!("{}/*/a.txt", d)),
+            vec![format!("{}/a/a.txt", d), format!("{}/m/a.txt", d), format!("{}/z/a.txt", d)],
+        );
+        assert_eq!(expand(&format!("{}/?/*/a.txt", d)), vec![format!("{}/a/deep/a.txt", d)]);
+        assert_eq!(expand(&format!("{}//[ab]//*.txt", d)), vec![
+            format!("{}/a/a.txt", d),
+            format!("{}/b/other.txt", d),
+        ]);
+        // リテラルの途中要素が存在しなければマッチなし
+        let missing = format!("{}/*/nosuch/a.txt", d);
+        assert_eq!(expand(&missing), vec![missing.clone()]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn expand_iter_yields_same_results_as_expand() {
+        let dir = std::env::temp_dir().join(format!("rush_glob_iter_{}", std::process::id()));
+        for sub in ["b", "a", "c"] {
+            std::fs::create_dir_all(dir.join(sub)).unwrap();
+            std::fs::write(dir.join(sub).join("x.rs"), "").unwrap();
+            std::fs::write(dir.join(sub).join("y.rs"), "").unwrap();
+        }
+        let d = dir.display();
+        for pattern in [format!("{}/*/*.rs", d), format!("{}/*/", d), format!("{}/[ab]/y.rs", d)] {
+            let streamed: Vec<String> = expand_iter(&pattern).collect();
+            assert!(!streamed.is_empty());
+            assert_eq!(streamed, expand(&pattern));
+        }
+        assert_eq!(expand_iter(&format!("{}/*/*.nomatch", d)).count(), 0);
+        // 遅延評価なので先頭だけ取り出せる
+        assert_eq!(expand_iter(&format!("{}/*/*.rs", d)).next(), Some(format!("{}/a/x.rs", d)));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn expand_skips_unreadable_directories() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = std::env::temp_dir().join(format!("rush_glob_perm_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("open")).unwrap();
+        std::fs::create_dir_all(dir.join("locked")).unwrap();
+        std::fs::write(dir.join("open/x.txt"), "").unwrap();
+        std::fs::write(dir.join("locked/x.txt"), "").unwrap();
+        std::fs::set_permissions(dir.join("locked"), std::fs::Permissions::from_mode(0o000)).unwrap();
+        let d = dir.display();
+
+        let mut expected = vec![format!("{}/open/x.txt", d)];
+        // root では権限に関係なく読めるので、実際に読めるかどうかで期待値を決める
+        if std::fs::read_dir(dir.join("locked")).is_ok() {
+            expected.insert(0, format!("{}/locked/x.txt", d));
+        }
+        assert_eq!(expand(&format!("{}/*/x.txt", d)), expected);
+
+        std::fs::set_permissions(dir.join("locked"), std::fs::Permissions::from_mode(0o755)).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
     #[test]
     fn bracket_char_list() {
         assert!(matches_pattern("[abc]", "a"));
@@ -290,4 +794,46 @@ mod tests {
         assert!(matches_pattern("[a-zA-Z]", "g"));
         assert!(!matches_pattern("[a-zA-Z]", "5"));
     }
+
+    #[test]
+    fn compiled_pattern_matches_like_string_matcher() {
+        let patterns = [
+            "", "*", "**", "?", "a*", "*.rs", "a*b*c", "*a*", "?x?", "[abc]", "[a-z]*", "[z-a]",
+            "[!abc]", "[^0-9]x", "[]a]", "[!]]", "[a-]", "[abc", "x[", "*[", "a\\b", "日*", "*本",
+        ];
+        let names = [
+            "", "a", "b", "x", "abc", "aXbYc", "ab", "main.rs", "]", "-", "[", "xyx", "5x", "m",
+            "a\\b", "x[", "日本", "A", "ba",
+        ];
+        for p in patterns {
+            let compiled = Pattern::new(p);
+            for n in names {
+                assert_eq!(compiled.matches(n), matches_pattern(p, n), "pattern {:?} name {:?}", p, n);
+            }
+        }
+        let opts = GlobOptions { nocaseglob: true, ..GlobOptions::default() };
+        let compiled = Pattern::with_options("V*.TXT", &opts);
+        assert!(compiled.matches("visible.txt"));
+        assert!(compiled.matches("Visible.txt"));
+        assert!(!Pattern::new("V*").matches("visible"));
+    }
+
+    #[test]
+    fn non_utf8_names_round_trip_through_expand() {
+        use std::os::unix::ffi::OsStrExt;
+        let dir = std::env::temp_dir().join(format!("rush_glob_raw_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let raw: &[u8] = b"a\xff\xfeb.bin";
+        std::fs::write(dir.join(std::ffi::OsStr::from_bytes(raw)), "").unwrap();
+
+        let found = expand(&format!("{}/*.bin", dir.display()));
+        assert_eq!(found.len(), 1);
+        let path = raw_path(&found[0]);
+        assert_eq!(path.file_name().unwrap().as_bytes(), raw);
+        assert!(std::fs::metadata(&path).is_ok());
+        // 有効な UTF-8 の名前はそのまま
+        assert_eq!(name_to_string(std::ffi::OsStr::new("日本.txt")), "日本.txt");
+        assert_eq!(raw_bytes("日本.txt").as_ref(), "日本.txt".as_bytes());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 }
@@ -10,18 +10,26 @@
 //! | 有効なコマンド（ビルトイン or PATH 内） | 太字緑 | `\x1b[1;32m` |
 //! | 無効なコマンド | 太字赤 | `\x1b[1;31m` |
 //! | 文字列（クォート内） | 黄 | `\x1b[33m` |
+//! | 閉じていないクォート（継続行入力待ち） | 赤 | `\x1b[31m` |
+//! | コメント（`# ...`） | 暗色 | `\x1b[2m` |
+//! | 整数リテラル引数（`head -n 10` の `10` 等） | 青 | `\x1b[34m` |
+//! | 存在するパスの引数（`~` / `$VAR` 展開後、`cd` は `CDPATH` も考慮） | 下線 | `\x1b[4m` |
+//! | 対応の取れない `(` / `$(` / `{` / `[[` | 太字赤 | `\x1b[1;31m` |
 //! | 演算子（`\|`, `\|\|`, `>`, `>>`, `<`, `2>`, `>&N`, `2>&1`, `&`, `&&`, `;`） | シアン | `\x1b[36m` |
 //! | 変数（`$VAR`, `${VAR}`, `$?`） | マゼンタ | `\x1b[35m` |
 //! | コマンド置換（`$(cmd)`, `` `cmd` ``） | シアン | `\x1b[36m` |
 //! | 引数・リダイレクト先 | デフォルト | （色なし） |
 //!
+//! 各色は `RUSH_COLORS` 環境変数で上書きできる（[`ColorTable::from_spec`] 参照）。
+//!
 //! ## 状態機械
 //!
 //! `command_position` と `redirect_target` の 2 フラグで状態を管理する:
 //! - `command_position = true`: 次のワードをコマンドとして着色（`|`, `||`, `&&`, `;` 後 or 行頭）
 //! - `redirect_target = true`: 次のワードをリダイレクト先として着色なし（`>` / `<` / `2>` 後）
 
-use std::collections::HashSet;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::os::unix::fs::PermissionsExt;
 
 use crate::builtins;
@@ -33,8 +41,120 @@ const RED_BOLD: &str = "\x1b[1;31m";
 const YELLOW: &str = "\x1b[33m";
 const CYAN: &str = "\x1b[36m";
 const MAGENTA: &str = "\x1b[35m";
+const BLUE: &str = "\x1b[34m";
+const RED: &str = "\x1b[31m";
+const DIM: &str = "\x1b[2m";
+const UNDERLINE: &str = "\x1b[4m";
 const RESET: &str = "\x1b[0m";
 
+// ── 色テーブル ────────────────────────────────────────────────────
+
+/// ハイライトに使う ANSI エスケープの組。
+///
+/// [`Default`] は上記のカラースキーム。`RUSH_COLORS` から [`ColorTable::from_env`] で構築する。
+pub struct ColorTable {
+    /// 有効なコマンド。
+    pub command: String,
+    /// 無効なコマンド・対応の取れない括弧。
+    pub error: String,
+    /// クォート文字列。
+    pub string: String,
+    /// 閉じていないクォート。
+    pub warning: String,
+    /// 演算子・リダイレクト。
+    pub operator: String,
+    /// 変数展開。
+    pub variable: String,
+    /// コマンド置換。
+    pub substitution: String,
+    /// コメント。
+    pub comment: String,
+    /// 整数リテラル引数。
+    pub number: String,
+    /// 存在するパスの引数。
+    pub path: String,
+    /// 色のリセット。色無効時は空文字列。
+    pub reset: String,
+}
+
+impl Default for ColorTable {
+    fn default() -> Self {
+        Self {
+            command: GREEN_BOLD.to_string(),
+            error: RED_BOLD.to_string(),
+            string: YELLOW.to_string(),
+            warning: RED.to_string(),
+            operator: CYAN.to_string(),
+            variable: MAGENTA.to_string(),
+            substitution: CYAN.to_string(),
+            comment: DIM.to_string(),
+            number: BLUE.to_string(),
+            path: UNDERLINE.to_string(),
+            reset: RESET.to_string(),
+        }
+    }
+}
+
+impl ColorTable {
+    /// 全要素を無着色にしたテーブル（`RUSH_COLORS=off`）。
+    pub fn off() -> Self {
+        Self {
+            command: String::new(),
+            error: String::new(),
+            string: String::new(),
+            warning: String::new(),
+            operator: String::new(),
+            variable: String::new(),
+            substitution: String::new(),
+            comment: String::new(),
+            number: String::new(),
+            path: String::new(),
+            reset: String::new(),
+        }
+    }
+
+    /// `RUSH_COLORS` を読んでテーブルを構築する。未設定ならデフォルト。
+    pub fn from_env() -> Self {
+        match std::env::var("RUSH_COLORS") {
+            Ok(spec) => Self::from_spec(&spec),
+            Err(_) => Self::default(),
+        }
+    }
+
+    /// `key=SGR:key=SGR...` 形式の指定をパースする（例: `command=32:string=33:error=31`）。
+    ///
+    /// SGR は `1;32` のような数値とセミコロンの並び。`off` / `none` なら全要素を無着色にする。
+    /// 未知のキー・不正な値は無視し、指定のないキーはデフォルト色のまま。
+    pub fn from_spec(spec: &str) -> Self {
+        let spec = spec.trim();
+        if spec == "off" || spec == "none" {
+            return Self::off();
+        }
+        let mut table = Self::default();
+        for entry in spec.split(':') {
+            let Some((key, value)) = entry.split_once('=') else { continue };
+            if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit() || b == b';') {
+                continue;
+            }
+            let slot = match key.trim() {
+                "command" => &mut table.command,
+                "error" => &mut table.error,
+                "string" => &mut table.string,
+                "warning" => &mut table.warning,
+                "operator" => &mut table.operator,
+                "variable" => &mut table.variable,
+                "substitution" => &mut table.substitution,
+                "comment" => &mut table.comment,
+                "number" => &mut table.number,
+                "path" => &mut table.path,
+                _ => continue,
+            };
+            *slot = format!("\x1b[{}m", value);
+        }
+        table
+    }
+}
+
 // ── PATH キャッシュ ───────────────────────────────────────────────
 
 /// `$PATH` 内の実行可能コマンド名をキャッシュする。
@@ -48,6 +168,9 @@ pub struct PathCache {
     commands: HashSet<String>,
     /// キャッシュ構築時の `$PATH` 値。変更検出に使う。
     path_str: String,
+    /// 引数パスの存在判定結果（展開後のパス → 存在するか）。
+    /// 遅いネットワーク FS で再描画毎に stat しないよう、[`refresh`](Self::refresh) までキャッシュする。
+    exists: RefCell<HashMap<String, bool>>,
 }
 
 impl PathCache {
@@ -55,13 +178,15 @@ impl PathCache {
         let mut cache = Self {
             commands: HashSet::new(),
             path_str: String::new(),
+            exists: RefCell::new(HashMap::new()),
         };
         cache.refresh();
         cache
     }
 
-    /// `$PATH` が変更されていればキャッシュを再構築する。
+    /// `$PATH` が変更されていればキャッシュを再構築する。パス存在キャッシュは毎回破棄する。
     pub fn refresh(&mut self) {
+        self.exists.borrow_mut().clear();
         let current = std::env::var("PATH").unwrap_or_default();
         if current == self.path_str && !self.commands.is_empty() {
             return;
@@ -109,6 +234,70 @@ fn is_executable(path: &std::path::Path) -> bool {
     false
 }
 
+/// 引数ワードが既存のパスを指すか判定する（結果は `cache` に保持）。
+///
+/// `~` と `$VAR` / `${VAR}` を展開してから stat する。コマンド置換やグロブを含むワードは対象外。
+/// `is_cd` なら相対パスを `$CDPATH` の各ディレクトリからも探す。
+pub fn path_exists(word: &str, is_cd: bool, cache: &PathCache) -> bool {
+    if word.is_empty() || word.starts_with('-') || word.contains("$(") || word.contains('`')
+        || word.contains(['*', '?', '['])
+    {
+        return false;
+    }
+    let Some(expanded) = expand_for_path_check(word) else { return false };
+    let key = if is_cd { format!("cd\0{}", expanded) } else { expanded.clone() };
+    if let Some(&hit) = cache.exists.borrow().get(&key) {
+        return hit;
+    }
+    let mut hit = std::path::Path::new(&expanded).exists();
+    if !hit && is_cd && !expanded.starts_with('/') {
+        if let Ok(cdpath) = std::env::var("CDPATH") {
+            hit = cdpath
+                .split(':')
+                .filter(|dir| !dir.is_empty())
+                .any(|dir| std::path::Path::new(dir).join(&expanded).is_dir());
+        }
+    }
+    cache.exists.borrow_mut().insert(key, hit);
+    hit
+}
+
+/// パス判定用に先頭 `~` と `$VAR` / `${VAR}` を展開する。未定義変数を含めば `None`。
+fn expand_for_path_check(word: &str) -> Option<String> {
+    let mut out = String::new();
+    let rest = if word == "~" || word.starts_with("~/") {
+        out.push_str(&std::env::var("HOME").ok()?);
+        &word[1..]
+    } else {
+        word
+    };
+    let bytes = rest.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'$' {
+            let (name, next) = if rest[i + 1..].starts_with('{') {
+                let close = rest[i + 2..].find('}')? + i + 2;
+                (&rest[i + 2..close], close + 1)
+            } else {
+                let end = rest[i + 1..]
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .map_or(rest.len(), |e| e + i + 1);
+                (&rest[i + 1..end], end)
+            };
+            if name.is_empty() {
+                return None;
+            }
+            out.push_str(&std::env::var(name).ok()?);
+            i = next;
+        } else {
+            let ch_end = rest[i..].chars().next().map_or(i + 1, |c| i + c.len_utf8());
+            out.push_str(&rest[i..ch_end]);
+            i = ch_end;
+        }
+    }
+    Some(out)
+}
+
 /// コマンド名が有効か（ビルトイン or PATH 内に存在）。
 pub fn is_valid_command(word: &str, cache: &PathCache) -> bool {
     builtins::is_builtin(word) || cache.has_command(word)
@@ -120,13 +309,17 @@ pub fn is_valid_command(word: &str, cache: &PathCache) -> bool {
 ///
 /// 返り値の可視文字数は `buf` と同一（エスケープシーケンスは端末が解釈する）。
 /// カーソル位置の計算には元の `buf` の文字数を使うこと。
-pub fn highlight(buf: &str, cache: &PathCache) -> String {
+/// 色は `colors` に従う。エディタは `RUSH_COLORS` から構築したテーブルを渡す。
+pub fn highlight(buf: &str, cache: &PathCache, colors: &ColorTable) -> String {
     let bytes = buf.as_bytes();
     let len = bytes.len();
     let mut result = String::with_capacity(buf.len() * 2);
     let mut pos = 0;
     let mut command_position = true;
     let mut redirect_target = false;
+    let unmatched = unmatched_openers(buf);
+    // 直近のコマンド名（`cd` の引数だけ CDPATH を考慮するため）
+    let mut current_command = "";
 
     while pos < len {
         match bytes[pos] {
@@ -136,16 +329,16 @@ pub fn highlight(buf: &str, cache: &PathCache) -> String {
             }
             b'|' => {
                 if pos + 1 < len && bytes[pos + 1] == b'|' {
-                    result.push_str(CYAN);
+                    result.push_str(&colors.operator);
                     result.push_str("||");
-                    result.push_str(RESET);
+                    result.push_str(&colors.reset);
                     pos += 2;
                     command_position = true;
                     redirect_target = false;
                 } else {
-                    result.push_str(CYAN);
+                    result.push_str(&colors.operator);
                     result.push('|');
-                    result.push_str(RESET);
+                    result.push_str(&colors.reset);
                     pos += 1;
                     command_position = true;
                     redirect_target = false;
@@ -153,45 +346,45 @@ pub fn highlight(buf: &str, cache: &PathCache) -> String {
             }
             b'&' => {
                 if pos + 1 < len && bytes[pos + 1] == b'&' {
-                    result.push_str(CYAN);
+                    result.push_str(&colors.operator);
                     result.push_str("&&");
-                    result.push_str(RESET);
+                    result.push_str(&colors.reset);
                     pos += 2;
                     command_position = true;
                     redirect_target = false;
                 } else {
-                    result.push_str(CYAN);
+                    result.push_str(&colors.operator);
                     result.push('&');
-                    result.push_str(RESET);
+                    result.push_str(&colors.reset);
                     pos += 1;
                 }
             }
             b';' => {
-                result.push_str(CYAN);
+                result.push_str(&colors.operator);
                 result.push(';');
-                result.push_str(RESET);
+                result.push_str(&colors.reset);
                 pos += 1;
                 command_position = true;
                 redirect_target = false;
             }
             b'(' => {
-                result.push_str(CYAN);
+                result.push_str(if unmatched.contains(&pos) { &colors.error } else { &colors.operator });
                 result.push('(');
-                result.push_str(RESET);
+                result.push_str(&colors.reset);
                 pos += 1;
                 command_position = true;
             }
             b')' => {
-                result.push_str(CYAN);
+                result.push_str(&colors.operator);
                 result.push(')');
-                result.push_str(RESET);
+                result.push_str(&colors.reset);
                 pos += 1;
                 command_position = false;
             }
             b'>' => {
                 if pos + 1 < len && bytes[pos + 1] == b'(' {
                     // >(cmd) — 出力プロセス置換
-                    result.push_str(CYAN);
+                    result.push_str(if unmatched.contains(&(pos + 1)) { &colors.error } else { &colors.operator });
                     result.push_str(">(");
                     pos += 2;
                     let mut depth = 1;
@@ -207,9 +400,9 @@ pub fn highlight(buf: &str, cache: &PathCache) -> String {
                         pos += 1;
                     }
                     result.push(')');
-                    result.push_str(RESET);
+                    result.push_str(&colors.reset);
                 } else {
-                    result.push_str(CYAN);
+                    result.push_str(&colors.operator);
                     result.push('>');
                     pos += 1;
                     if pos < len && bytes[pos] == b'>' {
@@ -224,14 +417,14 @@ pub fn highlight(buf: &str, cache: &PathCache) -> String {
                             pos += 1;
                         }
                     }
-                    result.push_str(RESET);
+                    result.push_str(&colors.reset);
                     redirect_target = true;
                 }
             }
             b'<' => {
                 if pos + 1 < len && bytes[pos + 1] == b'(' {
                     // <(cmd) — 入力プロセス置換
-                    result.push_str(CYAN);
+                    result.push_str(if unmatched.contains(&(pos + 1)) { &colors.error } else { &colors.operator });
                     result.push_str("<(");
                     pos += 2;
                     let mut depth = 1;
@@ -247,17 +440,19 @@ pub fn highlight(buf: &str, cache: &PathCache) -> String {
                         pos += 1;
                     }
                     result.push(')');
-                    result.push_str(RESET);
+                    result.push_str(&colors.reset);
                 } else {
-                    result.push_str(CYAN);
+                    result.push_str(&colors.operator);
                     result.push('<');
-                    result.push_str(RESET);
+                    result.push_str(&colors.reset);
                     pos += 1;
                     redirect_target = true;
                 }
             }
             b'\'' => {
-                result.push_str(YELLOW);
+                // 閉じクォートがなければ継続行入力待ちとして警告色
+                let terminated = buf[pos + 1..].contains('\'');
+                result.push_str(if terminated { &colors.string } else { &colors.warning });
                 result.push('\'');
                 pos += 1;
                 while pos < len && bytes[pos] != b'\'' {
@@ -268,12 +463,13 @@ pub fn highlight(buf: &str, cache: &PathCache) -> String {
                     result.push('\'');
                     pos += 1;
                 }
-                result.push_str(RESET);
+                result.push_str(&colors.reset);
                 command_position = false;
                 redirect_target = false;
             }
             b'"' => {
-                result.push_str(YELLOW);
+                let quote_color = if find_closing_dquote(bytes, pos + 1).is_some() { &colors.string } else { &colors.warning };
+                result.push_str(quote_color);
                 result.push('"');
                 pos += 1;
                 while pos < len && bytes[pos] != b'"' {
@@ -285,8 +481,8 @@ pub fn highlight(buf: &str, cache: &PathCache) -> String {
                     } else if bytes[pos] == b'$' {
                         pos += 1;
                         if pos < len && bytes[pos] == b'(' {
-                            // $(...) → シアン
-                            result.push_str(CYAN);
+                            // $(...) → シアン（閉じていなければ太字赤）
+                            result.push_str(if unmatched.contains(&pos) { &colors.error } else { &colors.substitution });
                             result.push_str("$(");
                             pos += 1;
                             let mut depth = 1;
@@ -302,9 +498,9 @@ pub fn highlight(buf: &str, cache: &PathCache) -> String {
                                 pos += 1;
                             }
                             result.push(')');
-                            result.push_str(YELLOW);
+                            result.push_str(quote_color);
                         } else if pos < len && bytes[pos] == b'{' {
-                            result.push_str(MAGENTA);
+                            result.push_str(&colors.variable);
                             result.push_str("${");
                             pos += 1;
                             while pos < len && bytes[pos] != b'}' && bytes[pos] != b'"' {
@@ -315,9 +511,9 @@ pub fn highlight(buf: &str, cache: &PathCache) -> String {
                                 result.push('}');
                                 pos += 1;
                             }
-                            result.push_str(YELLOW);
+                            result.push_str(quote_color);
                         } else {
-                            result.push_str(MAGENTA);
+                            result.push_str(&colors.variable);
                             result.push('$');
                             while pos < len
                                 && (bytes[pos].is_ascii_alphanumeric()
@@ -327,11 +523,11 @@ pub fn highlight(buf: &str, cache: &PathCache) -> String {
                                 result.push(bytes[pos] as char);
                                 pos += 1;
                             }
-                            result.push_str(YELLOW);
+                            result.push_str(quote_color);
                         }
                     } else if bytes[pos] == b'`' {
                         // バッククォート → シアン
-                        result.push_str(CYAN);
+                        result.push_str(&colors.substitution);
                         result.push('`');
                         pos += 1;
                         while pos < len && bytes[pos] != b'`' && bytes[pos] != b'"' {
@@ -342,7 +538,7 @@ pub fn highlight(buf: &str, cache: &PathCache) -> String {
                             result.push('`');
                             pos += 1;
                         }
-                        result.push_str(YELLOW);
+                        result.push_str(quote_color);
                     } else {
                         result.push(bytes[pos] as char);
                         pos += 1;
@@ -352,13 +548,13 @@ pub fn highlight(buf: &str, cache: &PathCache) -> String {
                     result.push('"');
                     pos += 1;
                 }
-                result.push_str(RESET);
+                result.push_str(&colors.reset);
                 command_position = false;
                 redirect_target = false;
             }
             // 2>& (fd 複製) の着色
             b'2' if pos + 2 < len && bytes[pos + 1] == b'>' && bytes[pos + 2] == b'&' => {
-                result.push_str(CYAN);
+                result.push_str(&colors.operator);
                 result.push_str("2>&");
                 pos += 3;
                 // 続く数字も含める
@@ -366,7 +562,14 @@ pub fn highlight(buf: &str, cache: &PathCache) -> String {
                     result.push(bytes[pos] as char);
                     pos += 1;
                 }
-                result.push_str(RESET);
+                result.push_str(&colors.reset);
+            }
+            // ワード先頭の `#` 以降は行末までコメント
+            b'#' if pos == 0 || matches!(bytes[pos - 1], b' ' | b'\t' | b'|' | b'&' | b';' | b'(' | b')') => {
+                result.push_str(&colors.comment);
+                result.push_str(&buf[pos..]);
+                result.push_str(&colors.reset);
+                pos = len;
             }
             _ => {
                 // 通常ワード（変数 $VAR、$()、バッククォートを含む可能性あり）
@@ -388,24 +591,46 @@ pub fn highlight(buf: &str, cache: &PathCache) -> String {
                 }
                 let word = &buf[word_start..pos];
 
-                if redirect_target {
+                if matches!(word, "{" | "}" | "[[" | "]]") {
+                    // グループ `{ }` / 条件式 `[[ ]]` の括弧
+                    result.push_str(if unmatched.contains(&word_start) { &colors.error } else { &colors.operator });
+                    result.push_str(word);
+                    result.push_str(&colors.reset);
+                    command_position = word == "{" || word == "}";
+                } else if redirect_target {
                     result.push_str(word);
                     redirect_target = false;
                 } else if command_position {
+                    current_command = word;
                     if word.starts_with('$') || word.contains("$(") || word.contains('`') {
-                        highlight_with_vars(&mut result, word);
+                        highlight_with_vars(&mut result, word, colors);
                     } else if is_valid_command(word, cache) {
-                        result.push_str(GREEN_BOLD);
+                        result.push_str(&colors.command);
                         result.push_str(word);
-                        result.push_str(RESET);
+                        result.push_str(&colors.reset);
                     } else {
-                        result.push_str(RED_BOLD);
+                        result.push_str(&colors.error);
                         result.push_str(word);
-                        result.push_str(RESET);
+                        result.push_str(&colors.reset);
                     }
                     command_position = false;
+                } else if !word.is_empty() && word.bytes().all(|b| b.is_ascii_digit()) {
+                    result.push_str(&colors.number);
+                    result.push_str(word);
+                    result.push_str(&colors.reset);
+                } else if path_exists(word, current_command == "cd", cache) {
+                    // 変数部分のマゼンタは残し、ワード全体に下線を引く
+                    let mut inner = String::new();
+                    highlight_with_vars(&mut inner, word, colors);
+                    result.push_str(&colors.path);
+                    if colors.reset.is_empty() {
+                        result.push_str(&inner);
+                    } else {
+                        result.push_str(&inner.replace(&colors.reset, &format!("{}{}", colors.reset, colors.path)));
+                    }
+                    result.push_str(&colors.reset);
                 } else {
-                    highlight_with_vars(&mut result, word);
+                    highlight_with_vars(&mut result, word, colors);
                 }
             }
         }
@@ -414,8 +639,96 @@ pub fn highlight(buf: &str, cache: &PathCache) -> String {
     result
 }
 
+/// 対応する閉じ括弧がない開き括弧のバイト位置を返す。
+///
+/// 対象は `(`（サブシェル・`$(`・`<(`/`>(` の `(` 位置）、単独ワードの `{` と `[[`。
+/// シングルクォート内・コメントは無視し、ダブルクォート内では `$(` のみ追跡する。
+/// 閉じていないクォート自体はここでは報告しない（クォートの着色で示す）。
+fn unmatched_openers(buf: &str) -> Vec<usize> {
+    #[derive(PartialEq)]
+    enum Open {
+        Paren,
+        Brace,
+        DBracket,
+        DQuote,
+    }
+
+    let bytes = buf.as_bytes();
+    let len = bytes.len();
+    let mut stack: Vec<(Open, usize)> = Vec::new();
+    let is_word_start = |i: usize| i == 0 || matches!(bytes[i - 1], b' ' | b'\t' | b';' | b'&' | b'|' | b'(' | b')');
+    let is_word_end = |i: usize| i >= len || matches!(bytes[i], b' ' | b'\t' | b';' | b'&' | b'|' | b')');
+    let mut i = 0;
+
+    while i < len {
+        let in_dquote = matches!(stack.last(), Some((Open::DQuote, _)));
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'"' if in_dquote => {
+                stack.pop();
+            }
+            b'"' => stack.push((Open::DQuote, i)),
+            b'$' if i + 1 < len && bytes[i + 1] == b'(' => {
+                stack.push((Open::Paren, i + 1));
+                i += 1;
+            }
+            _ if in_dquote => {}
+            b'\'' => {
+                i += 1;
+                while i < len && bytes[i] != b'\'' {
+                    i += 1;
+                }
+            }
+            b'#' if is_word_start(i) => break,
+            b'(' => stack.push((Open::Paren, i)),
+            b')' => {
+                if matches!(stack.last(), Some((Open::Paren, _))) {
+                    stack.pop();
+                }
+            }
+            b'{' if is_word_start(i) && is_word_end(i + 1) => stack.push((Open::Brace, i)),
+            b'}' if is_word_start(i) && is_word_end(i + 1) => {
+                if matches!(stack.last(), Some((Open::Brace, _))) {
+                    stack.pop();
+                }
+            }
+            b'[' if i + 1 < len && bytes[i + 1] == b'[' && is_word_start(i) && is_word_end(i + 2) => {
+                stack.push((Open::DBracket, i));
+                i += 1;
+            }
+            b']' if i + 1 < len && bytes[i + 1] == b']' && is_word_start(i) && is_word_end(i + 2) => {
+                if matches!(stack.last(), Some((Open::DBracket, _))) {
+                    stack.pop();
+                }
+                i += 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+
+    stack
+        .into_iter()
+        .filter(|(kind, _)| *kind != Open::DQuote)
+        .map(|(_, pos)| pos)
+        .collect()
+}
+
+/// `start` 以降でダブルクォートを閉じる `"` の位置を返す（`\"` はスキップ）。
+fn find_closing_dquote(bytes: &[u8], start: usize) -> Option<usize> {
+    let mut i = start;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'"' => return Some(i),
+            _ => i += 1,
+        }
+    }
+    None
+}
+
 /// ワード内の `$VAR` / `${VAR}` / `$?` / `$(cmd)` / `` `cmd` `` をシアン/マゼンタで着色する。
-fn highlight_with_vars(result: &mut String, word: &str) {
+fn highlight_with_vars(result: &mut String, word: &str, colors: &ColorTable) {
     let bytes = word.as_bytes();
     let len = bytes.len();
     let mut i = 0;
@@ -425,7 +738,7 @@ fn highlight_with_vars(result: &mut String, word: &str) {
             let next = bytes[i + 1];
             if next == b'(' {
                 // $(...) パターン → シアン
-                result.push_str(CYAN);
+                result.push_str(&colors.substitution);
                 result.push_str("$(");
                 i += 2;
                 let mut depth = 1;
@@ -441,10 +754,10 @@ fn highlight_with_vars(result: &mut String, word: &str) {
                     i += 1;
                 }
                 result.push(')');
-                result.push_str(RESET);
+                result.push_str(&colors.reset);
             } else if next == b'{' {
                 // ${VAR} パターン
-                result.push_str(MAGENTA);
+                result.push_str(&colors.variable);
                 result.push_str("${");
                 i += 2;
                 while i < len && bytes[i] != b'}' {
@@ -455,9 +768,9 @@ fn highlight_with_vars(result: &mut String, word: &str) {
                     result.push('}');
                     i += 1;
                 }
-                result.push_str(RESET);
+                result.push_str(&colors.reset);
             } else if next.is_ascii_alphabetic() || next == b'_' || next == b'?' {
-                result.push_str(MAGENTA);
+                result.push_str(&colors.variable);
                 result.push('$');
                 i += 1;
                 if i < len && bytes[i] == b'?' {
@@ -469,14 +782,14 @@ fn highlight_with_vars(result: &mut String, word: &str) {
                         i += 1;
                     }
                 }
-                result.push_str(RESET);
+                result.push_str(&colors.reset);
             } else {
                 result.push(bytes[i] as char);
                 i += 1;
             }
         } else if bytes[i] == b'`' {
             // バッククォート → シアン
-            result.push_str(CYAN);
+            result.push_str(&colors.substitution);
             result.push('`');
             i += 1;
             while i < len && bytes[i] != b'`' {
@@ -487,7 +800,7 @@ fn highlight_with_vars(result: &mut String, word: &str) {
                 result.push('`');
                 i += 1;
             }
-            result.push_str(RESET);
+            result.push_str(&colors.reset);
         } else {
             result.push(bytes[i] as char);
             i += 1;
@@ -504,8 +817,9 @@ mod tests {
         let cache = PathCache {
             commands: HashSet::new(),
             path_str: String::new(),
+            exists: RefCell::new(HashMap::new()),
         };
-        let out = highlight("echo", &cache);
+        let out = highlight("echo", &cache, &ColorTable::default());
         assert!(out.contains(GREEN_BOLD));
         assert!(out.contains("echo"));
     }
@@ -515,8 +829,9 @@ mod tests {
         let cache = PathCache {
             commands: HashSet::new(),
             path_str: String::new(),
+            exists: RefCell::new(HashMap::new()),
         };
-        let out = highlight("nosuchcmd", &cache);
+        let out = highlight("nosuchcmd", &cache, &ColorTable::default());
         assert!(out.contains(RED_BOLD));
     }
 
@@ -525,8 +840,9 @@ mod tests {
         let cache = PathCache {
             commands: HashSet::new(),
             path_str: String::new(),
+            exists: RefCell::new(HashMap::new()),
         };
-        let out = highlight("echo hello | exit", &cache);
+        let out = highlight("echo hello | exit", &cache, &ColorTable::default());
         assert!(out.contains(&format!("{}|{}", CYAN, RESET)));
     }
 
@@ -535,8 +851,9 @@ mod tests {
         let cache = PathCache {
             commands: HashSet::new(),
             path_str: String::new(),
+            exists: RefCell::new(HashMap::new()),
         };
-        let out = highlight("echo $HOME", &cache);
+        let out = highlight("echo $HOME", &cache, &ColorTable::default());
         assert!(out.contains(MAGENTA));
         assert!(out.contains("$HOME"));
     }
@@ -546,8 +863,9 @@ mod tests {
         let cache = PathCache {
             commands: HashSet::new(),
             path_str: String::new(),
+            exists: RefCell::new(HashMap::new()),
         };
-        let out = highlight("echo \"hello\"", &cache);
+        let out = highlight("echo \"hello\"", &cache, &ColorTable::default());
         assert!(out.contains(YELLOW));
     }
 
@@ -556,8 +874,9 @@ mod tests {
         let cache = PathCache {
             commands: HashSet::new(),
             path_str: String::new(),
+            exists: RefCell::new(HashMap::new()),
         };
-        let out = highlight("echo hello | exit", &cache);
+        let out = highlight("echo hello | exit", &cache, &ColorTable::default());
         // "exit" after pipe should be green (valid builtin)
         assert!(out.contains(&format!("{}exit{}", GREEN_BOLD, RESET)));
     }
@@ -570,4 +889,178 @@ mod tests {
             "fooba"
         );
     }
+
+    // ── クォート・コメント・数値 ──
+
+    fn empty_cache() -> PathCache {
+        PathCache {
+            commands: HashSet::new(),
+            path_str: String::new(),
+            exists: RefCell::new(HashMap::new()),
+        }
+    }
+
+    #[test]
+    fn mixed_line_quotes_comment_number() {
+        let out = highlight("echo 'a b' \"c\" 42 # note", &empty_cache(), &ColorTable::default());
+        assert!(out.starts_with(&format!("{}echo{}", GREEN_BOLD, RESET)));
+        assert!(out.contains(&format!("{}'a b'{}", YELLOW, RESET)));
+        assert!(out.contains(&format!("{}\"c\"{}", YELLOW, RESET)));
+        assert!(out.contains(&format!("{}42{}", BLUE, RESET)));
+        assert!(out.ends_with(&format!("{}# note{}", DIM, RESET)));
+    }
+
+    #[test]
+    fn hash_inside_word_is_not_comment() {
+        let out = highlight("echo a#b", &empty_cache(), &ColorTable::default());
+        assert!(!out.contains(DIM));
+        assert!(out.contains("a#b"));
+    }
+
+    #[test]
+    fn number_in_word_is_plain() {
+        let out = highlight("echo v42 -1", &empty_cache(), &ColorTable::default());
+        assert!(!out.contains(BLUE));
+    }
+
+    #[test]
+    fn unterminated_quotes_use_warning_color() {
+        let out = highlight("echo 'abc", &empty_cache(), &ColorTable::default());
+        assert!(out.contains(&format!("{}'abc", RED)));
+        let out = highlight("echo \"abc $HOME", &empty_cache(), &ColorTable::default());
+        assert!(out.contains(&format!("{}\"abc ", RED)));
+        assert!(!out.contains(YELLOW));
+        let out = highlight("echo \"a\\\"b\"", &empty_cache(), &ColorTable::default());
+        assert!(out.contains(YELLOW));
+        assert!(!out.contains(RED));
+    }
+
+    // ── 括弧の対応 ──
+
+    #[test]
+    fn unmatched_openers_balanced() {
+        assert!(unmatched_openers("(echo a) | { cat; }").is_empty());
+        assert!(unmatched_openers("echo $(date) \"$(pwd)\"").is_empty());
+        assert!(unmatched_openers("[[ -f x ]] && echo ')'").is_empty());
+        assert!(unmatched_openers("echo a{b,c} # (").is_empty());
+    }
+
+    #[test]
+    fn unmatched_openers_reports_positions() {
+        assert_eq!(unmatched_openers("(echo a"), vec![0]);
+        assert_eq!(unmatched_openers("echo $(date"), vec![6]);
+        assert_eq!(unmatched_openers("{ echo"), vec![0]);
+        assert_eq!(unmatched_openers("[[ -f x"), vec![0]);
+        assert_eq!(unmatched_openers("echo \"$(ls\""), vec![7]);
+        assert_eq!(unmatched_openers("(a) (b"), vec![4]);
+    }
+
+    #[test]
+    fn unbalanced_opener_is_red() {
+        let out = highlight("(echo a", &empty_cache(), &ColorTable::default());
+        assert!(out.starts_with(&format!("{}({}", RED_BOLD, RESET)));
+        let out = highlight("(echo a)", &empty_cache(), &ColorTable::default());
+        assert!(out.starts_with(&format!("{}({}", CYAN, RESET)));
+    }
+
+    #[test]
+    fn group_braces_colored_as_operators() {
+        let out = highlight("{ echo a; }", &empty_cache(), &ColorTable::default());
+        assert!(out.starts_with(&format!("{}{{{}", CYAN, RESET)));
+        assert!(out.contains(&format!("{}echo{}", GREEN_BOLD, RESET)));
+        let out = highlight("{ echo a", &empty_cache(), &ColorTable::default());
+        assert!(out.starts_with(&format!("{}{{{}", RED_BOLD, RESET)));
+    }
+
+    // ── RUSH_COLORS ──
+
+    #[test]
+    fn color_spec_overrides_and_defaults() {
+        let table = ColorTable::from_spec("command=32:string=1;33:bogus=1:error=x");
+        assert_eq!(table.command, "\x1b[32m");
+        assert_eq!(table.string, "\x1b[1;33m");
+        // 不正値・未指定キーはデフォルトのまま
+        assert_eq!(table.error, RED_BOLD);
+        assert_eq!(table.variable, MAGENTA);
+        assert_eq!(table.reset, RESET);
+    }
+
+    #[test]
+    fn color_spec_ignores_malformed_entries() {
+        let table = ColorTable::from_spec("::command:=32:operator=");
+        assert_eq!(table.command, GREEN_BOLD);
+        assert_eq!(table.operator, CYAN);
+    }
+
+    #[test]
+    fn color_spec_off_produces_plain_text() {
+        let table = ColorTable::from_spec("off");
+        let line = "echo \"$HOME\" 42 | nosuchcmd $(date) # c";
+        let out = highlight(line, &empty_cache(), &table);
+        assert_eq!(out, line);
+    }
+
+    #[test]
+    fn highlight_with_custom_command_color() {
+        let table = ColorTable::from_spec("command=34");
+        let out = highlight("echo hi", &empty_cache(), &table);
+        assert!(out.starts_with("\x1b[34mecho"));
+    }
+
+    // ── パス存在判定 ──
+
+    #[test]
+    fn path_exists_for_temp_file() {
+        let path = std::env::temp_dir().join(format!("rush_hl_exists_{}", std::process::id()));
+        std::fs::write(&path, "").unwrap();
+        let word = path.to_string_lossy().to_string();
+        let cache = empty_cache();
+        assert!(path_exists(&word, false, &cache));
+        assert!(!path_exists(&format!("{}_missing", word), false, &cache));
+        assert!(!path_exists("-la", false, &cache));
+
+        let out = highlight(&format!("cat {}", word), &cache, &ColorTable::default());
+        assert!(out.contains(&format!("{}{}{}", UNDERLINE, word, RESET)));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn path_exists_result_is_cached_until_refresh() {
+        let path = std::env::temp_dir().join(format!("rush_hl_cached_{}", std::process::id()));
+        let word = path.to_string_lossy().to_string();
+        let mut cache = empty_cache();
+        assert!(!path_exists(&word, false, &cache));
+        std::fs::write(&path, "").unwrap();
+        // 同一 refresh 内では前回の結果を使う
+        assert!(!path_exists(&word, false, &cache));
+        cache.refresh();
+        assert!(path_exists(&word, false, &cache));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn path_exists_expands_tilde_and_vars() {
+        std::env::set_var("RUSH_HL_DIR_TEST", std::env::temp_dir());
+        let cache = empty_cache();
+        assert!(path_exists("$RUSH_HL_DIR_TEST", false, &cache));
+        assert!(path_exists("${RUSH_HL_DIR_TEST}/", false, &cache));
+        assert!(!path_exists("$RUSH_HL_UNDEFINED_TEST/x", false, &cache));
+        if std::env::var("HOME").map(|h| std::path::Path::new(&h).exists()).unwrap_or(false) {
+            assert!(path_exists("~", false, &cache));
+        }
+        std::env::remove_var("RUSH_HL_DIR_TEST");
+    }
+
+    #[test]
+    fn path_exists_cd_uses_cdpath() {
+        let base = std::env::temp_dir().join(format!("rush_hl_cdpath_{}", std::process::id()));
+        std::fs::create_dir_all(base.join("proj")).unwrap();
+        // CDPATH はこのテストでのみ使用
+        std::env::set_var("CDPATH", &base);
+        let cache = empty_cache();
+        assert!(path_exists("proj", true, &cache));
+        assert!(!path_exists("proj", false, &cache));
+        std::env::remove_var("CDPATH");
+        std::fs::remove_dir_all(&base).unwrap();
+    }
 }
@@ -1,12 +1,14 @@
 //! コマンド履歴の管理。
 //!
-//! `~/.rush_history` にプレーンテキスト（1 行 1 コマンド）で永続化し、起動時に読み込む。
+//! `~/.rush_history` にプレーンテキスト（1 行 1 コマンド、直前に `#<epoch>` の時刻行）で永続化し、起動時に読み込む。
 //! ↑↓キーによるナビゲーションで過去のコマンドを呼び出せる。
 //!
 //! ## ファイル形式
 //!
 //! - パス: `$HOME/.rush_history`（`$HOME` 未設定時は `/tmp/.rush_history`）
 //! - 書き込み: 追記モード（[`OpenOptions::append`]）で 1 コマンドずつ追記
+//! - タイムスタンプ: 各コマンドの前に bash と同じ `#<epoch>` 行を書く。読み込み時は直後のエントリの時刻とし、
+//!   `#<epoch>` 行のないエントリ（古い形式のファイル）は時刻なしになる
 //! - 最大エントリ数: 1000（超過時は古いエントリから削除）
 //! - 直前と同一のコマンドは追加しない（連続重複排除）
 //!
@@ -14,6 +16,10 @@
 //!
 //! `nav_index` は `entries` のインデックスで、`entries.len()` は「現在の入力」を指す。
 //! ↑で `nav_index` を減少、↓で増加し、末尾に到達すると `saved_buf`（保存した入力）を復元する。
+//! Ctrl+R で選んだエントリからも [`set_nav`](History::set_nav) で同じ位置から↑↓を続けられる。
+//!
+//! [`set_prefix`](History::set_prefix) で接頭辞を設定すると、↑↓はその接頭辞で始まるエントリだけを辿る
+//! （readline の `history-search-backward` 相当）。接頭辞が空なら時系列にすべてのエントリを辿る。
 //!
 //! ## 逆方向検索 (Ctrl+R)
 //!
@@ -23,6 +29,7 @@
 //! ## ビルトイン連携
 //!
 //! - [`entries()`](History::entries): `history` ビルトインの一覧表示用
+//! - [`timestamp()`](History::timestamp) / [`format_timestamp`]: `HISTTIMEFORMAT` 設定時の時刻表示用
 //! - [`clear()`](History::clear): `history -c` による履歴クリア
 
 use std::fs::{self, OpenOptions};
@@ -33,12 +40,16 @@ use std::path::PathBuf;
 pub struct History {
     /// 履歴エントリのリスト（古い順）。
     entries: Vec<String>,
+    /// 各エントリの実行時刻（エポック秒）。`entries` と同じ長さに保つ。
+    times: Vec<Option<i64>>,
     /// 保持する最大エントリ数。
     max_size: usize,
     /// 現在のナビゲーション位置。`entries.len()` は「現在の入力」を意味する。
     nav_index: usize,
     /// ↑で履歴に入る前の入力バッファ。↓で末尾に戻ったときに復元する。
     saved_buf: String,
+    /// ↑↓で辿るエントリの接頭辞。空ならすべてのエントリを辿る。
+    nav_prefix: String,
     /// 履歴ファイルのパス（`~/.rush_history`）。
     path: PathBuf,
 }
@@ -50,29 +61,57 @@ impl History {
             .map(PathBuf::from)
             .unwrap_or_else(|_| PathBuf::from("/tmp"))
             .join(".rush_history");
+        Self::from_path(path)
+    }
+
+    /// 指定した履歴ファイルを使う `History` を作成し、既存エントリを読み込む。
+    fn from_path(path: PathBuf) -> Self {
         let mut h = Self {
             entries: Vec::new(),
+            times: Vec::new(),
             max_size: 1000,
             nav_index: 0,
             saved_buf: String::new(),
+            nav_prefix: String::new(),
             path,
         };
         h.load();
         h
     }
 
+    /// 指定したエントリで `History` を作成する（テスト用。ファイルには書き込まない）。
+    #[cfg(test)]
+    pub fn with_entries(entries: &[&str]) -> Self {
+        Self {
+            entries: entries.iter().map(|s| s.to_string()).collect(),
+            times: vec![None; entries.len()],
+            max_size: 1000,
+            nav_index: entries.len(),
+            saved_buf: String::new(),
+            nav_prefix: String::new(),
+            path: PathBuf::from("/dev/null"),
+        }
+    }
+
     /// 履歴ファイルからエントリを読み込む。ファイルが存在しなければ何もしない。
+    ///
+    /// `#<epoch>` 行は次のエントリの時刻として読み、エントリには含めない。
     fn load(&mut self) {
         if let Ok(file) = fs::File::open(&self.path) {
             let reader = BufReader::new(file);
+            let mut pending_time = None;
             for line in reader.lines().flatten() {
-                if !line.is_empty() {
+                if let Some(time) = parse_time_line(&line) {
+                    pending_time = Some(time);
+                } else if !line.is_empty() {
                     self.entries.push(line);
+                    self.times.push(pending_time.take());
                 }
             }
             if self.entries.len() > self.max_size {
                 let start = self.entries.len() - self.max_size;
                 self.entries = self.entries[start..].to_vec();
+                self.times = self.times[start..].to_vec();
             }
         }
         self.nav_index = self.entries.len();
@@ -87,16 +126,21 @@ impl History {
         if self.entries.last().map_or(false, |last| last == line) {
             return;
         }
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as i64);
         self.entries.push(line.to_string());
+        self.times.push(Some(now));
         if self.entries.len() > self.max_size {
             self.entries.remove(0);
+            self.times.remove(0);
         }
         if let Ok(mut file) = OpenOptions::new()
             .create(true)
             .append(true)
             .open(&self.path)
         {
-            let _ = writeln!(file, "{}", line);
+            let _ = writeln!(file, "#{}\n{}", now, line);
         }
     }
 
@@ -104,6 +148,7 @@ impl History {
     pub fn reset_nav(&mut self) {
         self.nav_index = self.entries.len();
         self.saved_buf.clear();
+        self.nav_prefix.clear();
     }
 
     /// 現在の入力バッファを保存（初回 Up 時）。
@@ -111,19 +156,32 @@ impl History {
         self.saved_buf = buf.to_string();
     }
 
+    /// ↑↓で辿るエントリを `prefix` で始まるものに絞る（初回 Up 時）。空なら絞らない。
+    pub fn set_prefix(&mut self, prefix: &str) {
+        self.nav_prefix = prefix.to_string();
+    }
+
+    /// 現在の接頭辞を返す。
+    pub fn prefix(&self) -> &str {
+        &self.nav_prefix
+    }
+
+    /// ナビゲーション位置を `index` に移す（Ctrl+R で選んだエントリから↑↓を続ける用）。接頭辞は解除する。
+    pub fn set_nav(&mut self, index: usize) {
+        self.nav_index = index.min(self.entries.len());
+        self.nav_prefix.clear();
+    }
+
     /// ナビゲーション位置が末尾（= まだ履歴に入っていない）か。
     pub fn at_end(&self) -> bool {
         self.nav_index == self.entries.len()
     }
 
-    /// ↑: 一つ前のエントリを返す。先頭なら None。
+    /// ↑: 接頭辞に一致する一つ前のエントリを返す。なければ None（位置は変わらない）。
     pub fn prev(&mut self) -> Option<&str> {
-        if self.nav_index > 0 {
-            self.nav_index -= 1;
-            Some(&self.entries[self.nav_index])
-        } else {
-            None
-        }
+        let index = (0..self.nav_index).rev().find(|&i| self.entries[i].starts_with(&self.nav_prefix))?;
+        self.nav_index = index;
+        Some(&self.entries[index])
     }
 
     /// 全エントリへの参照を返す（`history` ビルトイン用）。
@@ -131,6 +189,11 @@ impl History {
         &self.entries
     }
 
+    /// `index` 番目（0-indexed）のエントリの実行時刻（エポック秒）を返す。記録がなければ None。
+    pub fn timestamp(&self, index: usize) -> Option<i64> {
+        self.times.get(index).copied().flatten()
+    }
+
     /// エントリ数を返す。
     #[allow(dead_code)]
     pub fn len(&self) -> usize {
@@ -154,6 +217,7 @@ impl History {
     /// 履歴をクリアする（`history -c` 用）。
     pub fn clear(&mut self) {
         self.entries.clear();
+        self.times.clear();
         self.nav_index = 0;
     }
 
@@ -171,33 +235,54 @@ impl History {
         None
     }
 
-    /// ↓: 一つ次のエントリを返す。末尾到達時は saved_buf を復元。
+    /// ↓: 接頭辞に一致する一つ次のエントリを返す。なければ末尾に戻って saved_buf を復元。
     pub fn next(&mut self) -> Option<&str> {
-        if self.nav_index < self.entries.len() {
-            self.nav_index += 1;
-            if self.nav_index == self.entries.len() {
-                Some(&self.saved_buf)
-            } else {
-                Some(&self.entries[self.nav_index])
-            }
+        if self.nav_index >= self.entries.len() {
+            return None;
+        }
+        let len = self.entries.len();
+        self.nav_index = (self.nav_index + 1..len)
+            .find(|&i| self.entries[i].starts_with(&self.nav_prefix))
+            .unwrap_or(len);
+        if self.nav_index == len {
+            Some(&self.saved_buf)
         } else {
-            None
+            Some(&self.entries[self.nav_index])
         }
     }
 }
 
+/// 履歴ファイルの `#<epoch>` 行ならエポック秒を返す。
+fn parse_time_line(line: &str) -> Option<i64> {
+    let digits = line.strip_prefix('#')?;
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+/// エポック秒を `strftime(3)` 形式の `fmt`（`HISTTIMEFORMAT`）でローカル時刻として整形する。
+pub fn format_timestamp(epoch: i64, fmt: &str) -> String {
+    let c_fmt = match std::ffi::CString::new(fmt) {
+        Ok(c) => c,
+        Err(_) => return String::new(),
+    };
+    let time = epoch as libc::time_t;
+    let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+        return String::new();
+    }
+    let mut buf = [0u8; 256];
+    let n = unsafe { libc::strftime(buf.as_mut_ptr() as *mut libc::c_char, buf.len(), c_fmt.as_ptr(), &tm) };
+    String::from_utf8_lossy(&buf[..n]).into_owned()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     fn make_history(entries: &[&str]) -> History {
-        History {
-            entries: entries.iter().map(|s| s.to_string()).collect(),
-            max_size: 1000,
-            nav_index: entries.len(),
-            saved_buf: String::new(),
-            path: PathBuf::from("/dev/null"),
-        }
+        History::with_entries(entries)
     }
 
     #[test]
@@ -298,4 +383,59 @@ mod tests {
         h.reset_nav();
         assert!(h.at_end());
     }
+
+    #[test]
+    fn prefix_navigation_skips_non_matching_entries() {
+        let mut h = make_history(&["git status", "ls", "git log", "make", "git diff"]);
+        h.save_current("git ");
+        h.set_prefix("git ");
+
+        assert_eq!(h.prev(), Some("git diff"));
+        assert_eq!(h.prev(), Some("git log"));
+        assert_eq!(h.prev(), Some("git status"));
+        assert_eq!(h.prev(), None);
+        assert_eq!(h.next(), Some("git log"));
+        assert_eq!(h.next(), Some("git diff"));
+        assert_eq!(h.next(), Some("git "));
+        assert!(h.at_end());
+
+        // 一致するエントリがなければ動かない
+        h.set_prefix("cargo");
+        assert_eq!(h.prev(), None);
+        assert!(h.at_end());
+
+        // reset_nav で接頭辞も解除され、時系列に戻る
+        h.reset_nav();
+        assert_eq!(h.prev(), Some("git diff"));
+        assert_eq!(h.prev(), Some("make"));
+    }
+
+    #[test]
+    fn timestamps_round_trip_through_history_file() {
+        let path = std::env::temp_dir().join(format!("rush_history_ts_{}", std::process::id()));
+        std::fs::write(&path, "#1700000000\nls -la\nplain entry\n#not-a-time\n").unwrap();
+
+        let mut h = History::from_path(path.clone());
+        assert_eq!(h.entries(), ["ls -la", "plain entry", "#not-a-time"]);
+        assert_eq!(h.timestamp(0), Some(1700000000));
+        assert_eq!(h.timestamp(1), None);
+        assert_eq!(h.timestamp(2), None);
+
+        h.add("echo new");
+        let written = h.timestamp(3).unwrap();
+        assert!(written >= 1700000000);
+
+        let reloaded = History::from_path(path.clone());
+        assert_eq!(reloaded.entries(), h.entries());
+        assert_eq!(reloaded.timestamp(0), Some(1700000000));
+        assert_eq!(reloaded.timestamp(3), Some(written));
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn format_timestamp_uses_strftime() {
+        assert_eq!(format_timestamp(1700000000, "%s "), "1700000000 ");
+        assert_eq!(format_timestamp(1700000000, "%%"), "%");
+        assert_eq!(format_timestamp(0, ""), "");
+    }
 }
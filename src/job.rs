@@ -31,6 +31,15 @@ pub enum JobStatus {
     Done(i32),
 }
 
+/// プロンプト前に通知するジョブの状態変化。
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum JobNotice {
+    /// バックグラウンドで停止した（SIGTTIN / SIGTSTP 等）。
+    Stopped,
+    /// 停止中のジョブが外部から `SIGCONT` で再開された。
+    Continued,
+}
+
 /// ジョブ。パイプラインのプロセスグループに対応する。
 ///
 /// バックグラウンド実行（`&`）または Ctrl+Z による停止でジョブテーブルに登録される。
@@ -46,6 +55,8 @@ pub struct Job {
     pub processes: Vec<JobProcess>,
     /// Done 通知をユーザに表示済みかどうか。`true` なら次回の [`JobTable::remove_done`] で削除。
     pub notified: bool,
+    /// 未通知の状態変化。[`notify_and_clean`] が一度だけ表示してクリアする。
+    pub notice: Option<JobNotice>,
 }
 
 impl Job {
@@ -110,9 +121,14 @@ impl Job {
 /// ジョブテーブル。ジョブの追加・検索・状態更新・削除を管理する。
 ///
 /// [`Shell`](crate::shell::Shell) が所有し、executor と builtins の両方からアクセスされる。
+/// bash と同様にカレントジョブ（`+`）と直前のジョブ（`-`）を追跡する。
 pub struct JobTable {
     jobs: Vec<Job>,
     next_id: usize,
+    /// カレントジョブ（`%+`）。ジョブの追加・停止・`fg`/`bg` で切り替わる。
+    current: Option<usize>,
+    /// 直前のジョブ（`%-`）。カレントが切り替わると旧カレントがここに移る。
+    previous: Option<usize>,
 }
 
 impl JobTable {
@@ -120,11 +136,28 @@ impl JobTable {
         Self {
             jobs: Vec::new(),
             next_id: 1,
+            current: None,
+            previous: None,
         }
     }
 
-    /// ジョブを追加し、割り当てた ID を返す。最小未使用 ID を再利用する。
+    /// バックグラウンドジョブを追加してカレントジョブにし、割り当てた ID を返す。
     pub fn insert(&mut self, pgid: pid_t, cmd: String, pids: Vec<pid_t>) -> usize {
+        let id = self.push(pgid, cmd, pids);
+        self.make_current(id);
+        id
+    }
+
+    /// フォアグラウンドジョブを一時登録し、割り当てた ID を返す。
+    ///
+    /// カレント/直前のマーカーは動かさない。停止した場合のみ
+    /// [`mark_pid`](Self::mark_pid) でカレントジョブになる。
+    pub fn insert_foreground(&mut self, pgid: pid_t, cmd: String, pids: Vec<pid_t>) -> usize {
+        self.push(pgid, cmd, pids)
+    }
+
+    /// ジョブを末尾に追加し、割り当てた ID を返す。最小未使用 ID を再利用する。
+    fn push(&mut self, pgid: pid_t, cmd: String, pids: Vec<pid_t>) -> usize {
         // 最小未使用 ID を探す
         let mut id = 1;
         loop {
@@ -150,6 +183,7 @@ impl JobTable {
             command: cmd,
             processes,
             notified: false,
+            notice: None,
         });
 
         if id >= self.next_id {
@@ -158,6 +192,44 @@ impl JobTable {
         id
     }
 
+    /// 指定ジョブをカレントジョブにし、旧カレントを直前のジョブに移す。
+    pub fn make_current(&mut self, id: usize) {
+        if self.current == Some(id) {
+            return;
+        }
+        self.previous = self.current;
+        self.current = Some(id);
+    }
+
+    /// 削除されたジョブをマーカーから外し、空いたマーカーを残りの最新ジョブで埋め直す。
+    fn forget_markers(&mut self, id: usize) {
+        if self.current == Some(id) {
+            self.current = self.previous.take();
+        } else if self.previous == Some(id) {
+            self.previous = None;
+        }
+        if self.current.is_none() {
+            self.current = self.jobs.last().map(|j| j.id);
+        }
+        if self.previous.is_none() {
+            self.previous = self.jobs.iter().rev().map(|j| j.id).find(|&j| Some(j) != self.current);
+        }
+    }
+
+    /// `jobs` 表示用のマーカー文字を返す。カレントは `+`、直前は `-`、それ以外は空白。
+    ///
+    /// `%+` / `%-` の解決と食い違わないよう [`current_job_id`](Self::current_job_id) /
+    /// [`previous_job_id`](Self::previous_job_id) から決める。
+    pub fn marker(&self, id: usize) -> char {
+        if self.current_job_id() == Some(id) {
+            '+'
+        } else if self.previous_job_id() == Some(id) {
+            '-'
+        } else {
+            ' '
+        }
+    }
+
     /// ID でジョブを検索する。
     pub fn get(&self, id: usize) -> Option<&Job> {
         self.jobs.iter().find(|j| j.id == id)
@@ -168,51 +240,91 @@ impl JobTable {
         self.jobs.iter_mut().find(|j| j.id == id)
     }
 
-    /// 最新の非 Done ジョブの ID を返す。
+    /// カレントジョブ（`%+`）の ID を返す。
+    ///
+    /// 完了済み（Done）のジョブは飛ばし、直前のジョブ、残りの最新ジョブの順に繰り上げる。
     pub fn current_job_id(&self) -> Option<usize> {
-        self.jobs
-            .iter()
-            .rev()
-            .find(|j| !matches!(j.status(), JobStatus::Done(_)))
-            .map(|j| j.id)
+        self.live_marker(None)
+    }
+
+    /// 直前のジョブ（`%-`）の ID を返す。完了済みのジョブとカレントジョブは飛ばす。
+    pub fn previous_job_id(&self) -> Option<usize> {
+        self.live_marker(self.current_job_id())
+    }
+
+    /// マーカー（カレント → 直前）と新しい順のジョブから、`skip` 以外で最初の未完了ジョブを選ぶ。
+    fn live_marker(&self, skip: Option<usize>) -> Option<usize> {
+        let live = |id: &usize| {
+            Some(*id) != skip
+                && self.get(*id).is_some_and(|j| !matches!(j.status(), JobStatus::Done(_)))
+        };
+        [self.current, self.previous]
+            .into_iter()
+            .flatten()
+            .find(live)
+            .or_else(|| self.jobs.iter().rev().map(|j| j.id).find(live))
     }
 
     /// `waitpid` の結果でプロセスの状態を更新する。
     ///
-    /// `WIFSTOPPED` なら停止、それ以外（正常終了・シグナル終了）なら完了としてマークする。
+    /// `WIFSTOPPED` なら停止、`WIFCONTINUED` なら再開、それ以外（正常終了・シグナル終了）なら
+    /// 完了としてマークする。停止したジョブはカレントジョブになる。
+    /// 停止・再開の遷移は [`Job::notice`] に記録する（完了時は Done 通知に任せてクリア）。
     /// 該当 PID がテーブルに存在しない場合は何もしない。
     pub fn mark_pid(&mut self, pid: pid_t, raw_status: i32) {
         for job in &mut self.jobs {
-            for proc in &mut job.processes {
-                if proc.pid == pid {
-                    proc.status = raw_status;
-                    if libc::WIFSTOPPED(raw_status) {
-                        proc.stopped = true;
-                        proc.completed = false;
-                    } else {
-                        proc.completed = true;
-                        proc.stopped = false;
-                    }
-                    return;
+            let Some(proc) = job.processes.iter_mut().find(|p| p.pid == pid) else {
+                continue;
+            };
+            if libc::WIFCONTINUED(raw_status) {
+                // fg / bg は再開時に停止フラグを下ろすため、ここで遷移するのは外部からの再開のみ
+                if proc.stopped {
+                    proc.stopped = false;
+                    job.notice = Some(JobNotice::Continued);
+                }
+                return;
+            }
+            proc.status = raw_status;
+            if libc::WIFSTOPPED(raw_status) {
+                if !proc.stopped {
+                    job.notice = Some(JobNotice::Stopped);
+                }
+                proc.stopped = true;
+                proc.completed = false;
+                let id = job.id;
+                self.make_current(id);
+            } else {
+                proc.completed = true;
+                proc.stopped = false;
+                if matches!(job.status(), JobStatus::Done(_)) {
+                    job.notice = None;
                 }
             }
+            return;
         }
     }
 
     /// 指定 ID のジョブを削除する。
     pub fn remove_job(&mut self, id: usize) {
         self.jobs.retain(|j| j.id != id);
+        self.forget_markers(id);
     }
 
     /// 通知済み Done ジョブを削除する。
     pub fn remove_done(&mut self) {
-        self.jobs.retain(|j| {
-            !(j.notified && matches!(j.status(), JobStatus::Done(_)))
-        });
+        let removed: Vec<usize> = self
+            .jobs
+            .iter()
+            .filter(|j| j.notified && matches!(j.status(), JobStatus::Done(_)))
+            .map(|j| j.id)
+            .collect();
+        for id in removed {
+            self.remove_job(id);
+        }
     }
 
     /// 全ジョブのイテレータ。
-    pub fn iter(&self) -> impl Iterator<Item = &Job> {
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Job> {
         self.jobs.iter()
     }
 }
@@ -250,7 +362,13 @@ pub fn wait_for_fg(jobs: &mut JobTable, pgid: pid_t) -> (i32, bool) {
         if let Some(job) = job {
             match job.status() {
                 JobStatus::Done(code) => return (code, false),
-                JobStatus::Stopped => return (148, true), // 128 + SIGTSTP(20) = 148
+                JobStatus::Stopped => {
+                    // 停止の表示は呼び出し側が行うため、プロンプト前の通知は不要
+                    if let Some(job) = jobs.jobs.iter_mut().find(|j| j.pgid == pgid) {
+                        job.notice = None;
+                    }
+                    return (148, true); // 128 + SIGTSTP(20) = 148
+                }
                 JobStatus::Running => continue,
             }
         } else {
@@ -282,13 +400,13 @@ pub fn wait_for_fg(jobs: &mut JobTable, pgid: pid_t) -> (i32, bool) {
 
 /// 非ブロッキングでバックグラウンドジョブを reap する。
 ///
-/// `waitpid(-1, WNOHANG | WUNTRACED)` を reap できるプロセスがなくなるまで繰り返し、
+/// `waitpid(-1, WNOHANG | WUNTRACED | WCONTINUED)` を reap できるプロセスがなくなるまで繰り返し、
 /// 各プロセスの状態をジョブテーブルに反映する。プロンプト表示前と `execute()` 冒頭で呼ばれる。
 pub fn reap_jobs(jobs: &mut JobTable) {
     loop {
         let mut raw_status: i32 = 0;
         let pid = unsafe {
-            libc::waitpid(-1, &mut raw_status, libc::WNOHANG | libc::WUNTRACED)
+            libc::waitpid(-1, &mut raw_status, libc::WNOHANG | libc::WUNTRACED | libc::WCONTINUED)
         };
 
         if pid <= 0 {
@@ -299,18 +417,39 @@ pub fn reap_jobs(jobs: &mut JobTable) {
     }
 }
 
-/// Done ジョブの通知を stderr に出力し、テーブルから削除する。
+/// 未表示の通知行（Done と停止・再開の遷移）を `[N]+  Stopped   command` 形式で返す。
+pub fn pending_notifications(jobs: &JobTable) -> Vec<String> {
+    let mut lines = Vec::new();
+    for job in jobs.iter() {
+        let label = if matches!(job.status(), JobStatus::Done(_)) {
+            if job.notified {
+                continue;
+            }
+            job.status_str()
+        } else {
+            match job.notice {
+                Some(JobNotice::Stopped) => "Stopped",
+                Some(JobNotice::Continued) => "Continued",
+                None => continue,
+            }
+        };
+        lines.push(format!("[{}]{}  {}   {}", job.id, jobs.marker(job.id), label, job.command));
+    }
+    lines
+}
+
+/// ジョブの通知を stderr に出力し、Done ジョブをテーブルから削除する。
 ///
-/// `[N]   Done   command` 形式で表示後、`notified` フラグを立てて [`JobTable::remove_done`] で削除。
-/// プロンプト表示前に呼ばれ、bash と同様のタイミングでユーザに完了を通知する。
+/// [`pending_notifications`] の各行を表示後、停止・再開の通知をクリアし、
+/// Done ジョブは `notified` フラグを立てて [`JobTable::remove_done`] で削除。
+/// プロンプト表示前に呼ばれ、bash と同様のタイミングでユーザに状態変化を通知する。
 pub fn notify_and_clean(jobs: &mut JobTable) {
-    for job in jobs.iter() {
-        if matches!(job.status(), JobStatus::Done(_)) && !job.notified {
-            eprintln!("[{}]   {}   {}", job.id, job.status_str(), job.command);
-        }
+    for line in pending_notifications(jobs) {
+        eprintln!("{}", line);
     }
     // notified フラグを立ててから削除
     for job in &mut jobs.jobs {
+        job.notice = None;
         if matches!(job.status(), JobStatus::Done(_)) {
             job.notified = true;
         }
@@ -338,3 +477,155 @@ pub fn take_terminal_back(terminal_fd: i32, shell_pgid: pid_t) {
         libc::tcsetpgrp(terminal_fd, shell_pgid);
     }
 }
+
+// ── Tests ───────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `waitpid` が SIGTSTP による停止で返す raw status。
+    const STOPPED: i32 = (libc::SIGTSTP << 8) | 0x7f;
+
+    /// `waitpid` が `SIGCONT` による再開で返す raw status。
+    const CONTINUED: i32 = 0xffff;
+
+    fn markers(jobs: &JobTable) -> (Option<usize>, Option<usize>) {
+        (jobs.current_job_id(), jobs.previous_job_id())
+    }
+
+    #[test]
+    fn insert_shifts_current_to_previous() {
+        let mut jobs = JobTable::new();
+        assert_eq!(markers(&jobs), (None, None));
+        jobs.insert(100, "sleep 1".to_string(), vec![100]);
+        assert_eq!(markers(&jobs), (Some(1), None));
+        jobs.insert(200, "sleep 2".to_string(), vec![200]);
+        assert_eq!(markers(&jobs), (Some(2), Some(1)));
+        jobs.insert(300, "sleep 3".to_string(), vec![300]);
+        assert_eq!(markers(&jobs), (Some(3), Some(2)));
+        assert_eq!((jobs.marker(1), jobs.marker(2), jobs.marker(3)), (' ', '-', '+'));
+    }
+
+    #[test]
+    fn stop_and_resume_make_job_current() {
+        let mut jobs = JobTable::new();
+        jobs.insert(100, "vim".to_string(), vec![100]);
+        jobs.insert(200, "sleep 2".to_string(), vec![200]);
+        jobs.insert(300, "sleep 3".to_string(), vec![300]);
+        // 停止したジョブがカレントになる
+        jobs.mark_pid(100, STOPPED);
+        assert_eq!(markers(&jobs), (Some(1), Some(3)));
+        // fg / bg で再開したジョブもカレントになる
+        jobs.make_current(2);
+        assert_eq!(markers(&jobs), (Some(2), Some(1)));
+        // カレントを再指定しても変わらない
+        jobs.make_current(2);
+        assert_eq!(markers(&jobs), (Some(2), Some(1)));
+        // 直前のジョブを選ぶと入れ替わる
+        jobs.make_current(1);
+        assert_eq!(markers(&jobs), (Some(1), Some(2)));
+    }
+
+    #[test]
+    fn removal_promotes_remaining_jobs() {
+        let mut jobs = JobTable::new();
+        jobs.insert(100, "sleep 1".to_string(), vec![100]);
+        jobs.insert(200, "sleep 2".to_string(), vec![200]);
+        jobs.insert(300, "sleep 3".to_string(), vec![300]);
+        // カレントが消えると直前のジョブが繰り上がる
+        jobs.remove_job(3);
+        assert_eq!(markers(&jobs), (Some(2), Some(1)));
+        // 直前のジョブが消えると残りの最新ジョブで埋める
+        jobs.insert(400, "sleep 4".to_string(), vec![400]);
+        assert_eq!(markers(&jobs), (Some(3), Some(2)));
+        jobs.remove_job(2);
+        assert_eq!(markers(&jobs), (Some(3), Some(1)));
+        // 完了して通知済みになったジョブも外れる
+        jobs.mark_pid(400, 0);
+        jobs.get_mut(3).unwrap().notified = true;
+        jobs.remove_done();
+        assert_eq!(markers(&jobs), (Some(1), None));
+        jobs.remove_job(1);
+        assert_eq!(markers(&jobs), (None, None));
+    }
+
+    #[test]
+    fn foreground_jobs_keep_markers() {
+        let mut jobs = JobTable::new();
+        jobs.insert(100, "sleep 1".to_string(), vec![100]);
+        jobs.insert(200, "sleep 2".to_string(), vec![200]);
+        jobs.make_current(1);
+        assert_eq!(markers(&jobs), (Some(1), Some(2)));
+        // 完了したフォアグラウンドジョブはマーカーを動かさない
+        let fg = jobs.insert_foreground(300, "true".to_string(), vec![300]);
+        assert_eq!(markers(&jobs), (Some(1), Some(2)));
+        jobs.mark_pid(300, 0);
+        jobs.remove_job(fg);
+        assert_eq!(markers(&jobs), (Some(1), Some(2)));
+        // 停止したフォアグラウンドジョブはカレントになる
+        let fg = jobs.insert_foreground(400, "vim".to_string(), vec![400]);
+        jobs.mark_pid(400, STOPPED);
+        assert_eq!(markers(&jobs), (Some(fg), Some(1)));
+    }
+
+    #[test]
+    fn done_jobs_are_skipped_by_markers() {
+        let mut jobs = JobTable::new();
+        jobs.insert(100, "sleep 1".to_string(), vec![100]);
+        jobs.insert(200, "sleep 2".to_string(), vec![200]);
+        jobs.insert(300, "sleep 3".to_string(), vec![300]);
+        // カレントが完了すると直前のジョブが繰り上がる
+        jobs.mark_pid(300, 0);
+        assert_eq!(markers(&jobs), (Some(2), Some(1)));
+        // 表示のマーカーも `%+` / `%-` の解決と一致する
+        assert_eq!((jobs.marker(1), jobs.marker(2), jobs.marker(3)), ('-', '+', ' '));
+        // 直前のジョブも完了すると空く
+        jobs.mark_pid(100, 0);
+        assert_eq!(markers(&jobs), (Some(2), None));
+    }
+
+    #[test]
+    fn stop_and_continue_are_notified_once() {
+        let mut jobs = JobTable::new();
+        jobs.insert(100, "cat".to_string(), vec![100]);
+        jobs.insert(200, "sleep 9".to_string(), vec![200]);
+        assert!(pending_notifications(&jobs).is_empty());
+
+        // SIGTTIN 等でバックグラウンドジョブが停止
+        jobs.mark_pid(100, STOPPED);
+        assert_eq!(jobs.get(1).unwrap().notice, Some(JobNotice::Stopped));
+        assert_eq!(pending_notifications(&jobs), vec!["[1]+  Stopped   cat"]);
+        notify_and_clean(&mut jobs);
+        assert!(pending_notifications(&jobs).is_empty());
+        // 同じ停止の再報告では通知しない
+        jobs.mark_pid(100, STOPPED);
+        assert!(pending_notifications(&jobs).is_empty());
+
+        // 外部からの SIGCONT で再開
+        jobs.mark_pid(100, CONTINUED);
+        assert_eq!(jobs.get(1).unwrap().status(), JobStatus::Running);
+        assert_eq!(pending_notifications(&jobs), vec!["[1]+  Continued   cat"]);
+        notify_and_clean(&mut jobs);
+        assert!(pending_notifications(&jobs).is_empty());
+        // 停止していないプロセスの再開は通知しない
+        jobs.mark_pid(200, CONTINUED);
+        assert!(pending_notifications(&jobs).is_empty());
+    }
+
+    #[test]
+    fn done_replaces_pending_stop_notice() {
+        let mut jobs = JobTable::new();
+        jobs.insert(100, "make".to_string(), vec![100]);
+        jobs.insert(200, "sleep 9".to_string(), vec![200]);
+        jobs.mark_pid(100, STOPPED);
+        jobs.mark_pid(100, 0);
+        assert_eq!(jobs.get(1).unwrap().notice, None);
+        // 完了したジョブは `%+` にならないのでマーカーも付かない
+        assert_eq!(pending_notifications(&jobs), vec!["[1]   Done   make"]);
+        notify_and_clean(&mut jobs);
+        assert!(jobs.get(1).is_none());
+        // 停止したジョブを削除すると直前のジョブがカレントに戻る
+        assert_eq!(markers(&jobs), (Some(2), None));
+    }
+}
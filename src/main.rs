@@ -4,12 +4,13 @@
 //!
 //! ## 主な機能
 //!
-//! - エイリアス展開（再帰ガード付き）
+//! - エイリアス展開（再帰ガード付き、[`shell::expand_alias`]）
 //! - `history` ビルトイン（editor 所有の履歴への直接アクセス）
-//! - 継続行入力（末尾 `\`・未完了パイプ/演算子・未閉クォートで `> ` プロンプト）
-//! - `~/.rushrc` 読み込み
-//! - 非インタラクティブモード（`rush -c 'cmd'`、`rush script.sh`）
-//! - プロンプトカスタマイズ（`$PROMPT` 環境変数: `\u`/`\h`/`\w`/`\W`/`\$`/`\?`）
+//! - 継続行入力（末尾 `\`・未完了パイプ/演算子・未閉クォートで継続プロンプト `$PS2`、既定は `> `）
+//! - `~/.rushrc` 読み込み（`$RUSHRC` / `$ENV` / `--rcfile PATH` で差し替え、`--norc` で抑止）
+//! - ログインシェル（`-l` / `--login` / `argv[0]` が `-` 始まり）は `/etc/profile` → `~/.rush_profile` を読み込み
+//! - 非インタラクティブモード（`rush -c 'cmd'`、`rush script.sh`、`echo 'cmd' | rush`）
+//! - プロンプトカスタマイズ（`$PROMPT` 環境変数: `\u`/`\h`/`\w`/`\W`/`\$`/`\?`、右端に表示する `$RPROMPT`）
 //! - `if`/`then`/`elif`/`else`/`fi` 複合コマンド（ネスト対応、ワンライナー・複数行両対応）
 //! - `for`/`while`/`until`/`do`/`done` ループ（`break`/`continue` 対応、ネスト対応）
 //! - `case`/`in`/`)`/`;;`/`esac` パターンマッチ（OR パターン、glob、ネスト対応）
@@ -43,22 +44,20 @@ mod parser;
 mod shell;
 mod spawn;
 
-use std::collections::HashMap;
-
 use shell::Shell;
 
-/// `~/.rushrc` を読み込んで各行を実行する。ファイルが存在しなければサイレントスキップ。
-fn load_rc(shell: &mut Shell) {
-    let home = match std::env::var("HOME") {
-        Ok(h) => h,
-        Err(_) => return,
-    };
-    let rc_path = format!("{}/.rushrc", home);
-    let content = match std::fs::read_to_string(&rc_path) {
-        Ok(c) => c,
-        Err(_) => return, // ファイルなし → サイレントスキップ
-    };
-    run_string(shell, &content);
+/// 起動ファイル（ログインシェルは profile、対話シェルは `~/.rushrc` 等）を順に読み込んで実行する。
+/// 存在しないファイルはサイレントスキップ。
+///
+/// 読み込むファイルの決定は [`shell::startup_files`] を参照。
+fn load_startup_files(shell: &mut Shell, inv: &shell::Invocation, interactive: bool) {
+    for path in shell::startup_files(inv, interactive) {
+        let content = match std::fs::read_to_string(&path) {
+            Ok(c) => c,
+            Err(_) => continue, // ファイルなし → サイレントスキップ
+        };
+        shell.run(&content);
+    }
 }
 
 /// `history` / `history N` / `history -c` を処理する。
@@ -73,11 +72,7 @@ fn handle_history(editor: &mut editor::LineEditor, cmd: &str) -> i32 {
         Some(n_str) => match n_str.parse::<usize>() {
             Ok(n) => {
                 let history = editor.history();
-                let entries = history.entries();
-                let start = entries.len().saturating_sub(n);
-                for (i, entry) in entries[start..].iter().enumerate() {
-                    println!("{:5}  {}", start + i + 1, entry);
-                }
+                print_history(history, history.entries().len().saturating_sub(n));
                 0
             }
             Err(_) => {
@@ -86,16 +81,26 @@ fn handle_history(editor: &mut editor::LineEditor, cmd: &str) -> i32 {
             }
         },
         None => {
-            let history = editor.history();
-            let entries = history.entries();
-            for (i, entry) in entries.iter().enumerate() {
-                println!("{:5}  {}", i + 1, entry);
-            }
+            print_history(editor.history(), 0);
             0
         }
     }
 }
 
+/// `start` 番目以降の履歴を番号付きで表示する。
+/// `HISTTIMEFORMAT` が設定されていれば各エントリの前に時刻を整形して表示する（時刻がなければ `??`）。
+fn print_history(history: &history::History, start: usize) {
+    let time_format = std::env::var("HISTTIMEFORMAT").ok();
+    for (i, entry) in history.entries().iter().enumerate().skip(start) {
+        let time = match (&time_format, history.timestamp(i)) {
+            (Some(fmt), Some(t)) => history::format_timestamp(t, fmt),
+            (Some(_), None) => "??".to_string(),
+            (None, _) => String::new(),
+        };
+        println!("{:5}  {}{}", i + 1, time, entry);
+    }
+}
+
 /// プロンプトを構築する。`$PROMPT` 環境変数が設定されていればエスケープ展開する。
 ///
 /// 対応エスケープ:
@@ -119,6 +124,15 @@ fn build_prompt(last_status: i32) -> String {
     }
 }
 
+/// 継続行のプロンプトを構築する。`$PS2` が設定されていれば `$PROMPT` と同じエスケープで展開し、
+/// なければ `> `。
+fn continuation_prompt(last_status: i32) -> String {
+    match std::env::var("PS2") {
+        Ok(fmt) => expand_prompt(&fmt, last_status),
+        Err(_) => "> ".to_string(),
+    }
+}
+
 fn expand_prompt(fmt: &str, last_status: i32) -> String {
     let mut result = String::new();
     let bytes = fmt.as_bytes();
@@ -143,13 +157,7 @@ fn expand_prompt(fmt: &str, last_status: i32) -> String {
                     let cwd = std::env::current_dir()
                         .map(|p| p.to_string_lossy().to_string())
                         .unwrap_or_default();
-                    let home = std::env::var("HOME").unwrap_or_default();
-                    if !home.is_empty() && cwd.starts_with(&home) {
-                        result.push('~');
-                        result.push_str(&cwd[home.len()..]);
-                    } else {
-                        result.push_str(&cwd);
-                    }
+                    result.push_str(&parser::abbreviate_home(&cwd));
                     i += 2;
                 }
                 b'W' => {
@@ -304,139 +312,6 @@ fn expand_history(line: &str, history: &history::History) -> Result<String, Stri
     Ok(result)
 }
 
-/// エイリアス展開: 行の最初のワードがエイリアスならその値に置換する。
-/// 再帰ガード付き（同じエイリアスは 1 回のみ展開）。
-fn expand_alias(line: &str, aliases: &HashMap<String, String>) -> String {
-    let trimmed = line.trim_start();
-    if trimmed.is_empty() {
-        return line.to_string();
-    }
-    let word_end = trimmed
-        .find(|c: char| c.is_whitespace())
-        .unwrap_or(trimmed.len());
-    let first_word = &trimmed[..word_end];
-    let rest = &trimmed[word_end..];
-
-    if let Some(value) = aliases.get(first_word) {
-        // 再帰ガード: 展開結果の最初の単語が同じエイリアスなら停止
-        let expanded_first = value
-            .split_whitespace()
-            .next()
-            .unwrap_or("");
-        if expanded_first == first_word {
-            return line.to_string();
-        }
-        let new_line = format!("{}{}", value, rest);
-        // 再帰展開（別のエイリアスが先頭に来る場合）
-        expand_alias(&new_line, aliases)
-    } else {
-        line.to_string()
-    }
-}
-
-/// 文字列を 1 行ずつパースして実行する。
-///
-/// `if` で始まる行は `fi` まで収集して [`executor::execute_if_block`] で実行。
-/// `for`/`while`/`until` で始まる行は `done` まで収集してループとして実行。
-/// ヒアドキュメントの本文収集にも対応。
-fn run_string(shell: &mut Shell, input: &str) {
-    let lines: Vec<&str> = input.lines().collect();
-    let mut i = 0;
-    while i < lines.len() {
-        let trimmed = lines[i].trim();
-        i += 1;
-        if trimmed.is_empty() || trimmed.starts_with('#') {
-            continue;
-        }
-        let expanded = expand_alias(trimmed, &shell.aliases);
-
-        // if ブロックの検出: `if` で始まる行を見つけたら `fi` まで収集
-        if executor::starts_with_if(&expanded) {
-            let (block, next_i) = executor::collect_if_block(&lines, i - 1);
-            shell.last_status = executor::execute_if_block(shell, &block);
-            i = next_i;
-            if shell.should_exit || shell.errexit_pending {
-                break;
-            }
-            continue;
-        }
-
-        // for/while/until ブロックの検出
-        if executor::starts_with_for(&expanded)
-            || executor::starts_with_while(&expanded)
-            || executor::starts_with_until(&expanded)
-        {
-            let (block, next_i) = executor::collect_loop_block(&lines, i - 1);
-            if executor::starts_with_for(&expanded) {
-                shell.last_status = executor::execute_for_block(shell, &block);
-            } else {
-                shell.last_status = executor::execute_while_block(
-                    shell, &block, executor::starts_with_until(&expanded));
-            }
-            i = next_i;
-            if shell.should_exit || shell.errexit_pending {
-                break;
-            }
-            continue;
-        }
-
-        // case ブロックの検出
-        if executor::starts_with_case(&expanded) {
-            let (block, next_i) = executor::collect_case_block(&lines, i - 1);
-            shell.last_status = executor::execute_case_block(shell, &block);
-            i = next_i;
-            if shell.should_exit || shell.errexit_pending {
-                break;
-            }
-            continue;
-        }
-
-        // 関数定義の検出
-        if let Some((name, rest)) = executor::parse_function_def(&expanded) {
-            let (body, next_i) = executor::collect_function_body(&lines, i - 1, &rest);
-            shell.functions.insert(name, body);
-            i = next_i;
-            continue;
-        }
-
-        match parser::parse(&expanded, shell.last_status, &shell.positional_args, shell.set_nounset, &shell.arrays) {
-            Ok(Some(mut list)) => {
-                // ヒアドキュメントの本文を収集
-                let delims = parser::heredoc_delimiters(&list);
-                if !delims.is_empty() {
-                    let mut bodies = Vec::new();
-                    for delim in &delims {
-                        let mut body = String::new();
-                        while i < lines.len() {
-                            let line = lines[i];
-                            i += 1;
-                            if line.trim() == delim.as_str() {
-                                break;
-                            }
-                            if !body.is_empty() {
-                                body.push('\n');
-                            }
-                            body.push_str(line);
-                        }
-                        bodies.push(body);
-                    }
-                    parser::fill_heredoc_bodies(&mut list, &bodies);
-                }
-                let cmd_text = expanded.trim().to_string();
-                shell.last_status = executor::execute(shell, &list, &cmd_text);
-            }
-            Ok(None) => {}
-            Err(e) => {
-                eprintln!("rush: {}", e);
-                shell.last_status = 2;
-            }
-        }
-        if shell.should_exit || shell.errexit_pending {
-            break;
-        }
-    }
-}
-
 /// スクリプトファイルを行単位で実行する。
 fn run_file(shell: &mut Shell, path: &str) {
     let content = match std::fs::read_to_string(path) {
@@ -447,25 +322,36 @@ fn run_file(shell: &mut Shell, path: &str) {
             return;
         }
     };
-    run_string(shell, &content);
+    shell.run(&content);
 }
 
 fn main() {
     let args: Vec<String> = std::env::args().collect();
+    let inv = match shell::parse_invocation(&args) {
+        Ok(inv) => inv,
+        Err(msg) => {
+            eprintln!("rush: {}", msg);
+            std::process::exit(2);
+        }
+    };
 
-    // 非インタラクティブモード: rush -c 'command' または rush script.sh
-    if args.len() > 1 {
+    // 非インタラクティブモード: rush -c 'command'、rush script.sh、またはパイプされた stdin
+    let stdin_is_tty = unsafe { libc::isatty(libc::STDIN_FILENO) } == 1;
+    if inv.command.is_some() || inv.script.is_some() || inv.reads_script_from_stdin(stdin_is_tty) {
         let mut shell = Shell::new();
-        if args[1] == "-c" {
-            if args.len() < 3 {
-                eprintln!("rush: -c: option requires an argument");
-                std::process::exit(2);
+        load_startup_files(&mut shell, &inv, false);
+        if let Some(ref cmd) = inv.command {
+            if let Some((name, rest)) = inv.args.split_first() {
+                shell.set_script_args(name, rest);
             }
-            run_string(&mut shell, &args[2]);
+            shell.run(cmd);
+        } else if let Some(ref script) = inv.script {
+            shell.set_script_args(script, &inv.args);
+            run_file(&mut shell, script);
         } else {
-            run_file(&mut shell, &args[1]);
+            shell.run_reader(std::io::stdin().lock());
         }
-        std::process::exit(shell.last_status);
+        std::process::exit(shell.shutdown());
     }
 
     // シグナル設定: シェル自体は SIGINT/SIGTSTP/SIGTTOU/SIGTTIN を無視する。
@@ -476,6 +362,9 @@ fn main() {
         libc::signal(libc::SIGTTOU, libc::SIG_IGN);
         libc::signal(libc::SIGTTIN, libc::SIG_IGN);
     }
+    // 端末サイズを COLUMNS / LINES に設定し、ウィンドウサイズ変更（SIGWINCH）に追従する
+    shell::update_terminal_size_vars();
+    shell::install_winch_handler();
 
     // シェルを自身のプロセスグループリーダーにし、ターミナルを掌握する。
     unsafe {
@@ -485,7 +374,7 @@ fn main() {
     }
 
     let mut shell = Shell::new();
-    load_rc(&mut shell);
+    load_startup_files(&mut shell, &inv, true);
     // 行エディタ: raw モードによるキー入力、履歴、Tab 補完、シンタックスハイライトを統合。
     // raw モードは read_line() 内でのみ有効で、コマンド実行中は cooked モードに戻る。
     let mut editor = editor::LineEditor::new();
@@ -494,12 +383,22 @@ fn main() {
         // プロンプト前にバックグラウンドジョブを reap し、完了通知を出力
         job::reap_jobs(&mut shell.jobs);
         job::notify_and_clean(&mut shell.jobs);
+        shell::refresh_terminal_size();
+        // RUSH_SET_TITLE=1 ならプロンプト表示中はタイトルをシェル名に戻す
+        shell::set_terminal_title("rush");
 
         // プロンプト構築: $PROMPT が設定されていればエスケープ展開、なければデフォルト
         let prompt = build_prompt(shell.last_status);
 
+        // 右プロンプト: $RPROMPT を $PROMPT と同じエスケープで展開し、最初の行にだけ表示する
+        editor.set_right_prompt(
+            std::env::var("RPROMPT").map(|fmt| expand_prompt(&fmt, shell.last_status)).unwrap_or_default(),
+        );
+
         // 行エディタで 1 行読み取る（raw モード → Enter で確定 → cooked モードに復帰）
-        match editor.read_line(&prompt) {
+        let input = editor.read_line(&prompt);
+        editor.set_right_prompt(String::new());
+        match input {
             Some(line) if !line.trim().is_empty() => {
                 // 履歴展開（`!!`, `!N`, `!-N`, `!prefix`）
                 let line = match expand_history(&line, editor.history()) {
@@ -516,8 +415,10 @@ fn main() {
                     }
                 };
                 editor.add_history(&line);
+                shell::set_terminal_title(&line);
+                let ps2 = continuation_prompt(shell.last_status);
                 // エイリアス展開（コマンド位置の最初の単語のみ、再帰ガード付き）
-                let mut accumulated = expand_alias(&line, &shell.aliases);
+                let mut accumulated = shell::expand_alias(&line, &shell.aliases);
 
                 // 継続行入力ループ: 末尾 `\`、未完了パイプ/演算子、未閉クォートに対応
                 loop {
@@ -525,7 +426,7 @@ fn main() {
                     let trimmed_end = accumulated.trim_end();
                     if trimmed_end.ends_with('\\') {
                         accumulated = trimmed_end[..trimmed_end.len() - 1].to_string();
-                        match editor.read_line("> ") {
+                        match editor.read_line(&ps2) {
                             Some(next) => {
                                 accumulated.push_str(&next);
                                 continue;
@@ -542,8 +443,8 @@ fn main() {
                     }
 
                     // if 複合コマンド: `if` で始まる入力を検出したら、
-                    // `> ` プロンプトで `fi` まで対話的に行を収集し、
-                    // ブロック全体を execute_if_block() で実行する。
+                    // 継続プロンプト（`$PS2`）で `fi` まで対話的に行を収集し、
+                    // ブロック全体を execute_block() で実行する。
                     if executor::starts_with_if(accumulated.trim()) {
                         let mut block = accumulated.clone();
                         let mut depth = 0i32;
@@ -555,7 +456,7 @@ fn main() {
                             }
                         }
                         while depth > 0 {
-                            match editor.read_line("> ") {
+                            match editor.read_line(&ps2) {
                                 Some(next) => {
                                     block.push('\n');
                                     block.push_str(&next);
@@ -574,18 +475,16 @@ fn main() {
                             }
                         }
                         if depth == 0 {
-                            shell.last_status = executor::execute_if_block(&mut shell, &block);
+                            shell.last_status = executor::execute_block(&mut shell, &block);
                         }
                         break;
                     }
 
-                    // for/while/until ループ: `> ` プロンプトで `done` まで収集
+                    // for/while/until ループ: 継続プロンプト（`$PS2`）で `done` まで収集
                     if executor::starts_with_for(accumulated.trim())
                         || executor::starts_with_while(accumulated.trim())
                         || executor::starts_with_until(accumulated.trim())
                     {
-                        let is_until = executor::starts_with_until(accumulated.trim());
-                        let is_for = executor::starts_with_for(accumulated.trim());
                         let mut block = accumulated.clone();
                         let mut depth = 0i32;
                         for token in executor::shell_tokens_pub(block.trim()) {
@@ -596,7 +495,7 @@ fn main() {
                             }
                         }
                         while depth > 0 {
-                            match editor.read_line("> ") {
+                            match editor.read_line(&ps2) {
                                 Some(next) => {
                                     block.push('\n');
                                     block.push_str(&next);
@@ -615,17 +514,12 @@ fn main() {
                             }
                         }
                         if depth == 0 {
-                            if is_for {
-                                shell.last_status = executor::execute_for_block(&mut shell, &block);
-                            } else {
-                                shell.last_status = executor::execute_while_block(
-                                    &mut shell, &block, is_until);
-                            }
+                            shell.last_status = executor::execute_block(&mut shell, &block);
                         }
                         break;
                     }
 
-                    // case 文: `> ` プロンプトで `esac` まで収集
+                    // case 文: 継続プロンプト（`$PS2`）で `esac` まで収集
                     if executor::starts_with_case(accumulated.trim()) {
                         let mut block = accumulated.clone();
                         let mut depth = 0i32;
@@ -637,7 +531,7 @@ fn main() {
                             }
                         }
                         while depth > 0 {
-                            match editor.read_line("> ") {
+                            match editor.read_line(&ps2) {
                                 Some(next) => {
                                     block.push('\n');
                                     block.push_str(&next);
@@ -656,11 +550,26 @@ fn main() {
                             }
                         }
                         if depth == 0 {
-                            shell.last_status = executor::execute_case_block(&mut shell, &block);
+                            shell.last_status = executor::execute_block(&mut shell, &block);
                         }
                         break;
                     }
 
+                    // 関数定義の頭部だけの行（`name()` / `function name`）: `{` を含む次の行を連結
+                    if executor::is_function_header(accumulated.trim()) {
+                        match editor.read_line(&ps2) {
+                            Some(next) => {
+                                accumulated.push(' ');
+                                accumulated.push_str(&next);
+                                continue;
+                            }
+                            None => {
+                                println!();
+                                break;
+                            }
+                        }
+                    }
+
                     // 関数定義: `name() {` を検出して `}` まで収集
                     if let Some((name, rest)) = executor::parse_function_def(accumulated.trim()) {
                         let mut body = rest.clone();
@@ -682,7 +591,7 @@ fn main() {
                                 }
                             }
                             while depth > 0 {
-                                match editor.read_line("> ") {
+                                match editor.read_line(&ps2) {
                                     Some(next) => {
                                         let next_trimmed = next.trim();
                                         for token in executor::shell_tokens_pub(next_trimmed) {
@@ -716,8 +625,8 @@ fn main() {
                         break;
                     }
 
-                    // パース: 不完全入力なら `> ` プロンプトで継続行を読み取る
-                    match parser::parse(&accumulated, shell.last_status, &shell.positional_args, shell.set_nounset, &shell.arrays) {
+                    // パース: 不完全入力なら継続プロンプト（`$PS2`）で継続行を読み取る
+                    match parser::parse(&accumulated, shell.last_status, &shell.shell_name, &shell.positional_args, shell.set_nounset, &shell.arrays) {
                         Ok(Some(mut list)) => {
                             // ヒアドキュメントの本文を対話的に収集
                             let delims = parser::heredoc_delimiters(&list);
@@ -726,15 +635,13 @@ fn main() {
                                 for delim in &delims {
                                     let mut body = String::new();
                                     loop {
-                                        match editor.read_line("> ") {
+                                        match editor.read_line(&ps2) {
                                             Some(line) => {
                                                 if line.trim() == delim.as_str() {
                                                     break;
                                                 }
-                                                if !body.is_empty() {
-                                                    body.push('\n');
-                                                }
                                                 body.push_str(&line);
+                                                body.push('\n');
                                             }
                                             None => break,
                                         }
@@ -749,8 +656,8 @@ fn main() {
                         }
                         Ok(None) => break,
                         Err(parser::ParseError::IncompleteInput)
-                        | Err(parser::ParseError::UnterminatedQuote(_)) => {
-                            match editor.read_line("> ") {
+                        | Err(parser::ParseError::UnterminatedQuote(..)) => {
+                            match editor.read_line(&ps2) {
                                 Some(next) => {
                                     accumulated.push('\n');
                                     accumulated.push_str(&next);
@@ -784,5 +691,5 @@ fn main() {
         }
     }
 
-    std::process::exit(shell.last_status);
+    std::process::exit(shell.shutdown());
 }
@@ -8,12 +8,12 @@
 //! - パイプライン: `cmd1 | cmd2 | cmd3`
 //! - リダイレクト: `>`, `>>`, `<`, `2>`, `2>>`, `<<DELIM`（ヒアドキュメント）, `<<<`（ヒアストリング）
 //! - クォート: シングル (`'...'`) / ダブル (`"..."`)
-//! - 変数展開: `$VAR`, `${VAR}`, `$?`, `$$`, `$!`, `$0`, `$RANDOM`, `$SECONDS`,
+//! - 変数展開: `$VAR`, `${VAR}`, `$?`, `$$`, `$!`, `$0`, `$RANDOM`, `$SECONDS`, `$LINENO`, `$PPID`,
 //!   `$1`〜`$9`（位置パラメータ）, `$@`, `$*`（全引数）, `$#`（引数個数）
 //!   （ダブルクォート内・裸ワードで展開、シングルクォートではリテラル）
-//! - パラメータ展開: `${var:-default}`, `${var:=val}`, `${var:+alt}`, `${var:?msg}`,
+//! - パラメータ展開: `${var:-default}`, `${var:=val}`, `${var:+alt}`, `${var:?msg}`（`:` なしの形も）,
 //!   `${#var}`, `${var%pat}`, `${var%%pat}`, `${var#pat}`, `${var##pat}`,
-//!   `${var/pat/repl}`, `${var//pat/repl}`
+//!   `${var/pat/repl}`, `${var//pat/repl}`, `${!prefix@}`, `${!prefix*}`
 //! - チルダ展開: `~` → `$HOME`, `~/path`, `~user`, `VAR=~/path`
 //! - コマンド置換パススルー: `$(cmd)`, `` `cmd` `` — パーサーでは展開せずリテラル保持、executor で展開
 //! - 算術展開: `$((expr))` — 四則演算・剰余・括弧・変数参照を i64 で計算
@@ -23,12 +23,15 @@
 //! - エスケープ: `\"`, `\\`, `\$`（ダブルクォート内）, `\X`（裸ワード）
 //! - インライン代入: `VAR=val cmd`（コマンド先頭の `VAR=val` を代入として検出）
 //! - サブシェル: `( cmd1; cmd2 )` — 本体テキストを `Command.subshell_body` に格納
+//! - 複合コマンド: `if` / `for` / `while` / `until` / `case` — 予約語で区切った [`Compound`] を `Command.compound` に格納
 //! - 継続行検出: 末尾の `|`, `&&`, `||` を [`ParseError::IncompleteInput`] として報告
 
 use std::borrow::Cow;
 use std::collections::{BTreeMap, HashMap};
 use std::fmt;
 
+use crate::glob;
+
 /// 配列変数マップ型。キーは配列名、値はインデックス→要素の順序付きマップ。
 pub type ArrayMap = HashMap<String, BTreeMap<usize, String>>;
 
@@ -39,6 +42,9 @@ pub type ArrayMap = HashMap<String, BTreeMap<usize, String>>;
 #[derive(Debug, PartialEq)]
 pub struct CommandList<'a> {
     pub items: Vec<ListItem<'a>>,
+    /// パース中の `${var:=default}` で未設定から作られた変数名。
+    /// executor が非 export のシェル変数として扱う。
+    pub default_assigned: Vec<String>,
 }
 
 /// リスト内の 1 要素。
@@ -68,6 +74,8 @@ pub struct Pipeline<'a> {
     pub commands: Vec<Command<'a>>,
     /// 末尾に `&` が指定された場合に `true`。
     pub background: bool,
+    /// 先頭に `time` 予約語が指定された場合に `true`。executor が実行時間を報告する。
+    pub timed: bool,
 }
 
 /// 単一コマンド。引数リストとリダイレクト指定を持つ。
@@ -87,23 +95,28 @@ pub struct Command<'a> {
     /// サブシェル `( cmd1; cmd2 )` の本体テキスト。
     /// `Some` のとき args は空で、executor が fork して本体を実行する。
     pub subshell_body: Option<String>,
+    /// 複合コマンド `if` / `for` / `while` / `until`。`Some` のとき args は空。
+    pub compound: Option<Compound>,
     /// 配列代入 `arr=(a b c)`。
     pub array_assignments: Vec<(String, Vec<String>)>,
     /// 配列追加 `arr+=(a b c)`。
     pub array_appends: Vec<(String, Vec<String>)>,
     /// インデックス代入 `arr[N]=val`。
     pub indexed_assignments: Vec<(String, usize, String)>,
+    /// 引数・リダイレクトの算術展開（`$((x=1))`, `$((x++))` 等）による代入。
+    /// パース時には書き込まず、executor がコマンドを実行するときに反映する。
+    pub arith_assignments: Vec<(String, String)>,
 }
 
 /// ファイルリダイレクト指定。種別とターゲットファイルパスを持つ。
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct Redirect<'a> {
     pub kind: RedirectKind,
     pub target: Cow<'a, str>,
 }
 
 /// リダイレクトの種別。
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum RedirectKind {
     /// `>` — stdout を上書き
     Output,
@@ -115,58 +128,95 @@ pub enum RedirectKind {
     Stderr,
     /// `2>>` — stderr を追記
     StderrAppend,
+    /// `N>file` / `N>>file`（N は 3〜9）— 任意の fd をファイルに向ける（`append` なら追記）
+    FdOutput { fd: i32, append: bool },
     /// `N>&M` — fd 複製（src_fd を dst_fd のコピーにする）
     FdDup { src_fd: i32, dst_fd: i32 },
-    /// `<<DELIM` — ヒアドキュメント（stdin にテキストブロックを供給）
+    /// `<<DELIM` — ヒアドキュメント（stdin にテキストブロックを供給）。本文は変数・コマンド置換を展開する
     HereDoc,
-    /// `<<<` — ヒアストリング（stdin に文字列を供給）
+    /// `<<'DELIM'` — デリミタがクォートされたヒアドキュメント。本文を展開せずそのまま供給する
+    HereDocLiteral,
+    /// `<<<` — ヒアストリング（stdin に文字列を供給）。コマンド置換は実行時に展開する
     HereString,
+    /// `<<<'word'` — コマンド置換がクォートの内側にしかないヒアストリング。置換を実行せずそのまま供給する
+    HereStringLiteral,
 }
 
 // ── Error ───────────────────────────────────────────────────────────
 
+/// 構文エラーの位置。
+///
+/// `offset` は入力先頭からのバイト位置、`column` はその行での 1 始まりの桁（文字単位）。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ErrorPos {
+    pub offset: usize,
+    pub column: usize,
+}
+
+impl ErrorPos {
+    /// `input` の `offset` バイト目を指す位置を作る。
+    pub fn at(input: &str, offset: usize) -> Self {
+        let offset = offset.min(input.len());
+        let line_start = input[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let column = input[line_start..offset].chars().count() + 1;
+        Self { offset, column }
+    }
+}
+
 /// パース時に発生しうるエラー。
 #[derive(Debug, PartialEq)]
 pub enum ParseError {
-    /// クォートが閉じられていない。引数は開始クォート文字（`'` or `"`）。
-    UnterminatedQuote(char),
-    /// リダイレクト演算子の後にターゲットファイル名がない。
-    MissingRedirectTarget,
-    /// パイプ、`&&`、`||` の前後にコマンドがない。
-    EmptyPipelineSegment,
-    /// fd 複製リダイレクトの dst_fd が不正。
-    BadFdRedirect,
+    /// クォートが閉じられていない。引数は開始クォート文字（`'` or `"`）とその位置。
+    UnterminatedQuote(char, ErrorPos),
+    /// リダイレクト演算子の後にターゲットファイル名がない。位置は演算子。
+    MissingRedirectTarget(ErrorPos),
+    /// パイプ、`&&`、`||` の前後にコマンドがない。位置は予期しないトークン。
+    EmptyPipelineSegment(ErrorPos),
+    /// fd 複製リダイレクトの dst_fd が不正。位置は `N>&` 演算子。
+    BadFdRedirect(ErrorPos),
+    /// その位置に現れてはいけないトークン（case の外の `;;`、`if` の中の `done` 等）。
+    UnexpectedToken(String, ErrorPos),
     /// 入力が不完全（末尾の `|`, `&&`, `||` 等）。対話モードでは継続行入力のトリガー。
     IncompleteInput,
     /// `set -u` (nounset) で未定義変数を参照した。
     UnboundVariable(String),
+    /// 算術展開の評価エラー（ゼロ除算・オーバーフロー）。引数は `式: 理由` 形式。
+    ArithError(String),
 }
 
 impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::UnterminatedQuote(c) => write!(f, "unexpected EOF while looking for matching `{c}`"),
-            Self::MissingRedirectTarget => write!(f, "syntax error: missing redirect target"),
-            Self::EmptyPipelineSegment => write!(f, "syntax error near unexpected token"),
-            Self::BadFdRedirect => write!(f, "syntax error: invalid file descriptor in redirect"),
+            Self::UnterminatedQuote(c, pos) => {
+                write!(f, "syntax error near column {}: unexpected EOF while looking for matching `{c}`", pos.column)
+            }
+            Self::MissingRedirectTarget(pos) => write!(f, "syntax error near column {}: missing redirect target", pos.column),
+            Self::EmptyPipelineSegment(pos) => write!(f, "syntax error near column {}: unexpected token", pos.column),
+            Self::BadFdRedirect(pos) => {
+                write!(f, "syntax error near column {}: invalid file descriptor in redirect", pos.column)
+            }
+            Self::UnexpectedToken(op, pos) => write!(f, "syntax error near column {}: unexpected token `{op}`", pos.column),
             Self::IncompleteInput => write!(f, "syntax error: unexpected end of input"),
             Self::UnboundVariable(name) => write!(f, "{}: unbound variable", name),
+            Self::ArithError(msg) => write!(f, "{}", msg),
         }
     }
 }
 
 // ── Tilde expansion ─────────────────────────────────────────────────
 
-/// チルダ展開: `~` → $HOME, `~/path` → $HOME/path, `~user` → user のホーム。
-/// `=` の後のチルダも展開する（`export VAR=~/foo`）。
+/// チルダ展開: `~` → $HOME, `~/path` → $HOME/path, `~user` → user のホーム、
+/// `~+` → $PWD, `~-` → $OLDPWD。
+/// `=` の後の値では `:` で区切った各要素の先頭のチルダも展開する（`export VAR=~/foo`, `PATH=~/a:~/b`）。
 pub fn expand_tilde(s: &str) -> Cow<'_, str> {
     if !s.starts_with('~') {
-        // `=` の後にチルダがあるケースをチェック
+        // `=` の後にチルダがあるケースをチェック。値は `:` 区切りの要素ごとに展開する（`PATH=~/bin:~/sbin`）
         if let Some(eq) = s.find('=') {
-            if s[eq + 1..].starts_with('~') {
-                let (key, val) = s.split_at(eq + 1);
-                if let Cow::Owned(expanded) = expand_tilde_prefix(val) {
-                    return Cow::Owned(format!("{}{}", key, expanded));
+            let (key, val) = s.split_at(eq + 1);
+            if val.starts_with('~') || val.contains(":~") {
+                let parts: Vec<Cow<'_, str>> = val.split(':').map(expand_tilde_prefix).collect();
+                if parts.iter().any(|p| matches!(p, Cow::Owned(_))) {
+                    return Cow::Owned(format!("{}{}", key, parts.join(":")));
                 }
             }
         }
@@ -183,37 +233,160 @@ fn expand_tilde_prefix(s: &str) -> Cow<'_, str> {
     let user_part = &s[1..rest_start];
     let rest = &s[rest_start..];
 
-    if user_part.is_empty() {
-        // ~ or ~/path → $HOME
-        match std::env::var("HOME") {
-            Ok(home) => Cow::Owned(format!("{}{}", home, rest)),
+    if user_part.is_empty() || user_part == "+" || user_part == "-" {
+        // ~ or ~/path → $HOME、~+ → $PWD、~- → $OLDPWD（未設定なら展開しない）
+        let var = match user_part {
+            "+" => "PWD",
+            "-" => "OLDPWD",
+            _ => "HOME",
+        };
+        match std::env::var(var) {
+            Ok(dir) => Cow::Owned(format!("{}{}", dir, rest)),
             Err(_) => Cow::Borrowed(s),
         }
     } else {
         // ~user → getpwnam
-        let c_user = match std::ffi::CString::new(user_part) {
-            Ok(c) => c,
-            Err(_) => return Cow::Borrowed(s),
-        };
-        let pw = unsafe { libc::getpwnam(c_user.as_ptr()) };
-        if pw.is_null() {
-            return Cow::Borrowed(s);
+        match user_home(user_part).and_then(|h| String::from_utf8(h).ok()) {
+            Some(h) => Cow::Owned(format!("{}{}", h, rest)),
+            None => Cow::Borrowed(s),
         }
-        let home = unsafe { std::ffi::CStr::from_ptr((*pw).pw_dir) };
-        match home.to_str() {
-            Ok(h) => Cow::Owned(format!("{}{}", h, rest)),
-            Err(_) => Cow::Borrowed(s),
+    }
+}
+
+/// ユーザ名からホームディレクトリを `getpwnam` で引く。
+fn user_home(user: &str) -> Option<Vec<u8>> {
+    let c_user = std::ffi::CString::new(user).ok()?;
+    let pw = unsafe { libc::getpwnam(c_user.as_ptr()) };
+    if pw.is_null() {
+        return None;
+    }
+    Some(unsafe { std::ffi::CStr::from_ptr((*pw).pw_dir) }.to_bytes().to_vec())
+}
+
+/// チルダ展開の逆変換: 先頭の `$HOME` を `~` に、他ユーザのホームを `~user` に省略する。
+///
+/// ディレクトリスタック表示（`dirs`/`pushd`/`popd`）とプロンプトの `\w` で共用する。
+/// `~user` はパスの祖先ディレクトリ名をユーザ名とみなし、そのホームと一致する場合のみ使う。
+pub fn abbreviate_home(path: &str) -> Cow<'_, str> {
+    if let Ok(home) = std::env::var("HOME") {
+        let home = home.trim_end_matches('/');
+        if !home.is_empty() {
+            if let Some(rest) = path.strip_prefix(home) {
+                if rest.is_empty() || rest.starts_with('/') {
+                    return Cow::Owned(format!("~{}", rest));
+                }
+            }
+        }
+    }
+    // /home/alice/src → ~alice/src（祖先ディレクトリ名 = ユーザ名でホームが一致する場合）
+    let mut end = path.len();
+    while let Some(slash) = path[..end].rfind('/') {
+        let name = &path[slash + 1..end];
+        if !name.is_empty() && user_home(name).is_some_and(|dir| dir == path.as_bytes()[..end]) {
+            return Cow::Owned(format!("~{}{}", name, &path[end..]));
         }
+        end = slash;
     }
+    Cow::Borrowed(path)
 }
 
 // ── Variable expansion (crate-private) ──────────────────────────────
 
+/// 非クォートのコマンド置換（`$(...)` / `` `...` ``）の直前に置くマーカー。
+///
+/// executor はこのマーカーの付いた置換の出力だけを `$IFS` でフィールド分割する。
+/// クォート内の `"$(cmd)"` は出力の内部の改行を含めてそのまま 1 ワードになる。
+pub const UNQUOTED_SUB: char = '\x1D';
+
+/// `$(` の直後の位置 `start` から、対応する `)` の位置を返す。閉じていなければ `None`。
+///
+/// クォート・バックスラッシュ・バッククォート・ネストした `(...)` / `$(...)`
+/// （ダブルクォート内のものを含む）を読み飛ばす。executor のコマンド置換展開と共用する。
+pub fn command_sub_end(bytes: &[u8], start: usize) -> Option<usize> {
+    let len = bytes.len();
+    let mut depth = 1;
+    let mut i = start;
+    while i < len {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'\'' => {
+                i += 1;
+                while i < len && bytes[i] != b'\'' { i += 1; }
+            }
+            b'"' => i = double_quote_end(bytes, i + 1)?,
+            b'`' => {
+                i += 1;
+                while i < len && bytes[i] != b'`' { i += 1; }
+            }
+            b'(' => depth += 1,
+            b')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// ダブルクォートの開始直後の位置 `start` から、閉じ `"` の位置を返す。中の `$(...)` は丸ごと読み飛ばす。
+fn double_quote_end(bytes: &[u8], start: usize) -> Option<usize> {
+    let len = bytes.len();
+    let mut i = start;
+    while i < len {
+        match bytes[i] {
+            b'\\' => i += 1,
+            b'"' => return Some(i),
+            b'$' if bytes.get(i + 1) == Some(&b'(') => i = command_sub_end(bytes, i + 2)?,
+            b'`' => {
+                i += 1;
+                while i < len && bytes[i] != b'`' { i += 1; }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// 変数展開の失敗理由。
+#[derive(Debug, PartialEq)]
+pub enum ExpandError {
+    /// `set -u` (nounset) で未定義変数を参照した。引数は変数名。
+    Unbound(String),
+    /// 算術展開の評価エラー（ゼロ除算・オーバーフロー）。引数は `式: 理由` 形式。
+    Arith(String),
+}
+
+impl fmt::Display for ExpandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unbound(name) => write!(f, "{}: unbound variable", name),
+            Self::Arith(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
 /// `$VAR` / `${VAR}` / `$?` を展開する。`$` が含まれなければゼロコピーの `Cow::Borrowed` を返す。
-pub fn expand_variables<'a>(s: &'a str, last_status: i32, pos_args: &[String], nounset: bool, arrays: &ArrayMap) -> Result<Cow<'a, str>, String> {
-    if !s.contains('$') {
+pub fn expand_variables<'a>(s: &'a str, last_status: i32, shell_name: &str, pos_args: &[String], nounset: bool, arrays: &ArrayMap) -> Result<Cow<'a, str>, ExpandError> {
+    expand_variables_impl(s, last_status, shell_name, pos_args, nounset, arrays, false)
+}
+
+/// 非クォートのワード用の [`expand_variables`]。展開結果を `$IFS` でフィールド分割し、
+/// 区切りを `\x1F` に置き換える（executor が個別の引数に分割する）。
+/// 展開の結果ワード全体が空になった場合は `\x1F` のみを返し、ワードごと消える。
+fn expand_variables_split<'a>(s: &'a str, last_status: i32, shell_name: &str, pos_args: &[String], nounset: bool, arrays: &ArrayMap) -> Result<Cow<'a, str>, ExpandError> {
+    expand_variables_impl(s, last_status, shell_name, pos_args, nounset, arrays, true)
+}
+
+fn expand_variables_impl<'a>(s: &'a str, last_status: i32, shell_name: &str, pos_args: &[String], nounset: bool, arrays: &ArrayMap, field_split: bool) -> Result<Cow<'a, str>, ExpandError> {
+    if !s.contains('$') && (!field_split || !s.contains('`')) {
         return Ok(Cow::Borrowed(s));
     }
+    let ifs = if field_split { ifs_chars() } else { String::new() };
 
     let bytes = s.as_bytes();
     let len = bytes.len();
@@ -222,6 +395,16 @@ pub fn expand_variables<'a>(s: &'a str, last_status: i32, pos_args: &[String], n
     let mut start = 0; // コピーされていない部分の先頭
 
     while pos < len {
+        if field_split && bytes[pos] == b'`' {
+            // 非クォートのバッククォート置換 → 出力を分割させるためマーカーを付けてリテラル保持
+            result.push_str(&s[start..pos]);
+            result.push(UNQUOTED_SUB);
+            let end = bytes[pos + 1..].iter().position(|&b| b == b'`').map_or(len, |off| pos + 1 + off + 1);
+            result.push_str(&s[pos..end]);
+            pos = end;
+            start = pos;
+            continue;
+        }
         if bytes[pos] != b'$' {
             pos += 1;
             continue;
@@ -254,7 +437,7 @@ pub fn expand_variables<'a>(s: &'a str, last_status: i32, pos_args: &[String], n
                                     paren_depth -= 1;
                                 } else if pos + 1 < len && bytes[pos + 1] == b')' {
                                     let expr = &s[expr_start..pos];
-                                    result.push_str(&eval_arithmetic(expr, last_status, pos_args, nounset, arrays)?);
+                                    result.push_str(&eval_arithmetic(expr, last_status, shell_name, pos_args, nounset, arrays)?);
                                     pos += 2; // skip '))'
                                     found = true;
                                     break;
@@ -272,35 +455,15 @@ pub fn expand_variables<'a>(s: &'a str, last_status: i32, pos_args: &[String], n
                     }
                 } else {
                     // $(...) — コマンド置換。executor で展開するのでリテラル保持。
-                    result.push_str("$(");
-                    pos += 1; // skip '('
-                    let mut depth = 1;
-                    while pos < len && depth > 0 {
-                        match bytes[pos] {
-                            b'(' => depth += 1,
-                            b')' => {
-                                depth -= 1;
-                                if depth == 0 {
-                                    pos += 1;
-                                    break;
-                                }
-                            }
-                            b'\'' => {
-                                result.push(bytes[pos] as char);
-                                pos += 1;
-                                while pos < len && bytes[pos] != b'\'' {
-                                    result.push(bytes[pos] as char);
-                                    pos += 1;
-                                }
-                            }
-                            _ => {}
-                        }
-                        if depth > 0 {
-                            result.push(bytes[pos] as char);
-                            pos += 1;
-                        }
+                    // 非クォートなら出力をフィールド分割させるためマーカーを付ける
+                    let end = command_sub_end(bytes, pos + 1).unwrap_or(len);
+                    if field_split {
+                        result.push(UNQUOTED_SUB);
                     }
+                    result.push('$');
+                    result.push_str(&s[pos..end]);
                     result.push(')');
+                    pos = (end + 1).min(len);
                 }
             }
             b'?' => {
@@ -317,7 +480,7 @@ pub fn expand_variables<'a>(s: &'a str, last_status: i32, pos_args: &[String], n
                 pos += 1;
             }
             b'0' => {
-                result.push_str("rush");
+                result.push_str(shell_name);
                 pos += 1;
             }
             b'1'..=b'9' => {
@@ -325,25 +488,19 @@ pub fn expand_variables<'a>(s: &'a str, last_status: i32, pos_args: &[String], n
                 let n = (bytes[pos] - b'0') as usize;
                 pos += 1;
                 if let Some(val) = pos_args.get(n - 1) {
-                    result.push_str(val);
+                    push_split(&mut result, val, &ifs);
                 } else if nounset {
-                    return Err(format!("${}", n));
+                    return Err(ExpandError::Unbound(format!("${}", n)));
                 }
             }
             b'@' => {
                 // $@ — 全位置パラメータ（個別の単語として展開）
-                for (i, arg) in pos_args.iter().enumerate() {
-                    if i > 0 { result.push(' '); }
-                    result.push_str(arg);
-                }
+                result.push_str(&join_positional_at(pos_args));
                 pos += 1;
             }
             b'*' => {
-                // $* — 全位置パラメータ（単一文字列として展開）
-                for (i, arg) in pos_args.iter().enumerate() {
-                    if i > 0 { result.push(' '); }
-                    result.push_str(arg);
-                }
+                // $* — 全位置パラメータ（IFS の先頭文字で結合した単一文字列）
+                push_split(&mut result, &join_positional_star(pos_args), &ifs);
                 pos += 1;
             }
             b'#' => {
@@ -371,7 +528,7 @@ pub fn expand_variables<'a>(s: &'a str, last_status: i32, pos_args: &[String], n
                     let inner = &s[var_start..pos];
                     pos += 1; // skip '}'
                     if !inner.is_empty() {
-                        result.push_str(&expand_braced_param(inner, last_status, pos_args, nounset, arrays)?);
+                        push_split(&mut result, &expand_braced_param(inner, last_status, shell_name, pos_args, nounset, arrays)?, &ifs);
                     }
                 } else {
                     // 閉じ '}' がない → リテラル "${"
@@ -389,11 +546,11 @@ pub fn expand_variables<'a>(s: &'a str, last_status: i32, pos_args: &[String], n
                     result.push_str(&val);
                 } else if let Some(arr) = arrays.get(var_name) {
                     // $arr → arr[0] と等価
-                    result.push_str(arr.get(&0).map(|s| s.as_str()).unwrap_or(""));
-                } else if let Ok(val) = std::env::var(var_name) {
-                    result.push_str(&val);
+                    push_split(&mut result, arr.get(&0).map(|s| s.as_str()).unwrap_or(""), &ifs);
+                } else if let Some(val) = env_var(var_name) {
+                    push_split(&mut result, &val, &ifs);
                 } else if nounset {
-                    return Err(var_name.to_string());
+                    return Err(ExpandError::Unbound(var_name.to_string()));
                 }
                 // 未定義 → 空文字（何も追加しない）
             }
@@ -409,18 +566,251 @@ pub fn expand_variables<'a>(s: &'a str, last_status: i32, pos_args: &[String], n
     // 残りの部分をコピー
     result.push_str(&s[start..]);
 
+    if field_split && result.is_empty() {
+        result.push('\x1F');
+    }
     Ok(Cow::Owned(result))
 }
 
+/// フィールド分割に使う `$IFS` の文字集合。未設定ならスペース・タブ・改行。
+pub fn ifs_chars() -> String {
+    std::env::var("IFS").unwrap_or_else(|_| " \t\n".to_string())
+}
+
+/// 展開結果を追加する。`ifs` が空でなければ IFS 文字を `\x1F` に置き換える。
+///
+/// 空白の IFS 文字は連続しても 1 つの区切りだが（空フィールドは executor が捨てる）、
+/// 空白以外の IFS 文字（`IFS=:` の `:` 等）は 1 文字ごとに区切るので、`a::b` の間や先頭の `:` の前には
+/// 空フィールドができる。空フィールドは [`glob::QUOTED_GLOB`] だけのフィールドとして残す
+/// （[`glob::strip_quoted`] で空文字列になる）。末尾の区切りは空フィールドを作らない。
+pub fn push_split(result: &mut String, val: &str, ifs: &str) {
+    if ifs.is_empty() {
+        result.push_str(val);
+        return;
+    }
+    #[derive(PartialEq)]
+    enum State { Start, InField, AfterSpace, AfterDelim }
+    let mut state = if result.is_empty() {
+        State::Start
+    } else if result.ends_with('\x1F') {
+        State::AfterSpace
+    } else {
+        State::InField
+    };
+    for c in val.chars() {
+        if !ifs.contains(c) {
+            result.push(c);
+            state = State::InField;
+        } else if c.is_ascii_whitespace() {
+            result.push('\x1F');
+            if state == State::InField {
+                state = State::AfterSpace;
+            }
+        } else {
+            match state {
+                // 直前の空白と合わせて 1 つの区切り
+                State::InField | State::AfterSpace => result.push('\x1F'),
+                State::Start | State::AfterDelim => {
+                    result.push(glob::QUOTED_GLOB);
+                    result.push('\x1F');
+                }
+            }
+            state = State::AfterDelim;
+        }
+    }
+}
+
 /// 変数名の先頭文字として有効か（ASCII英字 or `_`）
 /// シェル起動時刻（`$SECONDS` 用）。プロセス起動からの秒数を返す。
 static SHELL_START: std::sync::LazyLock<std::time::Instant> =
     std::sync::LazyLock::new(std::time::Instant::now);
 
+thread_local! {
+    /// `$LINENO` の値。[`Shell::run`](crate::shell::Shell::run) と `source` が実行中の行番号（1 始まり）を設定する。
+    static LINENO: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+/// `$LINENO` として展開される行番号を設定する。
+pub fn set_lineno(n: usize) {
+    LINENO.with(|l| l.set(n));
+}
+
+/// 現在の `$LINENO` の値を返す。
+pub fn lineno() -> usize {
+    LINENO.with(|l| l.get())
+}
+
+thread_local! {
+    /// `Some` の間（[`parse`] / [`expand_words`] / [`expand_text`] の実行中）は
+    /// `${var:=default}` で未設定から作られた変数名を記録する。
+    static DEFAULT_ASSIGNED: std::cell::RefCell<Option<Vec<String>>> = const { std::cell::RefCell::new(None) };
+}
+
+/// 展開の間だけ `${var:=default}` の記録を有効にし、終了時に外側の記録へ戻す。
+///
+/// [`finish`](Self::finish) せずに抜けた（エラーで早期リターンした）ときは、記録を外側のスコープへ引き継ぐ。
+struct DefaultAssignedScope(Option<Vec<String>>);
+
+impl DefaultAssignedScope {
+    fn enter() -> Self {
+        Self(DEFAULT_ASSIGNED.with(|names| names.borrow_mut().replace(Vec::new())))
+    }
+
+    /// 記録した変数名を取り出してスコープを閉じる。
+    fn finish(self) -> Vec<String> {
+        DEFAULT_ASSIGNED.with(|names| names.borrow_mut().replace(Vec::new())).unwrap_or_default()
+    }
+}
+
+impl Drop for DefaultAssignedScope {
+    fn drop(&mut self) {
+        let mut outer = self.0.take();
+        let names = DEFAULT_ASSIGNED.with(|names| names.borrow_mut().take()).unwrap_or_default();
+        if let Some(outer) = outer.as_mut() {
+            outer.extend(names);
+        }
+        DEFAULT_ASSIGNED.with(|names| *names.borrow_mut() = outer);
+    }
+}
+
+/// [`parse`] 中の算術展開による代入の記録。
+struct ArithWrites {
+    /// 代入 `(名前, 値)` を評価順に並べたもの。同じコマンドの後続の参照はここを優先する。
+    log: Vec<(String, String)>,
+    /// `log` のうち既に [`Command::arith_assignments`] に移した件数。
+    claimed: usize,
+}
+
+thread_local! {
+    /// `Some` の間（[`parse`] の実行中）は算術展開の代入を環境に書かずに記録する。
+    /// 実行されないコマンド（`false && : $((y++))`）の代入が反映されないようにするため。
+    static ARITH_WRITES: std::cell::RefCell<Option<ArithWrites>> = const { std::cell::RefCell::new(None) };
+}
+
+/// [`parse`] の間だけ算術代入の記録を有効にし、終了時（エラーによる早期リターンを含む）に元へ戻す。
+struct ArithWritesScope(Option<ArithWrites>);
+
+impl ArithWritesScope {
+    fn enter() -> Self {
+        let fresh = ArithWrites { log: Vec::new(), claimed: 0 };
+        Self(ARITH_WRITES.with(|w| w.borrow_mut().replace(fresh)))
+    }
+}
+
+impl Drop for ArithWritesScope {
+    fn drop(&mut self) {
+        let saved = self.0.take();
+        ARITH_WRITES.with(|w| *w.borrow_mut() = saved);
+    }
+}
+
+/// 算術展開の代入。パース中なら記録し、それ以外（executor での展開）は直ちに環境へ書く。
+fn arith_write(name: &str, val: i64) {
+    let val = val.to_string();
+    let deferred = ARITH_WRITES.with(|w| match w.borrow_mut().as_mut() {
+        Some(writes) => {
+            writes.log.push((name.to_string(), val.clone()));
+            true
+        }
+        None => false,
+    });
+    if !deferred {
+        std::env::set_var(name, val);
+    }
+}
+
+/// 変数の値。パース中のコマンドが算術展開で代入済みならその値を、なければ環境変数を返す。
+///
+/// 既に前のコマンドへ割り当てた代入は見ない（そのコマンドが実行されるとは限らないため）。
+fn env_var(name: &str) -> Option<String> {
+    let pending = ARITH_WRITES.with(|w| {
+        w.borrow().as_ref().and_then(|writes| {
+            writes.log[writes.claimed..].iter().rev().find(|(n, _)| n == name).map(|(_, v)| v.clone())
+        })
+    });
+    pending.or_else(|| std::env::var(name).ok())
+}
+
+/// まだどのコマンドにも割り当てていない算術代入を取り出す。
+fn take_arith_writes() -> Vec<(String, String)> {
+    ARITH_WRITES.with(|w| match w.borrow_mut().as_mut() {
+        Some(writes) => {
+            let taken = writes.log[writes.claimed..].to_vec();
+            writes.claimed = writes.log.len();
+            taken
+        }
+        None => Vec::new(),
+    })
+}
+
+thread_local! {
+    /// `RANDOM=N` で種を与えた後の擬似乱数の状態。未設定なら `$RANDOM` は時刻ベースの乱数。
+    static RANDOM_STATE: std::cell::Cell<Option<u32>> = const { std::cell::Cell::new(None) };
+}
+
+/// `RANDOM` への代入値で `$RANDOM` の乱数列に種を与える。以降の読み取りは決定的な列になる。
+/// 数値でない値は文字列のハッシュを種にする。
+pub fn seed_random(value: &str) {
+    let seed = value.trim().parse::<u32>().unwrap_or_else(|_| {
+        value.bytes().fold(5381u32, |h, b| h.wrapping_mul(33) ^ b as u32)
+    });
+    RANDOM_STATE.with(|s| s.set(Some(seed)));
+}
+
+/// 種付き乱数列を 1 つ進めて 0〜32767 の値を返す。種がなければ `None`。
+fn next_seeded_random() -> Option<u16> {
+    RANDOM_STATE.with(|s| {
+        let state = s.get()?.wrapping_mul(1103515245).wrapping_add(12345);
+        s.set(Some(state));
+        Some(((state >> 16) & 0x7fff) as u16)
+    })
+}
+
+/// `$@` の展開値。各位置パラメータを `\x1F` で区切り、executor で個別の引数に分割させる
+/// （`${arr[@]}` と同じ規約）。位置パラメータがなければ `\x1F` のみを返し、ワードごと消える。
+fn join_positional_at(pos_args: &[String]) -> String {
+    if pos_args.is_empty() {
+        return "\x1F".to_string();
+    }
+    pos_args.join("\x1F")
+}
+
+/// 代入値など単一文字列が必要な文脈で、`\x1F` 区切りのワードリストをスペース結合に戻す。
+/// コマンド置換の [`UNQUOTED_SUB`] マーカーとクォートされたグロブ文字の
+/// [`glob::QUOTED_GLOB`] マーカーも取り除く（出力は分割もパス名展開もしない）。
+fn flatten_word_list(s: &str) -> Cow<'_, str> {
+    if !s.contains(['\x1F', UNQUOTED_SUB, glob::QUOTED_GLOB]) {
+        return Cow::Borrowed(s);
+    }
+    let s = s.replace([UNQUOTED_SUB, glob::QUOTED_GLOB], "");
+    Cow::Owned(s.split('\x1F').filter(|w| !w.is_empty()).collect::<Vec<_>>().join(" "))
+}
+
+/// リダイレクト先など分割しない位置のワードを [`flatten_word_list`] で 1 ワードに戻す。
+fn join_fields(w: Cow<'_, str>) -> Cow<'_, str> {
+    if w.contains(['\x1F', UNQUOTED_SUB, glob::QUOTED_GLOB]) {
+        Cow::Owned(flatten_word_list(&w).into_owned())
+    } else {
+        w
+    }
+}
+
+/// `$*` の展開値。位置パラメータを `IFS` の先頭文字（未設定ならスペース）で結合する。
+fn join_positional_star(pos_args: &[String]) -> String {
+    let sep = match std::env::var("IFS") {
+        Ok(ifs) => ifs.chars().next().map(String::from).unwrap_or_default(),
+        Err(_) => " ".to_string(),
+    };
+    pos_args.join(&sep)
+}
+
 /// 動的特殊変数を解決する。該当しなければ `None`。
 fn resolve_special_var(name: &str) -> Option<String> {
     match name {
         "RANDOM" => {
+            if let Some(val) = next_seeded_random() {
+                return Some(val.to_string());
+            }
             // 簡易乱数: PID ^ 時刻ベース
             let t = std::time::SystemTime::now()
                 .duration_since(std::time::UNIX_EPOCH)
@@ -434,6 +824,8 @@ fn resolve_special_var(name: &str) -> Option<String> {
             let elapsed = SHELL_START.elapsed().as_secs();
             Some(elapsed.to_string())
         }
+        "LINENO" => Some(lineno().to_string()),
+        "PPID" => Some(unsafe { libc::getppid() }.to_string()),
         _ => None,
     }
 }
@@ -456,15 +848,16 @@ fn parse_array_subscript(s: &str) -> Option<(&str, &str)> {
 }
 
 /// 配列要素に展開演算子を適用する。
-fn apply_expansion_op(val: &str, _var_name: &str, op: &str, last_status: i32, pos_args: &[String], _nounset: bool, arrays: &ArrayMap) -> Result<String, String> {
+#[allow(clippy::too_many_arguments)]
+fn apply_expansion_op(val: &str, _var_name: &str, op: &str, last_status: i32, shell_name: &str, pos_args: &[String], _nounset: bool, arrays: &ArrayMap) -> Result<String, ExpandError> {
     let val = val.to_string();
     if op.starts_with(":-") {
         let operand = &op[2..];
-        return Ok(if val.is_empty() { expand_variables(operand, last_status, pos_args, false, arrays)?.into_owned() } else { val });
+        return Ok(if val.is_empty() { expand_variables(operand, last_status, shell_name, pos_args, false, arrays)?.into_owned() } else { val });
     }
     if op.starts_with(":+") {
         let operand = &op[2..];
-        return Ok(if val.is_empty() { String::new() } else { expand_variables(operand, last_status, pos_args, false, arrays)?.into_owned() });
+        return Ok(if val.is_empty() { String::new() } else { expand_variables(operand, last_status, shell_name, pos_args, false, arrays)?.into_owned() });
     }
     if op.starts_with(":?") {
         let operand = &op[2..];
@@ -497,20 +890,51 @@ fn apply_expansion_op(val: &str, _var_name: &str, op: &str, last_status: i32, po
     Ok(val)
 }
 
-/// `${...}` 内のパラメータ展開を処理する。
-/// 対応: `${var:-default}`, `${var:=default}`, `${var:+alt}`, `${var:?msg}`,
-///       `${#var}`, `${var%pat}`, `${var%%pat}`, `${var#pat}`, `${var##pat}`,
-///       `${var/pat/repl}`, `${var//pat/repl}`, 配列添字展開
-/// 変数名から値を取得する。動的特殊変数を優先し、なければ環境変数を参照。
-fn get_var(name: &str, arrays: &ArrayMap) -> String {
-    if let Some(v) = resolve_special_var(name) { return v; }
+/// `${NAME}` の参照先の値を返す。未設定なら `None`（`set -u` の検査に使う）。
+///
+/// 数字だけの名前は位置パラメータ（`${0}` はシェル名）、それ以外は動的特殊変数・配列の 0 番要素・環境変数の順に参照する。
+fn lookup_var(name: &str, shell_name: &str, pos_args: &[String], arrays: &ArrayMap) -> Option<String> {
+    if !name.is_empty() && name.bytes().all(|b| b.is_ascii_digit()) {
+        return match name.parse::<usize>() {
+            Ok(0) => Some(shell_name.to_string()),
+            Ok(n) => pos_args.get(n - 1).cloned(),
+            Err(_) => None,
+        };
+    }
+    if let Some(v) = resolve_special_var(name) { return Some(v); }
     if let Some(arr) = arrays.get(name) {
-        return arr.get(&0).cloned().unwrap_or_default();
+        return Some(arr.get(&0).cloned().unwrap_or_default());
     }
-    std::env::var(name).unwrap_or_default()
+    env_var(name)
+}
+
+/// `prefix` で始まる変数名（環境変数と配列）をソートして返す。`${!prefix@}` 用。
+fn names_with_prefix(prefix: &str, arrays: &ArrayMap) -> Vec<String> {
+    let mut names: Vec<String> = std::env::vars_os()
+        .filter_map(|(k, _)| k.into_string().ok())
+        .chain(arrays.keys().cloned())
+        .filter(|k| k.starts_with(prefix) && k.bytes().all(is_var_char))
+        .collect();
+    names.sort();
+    names.dedup();
+    names
 }
 
-fn expand_braced_param(inner: &str, last_status: i32, pos_args: &[String], nounset: bool, arrays: &ArrayMap) -> Result<String, String> {
+/// `${...}` 内のパラメータ展開を処理する。
+/// 対応: `${var:-default}`, `${var:=default}`, `${var:+alt}`, `${var:?msg}`（`:` なしの形も）,
+///       `${#var}`, `${var%pat}`, `${var%%pat}`, `${var#pat}`, `${var##pat}`,
+///       `${var/pat/repl}`, `${var//pat/repl}`, `${!prefix@}`, `${!prefix*}`, 配列添字展開
+fn expand_braced_param(inner: &str, last_status: i32, shell_name: &str, pos_args: &[String], nounset: bool, arrays: &ArrayMap) -> Result<String, ExpandError> {
+    // ${!prefix@} / ${!prefix*} — prefix で始まる変数名の一覧
+    if let Some(rest) = inner.strip_prefix('!') {
+        if let Some(prefix) = rest.strip_suffix('@').or_else(|| rest.strip_suffix('*')) {
+            if !prefix.is_empty() && is_var_start(prefix.as_bytes()[0]) && prefix.bytes().all(is_var_char) {
+                let names = names_with_prefix(prefix, arrays);
+                return Ok(if rest.ends_with('@') { names.join("\x1F") } else { join_positional_star(&names) });
+            }
+        }
+    }
+
     // ${#var} — 文字数 / ${#arr[@]} — 要素数
     if let Some(var_name) = inner.strip_prefix('#') {
         // ${#arr[@]} / ${#arr[*]} — 配列要素数
@@ -525,7 +949,11 @@ fn expand_braced_param(inner: &str, last_status: i32, pos_args: &[String], nouns
                 return Ok(val.chars().count().to_string());
             }
         }
-        let val = get_var(var_name, arrays);
+        let val = match lookup_var(var_name, shell_name, pos_args, arrays) {
+            Some(val) => val,
+            None if nounset => return Err(ExpandError::Unbound(var_name.to_string())),
+            None => String::new(),
+        };
         return Ok(val.chars().count().to_string());
     }
 
@@ -538,13 +966,11 @@ fn expand_braced_param(inner: &str, last_status: i32, pos_args: &[String], nouns
 
     // 変数名の後に演算子がなければ通常の ${VAR}
     if name_end == bytes.len() {
-        let val = get_var(inner, arrays);
-        if val.is_empty() && nounset && resolve_special_var(inner).is_none()
-            && !arrays.contains_key(inner) && std::env::var(inner).is_err()
-        {
-            return Err(inner.to_string());
-        }
-        return Ok(val);
+        return match lookup_var(inner, shell_name, pos_args, arrays) {
+            Some(val) => Ok(val),
+            None if nounset => Err(ExpandError::Unbound(inner.to_string())),
+            None => Ok(String::new()),
+        };
     }
 
     let var_name = &inner[..name_end];
@@ -582,39 +1008,57 @@ fn expand_braced_param(inner: &str, last_status: i32, pos_args: &[String], nouns
                 return Ok(val);
             }
             // 展開演算子を val に適用
-            return apply_expansion_op(&val, var_name, after_bracket, last_status, pos_args, nounset, arrays);
+            return apply_expansion_op(&val, var_name, after_bracket, last_status, shell_name, pos_args, nounset, arrays);
         }
     }
 
-    let val = get_var(var_name, arrays);
-
-    // ${var:-default}, ${var:=default}, ${var:+alt}, ${var:?msg}
-    if op_and_rest.starts_with(":-") {
-        let operand = &op_and_rest[2..];
-        return Ok(if val.is_empty() { expand_variables(operand, last_status, pos_args, false, arrays)?.into_owned() } else { val });
-    }
-    if op_and_rest.starts_with(":=") {
-        let operand = &op_and_rest[2..];
-        if val.is_empty() {
-            let def = expand_variables(operand, last_status, pos_args, false, arrays)?.into_owned();
-            std::env::set_var(var_name, &def);
-            return Ok(def);
-        }
-        return Ok(val);
-    }
-    if op_and_rest.starts_with(":+") {
-        let operand = &op_and_rest[2..];
-        return Ok(if val.is_empty() { String::new() } else { expand_variables(operand, last_status, pos_args, false, arrays)?.into_owned() });
-    }
-    if op_and_rest.starts_with(":?") {
-        let operand = &op_and_rest[2..];
-        if val.is_empty() {
-            let msg = if operand.is_empty() { "parameter null or not set" } else { operand };
-            eprintln!("rush: {}: {}", var_name, msg);
-            return Ok(String::new());
-        }
-        return Ok(val);
+    // ${var:-default}, ${var:=default}, ${var:+alt}, ${var:?msg} と `:` なしの ${var-default} 等。
+    // `:` 付きは空文字も未設定とみなし、`:` なしは未設定のときだけ既定値側を使う。
+    // これらは未設定を扱う構文なので `set -u` でもエラーにしない
+    let (colon, op_rest) = match op_and_rest.strip_prefix(':') {
+        Some(rest) => (true, rest),
+        None => (false, op_and_rest),
+    };
+    if let Some(op) = op_rest.chars().next().filter(|c| matches!(c, '-' | '=' | '+' | '?')) {
+        let operand = &op_rest[1..];
+        let value = lookup_var(var_name, shell_name, pos_args, arrays);
+        let missing = match &value {
+            None => true,
+            Some(v) => colon && v.is_empty(),
+        };
+        let val = value.unwrap_or_default();
+        return match op {
+            '-' => Ok(if missing { expand_variables(operand, last_status, shell_name, pos_args, false, arrays)?.into_owned() } else { val }),
+            '=' if missing => {
+                let def = expand_variables(operand, last_status, shell_name, pos_args, false, arrays)?.into_owned();
+                // 未設定だった変数は export しない（既存の変数は export 属性をそのまま保つ）
+                if std::env::var_os(var_name).is_none() {
+                    DEFAULT_ASSIGNED.with(|names| {
+                        if let Some(names) = names.borrow_mut().as_mut() {
+                            names.push(var_name.to_string());
+                        }
+                    });
+                }
+                std::env::set_var(var_name, &def);
+                Ok(def)
+            }
+            '+' => Ok(if missing { String::new() } else { expand_variables(operand, last_status, shell_name, pos_args, false, arrays)?.into_owned() }),
+            '?' if missing => {
+                let msg = if operand.is_empty() { "parameter null or not set" } else { operand };
+                eprintln!("rush: {}: {}", var_name, msg);
+                Ok(String::new())
+            }
+            _ => Ok(val),
+        };
     }
+
+    // `${var%pat}` 等は `${var}` と同じく、`set -u` で未設定をエラーにする
+    let val = match lookup_var(var_name, shell_name, pos_args, arrays) {
+        Some(val) => val,
+        None if nounset => return Err(ExpandError::Unbound(var_name.to_string())),
+        None => String::new(),
+    };
+
     // ${var%%pat} — 最長後方一致を削除
     if op_and_rest.starts_with("%%") {
         return Ok(strip_suffix_longest(&val, &op_and_rest[2..]));
@@ -657,9 +1101,10 @@ fn expand_braced_param(inner: &str, last_status: i32, pos_args: &[String], nouns
 
 /// glob パターンで最短前方一致を削除する。
 fn strip_prefix_shortest(val: &str, pattern: &str) -> String {
+    let pattern = crate::glob::Pattern::new(pattern);
     for end in 0..=val.len() {
         if !val.is_char_boundary(end) { continue; }
-        if crate::glob::matches_pattern(pattern, &val[..end]) {
+        if pattern.matches(&val[..end]) {
             return val[end..].to_string();
         }
     }
@@ -668,9 +1113,10 @@ fn strip_prefix_shortest(val: &str, pattern: &str) -> String {
 
 /// glob パターンで最長前方一致を削除する。
 fn strip_prefix_longest(val: &str, pattern: &str) -> String {
+    let pattern = crate::glob::Pattern::new(pattern);
     for end in (0..=val.len()).rev() {
         if !val.is_char_boundary(end) { continue; }
-        if crate::glob::matches_pattern(pattern, &val[..end]) {
+        if pattern.matches(&val[..end]) {
             return val[end..].to_string();
         }
     }
@@ -679,9 +1125,10 @@ fn strip_prefix_longest(val: &str, pattern: &str) -> String {
 
 /// glob パターンで最短後方一致を削除する。
 fn strip_suffix_shortest(val: &str, pattern: &str) -> String {
+    let pattern = crate::glob::Pattern::new(pattern);
     for start in (0..=val.len()).rev() {
         if !val.is_char_boundary(start) { continue; }
-        if crate::glob::matches_pattern(pattern, &val[start..]) {
+        if pattern.matches(&val[start..]) {
             return val[..start].to_string();
         }
     }
@@ -690,9 +1137,10 @@ fn strip_suffix_shortest(val: &str, pattern: &str) -> String {
 
 /// glob パターンで最長後方一致を削除する。
 fn strip_suffix_longest(val: &str, pattern: &str) -> String {
+    let pattern = crate::glob::Pattern::new(pattern);
     for start in 0..=val.len() {
         if !val.is_char_boundary(start) { continue; }
-        if crate::glob::matches_pattern(pattern, &val[start..]) {
+        if pattern.matches(&val[start..]) {
             return val[..start].to_string();
         }
     }
@@ -701,11 +1149,12 @@ fn strip_suffix_longest(val: &str, pattern: &str) -> String {
 
 /// glob パターンで最初の一致を置換する。
 fn glob_replace_first(val: &str, pattern: &str, replacement: &str) -> String {
+    let pattern = crate::glob::Pattern::new(pattern);
     for start in 0..val.len() {
         if !val.is_char_boundary(start) { continue; }
         for end in start + 1..=val.len() {
             if !val.is_char_boundary(end) { continue; }
-            if crate::glob::matches_pattern(pattern, &val[start..end]) {
+            if pattern.matches(&val[start..end]) {
                 return format!("{}{}{}", &val[..start], replacement, &val[end..]);
             }
         }
@@ -715,6 +1164,7 @@ fn glob_replace_first(val: &str, pattern: &str, replacement: &str) -> String {
 
 /// glob パターンで全ての一致を置換する。
 fn glob_replace_all(val: &str, pattern: &str, replacement: &str) -> String {
+    let pattern = crate::glob::Pattern::new(pattern);
     let mut result = String::new();
     let mut pos = 0;
     while pos < val.len() {
@@ -722,7 +1172,7 @@ fn glob_replace_all(val: &str, pattern: &str, replacement: &str) -> String {
         let mut matched = false;
         for end in (pos + 1..=val.len()).rev() {
             if !val.is_char_boundary(end) { continue; }
-            if crate::glob::matches_pattern(pattern, &val[pos..end]) {
+            if pattern.matches(&val[pos..end]) {
                 result.push_str(replacement);
                 pos = end;
                 matched = true;
@@ -742,25 +1192,40 @@ fn glob_replace_all(val: &str, pattern: &str, replacement: &str) -> String {
 
 /// `$((expr))` の算術式を評価し、結果を文字列で返す。
 /// 式中の `$VAR` は先に変数展開し、裸の変数名は環境変数として参照する。
-fn eval_arithmetic(expr: &str, last_status: i32, pos_args: &[String], nounset: bool, arrays: &ArrayMap) -> Result<String, String> {
-    let expanded = expand_variables(expr, last_status, pos_args, nounset, arrays)?;
+/// 除算はゼロ方向に切り捨て、ゼロ除算とオーバーフローは [`ExpandError::Arith`] を返す。
+fn eval_arithmetic(expr: &str, last_status: i32, shell_name: &str, pos_args: &[String], nounset: bool, arrays: &ArrayMap) -> Result<String, ExpandError> {
+    let expanded = expand_variables(expr, last_status, shell_name, pos_args, nounset, arrays)?;
     let mut parser = ArithParser::new(&expanded);
-    match parser.parse_expr() {
+    match parser.parse_assign() {
         Some(val) => Ok(val.to_string()),
-        None => Ok("0".to_string()),
+        None => match parser.error {
+            Some(msg) => Err(ExpandError::Arith(format!("{}: {}", expr.trim(), msg))),
+            None => Ok("0".to_string()),
+        },
     }
 }
 
 /// 算術式の再帰下降パーサー。
-/// 優先順位: 加減算 < 乗除剰余 < 単項 +/- < 括弧・数値・変数
+/// 優先順位: 代入 < 加減算 < 乗除剰余 < 単項 +/-・前置 ++/-- < 括弧・数値・変数・後置 ++/--
+///
+/// 代入・インクリメントは [`arith_write`] で書き戻す（パース中はコマンドの実行時まで遅延）。
+///
+/// 評価エラー時は `error` に理由を記録して `None` を返す。
 struct ArithParser<'a> {
     input: &'a [u8],
     pos: usize,
+    error: Option<&'static str>,
 }
 
 impl<'a> ArithParser<'a> {
     fn new(s: &'a str) -> Self {
-        Self { input: s.as_bytes(), pos: 0 }
+        Self { input: s.as_bytes(), pos: 0, error: None }
+    }
+
+    /// エラー理由を記録して `None` を返す。
+    fn fail(&mut self, msg: &'static str) -> Option<i64> {
+        self.error = Some(msg);
+        None
     }
 
     fn skip_ws(&mut self) {
@@ -769,7 +1234,68 @@ impl<'a> ArithParser<'a> {
         }
     }
 
-    /// 最上位: 加減算
+    /// 識別子を読み進めて返す。識別子でなければ位置を変えずに `None`。
+    fn read_ident(&mut self) -> Option<&'a str> {
+        if self.pos >= self.input.len() || !is_var_start(self.input[self.pos]) {
+            return None;
+        }
+        let start = self.pos;
+        while self.pos < self.input.len() && is_var_char(self.input[self.pos]) {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.input[start..self.pos]).ok()
+    }
+
+    /// 現在位置が `s` で始まるか。
+    fn starts_with(&self, s: &[u8]) -> bool {
+        self.input[self.pos..].starts_with(s)
+    }
+
+    /// 変数の整数値（未定義・非数値は 0）。
+    fn var_value(name: &str) -> i64 {
+        env_var(name).and_then(|v| v.trim().parse().ok()).unwrap_or(0)
+    }
+
+    /// 最上位: 代入 (`=`, `+=`, `-=`, `*=`, `/=`, `%=`)。右結合。
+    fn parse_assign(&mut self) -> Option<i64> {
+        self.skip_ws();
+        let save = self.pos;
+        if let Some(name) = self.read_ident() {
+            self.skip_ws();
+            let op = if self.starts_with(b"==") {
+                None
+            } else if self.starts_with(b"=") {
+                Some(b'=')
+            } else if self.pos + 1 < self.input.len() && self.input[self.pos + 1] == b'='
+                && matches!(self.input[self.pos], b'+' | b'-' | b'*' | b'/' | b'%')
+            {
+                Some(self.input[self.pos])
+            } else {
+                None
+            };
+            if let Some(op) = op {
+                self.pos += if op == b'=' { 1 } else { 2 };
+                let rhs = self.parse_assign()?;
+                let cur = Self::var_value(name);
+                let val = match op {
+                    b'=' => Some(rhs),
+                    b'+' => cur.checked_add(rhs),
+                    b'-' => cur.checked_sub(rhs),
+                    b'*' => cur.checked_mul(rhs),
+                    _ if rhs == 0 => return self.fail("division by 0"),
+                    b'/' => cur.checked_div(rhs),
+                    _ => Some(cur.checked_rem(rhs).unwrap_or(0)),
+                };
+                let Some(val) = val else { return self.fail("integer overflow") };
+                arith_write(name, val);
+                return Some(val);
+            }
+        }
+        self.pos = save;
+        self.parse_expr()
+    }
+
+    /// 加減算
     fn parse_expr(&mut self) -> Option<i64> {
         let mut left = self.parse_term()?;
         loop {
@@ -779,12 +1305,18 @@ impl<'a> ArithParser<'a> {
                 b'+' => {
                     self.pos += 1;
                     let right = self.parse_term()?;
-                    left = left.wrapping_add(right);
+                    left = match left.checked_add(right) {
+                        Some(v) => v,
+                        None => return self.fail("integer overflow"),
+                    };
                 }
                 b'-' => {
                     self.pos += 1;
                     let right = self.parse_term()?;
-                    left = left.wrapping_sub(right);
+                    left = match left.checked_sub(right) {
+                        Some(v) => v,
+                        None => return self.fail("integer overflow"),
+                    };
                 }
                 _ => break,
             }
@@ -802,25 +1334,30 @@ impl<'a> ArithParser<'a> {
                 b'*' => {
                     self.pos += 1;
                     let right = self.parse_unary()?;
-                    left = left.wrapping_mul(right);
+                    left = match left.checked_mul(right) {
+                        Some(v) => v,
+                        None => return self.fail("integer overflow"),
+                    };
                 }
                 b'/' => {
                     self.pos += 1;
                     let right = self.parse_unary()?;
                     if right == 0 {
-                        eprintln!("rush: division by 0");
-                        return Some(0);
+                        return self.fail("division by 0");
                     }
-                    left /= right;
+                    // i64::MIN / -1 のみ溢れる。Rust の `/` はゼロ方向切り捨て。
+                    left = match left.checked_div(right) {
+                        Some(v) => v,
+                        None => return self.fail("integer overflow"),
+                    };
                 }
                 b'%' => {
                     self.pos += 1;
                     let right = self.parse_unary()?;
                     if right == 0 {
-                        eprintln!("rush: division by 0");
-                        return Some(0);
+                        return self.fail("division by 0");
                     }
-                    left %= right;
+                    left = left.checked_rem(right).unwrap_or(0);
                 }
                 _ => break,
             }
@@ -828,15 +1365,33 @@ impl<'a> ArithParser<'a> {
         Some(left)
     }
 
-    /// 単項演算子: +, -
+    /// 単項演算子: +, -, 前置 ++/--
     fn parse_unary(&mut self) -> Option<i64> {
         self.skip_ws();
         if self.pos >= self.input.len() { return Some(0); }
+        if self.starts_with(b"++") || self.starts_with(b"--") {
+            let delta = if self.input[self.pos] == b'+' { 1 } else { -1 };
+            let save = self.pos;
+            self.pos += 2;
+            self.skip_ws();
+            if let Some(name) = self.read_ident() {
+                let Some(val) = Self::var_value(name).checked_add(delta) else {
+                    return self.fail("integer overflow");
+                };
+                arith_write(name, val);
+                return Some(val);
+            }
+            // 変数が続かなければ単項 +/- の連続として扱う
+            self.pos = save;
+        }
         match self.input[self.pos] {
             b'-' => {
                 self.pos += 1;
                 let val = self.parse_unary()?;
-                Some(val.wrapping_neg())
+                match val.checked_neg() {
+                    Some(v) => Some(v),
+                    None => self.fail("integer overflow"),
+                }
             }
             b'+' => {
                 self.pos += 1;
@@ -853,7 +1408,7 @@ impl<'a> ArithParser<'a> {
         match self.input[self.pos] {
             b'(' => {
                 self.pos += 1;
-                let val = self.parse_expr()?;
+                let val = self.parse_assign()?;
                 self.skip_ws();
                 if self.pos < self.input.len() && self.input[self.pos] == b')' {
                     self.pos += 1;
@@ -866,23 +1421,414 @@ impl<'a> ArithParser<'a> {
                     self.pos += 1;
                 }
                 let num_str = std::str::from_utf8(&self.input[start..self.pos]).ok()?;
-                Some(num_str.parse::<i64>().unwrap_or(0))
+                match num_str.parse::<i64>() {
+                    Ok(v) => Some(v),
+                    Err(_) => self.fail("integer overflow"),
+                }
             }
             b if is_var_start(b) => {
                 // 変数参照（算術コンテキストでは裸の名前も変数として扱う）
-                let start = self.pos;
-                while self.pos < self.input.len() && is_var_char(self.input[self.pos]) {
-                    self.pos += 1;
+                let var_name = self.read_ident()?;
+                let val = Self::var_value(var_name);
+                // 後置 ++/--: 変数を更新し、更新前の値を返す
+                let save = self.pos;
+                self.skip_ws();
+                if self.starts_with(b"++") || self.starts_with(b"--") {
+                    let delta = if self.input[self.pos] == b'+' { 1 } else { -1 };
+                    self.pos += 2;
+                    let Some(new_val) = val.checked_add(delta) else {
+                        return self.fail("integer overflow");
+                    };
+                    arith_write(var_name, new_val);
+                } else {
+                    self.pos = save;
                 }
-                let var_name = std::str::from_utf8(&self.input[start..self.pos]).ok()?;
-                let val = std::env::var(var_name).unwrap_or_default();
-                Some(val.parse::<i64>().unwrap_or(0))
+                Some(val)
             }
             _ => Some(0),
         }
     }
 }
 
+// ── Compound commands ───────────────────────────────────────────────
+
+/// 複合コマンド `if` / `for` / `while` / `until` / `case` の AST。
+///
+/// 条件と本体はパース時に展開せず、ソーステキストのまま保持する。
+/// executor が実行のたびに [`parse`] し直すので、ループ変数の更新が本体に反映される。
+#[derive(Debug, PartialEq, Clone)]
+pub enum Compound {
+    /// `if C1; then B1; elif C2; then B2; else B3; fi`。
+    /// `clauses` は if と各 elif の `(条件, 本体)` を順に並べたもの。
+    If { clauses: Vec<(Clause, Clause)>, else_body: Option<Clause> },
+    /// `for NAME [in WORDS...]; do BODY; done`。`words` は未展開のワード（`in` がなければ `None`）。
+    For { var: String, words: Option<Vec<String>>, body: Clause },
+    /// `while COND; do BODY; done`。`until` なら条件が偽の間ループする。
+    While { condition: Clause, body: Clause, until: bool },
+    /// `case WORD in PATTERN) BODY;; ... esac`。
+    /// 節は `;;` / `;&` / `;;&` を含む行単位の解析が必要なため、`case`〜`esac` のテキストをそのまま保持する。
+    /// `line` は `case` の行（パース入力の先頭行を 0 とする）。
+    Case { block: String, line: usize },
+}
+
+/// 複合コマンドの条件・本体のソーステキスト。
+#[derive(Debug, PartialEq, Clone)]
+pub struct Clause {
+    pub text: String,
+    /// テキストの開始行（パース入力の先頭行を 0 とする）。executor が `$LINENO` の計算に使う。
+    pub line: usize,
+}
+
+/// [`Compound`] を開始する予約語。
+const COMPOUND_KEYWORDS: [&str; 5] = ["if", "for", "while", "until", "case"];
+
+/// 複合コマンドの構造解析で意味を持つ予約語。
+const RESERVED_WORDS: [&str; 14] = [
+    "if", "then", "elif", "else", "fi", "for", "while", "until", "do", "done", "case", "esac", "{", "}",
+];
+
+/// `pos` 以降の次の生トークン（ワード・演算子・改行）の範囲を返す。
+///
+/// 展開は行わず、クォート・`$(...)`・`${...}`・バッククォートはワードの一部として読み飛ばす。
+/// 空白・行継続・コメントはスキップする。
+fn next_raw_token(bytes: &[u8], mut pos: usize) -> Option<(usize, usize)> {
+    let len = bytes.len();
+    loop {
+        while pos < len && matches!(bytes[pos], b' ' | b'\t') {
+            pos += 1;
+        }
+        if bytes[pos..].starts_with(b"\\\n") {
+            pos += 2;
+            continue;
+        }
+        if pos < len && bytes[pos] == b'#' {
+            while pos < len && bytes[pos] != b'\n' {
+                pos += 1;
+            }
+        }
+        break;
+    }
+    if pos >= len {
+        return None;
+    }
+    let start = pos;
+    let rest = &bytes[pos..];
+    let op_len = if rest.starts_with(b";;&") {
+        3
+    } else if rest.starts_with(b";;") || rest.starts_with(b";&") || rest.starts_with(b"&&") || rest.starts_with(b"||") {
+        2
+    } else if matches!(rest[0], b'\n' | b';' | b'&' | b'|' | b'(' | b')') {
+        1
+    } else if matches!(rest[0], b'<' | b'>') {
+        rest.iter().take_while(|&&b| matches!(b, b'<' | b'>' | b'&' | b'|' | b'-')).count()
+    } else {
+        0
+    };
+    if op_len > 0 {
+        return Some((start, start + op_len));
+    }
+
+    while pos < len {
+        match bytes[pos] {
+            b' ' | b'\t' | b'\n' | b';' | b'&' | b'|' | b'(' | b')' | b'<' | b'>' => break,
+            b'\\' => pos += 2,
+            b'\'' => {
+                pos += 1;
+                while pos < len && bytes[pos] != b'\'' {
+                    pos += 1;
+                }
+                pos += 1;
+            }
+            b'"' => pos = double_quote_end(bytes, pos + 1).map_or(len, |end| end + 1),
+            b'`' => {
+                pos += 1;
+                while pos < len && bytes[pos] != b'`' {
+                    if bytes[pos] == b'\\' {
+                        pos += 1;
+                    }
+                    pos += 1;
+                }
+                pos += 1;
+            }
+            b'$' if bytes.get(pos + 1) == Some(&b'(') => {
+                pos = command_sub_end(bytes, pos + 2).map_or(len, |end| end + 1);
+            }
+            b'$' if bytes.get(pos + 1) == Some(&b'{') => {
+                let mut depth = 0;
+                pos += 1;
+                while pos < len {
+                    match bytes[pos] {
+                        b'{' => depth += 1,
+                        b'}' => {
+                            depth -= 1;
+                            if depth == 0 {
+                                break;
+                            }
+                        }
+                        b'\\' => pos += 1,
+                        b'\'' => {
+                            pos += 1;
+                            while pos < len && bytes[pos] != b'\'' {
+                                pos += 1;
+                            }
+                        }
+                        b'"' => pos = double_quote_end(bytes, pos + 1).unwrap_or(len),
+                        _ => {}
+                    }
+                    pos += 1;
+                }
+                pos += 1;
+            }
+            _ => pos += 1,
+        }
+    }
+    Some((start, pos.min(len)))
+}
+
+/// 複合コマンドのテキストを予約語の位置で区切るスキャナ。
+///
+/// 予約語はコマンド位置（行頭・`;`・`&&` 等の直後、`then`/`do` 等の直後）に
+/// クォートなしで現れたときだけ認識する。ネストした複合コマンドは深さを数えて読み飛ばし、
+/// case のパターン（`done)` 等）は予約語として扱わない。
+struct CompoundScanner<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> CompoundScanner<'a> {
+    /// 次の生トークンを読み、`(テキスト, 開始位置)` を返す。
+    fn next_token(&mut self) -> Option<(&'a str, usize)> {
+        let (start, end) = next_raw_token(self.input.as_bytes(), self.pos)?;
+        self.pos = end;
+        Some((&self.input[start..end], start))
+    }
+
+    /// 次の生トークンを読み進めずに返す。
+    fn peek_token(&self) -> Option<&'a str> {
+        next_raw_token(self.input.as_bytes(), self.pos).map(|(start, end)| &self.input[start..end])
+    }
+
+    /// 改行と（`semi` なら）`;` を読み飛ばす。
+    fn skip_separators(&mut self, semi: bool) {
+        while let Some(tok) = self.peek_token() {
+            if tok == "\n" || (semi && tok == ";") {
+                self.next_token();
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// `stops` のいずれかの予約語がネストの外のコマンド位置に現れるまで読み進める。
+    ///
+    /// 戻り値は `(予約語, その開始位置)`。`self.pos` は予約語の直後になる。
+    /// 対応しない予約語（`if` の中の `done` 等）は構文エラー、入力の終わりは [`ParseError::IncompleteInput`]。
+    fn find_keyword(&mut self, stops: &[&'static str]) -> Result<(&'static str, usize), ParseError> {
+        self.scan(stops, false)
+    }
+
+    /// `case` の直後から対応する `esac` まで読み進め、`esac` の開始位置を返す。
+    fn find_case_end(&mut self) -> Result<usize, ParseError> {
+        self.scan(&[], true).map(|(_, at)| at)
+    }
+
+    /// [`find_keyword`](Self::find_keyword) / [`find_case_end`](Self::find_case_end) の本体。
+    /// `in_case` なら `case` を開いた状態から始め、それを閉じる `esac` で止まる。
+    fn scan(&mut self, stops: &[&'static str], in_case: bool) -> Result<(&'static str, usize), ParseError> {
+        let mut stack: Vec<&'static str> = if in_case { vec!["case"] } else { Vec::new() };
+        let mut cmd_pos = !in_case;
+        // case のパターン位置（`in` / `;;` の後から `)` まで）
+        let mut in_pattern = false;
+        // `case WORD in` の残りワード数
+        let mut case_header = if in_case { 2u8 } else { 0 };
+
+        while let Some((tok, start)) = self.next_token() {
+            if is_raw_operator(tok) {
+                let first = tok.as_bytes()[0];
+                if stack.last() == Some(&"case") && matches!(tok, ";;" | ";&" | ";;&") {
+                    in_pattern = true;
+                } else if in_pattern {
+                    if tok == ")" {
+                        in_pattern = false;
+                        cmd_pos = true;
+                    }
+                } else if first != b'<' && first != b'>' {
+                    cmd_pos = true;
+                }
+                continue;
+            }
+
+            if case_header > 0 {
+                case_header -= 1;
+                in_pattern = case_header == 0;
+                continue;
+            }
+            let reserved = RESERVED_WORDS.iter().copied().find(|&w| w == tok);
+            if in_pattern {
+                if reserved == Some("esac") {
+                    stack.pop();
+                    if in_case && stack.is_empty() {
+                        return Ok(("esac", start));
+                    }
+                    in_pattern = false;
+                    cmd_pos = false;
+                }
+                continue;
+            }
+            let Some(word) = reserved.filter(|_| cmd_pos) else {
+                cmd_pos = false;
+                continue;
+            };
+            let pos = ErrorPos::at(self.input, start);
+            match word {
+                _ if stack.is_empty() && stops.contains(&word) => return Ok((word, start)),
+                "if" | "while" | "until" | "for" | "case" => {
+                    stack.push(word);
+                    cmd_pos = word != "for" && word != "case";
+                    if word == "case" {
+                        case_header = 2;
+                    }
+                }
+                "fi" | "done" | "esac" => {
+                    let opener_ok = match stack.last() {
+                        Some(&"if") => word == "fi",
+                        Some(&("for" | "while" | "until")) => word == "done",
+                        Some(&"case") => word == "esac",
+                        _ => false,
+                    };
+                    if !opener_ok {
+                        return Err(ParseError::UnexpectedToken(word.to_string(), pos));
+                    }
+                    stack.pop();
+                    if in_case && stack.is_empty() {
+                        return Ok((word, start));
+                    }
+                    cmd_pos = false;
+                }
+                "then" | "elif" | "else" | "do" if stack.is_empty() => {
+                    return Err(ParseError::UnexpectedToken(word.to_string(), pos));
+                }
+                "}" => cmd_pos = false,
+                _ => cmd_pos = true,
+            }
+        }
+        Err(ParseError::IncompleteInput)
+    }
+}
+
+/// `input[start..end]`（予約語の間のテキスト）を条件・本体として切り出す。
+///
+/// 空白と `;` だけの節（`then; fi` の本体など）は空文字列に正規化する。
+fn clause_text(input: &str, start: usize, end: usize) -> Clause {
+    let text = &input[start..end];
+    let trimmed = text.trim();
+    let leading = text.len() - text.trim_start().len();
+    let line = line_of(input, start + leading);
+    if trimmed.trim_matches(|c: char| c == ';' || c.is_whitespace()).is_empty() {
+        return Clause { text: String::new(), line };
+    }
+    Clause { text: trimmed.to_string(), line }
+}
+
+/// `input[pos]` が何行目か（先頭行を 0 とする）を返す。
+fn line_of(input: &str, pos: usize) -> usize {
+    input.as_bytes()[..pos].iter().filter(|&&b| b == b'\n').count()
+}
+
+/// `input[pos..]`（開始予約語 `keyword` の直後）から複合コマンドを解析する。
+///
+/// 戻り値は AST と閉じ予約語（`fi` / `done` / `esac`）の直後の位置。
+fn parse_compound(input: &str, keyword: &str, pos: usize) -> Result<(Compound, usize), ParseError> {
+    let mut s = CompoundScanner { input, pos };
+    let compound = match keyword {
+        "if" => {
+            let mut clauses = Vec::new();
+            let mut else_body = None;
+            loop {
+                let cond_start = s.pos;
+                let (_, then_at) = s.find_keyword(&["then"])?;
+                let condition = clause_text(input, cond_start, then_at);
+                if condition.text.is_empty() {
+                    return Err(ParseError::UnexpectedToken("then".to_string(), ErrorPos::at(input, then_at)));
+                }
+                let body_start = s.pos;
+                let (kw, at) = s.find_keyword(&["elif", "else", "fi"])?;
+                clauses.push((condition, clause_text(input, body_start, at)));
+                match kw {
+                    "elif" => continue,
+                    "else" => {
+                        let body_start = s.pos;
+                        let (_, at) = s.find_keyword(&["fi"])?;
+                        else_body = Some(clause_text(input, body_start, at));
+                    }
+                    _ => {}
+                }
+                break;
+            }
+            Compound::If { clauses, else_body }
+        }
+        "for" => {
+            let (var, var_at) = s.next_token().ok_or(ParseError::IncompleteInput)?;
+            let valid = var.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+                && !var.as_bytes()[0].is_ascii_digit();
+            if !valid {
+                return Err(ParseError::UnexpectedToken(var.to_string(), ErrorPos::at(input, var_at)));
+            }
+            s.skip_separators(false);
+            let mut words = None;
+            if s.peek_token() == Some("in") {
+                s.next_token();
+                let mut list = Vec::new();
+                loop {
+                    match s.next_token() {
+                        Some((";" | "\n", _)) => break,
+                        Some((tok, at)) if is_raw_operator(tok) => {
+                            return Err(ParseError::UnexpectedToken(tok.to_string(), ErrorPos::at(input, at)));
+                        }
+                        Some((tok, _)) => list.push(tok.to_string()),
+                        None => return Err(ParseError::IncompleteInput),
+                    }
+                }
+                words = Some(list);
+            }
+            s.skip_separators(true);
+            match s.next_token() {
+                Some(("do", _)) => {}
+                Some((tok, at)) => {
+                    return Err(ParseError::UnexpectedToken(tok.to_string(), ErrorPos::at(input, at)));
+                }
+                None => return Err(ParseError::IncompleteInput),
+            }
+            let body_start = s.pos;
+            let (_, at) = s.find_keyword(&["done"])?;
+            Compound::For { var: var.to_string(), words, body: clause_text(input, body_start, at) }
+        }
+        "case" => {
+            let at = s.find_case_end()?;
+            let end = at + "esac".len();
+            let start = pos - keyword.len();
+            Compound::Case { block: input[start..end].to_string(), line: line_of(input, start) }
+        }
+        _ => {
+            let cond_start = s.pos;
+            let (_, do_at) = s.find_keyword(&["do"])?;
+            let condition = clause_text(input, cond_start, do_at);
+            if condition.text.is_empty() {
+                return Err(ParseError::UnexpectedToken("do".to_string(), ErrorPos::at(input, do_at)));
+            }
+            let body_start = s.pos;
+            let (_, at) = s.find_keyword(&["done"])?;
+            Compound::While { condition, body: clause_text(input, body_start, at), until: keyword == "until" }
+        }
+    };
+    Ok((compound, s.pos))
+}
+
+/// 生トークンが演算子（改行を含む）かを判定する。
+fn is_raw_operator(tok: &str) -> bool {
+    matches!(tok.as_bytes()[0], b'\n' | b';' | b'&' | b'|' | b'(' | b')' | b'<' | b'>')
+}
+
 // ── Tokenizer (crate-private) ───────────────────────────────────────
 
 /// トークナイザが生成する内部トークン型。
@@ -892,15 +1838,19 @@ enum Token<'a> {
     And,            // &&
     Or,             // ||
     Semi,           // ;
+    DSemi,          // ;;  — case 節の終端
+    SemiAmp,        // ;&  — case 節の終端（次の節へフォールスルー）
+    DSemiAmp,       // ;;& — case 節の終端（後続のパターン評価を続ける）
     Ampersand,      // &
     RedirectOut,    // >
     RedirectAppend, // >>
     RedirectIn,     // <
     RedirectErr,    // 2>
     RedirectErrAppend, // 2>>
+    RedirectFdOut(i32, bool), // N> / N>>（N は 3〜9、bool は追記か）
     FdDupPrefix(i32), // N>& — src_fd は N、次の Word が dst_fd
-    HereDoc,          // <<
-    HereString,       // <<<
+    HereDoc(bool),    // << — 引数はデリミタがクォートされているか
+    HereString(bool), // <<< — 引数は続くワードのコマンド置換がすべてクォートされているか
     LParen,           // (  — サブシェル開始
     RParen,           // )  — サブシェル終了
     ProcSubIn(Cow<'a, str>),   // <(cmd) — 入力プロセス置換
@@ -914,22 +1864,52 @@ enum Token<'a> {
 struct Tokenizer<'a, 'b> {
     input: &'a str,
     pos: usize,
+    /// 最後に返したトークンの開始バイト位置（エラー位置の報告用）。
+    token_start: usize,
     last_status: i32,
+    /// `$0` の値。
+    shell_name: &'b str,
     pos_args: &'b [String],
     nounset: bool,
     nounset_error: Option<String>,
+    /// 算術展開で最初に発生した評価エラー。
+    arith_error: Option<String>,
     arrays: &'b ArrayMap,
 }
 
 impl<'a, 'b> Tokenizer<'a, 'b> {
-    fn new(input: &'a str, last_status: i32, pos_args: &'b [String], nounset: bool, arrays: &'b ArrayMap) -> Self {
-        Self { input, pos: 0, last_status, pos_args, nounset, nounset_error: None, arrays }
+    fn new(input: &'a str, last_status: i32, shell_name: &'b str, pos_args: &'b [String], nounset: bool, arrays: &'b ArrayMap) -> Self {
+        Self { input, pos: 0, token_start: 0, last_status, shell_name, pos_args, nounset, nounset_error: None, arith_error: None, arrays }
+    }
+
+    /// `offset` バイト目を指すエラー位置を返す。
+    fn error_pos(&self, offset: usize) -> ErrorPos {
+        ErrorPos::at(self.input, offset)
     }
 
+    /// 展開エラーを記録する。種類ごとに最初の1件のみ保持し、パース終了時に報告する。
+    fn record_expand_error(&mut self, e: ExpandError) {
+        match e {
+            ExpandError::Unbound(name) => {
+                if self.nounset_error.is_none() { self.nounset_error = Some(name); }
+            }
+            ExpandError::Arith(msg) => {
+                if self.arith_error.is_none() { self.arith_error = Some(msg); }
+            }
+        }
+    }
+
+    /// 空白と行継続（バックスラッシュ + 改行）をスキップする。
     fn skip_whitespace(&mut self) {
         let bytes = self.input.as_bytes();
-        while self.pos < bytes.len() && bytes[self.pos].is_ascii_whitespace() {
-            self.pos += 1;
+        while self.pos < bytes.len() {
+            if bytes[self.pos].is_ascii_whitespace() {
+                self.pos += 1;
+            } else if bytes[self.pos] == b'\\' && bytes.get(self.pos + 1) == Some(&b'\n') {
+                self.pos += 2;
+            } else {
+                break;
+            }
         }
     }
 
@@ -941,6 +1921,87 @@ impl<'a, 'b> Tokenizer<'a, 'b> {
         self.input.as_bytes().get(self.pos + offset).copied()
     }
 
+    /// `<<` の直後にあるデリミタワードがクォート（`'`, `"`, `\`）を含むかを先読みで判定する。
+    /// クォートされたデリミタのヒアドキュメントは本文を展開しない。
+    fn heredoc_delim_quoted(&self) -> bool {
+        let bytes = self.input.as_bytes();
+        let mut pos = self.pos;
+        while pos < bytes.len() && (bytes[pos] == b' ' || bytes[pos] == b'\t') {
+            pos += 1;
+        }
+        while pos < bytes.len() {
+            match bytes[pos] {
+                b'\'' | b'"' | b'\\' => return true,
+                b' ' | b'\t' | b'\n' | b';' | b'|' | b'&' | b'<' | b'>' | b'(' | b')' => break,
+                _ => pos += 1,
+            }
+        }
+        false
+    }
+
+    /// `<<<` に続くワードがクォートを含み、かつシングルクォートやエスケープの外側に
+    /// `$(` / `` ` `` が無いかを先読みする。
+    ///
+    /// そうであれば true（ヒアストリングのコマンド置換を実行しない）。
+    /// ダブルクォート内の置換はシェルと同じく実行対象として扱う。
+    fn here_string_literal(&self) -> bool {
+        let bytes = self.input.as_bytes();
+        let mut pos = self.pos;
+        while pos < bytes.len() && (bytes[pos] == b' ' || bytes[pos] == b'\t') {
+            pos += 1;
+        }
+        let mut quote = None;
+        let mut quoted = false;
+        while pos < bytes.len() {
+            let b = bytes[pos];
+            match (quote, b) {
+                (Some(b'\''), b'\'') | (Some(b'"'), b'"') => quote = None,
+                (Some(b'\''), _) => {}
+                (_, b'$') if bytes.get(pos + 1) == Some(&b'(') => return false,
+                (_, b'`') => return false,
+                (_, b'\\') => {
+                    quoted = true;
+                    pos += 1;
+                }
+                (None, b'\'' | b'"') => {
+                    quoted = true;
+                    quote = Some(b);
+                }
+                (None, b' ' | b'\t' | b'\n' | b';' | b'|' | b'&' | b'<' | b'>' | b'(' | b')') => break,
+                _ => {}
+            }
+            pos += 1;
+        }
+        quoted
+    }
+
+    /// `self.pos` がワード境界（入力先頭、または空白・演算子の直後）かを返す。
+    fn at_word_boundary(&self) -> bool {
+        self.pos == 0
+            || matches!(
+                self.input.as_bytes()[self.pos - 1],
+                b' ' | b'\t' | b'\n' | b';' | b'|' | b'&' | b'(' | b')' | b'<' | b'>'
+            )
+    }
+
+    /// `self.pos` の文字（UTF-8 の 1 文字）を `buf` に追加し、その分だけ進める。
+    fn push_current_char(&mut self, buf: &mut String) {
+        if let Some(ch) = self.input[self.pos..].chars().next() {
+            buf.push(ch);
+            self.pos += ch.len_utf8();
+        }
+    }
+
+    /// `self.pos` のバッククォートに対応する閉じバッククォートの直後の位置を返す
+    /// （閉じていなければ入力末尾）。
+    fn backquote_end(&self) -> usize {
+        let bytes = self.input.as_bytes();
+        match bytes[self.pos + 1..].iter().position(|&b| b == b'`') {
+            Some(off) => self.pos + 1 + off + 1,
+            None => bytes.len(),
+        }
+    }
+
     /// サブシェル `(` の直後から対応する `)` までの本体テキストを抽出する。
     ///
     /// クォート・`$()`・ネストした `()` を正しくスキップする。
@@ -1008,11 +2069,9 @@ impl<'a, 'b> Tokenizer<'a, 'b> {
                                     paren_depth -= 1;
                                 } else if self.pos + 1 < len && bytes[self.pos + 1] == b')' {
                                     let expr = &self.input[expr_start..self.pos];
-                                    match eval_arithmetic(expr, self.last_status, self.pos_args, self.nounset, self.arrays) {
+                                    match eval_arithmetic(expr, self.last_status, self.shell_name, self.pos_args, self.nounset, self.arrays) {
                                         Ok(val) => buf.push_str(&val),
-                                        Err(var_name) => {
-                                            if self.nounset_error.is_none() { self.nounset_error = Some(var_name); }
-                                        }
+                                        Err(e) => self.record_expand_error(e),
                                     }
                                     self.pos += 2; // skip '))'
                                     found = true;
@@ -1031,35 +2090,11 @@ impl<'a, 'b> Tokenizer<'a, 'b> {
                     }
                 } else {
                     // $(...) — コマンド置換。リテラル保持。
-                    buf.push_str("$(");
-                    self.pos += 1; // skip '('
-                    let mut depth = 1;
-                    while self.pos < len && depth > 0 {
-                        match bytes[self.pos] {
-                            b'(' => depth += 1,
-                            b')' => {
-                                depth -= 1;
-                                if depth == 0 {
-                                    self.pos += 1;
-                                    break;
-                                }
-                            }
-                            b'\'' => {
-                                buf.push(bytes[self.pos] as char);
-                                self.pos += 1;
-                                while self.pos < len && bytes[self.pos] != b'\'' {
-                                    buf.push(bytes[self.pos] as char);
-                                    self.pos += 1;
-                                }
-                            }
-                            _ => {}
-                        }
-                        if depth > 0 {
-                            buf.push(bytes[self.pos] as char);
-                            self.pos += 1;
-                        }
-                    }
+                    let end = command_sub_end(bytes, self.pos + 1).unwrap_or(len);
+                    buf.push('$');
+                    buf.push_str(&self.input[self.pos..end]);
                     buf.push(')');
+                    self.pos = (end + 1).min(len);
                 }
             }
             b'?' => {
@@ -1076,7 +2111,15 @@ impl<'a, 'b> Tokenizer<'a, 'b> {
                 self.pos += 1;
             }
             b'0' => {
-                buf.push_str("rush");
+                buf.push_str(self.shell_name);
+                self.pos += 1;
+            }
+            b'@' | b'*' | b'#' | b'1'..=b'9' => {
+                // 位置パラメータ関連は expand_variables に委譲
+                match expand_variables(&self.input[self.pos - 1..self.pos + 1], self.last_status, self.shell_name, self.pos_args, self.nounset, self.arrays) {
+                    Ok(val) => buf.push_str(&val),
+                    Err(e) => self.record_expand_error(e),
+                }
                 self.pos += 1;
             }
             b'{' => {
@@ -1099,11 +2142,9 @@ impl<'a, 'b> Tokenizer<'a, 'b> {
                     let inner = &self.input[var_start..self.pos];
                     self.pos += 1; // skip '}'
                     if !inner.is_empty() {
-                        match expand_braced_param(inner, self.last_status, self.pos_args, self.nounset, self.arrays) {
+                        match expand_braced_param(inner, self.last_status, self.shell_name, self.pos_args, self.nounset, self.arrays) {
                             Ok(val) => buf.push_str(&val),
-                            Err(var_name) => {
-                                if self.nounset_error.is_none() { self.nounset_error = Some(var_name); }
-                            }
+                            Err(e) => self.record_expand_error(e),
                         }
                     }
                 } else {
@@ -1121,7 +2162,7 @@ impl<'a, 'b> Tokenizer<'a, 'b> {
                     buf.push_str(&val);
                 } else if let Some(arr) = self.arrays.get(var_name) {
                     buf.push_str(arr.get(&0).map(|s| s.as_str()).unwrap_or(""));
-                } else if let Ok(val) = std::env::var(var_name) {
+                } else if let Some(val) = env_var(var_name) {
                     buf.push_str(&val);
                 } else if self.nounset {
                     if self.nounset_error.is_none() { self.nounset_error = Some(var_name.to_string()); }
@@ -1139,7 +2180,16 @@ impl<'a, 'b> Iterator for Tokenizer<'a, 'b> {
 
     fn next(&mut self) -> Option<Self::Item> {
         self.skip_whitespace();
+        // ワード境界（入力先頭・空白・演算子の直後）の `#` から行末まではコメント。
+        // ワード途中・閉じクォート直後・クォート内の `#` はリテラル
+        while self.peek() == Some(b'#') && self.at_word_boundary() {
+            while self.peek().is_some_and(|b| b != b'\n') {
+                self.pos += 1;
+            }
+            self.skip_whitespace();
+        }
         let ch = self.peek()?;
+        self.token_start = self.pos;
 
         match ch {
             b'|' => {
@@ -1162,7 +2212,21 @@ impl<'a, 'b> Iterator for Tokenizer<'a, 'b> {
             }
             b';' => {
                 self.pos += 1;
-                Some(Ok(Token::Semi))
+                match (self.peek(), self.peek_at(1)) {
+                    (Some(b';'), Some(b'&')) => {
+                        self.pos += 2;
+                        Some(Ok(Token::DSemiAmp))
+                    }
+                    (Some(b';'), _) => {
+                        self.pos += 1;
+                        Some(Ok(Token::DSemi))
+                    }
+                    (Some(b'&'), _) => {
+                        self.pos += 1;
+                        Some(Ok(Token::SemiAmp))
+                    }
+                    _ => Some(Ok(Token::Semi)),
+                }
             }
             b'(' => {
                 self.pos += 1;
@@ -1201,15 +2265,46 @@ impl<'a, 'b> Iterator for Tokenizer<'a, 'b> {
                     }
                 } else if self.peek_at(1) == Some(b'<') && self.peek_at(2) == Some(b'<') {
                     self.pos += 3;
-                    Some(Ok(Token::HereString))
+                    Some(Ok(Token::HereString(self.here_string_literal())))
                 } else if self.peek_at(1) == Some(b'<') {
                     self.pos += 2;
-                    Some(Ok(Token::HereDoc))
+                    Some(Ok(Token::HereDoc(self.heredoc_delim_quoted())))
                 } else {
                     self.pos += 1;
                     Some(Ok(Token::RedirectIn))
                 }
             }
+            // トークン先頭の `N>`（N は 3〜9）は任意 fd へのリダイレクト
+            b'3'..=b'9' if self.peek_at(1) == Some(b'>') => {
+                let fd = (ch - b'0') as i32;
+                match self.peek_at(2) {
+                    Some(b'&') => {
+                        self.pos += 3;
+                        Some(Ok(Token::FdDupPrefix(fd)))
+                    }
+                    Some(b'>') => {
+                        self.pos += 3;
+                        Some(Ok(Token::RedirectFdOut(fd, true)))
+                    }
+                    _ => {
+                        self.pos += 2;
+                        Some(Ok(Token::RedirectFdOut(fd, false)))
+                    }
+                }
+            }
+            // トークン先頭の `1>` は `>` と同じ。`file1>` 等の途中はWordとして読まれる。
+            b'1' if self.peek_at(1) == Some(b'>') && self.peek_at(2) == Some(b'&') => {
+                self.pos += 3;
+                Some(Ok(Token::FdDupPrefix(1)))
+            }
+            b'1' if self.peek_at(1) == Some(b'>') && self.peek_at(2) == Some(b'>') => {
+                self.pos += 3;
+                Some(Ok(Token::RedirectAppend))
+            }
+            b'1' if self.peek_at(1) == Some(b'>') => {
+                self.pos += 2;
+                Some(Ok(Token::RedirectOut))
+            }
             // トークン先頭の `2>` のみ。`file2>` 等の途中はWordとして読まれる。
             b'2' if self.peek_at(1) == Some(b'>') && self.peek_at(2) == Some(b'&') => {
                 self.pos += 3;
@@ -1229,12 +2324,12 @@ impl<'a, 'b> Iterator for Tokenizer<'a, 'b> {
                 let start = self.pos;
                 loop {
                     if self.pos >= self.input.len() {
-                        return Some(Err(ParseError::UnterminatedQuote('\'')));
+                        return Some(Err(ParseError::UnterminatedQuote('\'', self.error_pos(self.token_start))));
                     }
                     if self.input.as_bytes()[self.pos] == b'\'' {
                         let word = &self.input[start..self.pos];
                         self.pos += 1; // skip closing quote
-                        return Some(Ok(Token::Word(Cow::Borrowed(word))));
+                        return Some(Ok(Token::Word(glob::quote_glob_chars(Cow::Borrowed(word)))));
                     }
                     self.pos += 1;
                 }
@@ -1249,9 +2344,13 @@ impl<'a, 'b> Iterator for Tokenizer<'a, 'b> {
                 while scan < self.input.len() {
                     match self.input.as_bytes()[scan] {
                         b'"' => break,
+                        b'$' if self.input.as_bytes().get(scan + 1) == Some(&b'(') => {
+                            // $(...) 内のクォート・エスケープはコマンド置換側で処理する
+                            scan = command_sub_end(self.input.as_bytes(), scan + 2).map_or(self.input.len(), |end| end + 1);
+                        }
                         b'\\' if scan + 1 < self.input.len() => {
                             let next = self.input.as_bytes()[scan + 1];
-                            if matches!(next, b'"' | b'\\' | b'$') {
+                            if matches!(next, b'"' | b'\\' | b'$' | b'\n') {
                                 has_escape = true;
                             }
                             scan += 2;
@@ -1268,20 +2367,13 @@ impl<'a, 'b> Iterator for Tokenizer<'a, 'b> {
                         match self.input.as_bytes()[self.pos] {
                             b'"' => {
                                 self.pos += 1; // skip closing quote
-                                return Some(Ok(Token::Word(Cow::Owned(buf))));
+                                return Some(Ok(Token::Word(glob::quote_glob_chars(Cow::Owned(buf)))));
                             }
                             b'`' => {
                                 // バッククォート → リテラル保持
-                                buf.push('`');
-                                self.pos += 1;
-                                while self.pos < self.input.len() && self.input.as_bytes()[self.pos] != b'`' {
-                                    buf.push(self.input.as_bytes()[self.pos] as char);
-                                    self.pos += 1;
-                                }
-                                if self.pos < self.input.len() {
-                                    buf.push('`');
-                                    self.pos += 1;
-                                }
+                                let end = self.backquote_end();
+                                buf.push_str(&self.input[self.pos..end]);
+                                self.pos = end;
                             }
                             b'\\' if self.pos + 1 < self.input.len() => {
                                 let next = self.input.as_bytes()[self.pos + 1];
@@ -1290,10 +2382,14 @@ impl<'a, 'b> Iterator for Tokenizer<'a, 'b> {
                                         buf.push(next as char);
                                         self.pos += 2;
                                     }
+                                    b'\n' => {
+                                        // 行継続: バックスラッシュと改行を両方除去
+                                        self.pos += 2;
+                                    }
                                     _ => {
                                         buf.push('\\');
-                                        buf.push(next as char);
-                                        self.pos += 2;
+                                        self.pos += 1;
+                                        self.push_current_char(&mut buf);
                                     }
                                 }
                             }
@@ -1308,26 +2404,29 @@ impl<'a, 'b> Iterator for Tokenizer<'a, 'b> {
                                     self.expand_var_inline(&mut buf);
                                 }
                             }
-                            _ => {
-                                buf.push(self.input.as_bytes()[self.pos] as char);
-                                self.pos += 1;
-                            }
+                            _ => self.push_current_char(&mut buf),
                         }
                     }
-                    return Some(Err(ParseError::UnterminatedQuote('"')));
+                    return Some(Err(ParseError::UnterminatedQuote('"', self.error_pos(self.token_start))));
                 } else {
                     // エスケープなし → 既存ロジック
                     loop {
                         if self.pos >= self.input.len() {
-                            return Some(Err(ParseError::UnterminatedQuote('"')));
+                            return Some(Err(ParseError::UnterminatedQuote('"', self.error_pos(self.token_start))));
+                        }
+                        if self.input.as_bytes()[self.pos] == b'$' && self.peek_at(1) == Some(b'(') {
+                            // $(...) 内の `"` で閉じないよう丸ごと読み飛ばす
+                            let len = self.input.len();
+                            self.pos = command_sub_end(self.input.as_bytes(), self.pos + 2).map_or(len, |end| end + 1);
+                            continue;
                         }
                         if self.input.as_bytes()[self.pos] == b'"' {
                             let word = &self.input[start..self.pos];
                             self.pos += 1; // skip closing quote
-                            match expand_variables(word, self.last_status, self.pos_args, self.nounset, self.arrays) {
-                                Ok(cow) => return Some(Ok(Token::Word(cow))),
-                                Err(var_name) => {
-                                    if self.nounset_error.is_none() { self.nounset_error = Some(var_name); }
+                            match expand_variables(word, self.last_status, self.shell_name, self.pos_args, self.nounset, self.arrays) {
+                                Ok(cow) => return Some(Ok(Token::Word(glob::quote_glob_chars(cow)))),
+                                Err(e) => {
+                                    self.record_expand_error(e);
                                     return Some(Ok(Token::Word(Cow::Borrowed(word))));
                                 }
                             }
@@ -1368,67 +2467,42 @@ impl<'a, 'b> Iterator for Tokenizer<'a, 'b> {
                                 && self.input.as_bytes()[self.pos + 1] == b'(' =>
                             {
                                 // $(...) をリテラル保持
-                                buf.push('$');
-                                buf.push('(');
-                                self.pos += 2;
-                                let mut depth = 1;
-                                while self.pos < self.input.len() && depth > 0 {
-                                    match self.input.as_bytes()[self.pos] {
-                                        b'(' => depth += 1,
-                                        b')' => depth -= 1,
-                                        b'\'' => {
-                                            buf.push(self.input.as_bytes()[self.pos] as char);
-                                            self.pos += 1;
-                                            while self.pos < self.input.len()
-                                                && self.input.as_bytes()[self.pos] != b'\''
-                                            {
-                                                buf.push(self.input.as_bytes()[self.pos] as char);
-                                                self.pos += 1;
-                                            }
-                                        }
-                                        _ => {}
-                                    }
-                                    if depth > 0 {
-                                        buf.push(self.input.as_bytes()[self.pos] as char);
-                                    }
-                                    self.pos += 1;
-                                }
+                                let len = self.input.len();
+                                let end = command_sub_end(self.input.as_bytes(), self.pos + 2).unwrap_or(len);
+                                buf.push_str(&self.input[self.pos..end]);
                                 buf.push(')');
+                                self.pos = (end + 1).min(len);
                             }
                             b'`' => {
                                 // バッククォート内をリテラル保持
-                                buf.push('`');
-                                self.pos += 1;
-                                while self.pos < self.input.len() && self.input.as_bytes()[self.pos] != b'`' {
-                                    buf.push(self.input.as_bytes()[self.pos] as char);
-                                    self.pos += 1;
-                                }
-                                if self.pos < self.input.len() {
-                                    buf.push('`');
-                                    self.pos += 1;
-                                }
+                                let end = self.backquote_end();
+                                buf.push_str(&self.input[self.pos..end]);
+                                self.pos = end;
+                            }
+                            b'\\' if self.input.as_bytes().get(self.pos + 1) == Some(&b'\n') => {
+                                // 行継続: バックスラッシュと改行を両方除去
+                                self.pos += 2;
                             }
                             b'\\' if self.pos + 1 < self.input.len() => {
-                                // `\X` → リテラル `X`
-                                self.pos += 1;
-                                buf.push(self.input.as_bytes()[self.pos] as char);
+                                // `\X` → リテラル `X`（グロブ文字ならパス名展開しない）
                                 self.pos += 1;
+                                if matches!(self.input.as_bytes()[self.pos], b'*' | b'?' | b'[') {
+                                    buf.push(glob::QUOTED_GLOB);
+                                }
+                                self.push_current_char(&mut buf);
                             }
                             b'\\' => {
                                 // 末尾のバックスラッシュ → そのまま
                                 buf.push('\\');
                                 self.pos += 1;
                             }
-                            _ => {
-                                buf.push(self.input.as_bytes()[self.pos] as char);
-                                self.pos += 1;
-                            }
+                            _ => self.push_current_char(&mut buf),
                         }
                     }
-                    match expand_variables(&buf, self.last_status, self.pos_args, self.nounset, self.arrays) {
+                    match expand_variables_split(&buf, self.last_status, self.shell_name, self.pos_args, self.nounset, self.arrays) {
                         Ok(expanded) => Some(Ok(Token::Word(Cow::Owned(expanded.into_owned())))),
-                        Err(var_name) => {
-                            if self.nounset_error.is_none() { self.nounset_error = Some(var_name); }
+                        Err(e) => {
+                            self.record_expand_error(e);
                             Some(Ok(Token::Word(Cow::Owned(buf))))
                         }
                     }
@@ -1442,22 +2516,8 @@ impl<'a, 'b> Iterator for Tokenizer<'a, 'b> {
                                 && self.input.as_bytes()[self.pos + 1] == b'(' =>
                             {
                                 // $(...) をまとめてスキップ
-                                self.pos += 2; // skip "$("
-                                let mut depth = 1;
-                                while self.pos < self.input.len() && depth > 0 {
-                                    match self.input.as_bytes()[self.pos] {
-                                        b'(' => depth += 1,
-                                        b')' => depth -= 1,
-                                        b'\'' => {
-                                            self.pos += 1;
-                                            while self.pos < self.input.len()
-                                                && self.input.as_bytes()[self.pos] != b'\''
-                                            { self.pos += 1; }
-                                        }
-                                        _ => {}
-                                    }
-                                    self.pos += 1;
-                                }
+                                let len = self.input.len();
+                                self.pos = command_sub_end(self.input.as_bytes(), self.pos + 2).map_or(len, |end| end + 1);
                             }
                             b'`' => {
                                 // バッククォートをスキップ
@@ -1471,10 +2531,10 @@ impl<'a, 'b> Iterator for Tokenizer<'a, 'b> {
                         }
                     }
                     let word = &self.input[start..self.pos];
-                    match expand_variables(word, self.last_status, self.pos_args, self.nounset, self.arrays) {
+                    match expand_variables_split(word, self.last_status, self.shell_name, self.pos_args, self.nounset, self.arrays) {
                         Ok(cow) => Some(Ok(Token::Word(cow))),
-                        Err(var_name) => {
-                            if self.nounset_error.is_none() { self.nounset_error = Some(var_name); }
+                        Err(e) => {
+                            self.record_expand_error(e);
                             Some(Ok(Token::Word(Cow::Borrowed(word))))
                         }
                     }
@@ -1493,7 +2553,7 @@ pub fn heredoc_delimiters(list: &CommandList<'_>) -> Vec<String> {
     for item in &list.items {
         for cmd in &item.pipeline.commands {
             for r in &cmd.redirects {
-                if r.kind == RedirectKind::HereDoc {
+                if matches!(r.kind, RedirectKind::HereDoc | RedirectKind::HereDocLiteral) {
                     delims.push(r.target.to_string());
                 }
             }
@@ -1508,7 +2568,7 @@ pub fn fill_heredoc_bodies(list: &mut CommandList<'_>, bodies: &[String]) {
     for item in &mut list.items {
         for cmd in &mut item.pipeline.commands {
             for r in &mut cmd.redirects {
-                if r.kind == RedirectKind::HereDoc {
+                if matches!(r.kind, RedirectKind::HereDoc | RedirectKind::HereDocLiteral) {
                     if idx < bodies.len() {
                         r.target = Cow::Owned(bodies[idx].clone());
                     }
@@ -1526,24 +2586,53 @@ pub fn fill_heredoc_bodies(list: &mut CommandList<'_>, bodies: &[String]) {
 /// - 構文エラー → `Err(ParseError)`
 ///
 /// `last_status` は `$?` 展開に使用される。
-pub fn parse<'a>(input: &'a str, last_status: i32, pos_args: &[String], nounset: bool, arrays: &ArrayMap) -> Result<Option<CommandList<'a>>, ParseError> {
-    let mut tokens = Tokenizer::new(input, last_status, pos_args, nounset, arrays);
+pub fn parse<'a>(input: &'a str, last_status: i32, shell_name: &str, pos_args: &[String], nounset: bool, arrays: &ArrayMap) -> Result<Option<CommandList<'a>>, ParseError> {
+    let _arith_scope = ArithWritesScope::enter();
+    let defaults = DefaultAssignedScope::enter();
+    let mut tokens = Tokenizer::new(input, last_status, shell_name, pos_args, nounset, arrays);
     let mut items: Vec<ListItem<'_>> = Vec::new();
     let mut commands: Vec<Command<'_>> = Vec::new();
     let mut args: Vec<Cow<'_, str>> = Vec::new();
     let mut redirects: Vec<Redirect<'_>> = Vec::new();
     let mut assignments: Vec<(String, String)> = Vec::new();
     let mut subshell_body: Option<String> = None;
+    let mut compound: Option<Compound> = None;
     let mut array_assignments: Vec<(String, Vec<String>)> = Vec::new();
     let mut array_appends: Vec<(String, Vec<String>)> = Vec::new();
     let mut indexed_assignments: Vec<(String, usize, String)> = Vec::new();
     let mut background = false;
+    let mut timed = false;
     let mut pending_token: Option<Result<Token<'_>, ParseError>> = None;
+    // リダイレクトだけのコマンドをエラーにするときの位置（現在のコマンドの最初のリダイレクト）
+    let mut first_redirect = 0;
 
     while let Some(result) = pending_token.take().or_else(|| tokens.next()) {
         let token = result?;
+        let token_pos = tokens.error_pos(tokens.token_start);
+        if redirects.is_empty() {
+            first_redirect = token_pos.offset;
+        }
         match token {
+            Token::Word(w) if compound.is_some() => {
+                // `fi` / `done` の直後にワードは続けられない
+                return Err(ParseError::UnexpectedToken(w.into_owned(), token_pos));
+            }
             Token::Word(w) => {
+                // コマンド位置のクォートなしの予約語で複合コマンドを開始する
+                let raw = &input[tokens.token_start..tokens.pos];
+                if args.is_empty() && assignments.is_empty() && redirects.is_empty() && COMPOUND_KEYWORDS.contains(&raw) {
+                    let (parsed, end) = parse_compound(input, raw, tokens.pos)?;
+                    tokens.pos = end;
+                    compound = Some(parsed);
+                    continue;
+                }
+                // パイプライン先頭の `time` 予約語
+                if w == "time" && !timed && args.is_empty() && commands.is_empty() && assignments.is_empty()
+                    && subshell_body.is_none() && compound.is_none() && redirects.is_empty()
+                {
+                    timed = true;
+                    continue;
+                }
                 // コマンド先頭の VAR=val を代入として検出
                 // 条件: args が空（まだコマンド名を見ていない）かつ有効な識別子=値の形式
                 if args.is_empty() {
@@ -1563,7 +2652,7 @@ pub fn parse<'a>(input: &'a str, last_status: i32, pos_args: &[String], nounset:
                                             loop {
                                                 match tokens.next() {
                                                     Some(Ok(Token::RParen)) => break,
-                                                    Some(Ok(Token::Word(elem))) => elements.push(elem.to_string()),
+                                                    Some(Ok(Token::Word(elem))) => elements.push(glob::strip_quoted(&elem).into_owned()),
                                                     None => break,
                                                     Some(Err(e)) => return Err(e),
                                                     _ => {}
@@ -1578,7 +2667,7 @@ pub fn parse<'a>(input: &'a str, last_status: i32, pos_args: &[String], nounset:
                             }
                             // arr+=val （非配列、通常の文字列追加）
                             let cur = std::env::var(name).unwrap_or_default();
-                            let new_val = format!("{}{}", cur, after_eq);
+                            let new_val = format!("{}{}", cur, flatten_word_list(after_eq));
                             assignments.push((name.to_string(), new_val));
                             continue;
                         }
@@ -1595,7 +2684,7 @@ pub fn parse<'a>(input: &'a str, last_status: i32, pos_args: &[String], nounset:
                                         if !name.is_empty() && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
                                             && !name.as_bytes()[0].is_ascii_digit()
                                         {
-                                            let value = w[bracket_end + 1 + eq_pos..].to_string();
+                                            let value = flatten_word_list(&w[bracket_end + 1 + eq_pos..]).into_owned();
                                             indexed_assignments.push((name.to_string(), idx, value));
                                             continue;
                                         }
@@ -1610,7 +2699,9 @@ pub fn parse<'a>(input: &'a str, last_status: i32, pos_args: &[String], nounset:
                         if !name.is_empty() && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
                             && !name.as_bytes()[0].is_ascii_digit()
                         {
-                            let value = &w[eq_pos + 1..];
+                            // 値のチルダ展開（`:` 区切りの各要素も対象: `PATH=~/bin:~/sbin`）
+                            let tilde_expanded = expand_tilde(&w);
+                            let value = &tilde_expanded[eq_pos + 1..];
                             // arr=(...) — 配列代入: 値が空で次トークンが LParen
                             if value.is_empty() {
                                 if let Some(next) = tokens.next() {
@@ -1620,7 +2711,7 @@ pub fn parse<'a>(input: &'a str, last_status: i32, pos_args: &[String], nounset:
                                             loop {
                                                 match tokens.next() {
                                                     Some(Ok(Token::RParen)) => break,
-                                                    Some(Ok(Token::Word(elem))) => elements.push(elem.to_string()),
+                                                    Some(Ok(Token::Word(elem))) => elements.push(glob::strip_quoted(&elem).into_owned()),
                                                     None => break,
                                                     Some(Err(e)) => return Err(e),
                                                     _ => {}
@@ -1633,7 +2724,7 @@ pub fn parse<'a>(input: &'a str, last_status: i32, pos_args: &[String], nounset:
                                     }
                                 }
                             }
-                            assignments.push((name.to_string(), value.to_string()));
+                            assignments.push((name.to_string(), flatten_word_list(value).into_owned()));
                             continue;
                         }
                     }
@@ -1641,19 +2732,21 @@ pub fn parse<'a>(input: &'a str, last_status: i32, pos_args: &[String], nounset:
                 args.push(w);
             }
             Token::Pipe => {
-                if args.is_empty() && assignments.is_empty() && subshell_body.is_none()
+                if args.is_empty() && assignments.is_empty() && subshell_body.is_none() && compound.is_none()
                     && array_assignments.is_empty() && array_appends.is_empty() && indexed_assignments.is_empty()
                 {
-                    return Err(ParseError::EmptyPipelineSegment);
+                    return Err(ParseError::EmptyPipelineSegment(token_pos));
                 }
                 commands.push(Command {
                     args: std::mem::take(&mut args),
                     redirects: std::mem::take(&mut redirects),
                     assignments: std::mem::take(&mut assignments),
                     subshell_body: subshell_body.take(),
+                    compound: compound.take(),
                     array_assignments: std::mem::take(&mut array_assignments),
                     array_appends: std::mem::take(&mut array_appends),
                     indexed_assignments: std::mem::take(&mut indexed_assignments),
+                    arith_assignments: take_arith_writes(),
                 });
             }
             Token::And | Token::Or | Token::Semi => {
@@ -1664,16 +2757,16 @@ pub fn parse<'a>(input: &'a str, last_status: i32, pos_args: &[String], nounset:
                 };
 
                 // `;` の前に何もなくてもスキップ（bash 互換）
-                if args.is_empty() && commands.is_empty() && assignments.is_empty() && subshell_body.is_none()
+                if args.is_empty() && commands.is_empty() && assignments.is_empty() && subshell_body.is_none() && compound.is_none()
                     && array_assignments.is_empty() && array_appends.is_empty() && indexed_assignments.is_empty()
                 {
                     if matches!(connector, Connector::Seq) {
-                        continue; // 先頭 `;` や `;;` はスキップ
+                        continue; // 先頭 `;` はスキップ
                     }
-                    return Err(ParseError::EmptyPipelineSegment);
+                    return Err(ParseError::EmptyPipelineSegment(token_pos));
                 }
 
-                if !args.is_empty() || !assignments.is_empty() || subshell_body.is_some()
+                if !args.is_empty() || !assignments.is_empty() || subshell_body.is_some() || compound.is_some()
                     || !array_assignments.is_empty() || !array_appends.is_empty() || !indexed_assignments.is_empty()
                 {
                     commands.push(Command {
@@ -1681,9 +2774,11 @@ pub fn parse<'a>(input: &'a str, last_status: i32, pos_args: &[String], nounset:
                         redirects: std::mem::take(&mut redirects),
                         assignments: std::mem::take(&mut assignments),
                         subshell_body: subshell_body.take(),
+                        compound: compound.take(),
                         array_assignments: std::mem::take(&mut array_assignments),
                         array_appends: std::mem::take(&mut array_appends),
                         indexed_assignments: std::mem::take(&mut indexed_assignments),
+                        arith_assignments: take_arith_writes(),
                     });
                 }
 
@@ -1691,20 +2786,21 @@ pub fn parse<'a>(input: &'a str, last_status: i32, pos_args: &[String], nounset:
                     pipeline: Pipeline {
                         commands: std::mem::take(&mut commands),
                         background,
+                        timed: std::mem::take(&mut timed),
                     },
                     connector,
                 });
                 background = false;
             }
             Token::Ampersand => {
-                if args.is_empty() && commands.is_empty() && assignments.is_empty() && subshell_body.is_none()
+                if args.is_empty() && commands.is_empty() && assignments.is_empty() && subshell_body.is_none() && compound.is_none()
                     && array_assignments.is_empty() && array_appends.is_empty() && indexed_assignments.is_empty()
                 {
-                    return Err(ParseError::EmptyPipelineSegment);
+                    return Err(ParseError::EmptyPipelineSegment(token_pos));
                 }
 
                 // `&` の後にコマンドが続くケースをサポート（`cmd1 & cmd2`）
-                if !args.is_empty() || !assignments.is_empty() || subshell_body.is_some()
+                if !args.is_empty() || !assignments.is_empty() || subshell_body.is_some() || compound.is_some()
                     || !array_assignments.is_empty() || !array_appends.is_empty() || !indexed_assignments.is_empty()
                 {
                     commands.push(Command {
@@ -1712,9 +2808,11 @@ pub fn parse<'a>(input: &'a str, last_status: i32, pos_args: &[String], nounset:
                         redirects: std::mem::take(&mut redirects),
                         assignments: std::mem::take(&mut assignments),
                         subshell_body: subshell_body.take(),
+                        compound: compound.take(),
                         array_assignments: std::mem::take(&mut array_assignments),
                         array_appends: std::mem::take(&mut array_appends),
                         indexed_assignments: std::mem::take(&mut indexed_assignments),
+                        arith_assignments: take_arith_writes(),
                     });
                 }
 
@@ -1722,22 +2820,26 @@ pub fn parse<'a>(input: &'a str, last_status: i32, pos_args: &[String], nounset:
                     pipeline: Pipeline {
                         commands: std::mem::take(&mut commands),
                         background: true,
+                        timed: std::mem::take(&mut timed),
                     },
                     connector: Connector::Seq,
                 });
                 background = false;
             }
-            Token::RedirectOut | Token::RedirectAppend | Token::RedirectIn | Token::RedirectErr | Token::RedirectErrAppend => {
+            Token::RedirectOut | Token::RedirectAppend | Token::RedirectIn | Token::RedirectErr | Token::RedirectErrAppend
+            | Token::RedirectFdOut(..) => {
                 let kind = match token {
                     Token::RedirectOut => RedirectKind::Output,
                     Token::RedirectAppend => RedirectKind::Append,
                     Token::RedirectIn => RedirectKind::Input,
                     Token::RedirectErr => RedirectKind::Stderr,
                     Token::RedirectErrAppend => RedirectKind::StderrAppend,
+                    Token::RedirectFdOut(fd, append) => RedirectKind::FdOutput { fd, append },
                     _ => unreachable!(),
                 };
                 match tokens.next() {
                     Some(Ok(Token::Word(target))) => {
+                        // `\x1F` 区切りは残す（複数ワードへの展開は executor が ambiguous redirect にする）
                         redirects.push(Redirect { kind, target });
                     }
                     Some(Ok(Token::ProcSubIn(body))) => {
@@ -1747,7 +2849,7 @@ pub fn parse<'a>(input: &'a str, last_status: i32, pos_args: &[String], nounset:
                         redirects.push(Redirect { kind, target: Cow::Owned(format!("\x1E>{}", body)) });
                     }
                     Some(Err(e)) => return Err(e),
-                    _ => return Err(ParseError::MissingRedirectTarget),
+                    _ => return Err(ParseError::MissingRedirectTarget(token_pos)),
                 }
             }
             Token::ProcSubIn(body) => {
@@ -1756,43 +2858,45 @@ pub fn parse<'a>(input: &'a str, last_status: i32, pos_args: &[String], nounset:
             Token::ProcSubOut(body) => {
                 args.push(Cow::Owned(format!("\x1E>{}", body)));
             }
-            Token::HereDoc => {
+            Token::HereDoc(quoted) => {
                 // <<DELIM — ヒアドキュメント（デリミタをターゲットに格納）
                 match tokens.next() {
                     Some(Ok(Token::Word(delim))) => {
-                        redirects.push(Redirect { kind: RedirectKind::HereDoc, target: delim });
+                        let kind = if quoted { RedirectKind::HereDocLiteral } else { RedirectKind::HereDoc };
+                        redirects.push(Redirect { kind, target: join_fields(delim) });
                     }
                     Some(Err(e)) => return Err(e),
-                    _ => return Err(ParseError::MissingRedirectTarget),
+                    _ => return Err(ParseError::MissingRedirectTarget(token_pos)),
                 }
             }
-            Token::HereString => {
+            Token::HereString(literal) => {
                 // <<<word — ヒアストリング
                 match tokens.next() {
                     Some(Ok(Token::Word(word))) => {
-                        redirects.push(Redirect { kind: RedirectKind::HereString, target: word });
+                        let kind = if literal { RedirectKind::HereStringLiteral } else { RedirectKind::HereString };
+                        redirects.push(Redirect { kind, target: join_fields(word) });
                     }
                     Some(Err(e)) => return Err(e),
-                    _ => return Err(ParseError::MissingRedirectTarget),
+                    _ => return Err(ParseError::MissingRedirectTarget(token_pos)),
                 }
             }
             Token::FdDupPrefix(src_fd) => {
                 match tokens.next() {
                     Some(Ok(Token::Word(w))) => {
-                        let dst_fd = w.parse::<i32>().map_err(|_| ParseError::BadFdRedirect)?;
+                        let dst_fd = w.parse::<i32>().map_err(|_| ParseError::BadFdRedirect(token_pos))?;
                         redirects.push(Redirect {
                             kind: RedirectKind::FdDup { src_fd, dst_fd },
                             target: Cow::Borrowed(""),
                         });
                     }
                     Some(Err(e)) => return Err(e),
-                    _ => return Err(ParseError::MissingRedirectTarget),
+                    _ => return Err(ParseError::MissingRedirectTarget(token_pos)),
                 }
             }
             Token::LParen => {
                 // サブシェル開始: コマンド位置（args/assignments が空）でのみ有効
                 if !args.is_empty() || !assignments.is_empty() {
-                    return Err(ParseError::EmptyPipelineSegment);
+                    return Err(ParseError::EmptyPipelineSegment(token_pos));
                 }
                 let body = tokens.collect_subshell_body()?;
                 // subshell_body をセットし、) の後のリダイレクトは通常通り redirects に蓄積。
@@ -1801,31 +2905,43 @@ pub fn parse<'a>(input: &'a str, last_status: i32, pos_args: &[String], nounset:
             }
             Token::RParen => {
                 // 対応しない `)` はエラー
-                return Err(ParseError::EmptyPipelineSegment);
+                return Err(ParseError::EmptyPipelineSegment(token_pos));
+            }
+            Token::DSemi | Token::SemiAmp | Token::DSemiAmp => {
+                // case 節の終端子は case の外では構文エラー（bash 互換）
+                let op = match token {
+                    Token::DSemi => ";;",
+                    Token::SemiAmp => ";&",
+                    _ => ";;&",
+                };
+                return Err(ParseError::UnexpectedToken(op.to_string(), token_pos));
             }
         }
     }
 
     // 末尾パイプ: commands があるが args がない → 継続行入力のトリガー
-    if !commands.is_empty() && args.is_empty() && redirects.is_empty() && assignments.is_empty() && subshell_body.is_none()
+    if !commands.is_empty() && args.is_empty() && redirects.is_empty() && assignments.is_empty() && subshell_body.is_none() && compound.is_none()
         && array_assignments.is_empty() && array_appends.is_empty() && indexed_assignments.is_empty()
     {
         return Err(ParseError::IncompleteInput);
     }
 
     // 最終パイプラインの処理
-    if !args.is_empty() || !assignments.is_empty() || subshell_body.is_some()
+    if !args.is_empty() || !assignments.is_empty() || subshell_body.is_some() || compound.is_some()
         || !array_assignments.is_empty() || !array_appends.is_empty() || !indexed_assignments.is_empty()
     {
-        commands.push(Command { args, redirects, assignments, subshell_body, array_assignments, array_appends, indexed_assignments });
+        commands.push(Command {
+            args, redirects, assignments, subshell_body, compound, array_assignments, array_appends, indexed_assignments,
+            arith_assignments: take_arith_writes(),
+        });
     } else if !redirects.is_empty() {
         // リダイレクトのみ（コマンドなし）
-        return Err(ParseError::EmptyPipelineSegment);
+        return Err(ParseError::EmptyPipelineSegment(tokens.error_pos(first_redirect)));
     }
 
     if !commands.is_empty() {
         items.push(ListItem {
-            pipeline: Pipeline { commands, background },
+            pipeline: Pipeline { commands, background, timed },
             connector: Connector::Seq,
         });
     }
@@ -1846,7 +2962,50 @@ pub fn parse<'a>(input: &'a str, last_status: i32, pos_args: &[String], nounset:
         return Err(ParseError::UnboundVariable(var_name));
     }
 
-    Ok(Some(CommandList { items }))
+    // 算術展開エラーチェック
+    if let Some(msg) = tokens.arith_error {
+        return Err(ParseError::ArithError(msg));
+    }
+
+    Ok(Some(CommandList { items, default_assigned: defaults.finish() }))
+}
+
+/// ワード列のテキストをトークン化し、コマンド引数と同じ規則で展開したワードを返す。
+///
+/// クォート除去・変数展開・算術展開は済み、コマンド置換・チルダ・ブレース・フィールド分割・glob は
+/// 引数と同じく executor の `expand_args_full` に残る。
+/// `for` のワードリストや `case` のワードのように、コマンド行ではないワードの展開に使う。
+/// ワード以外のトークン（演算子・リダイレクト）は構文エラーにする。
+/// `${var:=default}` で未設定から作られた変数名も合わせて返す（[`CommandList::default_assigned`] と同じ）。
+pub fn expand_words<'a>(input: &'a str, last_status: i32, shell_name: &str, pos_args: &[String], nounset: bool, arrays: &ArrayMap) -> Result<(Vec<Cow<'a, str>>, Vec<String>), ParseError> {
+    let defaults = DefaultAssignedScope::enter();
+    let mut tokens = Tokenizer::new(input, last_status, shell_name, pos_args, nounset, arrays);
+    let mut words = Vec::new();
+    while let Some(result) = tokens.next() {
+        match result? {
+            Token::Word(w) => words.push(w),
+            _ => {
+                let raw = &input[tokens.token_start..tokens.pos];
+                return Err(ParseError::UnexpectedToken(raw.to_string(), tokens.error_pos(tokens.token_start)));
+            }
+        }
+    }
+    if let Some(var_name) = tokens.nounset_error {
+        return Err(ParseError::UnboundVariable(var_name));
+    }
+    if let Some(msg) = tokens.arith_error {
+        return Err(ParseError::ArithError(msg));
+    }
+    Ok((words, defaults.finish()))
+}
+
+/// ヒアドキュメント本文のような、ワードに分けないテキストの変数展開（[`expand_variables`]）。
+///
+/// `${var:=default}` で未設定から作られた変数名も合わせて返す。
+pub fn expand_text(s: &str, last_status: i32, shell_name: &str, pos_args: &[String], nounset: bool, arrays: &ArrayMap) -> Result<(String, Vec<String>), ExpandError> {
+    let defaults = DefaultAssignedScope::enter();
+    let expanded = expand_variables(s, last_status, shell_name, pos_args, nounset, arrays)?.into_owned();
+    Ok((expanded, defaults.finish()))
 }
 
 // ── Tests ───────────────────────────────────────────────────────────
@@ -1857,7 +3016,7 @@ mod tests {
 
     /// パース結果から最初のパイプラインの各コマンドの引数を文字列ベクタとして取り出す。
     fn parse_args(input: &str) -> Vec<Vec<String>> {
-        let list = parse(input, 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse(input, 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         list.items[0]
             .pipeline
             .commands
@@ -1938,7 +3097,7 @@ mod tests {
 
     #[test]
     fn redirect_output() {
-        let list = parse("echo hello > out.txt", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo hello > out.txt", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         let p = &list.items[0].pipeline;
         assert_eq!(p.commands.len(), 1);
         assert_eq!(p.commands[0].args.len(), 2);
@@ -1949,7 +3108,7 @@ mod tests {
 
     #[test]
     fn redirect_append() {
-        let list = parse("echo hello >> out.txt", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo hello >> out.txt", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         let p = &list.items[0].pipeline;
         assert_eq!(p.commands[0].redirects[0].kind, RedirectKind::Append);
         assert_eq!(p.commands[0].redirects[0].target, "out.txt");
@@ -1957,7 +3116,7 @@ mod tests {
 
     #[test]
     fn redirect_input() {
-        let list = parse("cat < in.txt", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("cat < in.txt", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         let p = &list.items[0].pipeline;
         assert_eq!(p.commands[0].redirects[0].kind, RedirectKind::Input);
         assert_eq!(p.commands[0].redirects[0].target, "in.txt");
@@ -1965,7 +3124,7 @@ mod tests {
 
     #[test]
     fn redirect_stderr() {
-        let list = parse("ls 2> err.txt", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("ls 2> err.txt", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         let p = &list.items[0].pipeline;
         assert_eq!(p.commands[0].redirects[0].kind, RedirectKind::Stderr);
         assert_eq!(p.commands[0].redirects[0].target, "err.txt");
@@ -1973,7 +3132,7 @@ mod tests {
 
     #[test]
     fn redirect_stderr_append() {
-        let list = parse("cmd 2>> err.log", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("cmd 2>> err.log", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         let p = &list.items[0].pipeline;
         assert_eq!(p.commands[0].redirects[0].kind, RedirectKind::StderrAppend);
         assert_eq!(p.commands[0].redirects[0].target, "err.log");
@@ -1981,7 +3140,7 @@ mod tests {
 
     #[test]
     fn here_string() {
-        let list = parse("cat <<<hello", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("cat <<<hello", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         let p = &list.items[0].pipeline;
         assert_eq!(p.commands[0].args[0], "cat");
         assert_eq!(p.commands[0].redirects[0].kind, RedirectKind::HereString);
@@ -1990,37 +3149,62 @@ mod tests {
 
     #[test]
     fn here_string_with_space() {
-        let list = parse("cat <<< word", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("cat <<< word", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         let p = &list.items[0].pipeline;
         assert_eq!(p.commands[0].redirects[0].kind, RedirectKind::HereString);
         assert_eq!(p.commands[0].redirects[0].target, "word");
     }
 
+    #[test]
+    fn here_string_quoted_substitution_is_literal() {
+        for (input, kind) in [
+            ("cat <<<'$(echo hi)'", RedirectKind::HereStringLiteral),
+            ("cat <<<'plain'", RedirectKind::HereStringLiteral),
+            ("cat <<<plain", RedirectKind::HereString),
+            ("cat <<<$(echo hi)", RedirectKind::HereString),
+            ("cat <<<\"$(echo hi)\"", RedirectKind::HereString),
+            ("cat <<<'a'`echo b`", RedirectKind::HereString),
+        ] {
+            let list = parse(input, 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
+            assert_eq!(list.items[0].pipeline.commands[0].redirects[0].kind, kind, "{}", input);
+        }
+    }
+
     #[test]
     fn here_doc_delimiter() {
-        let list = parse("cat <<EOF", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("cat <<EOF", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         let p = &list.items[0].pipeline;
         assert_eq!(p.commands[0].redirects[0].kind, RedirectKind::HereDoc);
         assert_eq!(p.commands[0].redirects[0].target, "EOF");
     }
 
+    #[test]
+    fn here_doc_quoted_delimiter_is_literal() {
+        for input in ["cat <<'EOF'", "cat <<\"EOF\"", "cat << \\EOF"] {
+            let list = parse(input, 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
+            let r = &list.items[0].pipeline.commands[0].redirects[0];
+            assert_eq!(r.kind, RedirectKind::HereDocLiteral, "{}", input);
+            assert_eq!(r.target, "EOF");
+        }
+    }
+
     #[test]
     fn here_doc_delimiters_fn() {
-        let list = parse("cat <<EOF", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("cat <<EOF", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         let delims = heredoc_delimiters(&list);
         assert_eq!(delims, vec!["EOF"]);
     }
 
     #[test]
     fn redirect_no_space() {
-        let list = parse("echo hello >out.txt", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo hello >out.txt", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         let p = &list.items[0].pipeline;
         assert_eq!(p.commands[0].redirects[0].target, "out.txt");
     }
 
     #[test]
     fn multiple_redirects() {
-        let list = parse("cmd < in.txt > out.txt 2> err.txt", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("cmd < in.txt > out.txt 2> err.txt", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         let p = &list.items[0].pipeline;
         assert_eq!(p.commands[0].redirects.len(), 3);
         assert_eq!(p.commands[0].redirects[0].kind, RedirectKind::Input);
@@ -2032,7 +3216,7 @@ mod tests {
 
     #[test]
     fn pipeline_with_redirects() {
-        let list = parse("cat < in.txt | grep hello > out.txt", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("cat < in.txt | grep hello > out.txt", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         let p = &list.items[0].pipeline;
         assert_eq!(p.commands.len(), 2);
         assert_eq!(p.commands[0].redirects[0].kind, RedirectKind::Input);
@@ -2045,72 +3229,140 @@ mod tests {
 
     #[test]
     fn two_is_not_stderr_redirect_with_space() {
-        let list = parse("echo 2 > file", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo 2 > file", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         let p = &list.items[0].pipeline;
         assert_eq!(p.commands[0].args.len(), 2);
         assert_eq!(p.commands[0].args[1], "2");
         assert_eq!(p.commands[0].redirects[0].kind, RedirectKind::Output);
     }
 
+    // ── 行継続 ──
+
+    #[test]
+    fn backslash_newline_splices_bare_word() {
+        assert_eq!(parse_args("echo a\\\nb"), vec![vec!["echo", "ab"]]);
+        assert_eq!(parse_args("echo a \\\n b"), vec![vec!["echo", "a", "b"]]);
+    }
+
+    #[test]
+    fn backslash_newline_splices_double_quoted() {
+        assert_eq!(parse_args("echo \"a\\\nb\""), vec![vec!["echo", "ab"]]);
+        // シングルクォート内はリテラル
+        assert_eq!(parse_args("echo 'a\\\nb'"), vec![vec!["echo", "a\\\nb"]]);
+    }
+
+    // ── time 予約語 ──
+
+    #[test]
+    fn time_prefix_marks_pipeline() {
+        let list = parse("time sleep 1 | cat; echo time", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
+        assert!(list.items[0].pipeline.timed);
+        assert_eq!(list.items[0].pipeline.commands[0].args, vec!["sleep", "1"]);
+        assert_eq!(list.items[0].pipeline.commands.len(), 2);
+        assert!(!list.items[1].pipeline.timed);
+        assert_eq!(list.items[1].pipeline.commands[0].args, vec!["echo", "time"]);
+    }
+
+    // ── コメント ──
+
+    #[test]
+    fn comment_ends_line() {
+        assert_eq!(parse_args("echo hi # bye"), vec![vec!["echo", "hi"]]);
+        assert_eq!(parse_args("echo hi;# bye"), vec![vec!["echo", "hi"]]);
+        assert_eq!(parse("# only a comment", 0, "rush", &[], false, &HashMap::new()), Ok(None));
+    }
+
+    #[test]
+    fn hash_inside_word_or_quotes_is_literal() {
+        assert_eq!(parse_args("echo a#b"), vec![vec!["echo", "a#b"]]);
+        assert_eq!(parse_args("echo '#x' \"#y\""), vec![vec!["echo", "#x", "#y"]]);
+        // 閉じクォート直後の `#` はコメントを始めない
+        assert_eq!(parse_args("echo 'a'#b | cat"), vec![vec!["echo", "a", "#b"], vec!["cat"]]);
+        assert_eq!(parse_args("echo \"a\"#b"), vec![vec!["echo", "a", "#b"]]);
+    }
+
     // ── 空入力 ──
 
     #[test]
     fn empty_input() {
-        assert!(parse("", 0, &[], false, &HashMap::new()).unwrap().is_none());
-        assert!(parse("   ", 0, &[], false, &HashMap::new()).unwrap().is_none());
-        assert!(parse("\t\n", 0, &[], false, &HashMap::new()).unwrap().is_none());
+        assert!(parse("", 0, "rush", &[], false, &HashMap::new()).unwrap().is_none());
+        assert!(parse("   ", 0, "rush", &[], false, &HashMap::new()).unwrap().is_none());
+        assert!(parse("\t\n", 0, "rush", &[], false, &HashMap::new()).unwrap().is_none());
     }
 
     // ── エラーケース ──
 
+    /// 1 行の入力の `offset` バイト目を指すエラー位置。
+    fn at(offset: usize) -> ErrorPos {
+        ErrorPos { offset, column: offset + 1 }
+    }
+
     #[test]
     fn err_unterminated_single_quote() {
         assert_eq!(
-            parse("echo 'hello", 0, &[], false, &HashMap::new()),
-            Err(ParseError::UnterminatedQuote('\'')),
+            parse("echo 'hello", 0, "rush", &[], false, &HashMap::new()),
+            Err(ParseError::UnterminatedQuote('\'', at(5))),
         );
     }
 
     #[test]
     fn err_unterminated_double_quote() {
         assert_eq!(
-            parse("echo \"hello", 0, &[], false, &HashMap::new()),
-            Err(ParseError::UnterminatedQuote('"')),
+            parse("echo \"hello", 0, "rush", &[], false, &HashMap::new()),
+            Err(ParseError::UnterminatedQuote('"', at(5))),
         );
     }
 
     #[test]
     fn err_missing_redirect_target() {
-        assert_eq!(parse("echo >", 0, &[], false, &HashMap::new()), Err(ParseError::MissingRedirectTarget));
+        assert_eq!(parse("echo >", 0, "rush", &[], false, &HashMap::new()), Err(ParseError::MissingRedirectTarget(at(5))));
     }
 
     #[test]
     fn err_redirect_followed_by_pipe() {
-        assert_eq!(parse("echo > | cat", 0, &[], false, &HashMap::new()), Err(ParseError::MissingRedirectTarget));
+        assert_eq!(parse("echo > | cat", 0, "rush", &[], false, &HashMap::new()), Err(ParseError::MissingRedirectTarget(at(5))));
     }
 
     #[test]
     fn err_leading_pipe() {
-        assert_eq!(parse("| ls", 0, &[], false, &HashMap::new()), Err(ParseError::EmptyPipelineSegment));
+        assert_eq!(parse("| ls", 0, "rush", &[], false, &HashMap::new()), Err(ParseError::EmptyPipelineSegment(at(0))));
     }
 
     #[test]
     fn err_trailing_pipe() {
-        assert_eq!(parse("ls |", 0, &[], false, &HashMap::new()), Err(ParseError::IncompleteInput));
+        assert_eq!(parse("ls |", 0, "rush", &[], false, &HashMap::new()), Err(ParseError::IncompleteInput));
     }
 
     #[test]
     fn err_double_pipe_operator() {
         // `ls | | grep` → first `|` consumed as Pipe, then `| grep` → EmptyPipelineSegment
         // because after Pipe, args is empty and next token is `|` (Pipe)
-        assert_eq!(parse("ls | | grep", 0, &[], false, &HashMap::new()), Err(ParseError::EmptyPipelineSegment));
+        assert_eq!(parse("ls | | grep", 0, "rush", &[], false, &HashMap::new()), Err(ParseError::EmptyPipelineSegment(at(5))));
+    }
+
+    #[test]
+    fn err_positions_point_at_offending_token() {
+        let p = |input| parse(input, 0, "rush", &[], false, &HashMap::new());
+        assert_eq!(p("echo ok && | cat"), Err(ParseError::EmptyPipelineSegment(at(11))));
+        assert_eq!(p("ls -l 2>&x"), Err(ParseError::BadFdRedirect(at(6))));
+        assert_eq!(p("echo a; cat <"), Err(ParseError::MissingRedirectTarget(at(12))));
+        assert_eq!(p("a | > out"), Err(ParseError::EmptyPipelineSegment(at(4))));
+        // 複数行では行内の桁、マルチバイト文字は 1 桁と数える
+        assert_eq!(
+            p("echo ok\necho 日本 'x"),
+            Err(ParseError::UnterminatedQuote('\'', ErrorPos { offset: 20, column: 9 })),
+        );
+        assert_eq!(
+            p("echo >").unwrap_err().to_string(),
+            "syntax error near column 6: missing redirect target",
+        );
     }
 
     // ── background (&) ──
 
     #[test]
     fn background_simple() {
-        let list = parse("sleep 10 &", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("sleep 10 &", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         let p = &list.items[0].pipeline;
         assert!(p.background);
         assert_eq!(p.commands.len(), 1);
@@ -2119,7 +3371,7 @@ mod tests {
 
     #[test]
     fn background_pipeline() {
-        let list = parse("ls | grep foo &", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("ls | grep foo &", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         let p = &list.items[0].pipeline;
         assert!(p.background);
         assert_eq!(p.commands.len(), 2);
@@ -2127,13 +3379,13 @@ mod tests {
 
     #[test]
     fn background_bare_ampersand() {
-        assert_eq!(parse("&", 0, &[], false, &HashMap::new()), Err(ParseError::EmptyPipelineSegment));
+        assert_eq!(parse("&", 0, "rush", &[], false, &HashMap::new()), Err(ParseError::EmptyPipelineSegment(at(0))));
     }
 
     #[test]
     fn background_followed_by_command() {
         // `cmd & extra` → 2 items: cmd (background), extra (foreground)
-        let list = parse("cmd & extra", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("cmd & extra", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items.len(), 2);
         assert!(list.items[0].pipeline.background);
         assert_eq!(list.items[0].pipeline.commands[0].args[0], "cmd");
@@ -2143,7 +3395,7 @@ mod tests {
 
     #[test]
     fn no_background_flag() {
-        let list = parse("ls", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("ls", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert!(!list.items[0].pipeline.background);
     }
 
@@ -2151,7 +3403,7 @@ mod tests {
 
     #[test]
     fn cow_is_borrowed() {
-        let list = parse("echo hello", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo hello", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         for arg in &list.items[0].pipeline.commands[0].args {
             assert!(matches!(arg, Cow::Borrowed(_)), "expected Borrowed, got Owned");
         }
@@ -2159,7 +3411,7 @@ mod tests {
 
     #[test]
     fn cow_quoted_is_borrowed() {
-        let list = parse("echo 'hello world'", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo 'hello world'", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert!(matches!(&list.items[0].pipeline.commands[0].args[1], Cow::Borrowed(_)));
     }
 
@@ -2168,20 +3420,20 @@ mod tests {
     #[test]
     fn expand_env_var() {
         std::env::set_var("RUSH_TEST_VAR", "hello");
-        let list = parse("echo $RUSH_TEST_VAR", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo $RUSH_TEST_VAR", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items[0].pipeline.commands[0].args[1], "hello");
         std::env::remove_var("RUSH_TEST_VAR");
     }
 
     #[test]
     fn expand_last_status() {
-        let list = parse("echo $?", 42, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo $?", 42, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items[0].pipeline.commands[0].args[1], "42");
     }
 
     #[test]
     fn expand_dollar_dollar() {
-        let list = parse("echo $$", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo $$", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         let val: i32 = list.items[0].pipeline.commands[0].args[1].parse().unwrap();
         assert!(val > 0); // should be a valid PID
     }
@@ -2189,29 +3441,54 @@ mod tests {
     #[test]
     fn expand_dollar_bang() {
         std::env::set_var("RUSH_LAST_BG_PID", "12345");
-        let list = parse("echo $!", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo $!", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items[0].pipeline.commands[0].args[1], "12345");
         std::env::remove_var("RUSH_LAST_BG_PID");
     }
 
     #[test]
     fn expand_dollar_zero() {
-        let list = parse("echo $0", 0, &[], false, &HashMap::new()).unwrap().unwrap();
-        assert_eq!(list.items[0].pipeline.commands[0].args[1], "rush");
+        let list = parse("echo $0 ${0}", 0, "script.sh", &[], false, &HashMap::new()).unwrap().unwrap();
+        assert_eq!(list.items[0].pipeline.commands[0].args[1..], ["script.sh", "script.sh"]);
     }
 
     #[test]
     fn expand_random() {
-        let list = parse("echo $RANDOM", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo $RANDOM", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         let val: u64 = list.items[0].pipeline.commands[0].args[1]
             .parse()
             .expect("$RANDOM should be a number");
         assert!(val < 32768, "$RANDOM should be 0..32767, got {}", val);
     }
 
+    #[test]
+    fn seeded_random_is_reproducible() {
+        let draw = || -> Vec<u32> {
+            let list = parse("echo $RANDOM ${RANDOM} $RANDOM", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
+            list.items[0].pipeline.commands[0].args[1..].iter().map(|v| v.parse().unwrap()).collect()
+        };
+        seed_random("42");
+        let first = draw();
+        assert!(first.iter().all(|&v| v < 32768));
+        assert_ne!(first[0], first[1]);
+        seed_random("42");
+        assert_eq!(draw(), first);
+        seed_random("43");
+        assert_ne!(draw(), first);
+        RANDOM_STATE.with(|s| s.set(None));
+    }
+
+    #[test]
+    fn expand_ppid_and_lineno() {
+        let list = parse("echo $PPID $LINENO", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
+        let args = &list.items[0].pipeline.commands[0].args;
+        assert_eq!(args[1].parse::<i32>().unwrap(), unsafe { libc::getppid() });
+        assert!(args[2].parse::<usize>().is_ok());
+    }
+
     #[test]
     fn expand_seconds() {
-        let list = parse("echo $SECONDS", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo $SECONDS", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         let val: u64 = list.items[0].pipeline.commands[0].args[1]
             .parse()
             .expect("$SECONDS should be a number");
@@ -2221,7 +3498,7 @@ mod tests {
 
     #[test]
     fn expand_random_in_braces() {
-        let list = parse("echo ${RANDOM}", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo ${RANDOM}", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         let val: u64 = list.items[0].pipeline.commands[0].args[1]
             .parse()
             .expect("${RANDOM} should be a number");
@@ -2230,7 +3507,7 @@ mod tests {
 
     #[test]
     fn expand_seconds_in_braces() {
-        let list = parse("echo ${SECONDS}", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo ${SECONDS}", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         let val: u64 = list.items[0].pipeline.commands[0].args[1]
             .parse()
             .expect("${SECONDS} should be a number");
@@ -2240,14 +3517,14 @@ mod tests {
     #[test]
     fn expand_undefined_var() {
         std::env::remove_var("RUSH_NONEXISTENT_VAR_XYZ");
-        let list = parse("echo $RUSH_NONEXISTENT_VAR_XYZ", 0, &[], false, &HashMap::new()).unwrap().unwrap();
-        assert_eq!(list.items[0].pipeline.commands[0].args[1], "");
+        let list = parse("echo $RUSH_NONEXISTENT_VAR_XYZ", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
+        assert_eq!(list.items[0].pipeline.commands[0].args[1], "\x1F");
     }
 
     #[test]
     fn single_quote_no_expand() {
         std::env::set_var("RUSH_TEST_VAR2", "expanded");
-        let list = parse("echo '$RUSH_TEST_VAR2'", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo '$RUSH_TEST_VAR2'", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items[0].pipeline.commands[0].args[1], "$RUSH_TEST_VAR2");
         assert!(matches!(&list.items[0].pipeline.commands[0].args[1], Cow::Borrowed(_)));
         std::env::remove_var("RUSH_TEST_VAR2");
@@ -2256,7 +3533,7 @@ mod tests {
     #[test]
     fn double_quote_expand() {
         std::env::set_var("RUSH_TEST_VAR3", "world");
-        let list = parse("echo \"hello $RUSH_TEST_VAR3\"", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo \"hello $RUSH_TEST_VAR3\"", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items[0].pipeline.commands[0].args[1], "hello world");
         std::env::remove_var("RUSH_TEST_VAR3");
     }
@@ -2264,34 +3541,34 @@ mod tests {
     #[test]
     fn redirect_target_expand() {
         std::env::set_var("RUSH_TEST_DIR", "/tmp");
-        let list = parse("echo hello > $RUSH_TEST_DIR/out.txt", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo hello > $RUSH_TEST_DIR/out.txt", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items[0].pipeline.commands[0].redirects[0].target, "/tmp/out.txt");
         std::env::remove_var("RUSH_TEST_DIR");
     }
 
     #[test]
     fn bare_dollar_at_end() {
-        let list = parse("echo $", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo $", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items[0].pipeline.commands[0].args[1], "$");
     }
 
     #[test]
     fn dollar_at_expands_positional() {
-        // $@ expands to all positional parameters (empty when none set)
-        let list = parse("echo $@", 0, &[], false, &HashMap::new()).unwrap().unwrap();
-        assert_eq!(list.items[0].pipeline.commands[0].args[1], "");
+        // $@ expands to all positional parameters (a lone \x1F when none set, removed by the executor)
+        let list = parse("echo $@", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
+        assert_eq!(list.items[0].pipeline.commands[0].args[1], "\x1F");
     }
 
     #[test]
     fn no_dollar_cow_borrowed() {
-        let list = parse("echo hello", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo hello", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert!(matches!(&list.items[0].pipeline.commands[0].args[0], Cow::Borrowed(_)));
         assert!(matches!(&list.items[0].pipeline.commands[0].args[1], Cow::Borrowed(_)));
     }
 
     #[test]
     fn double_quote_no_dollar_cow_borrowed() {
-        let list = parse("echo \"hello\"", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo \"hello\"", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert!(matches!(&list.items[0].pipeline.commands[0].args[1], Cow::Borrowed(_)));
     }
 
@@ -2299,7 +3576,7 @@ mod tests {
 
     #[test]
     fn and_connector() {
-        let list = parse("echo a && echo b", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo a && echo b", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items.len(), 2);
         assert_eq!(list.items[0].connector, Connector::And);
         assert_eq!(list.items[0].pipeline.commands[0].args[0], "echo");
@@ -2311,21 +3588,21 @@ mod tests {
 
     #[test]
     fn or_connector() {
-        let list = parse("false || echo ok", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("false || echo ok", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items.len(), 2);
         assert_eq!(list.items[0].connector, Connector::Or);
     }
 
     #[test]
     fn seq_connector() {
-        let list = parse("echo a ; echo b", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo a ; echo b", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items.len(), 2);
         assert_eq!(list.items[0].connector, Connector::Seq);
     }
 
     #[test]
     fn mixed_connectors() {
-        let list = parse("a && b || c ; d", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("a && b || c ; d", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items.len(), 4);
         assert_eq!(list.items[0].connector, Connector::And);
         assert_eq!(list.items[1].connector, Connector::Or);
@@ -2335,7 +3612,7 @@ mod tests {
 
     #[test]
     fn background_then_command() {
-        let list = parse("sleep 1 & echo done", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("sleep 1 & echo done", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items.len(), 2);
         assert!(list.items[0].pipeline.background);
         assert!(!list.items[1].pipeline.background);
@@ -2344,72 +3621,131 @@ mod tests {
 
     #[test]
     fn leading_semi_skipped() {
-        let list = parse("; echo hello", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("; echo hello", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items.len(), 1);
         assert_eq!(list.items[0].pipeline.commands[0].args[0], "echo");
     }
 
     #[test]
     fn trailing_semi_ok() {
-        let list = parse("echo hello ;", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo hello ;", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items.len(), 1);
     }
 
     #[test]
-    fn double_semi_skipped() {
-        let list = parse("echo a ;; echo b", 0, &[], false, &HashMap::new()).unwrap().unwrap();
-        assert_eq!(list.items.len(), 2);
+    fn err_double_semi_outside_case() {
+        assert_eq!(
+            parse("echo a ;; echo b", 0, "rush", &[], false, &HashMap::new()),
+            Err(ParseError::UnexpectedToken(";;".to_string(), at(7))),
+        );
+        assert_eq!(parse("echo a ;& b", 0, "rush", &[], false, &HashMap::new()), Err(ParseError::UnexpectedToken(";&".to_string(), at(7))));
+        assert_eq!(parse("a;;&", 0, "rush", &[], false, &HashMap::new()), Err(ParseError::UnexpectedToken(";;&".to_string(), at(1))));
+        assert_eq!(
+            parse("echo a ;;", 0, "rush", &[], false, &HashMap::new()).unwrap_err().to_string(),
+            "syntax error near column 8: unexpected token `;;`",
+        );
     }
 
     #[test]
     fn only_semicolons() {
-        assert!(parse(";;;", 0, &[], false, &HashMap::new()).unwrap().is_none());
+        assert!(parse(";", 0, "rush", &[], false, &HashMap::new()).unwrap().is_none());
+        assert_eq!(parse(";;;", 0, "rush", &[], false, &HashMap::new()), Err(ParseError::UnexpectedToken(";;".to_string(), at(0))));
+    }
+
+    #[test]
+    fn expand_words_quotes_and_expands_like_args() {
+        let arrays = HashMap::new();
+        let pos = vec!["p1".to_string()];
+        let (words, _) = expand_words("a 'b c' \"$1\" $((1+2))", 0, "rush", &pos, false, &arrays).unwrap();
+        assert_eq!(words, vec!["a", "b c", "p1", "3"]);
+        assert!(expand_words("", 0, "rush", &[], false, &arrays).unwrap().0.is_empty());
+        std::env::remove_var("RUSH_EXPAND_WORDS_NEW");
+        let (words, created) = expand_words("${RUSH_EXPAND_WORDS_NEW:=v}", 0, "rush", &[], false, &arrays).unwrap();
+        std::env::remove_var("RUSH_EXPAND_WORDS_NEW");
+        assert_eq!((words, created), (vec![Cow::Borrowed("v")], vec!["RUSH_EXPAND_WORDS_NEW".to_string()]));
+        // 演算子は構文エラー
+        assert_eq!(expand_words("a | b", 0, "rush", &[], false, &arrays), Err(ParseError::UnexpectedToken("|".to_string(), at(2))));
+        assert_eq!(
+            expand_words("$RUSH_EXPAND_WORDS_UNSET", 0, "rush", &[], true, &arrays),
+            Err(ParseError::UnboundVariable("RUSH_EXPAND_WORDS_UNSET".to_string()))
+        );
+    }
+
+    /// トークナイザの出力を演算子の表記に戻す（ワードは `w`）。
+    fn token_kinds(input: &str) -> Vec<&'static str> {
+        let arrays = HashMap::new();
+        Tokenizer::new(input, 0, "rush", &[], false, &arrays)
+            .map(|t| match t.unwrap() {
+                Token::Word(_) => "w",
+                Token::Semi => ";",
+                Token::DSemi => ";;",
+                Token::SemiAmp => ";&",
+                Token::DSemiAmp => ";;&",
+                Token::Ampersand => "&",
+                Token::And => "&&",
+                Token::RParen => ")",
+                _ => "?",
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tokenizer_recognizes_case_terminators() {
+        assert_eq!(token_kinds("a) x;; b"), vec!["w", ")", "w", ";;", "w"]);
+        assert_eq!(token_kinds("x ;& y"), vec!["w", ";&", "w"]);
+        assert_eq!(token_kinds("x;;&y"), vec!["w", ";;&", "w"]);
+        // `;` の後に空白があれば別々の `;`
+        assert_eq!(token_kinds("x; ; y"), vec!["w", ";", ";", "w"]);
+        assert_eq!(token_kinds("x;&&y"), vec!["w", ";&", "&", "w"]);
+        assert_eq!(token_kinds("x ;;;"), vec!["w", ";;", ";"]);
+        // クォート内はワードのまま
+        assert_eq!(token_kinds("echo ';;' \";&\""), vec!["w", "w", "w"]);
     }
 
     #[test]
     fn err_leading_and() {
-        assert_eq!(parse("&& cmd", 0, &[], false, &HashMap::new()), Err(ParseError::EmptyPipelineSegment));
+        assert_eq!(parse("&& cmd", 0, "rush", &[], false, &HashMap::new()), Err(ParseError::EmptyPipelineSegment(at(0))));
     }
 
     #[test]
     fn err_trailing_and() {
-        assert_eq!(parse("cmd &&", 0, &[], false, &HashMap::new()), Err(ParseError::IncompleteInput));
+        assert_eq!(parse("cmd &&", 0, "rush", &[], false, &HashMap::new()), Err(ParseError::IncompleteInput));
     }
 
     #[test]
     fn err_leading_or() {
         // `||` at start: first `||` is Or token, empty pipeline before it
-        assert_eq!(parse("|| cmd", 0, &[], false, &HashMap::new()), Err(ParseError::EmptyPipelineSegment));
+        assert_eq!(parse("|| cmd", 0, "rush", &[], false, &HashMap::new()), Err(ParseError::EmptyPipelineSegment(at(0))));
     }
 
     #[test]
     fn err_trailing_or() {
-        assert_eq!(parse("cmd ||", 0, &[], false, &HashMap::new()), Err(ParseError::IncompleteInput));
+        assert_eq!(parse("cmd ||", 0, "rush", &[], false, &HashMap::new()), Err(ParseError::IncompleteInput));
     }
 
     // ── エスケープテスト ──
 
     #[test]
     fn escape_double_quote_in_dquote() {
-        let list = parse(r#"echo "hello\"world""#, 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse(r#"echo "hello\"world""#, 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items[0].pipeline.commands[0].args[1], "hello\"world");
     }
 
     #[test]
     fn escape_backslash_in_dquote() {
-        let list = parse(r#"echo "a\\b""#, 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse(r#"echo "a\\b""#, 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items[0].pipeline.commands[0].args[1], "a\\b");
     }
 
     #[test]
     fn escape_dollar_in_dquote() {
-        let list = parse(r#"echo "\$HOME""#, 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse(r#"echo "\$HOME""#, 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items[0].pipeline.commands[0].args[1], "$HOME");
     }
 
     #[test]
     fn escape_space_in_bare_word() {
-        let list = parse(r"echo file\ name", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse(r"echo file\ name", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items[0].pipeline.commands[0].args[1], "file name");
     }
 
@@ -2418,7 +3754,7 @@ mod tests {
     #[test]
     fn expand_braced_var() {
         std::env::set_var("RUSH_TEST_BRACE", "braced");
-        let list = parse("echo ${RUSH_TEST_BRACE}", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo ${RUSH_TEST_BRACE}", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items[0].pipeline.commands[0].args[1], "braced");
         std::env::remove_var("RUSH_TEST_BRACE");
     }
@@ -2426,7 +3762,7 @@ mod tests {
     #[test]
     fn expand_braced_var_with_suffix() {
         std::env::set_var("RUSH_TEST_BSUF", "val");
-        let list = parse("echo ${RUSH_TEST_BSUF}suffix", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo ${RUSH_TEST_BSUF}suffix", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items[0].pipeline.commands[0].args[1], "valsuffix");
         std::env::remove_var("RUSH_TEST_BSUF");
     }
@@ -2434,14 +3770,14 @@ mod tests {
     #[test]
     fn expand_braced_undefined() {
         std::env::remove_var("RUSH_TEST_BUNDEF_XYZ");
-        let list = parse("echo ${RUSH_TEST_BUNDEF_XYZ}", 0, &[], false, &HashMap::new()).unwrap().unwrap();
-        assert_eq!(list.items[0].pipeline.commands[0].args[1], "");
+        let list = parse("echo ${RUSH_TEST_BUNDEF_XYZ}", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
+        assert_eq!(list.items[0].pipeline.commands[0].args[1], "\x1F");
     }
 
     #[test]
     fn braced_unclosed() {
         // `${` without closing `}` → literal "${" then rest
-        let list = parse("echo ${abc", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo ${abc", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items[0].pipeline.commands[0].args[1], "${abc");
     }
 
@@ -2459,6 +3795,38 @@ mod tests {
         assert_eq!(expand_tilde("~/foo"), Cow::Owned::<str>(format!("{}/foo", home)));
     }
 
+    #[test]
+    fn abbreviate_home_prefix() {
+        let home = std::env::var("HOME").unwrap();
+        let home = home.trim_end_matches('/');
+        if home.is_empty() {
+            return;
+        }
+        assert_eq!(abbreviate_home(home), "~");
+        assert_eq!(abbreviate_home(&format!("{}/src/rush", home)), "~/src/rush");
+        // ホームと接頭辞が一致するだけの別ディレクトリは省略しない
+        assert_eq!(abbreviate_home(&format!("{}x/y", home)), format!("{}x/y", home));
+        assert!(matches!(abbreviate_home("/no/such/rush/dir"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn tilde_plus_and_minus() {
+        // PWD / OLDPWD は cd のテストも書き換えるため fork した子プロセス内で確かめる
+        let (_, status) = crate::executor::run_forked(1, || {
+            std::env::set_var("PWD", "/tmp/now");
+            std::env::set_var("OLDPWD", "/tmp/before");
+            let ok = expand_tilde("~+") == "/tmp/now"
+                && expand_tilde("~+/foo") == "/tmp/now/foo"
+                && expand_tilde("~-/bar") == "/tmp/before/bar"
+                && expand_tilde("X=~-") == "X=/tmp/before"
+                && expand_tilde("~+x") == "~+x";
+            std::env::remove_var("OLDPWD");
+            let ok = ok && matches!(expand_tilde("~-/bar"), Cow::Borrowed("~-/bar"));
+            if ok { 0 } else { 1 }
+        });
+        assert_eq!(status, 0);
+    }
+
     #[test]
     fn tilde_no_change() {
         assert!(matches!(expand_tilde("hello"), Cow::Borrowed(_)));
@@ -2470,6 +3838,22 @@ mod tests {
         assert_eq!(expand_tilde("X=~/bar"), Cow::Owned::<str>(format!("X={}/bar", home)));
     }
 
+    #[test]
+    fn tilde_in_colon_separated_value() {
+        let home = std::env::var("HOME").unwrap();
+        assert_eq!(expand_tilde("P=~/a:~/b"), Cow::Owned::<str>(format!("P={0}/a:{0}/b", home)));
+        assert_eq!(expand_tilde("P=/usr/bin:~/bin"), Cow::Owned::<str>(format!("P=/usr/bin:{}/bin", home)));
+        assert!(matches!(expand_tilde("P=/usr/bin:a~/b"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn assignment_value_expands_each_tilde() {
+        let home = std::env::var("HOME").unwrap();
+        let list = parse("P=~/a:~/b cmd", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
+        let cmd = &list.items[0].pipeline.commands[0];
+        assert_eq!(cmd.assignments, vec![("P".to_string(), format!("{0}/a:{0}/b", home))]);
+    }
+
     #[test]
     fn tilde_no_equals_tilde() {
         assert!(matches!(expand_tilde("X=hello"), Cow::Borrowed(_)));
@@ -2479,21 +3863,42 @@ mod tests {
 
     #[test]
     fn fd_dup_2_to_1() {
-        let list = parse("cmd 2>&1", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("cmd 2>&1", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         let r = &list.items[0].pipeline.commands[0].redirects[0];
         assert_eq!(r.kind, RedirectKind::FdDup { src_fd: 2, dst_fd: 1 });
     }
 
     #[test]
     fn fd_dup_stdout_to_stderr() {
-        let list = parse("cmd >&2", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("cmd >&2", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         let r = &list.items[0].pipeline.commands[0].redirects[0];
         assert_eq!(r.kind, RedirectKind::FdDup { src_fd: 1, dst_fd: 2 });
     }
 
+    #[test]
+    fn numbered_fd_redirects() {
+        let list = parse("exec 3>log 4>>more 5>&1", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
+        let cmd = &list.items[0].pipeline.commands[0];
+        assert_eq!(cmd.args, vec!["exec"]);
+        assert_eq!(cmd.redirects[0].kind, RedirectKind::FdOutput { fd: 3, append: false });
+        assert_eq!(cmd.redirects[0].target, "log");
+        assert_eq!(cmd.redirects[1].kind, RedirectKind::FdOutput { fd: 4, append: true });
+        assert_eq!(cmd.redirects[2].kind, RedirectKind::FdDup { src_fd: 5, dst_fd: 1 });
+    }
+
+    #[test]
+    fn explicit_fd1_redirects() {
+        let list = parse("cmd 1>&2 1>out 1>>log", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
+        let cmd = &list.items[0].pipeline.commands[0];
+        assert_eq!(cmd.args, vec!["cmd"]);
+        assert_eq!(cmd.redirects[0].kind, RedirectKind::FdDup { src_fd: 1, dst_fd: 2 });
+        assert_eq!(cmd.redirects[1].kind, RedirectKind::Output);
+        assert_eq!(cmd.redirects[2].kind, RedirectKind::Append);
+    }
+
     #[test]
     fn fd_dup_with_file_redirect() {
-        let list = parse("cmd > out 2>&1", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("cmd > out 2>&1", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         let redirects = &list.items[0].pipeline.commands[0].redirects;
         assert_eq!(redirects.len(), 2);
         assert_eq!(redirects[0].kind, RedirectKind::Output);
@@ -2503,45 +3908,73 @@ mod tests {
 
     #[test]
     fn fd_dup_bad_target() {
-        assert_eq!(parse("cmd 2>&abc", 0, &[], false, &HashMap::new()), Err(ParseError::BadFdRedirect));
+        assert_eq!(parse("cmd 2>&abc", 0, "rush", &[], false, &HashMap::new()), Err(ParseError::BadFdRedirect(at(4))));
     }
 
     // ── コマンド置換パススルーテスト ──
 
     #[test]
     fn cmd_sub_passthrough() {
-        let list = parse("echo $(date)", 0, &[], false, &HashMap::new()).unwrap().unwrap();
-        assert_eq!(list.items[0].pipeline.commands[0].args[1], "$(date)");
+        let list = parse("echo $(date)", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
+        assert_eq!(list.items[0].pipeline.commands[0].args[1], "\x1D$(date)");
     }
 
     #[test]
     fn backtick_passthrough() {
-        let list = parse("echo `date`", 0, &[], false, &HashMap::new()).unwrap().unwrap();
-        assert_eq!(list.items[0].pipeline.commands[0].args[1], "`date`");
+        let list = parse("echo `date`", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
+        assert_eq!(list.items[0].pipeline.commands[0].args[1], "\x1D`date`");
     }
 
     #[test]
     fn cmd_sub_nested() {
-        let list = parse("echo $(echo $(whoami))", 0, &[], false, &HashMap::new()).unwrap().unwrap();
-        assert_eq!(list.items[0].pipeline.commands[0].args[1], "$(echo $(whoami))");
+        let list = parse("echo $(echo $(whoami))", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
+        assert_eq!(list.items[0].pipeline.commands[0].args[1], "\x1D$(echo $(whoami))");
     }
 
     #[test]
     fn cmd_sub_in_double_quotes() {
-        let list = parse("echo \"today is $(date)\"", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo \"today is $(date)\"", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items[0].pipeline.commands[0].args[1], "today is $(date)");
     }
 
+    #[test]
+    fn cmd_sub_with_inner_quotes_in_double_quotes() {
+        assert_eq!(
+            parse_args("echo \"$(echo \"$(echo a b)\")\" \"\\$ $(echo \"x y\")\""),
+            vec![vec!["echo", "$(echo \"$(echo a b)\")", "$ $(echo \"x y\")"]],
+        );
+    }
+
+    #[test]
+    fn cmd_sub_keeps_multibyte_chars() {
+        assert_eq!(
+            parse_args("echo x$(echo é$(echo ü))y \"ß$(echo 'ö')\""),
+            vec![vec!["echo", "x\x1D$(echo é$(echo ü))y", "ß$(echo 'ö')"]],
+        );
+    }
+
+    #[test]
+    fn cmd_sub_marks_only_unquoted_substitutions() {
+        assert_eq!(
+            parse_args("echo \"$(a)\" $(b) `c` x=$(d)"),
+            vec![vec!["echo", "$(a)", "\x1D$(b)", "\x1D`c`", "x=\x1D$(d)"]],
+        );
+        // 代入値は分割しないのでマーカーを残さない
+        let list = parse("x=$(d) y=`e` cmd", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
+        let cmd = &list.items[0].pipeline.commands[0];
+        assert_eq!(cmd.assignments, vec![("x".to_string(), "$(d)".to_string()), ("y".to_string(), "`e`".to_string())]);
+    }
+
     // ── パラメータ展開テスト ──
 
     #[test]
     fn param_default() {
         std::env::remove_var("RUSH_TEST_PDEF");
-        let list = parse("echo ${RUSH_TEST_PDEF:-hello}", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo ${RUSH_TEST_PDEF:-hello}", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items[0].pipeline.commands[0].args[1], "hello");
 
         std::env::set_var("RUSH_TEST_PDEF", "world");
-        let list = parse("echo ${RUSH_TEST_PDEF:-hello}", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo ${RUSH_TEST_PDEF:-hello}", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items[0].pipeline.commands[0].args[1], "world");
         std::env::remove_var("RUSH_TEST_PDEF");
     }
@@ -2549,11 +3982,11 @@ mod tests {
     #[test]
     fn param_alt() {
         std::env::remove_var("RUSH_TEST_PALT");
-        let list = parse("echo ${RUSH_TEST_PALT:+yes}", 0, &[], false, &HashMap::new()).unwrap().unwrap();
-        assert_eq!(list.items[0].pipeline.commands[0].args[1], "");
+        let list = parse("echo ${RUSH_TEST_PALT:+yes}", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
+        assert_eq!(list.items[0].pipeline.commands[0].args[1], "\x1F");
 
         std::env::set_var("RUSH_TEST_PALT", "val");
-        let list = parse("echo ${RUSH_TEST_PALT:+yes}", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo ${RUSH_TEST_PALT:+yes}", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items[0].pipeline.commands[0].args[1], "yes");
         std::env::remove_var("RUSH_TEST_PALT");
     }
@@ -2561,7 +3994,7 @@ mod tests {
     #[test]
     fn param_length() {
         std::env::set_var("RUSH_TEST_PLEN", "hello");
-        let list = parse("echo ${#RUSH_TEST_PLEN}", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo ${#RUSH_TEST_PLEN}", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items[0].pipeline.commands[0].args[1], "5");
         std::env::remove_var("RUSH_TEST_PLEN");
     }
@@ -2569,9 +4002,9 @@ mod tests {
     #[test]
     fn param_strip_suffix() {
         std::env::set_var("RUSH_TEST_PSUF", "hello.tar.gz");
-        let list = parse("echo ${RUSH_TEST_PSUF%.*}", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo ${RUSH_TEST_PSUF%.*}", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items[0].pipeline.commands[0].args[1], "hello.tar");
-        let list = parse("echo ${RUSH_TEST_PSUF%%.*}", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo ${RUSH_TEST_PSUF%%.*}", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items[0].pipeline.commands[0].args[1], "hello");
         std::env::remove_var("RUSH_TEST_PSUF");
     }
@@ -2579,9 +4012,9 @@ mod tests {
     #[test]
     fn param_strip_prefix() {
         std::env::set_var("RUSH_TEST_PPRE", "/usr/local/bin");
-        let list = parse("echo ${RUSH_TEST_PPRE#*/}", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo ${RUSH_TEST_PPRE#*/}", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items[0].pipeline.commands[0].args[1], "usr/local/bin");
-        let list = parse("echo ${RUSH_TEST_PPRE##*/}", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo ${RUSH_TEST_PPRE##*/}", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items[0].pipeline.commands[0].args[1], "bin");
         std::env::remove_var("RUSH_TEST_PPRE");
     }
@@ -2589,9 +4022,9 @@ mod tests {
     #[test]
     fn param_replace() {
         std::env::set_var("RUSH_TEST_PREP", "hello world hello");
-        let list = parse("echo ${RUSH_TEST_PREP/hello/bye}", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo \"${RUSH_TEST_PREP/hello/bye}\"", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items[0].pipeline.commands[0].args[1], "bye world hello");
-        let list = parse("echo ${RUSH_TEST_PREP//hello/bye}", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo \"${RUSH_TEST_PREP//hello/bye}\"", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items[0].pipeline.commands[0].args[1], "bye world bye");
         std::env::remove_var("RUSH_TEST_PREP");
     }
@@ -2600,46 +4033,46 @@ mod tests {
 
     #[test]
     fn arith_basic() {
-        let list = parse("echo $((1+2))", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo $((1+2))", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items[0].pipeline.commands[0].args[1], "3");
     }
 
     #[test]
     fn arith_precedence() {
-        let list = parse("echo $((2+3*4))", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo $((2+3*4))", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items[0].pipeline.commands[0].args[1], "14");
     }
 
     #[test]
     fn arith_parens() {
-        let list = parse("echo $((2*(3+4)))", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo $((2*(3+4)))", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items[0].pipeline.commands[0].args[1], "14");
     }
 
     #[test]
     fn arith_div_mod() {
-        let list = parse("echo $((10/3))", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo $((10/3))", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items[0].pipeline.commands[0].args[1], "3");
-        let list = parse("echo $((10%3))", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo $((10%3))", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items[0].pipeline.commands[0].args[1], "1");
     }
 
     #[test]
     fn arith_negative() {
-        let list = parse("echo $((-5+3))", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo $((-5+3))", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items[0].pipeline.commands[0].args[1], "-2");
     }
 
     #[test]
     fn arith_spaces() {
-        let list = parse("echo $(( 10 + 20 ))", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo $(( 10 + 20 ))", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items[0].pipeline.commands[0].args[1], "30");
     }
 
     #[test]
     fn arith_variable() {
         std::env::set_var("RUSH_TEST_ARITH", "7");
-        let list = parse("echo $((RUSH_TEST_ARITH+3))", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo $((RUSH_TEST_ARITH+3))", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items[0].pipeline.commands[0].args[1], "10");
         std::env::remove_var("RUSH_TEST_ARITH");
     }
@@ -2647,14 +4080,77 @@ mod tests {
     #[test]
     fn arith_dollar_variable() {
         std::env::set_var("RUSH_TEST_ARITH2", "5");
-        let list = parse("echo $(($RUSH_TEST_ARITH2*2))", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo $(($RUSH_TEST_ARITH2*2))", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items[0].pipeline.commands[0].args[1], "10");
         std::env::remove_var("RUSH_TEST_ARITH2");
     }
 
+    #[test]
+    fn arith_div_truncates_toward_zero() {
+        let list = parse("echo $((-7/2)) $((-7%2))", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
+        assert_eq!(list.items[0].pipeline.commands[0].args[1], "-3");
+        assert_eq!(list.items[0].pipeline.commands[0].args[2], "-1");
+    }
+
+    #[test]
+    fn arith_division_by_zero_is_error() {
+        assert_eq!(
+            parse("echo $((1/0))", 0, "rush", &[], false, &HashMap::new()),
+            Err(ParseError::ArithError("1/0: division by 0".to_string()))
+        );
+        assert!(matches!(
+            parse("echo $((5 % 0))", 0, "rush", &[], false, &HashMap::new()),
+            Err(ParseError::ArithError(_))
+        ));
+    }
+
+    #[test]
+    fn arith_overflow_is_error() {
+        assert_eq!(
+            parse("echo $((9223372036854775807*2))", 0, "rush", &[], false, &HashMap::new()),
+            Err(ParseError::ArithError("9223372036854775807*2: integer overflow".to_string()))
+        );
+        // 範囲内の大きな積は正常に評価される
+        let list = parse("echo $((3037000499*3037000499))", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
+        assert_eq!(list.items[0].pipeline.commands[0].args[1], "9223372030926249001");
+    }
+
+    #[test]
+    fn arith_assignment_sets_variable() {
+        std::env::remove_var("RUSH_TEST_ARITH_ASSIGN");
+        let list = parse("echo $(( RUSH_TEST_ARITH_ASSIGN = 5 ))", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
+        assert_eq!(list.items[0].pipeline.commands[0].args[1], "5");
+        // 代入はコマンドに記録され、パース時には環境へ書かない
+        assert!(std::env::var("RUSH_TEST_ARITH_ASSIGN").is_err());
+        assert_eq!(
+            list.items[0].pipeline.commands[0].arith_assignments,
+            [("RUSH_TEST_ARITH_ASSIGN".to_string(), "5".to_string())]
+        );
+        std::env::set_var("RUSH_TEST_ARITH_ASSIGN", "5");
+        let list = parse("echo $((RUSH_TEST_ARITH_ASSIGN += 2)) $((RUSH_TEST_ARITH_ASSIGN *= 3))", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
+        assert_eq!(list.items[0].pipeline.commands[0].args[1], "7");
+        assert_eq!(list.items[0].pipeline.commands[0].args[2], "21");
+        std::env::set_var("RUSH_TEST_ARITH_ASSIGN", "21");
+        let list = parse("echo $((RUSH_TEST_ARITH_ASSIGN -= 1)) $((RUSH_TEST_ARITH_ASSIGN /= 4)) $((RUSH_TEST_ARITH_ASSIGN %= 3))", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
+        assert_eq!(list.items[0].pipeline.commands[0].args[1..], ["20", "5", "2"]);
+        std::env::remove_var("RUSH_TEST_ARITH_ASSIGN");
+    }
+
+    #[test]
+    fn arith_increment_decrement() {
+        std::env::set_var("RUSH_TEST_ARITH_INC", "1");
+        let list = parse("echo $((RUSH_TEST_ARITH_INC++)) $((++RUSH_TEST_ARITH_INC)) $((RUSH_TEST_ARITH_INC--)) $((--RUSH_TEST_ARITH_INC))", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
+        assert_eq!(list.items[0].pipeline.commands[0].args[1..], ["1", "3", "3", "1"]);
+        assert_eq!(list.items[0].pipeline.commands[0].arith_assignments.last().unwrap().1, "1");
+        // 変数が続かない -- は単項マイナスの連続
+        let list = parse("echo $((--5))", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
+        assert_eq!(list.items[0].pipeline.commands[0].args[1], "5");
+        std::env::remove_var("RUSH_TEST_ARITH_INC");
+    }
+
     #[test]
     fn arith_in_double_quotes() {
-        let list = parse("echo \"result=$((1+2))\"", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo \"result=$((1+2))\"", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items[0].pipeline.commands[0].args[1], "result=3");
     }
 
@@ -2662,24 +4158,24 @@ mod tests {
 
     #[test]
     fn incomplete_trailing_pipe() {
-        assert_eq!(parse("ls |", 0, &[], false, &HashMap::new()), Err(ParseError::IncompleteInput));
+        assert_eq!(parse("ls |", 0, "rush", &[], false, &HashMap::new()), Err(ParseError::IncompleteInput));
         // 継続入力後の再パースは成功する
-        let list = parse("ls |\ngrep foo", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("ls |\ngrep foo", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items[0].pipeline.commands.len(), 2);
     }
 
     #[test]
     fn incomplete_trailing_and() {
-        assert_eq!(parse("true &&", 0, &[], false, &HashMap::new()), Err(ParseError::IncompleteInput));
-        let list = parse("true &&\necho ok", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        assert_eq!(parse("true &&", 0, "rush", &[], false, &HashMap::new()), Err(ParseError::IncompleteInput));
+        let list = parse("true &&\necho ok", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items.len(), 2);
         assert_eq!(list.items[0].connector, Connector::And);
     }
 
     #[test]
     fn incomplete_trailing_or() {
-        assert_eq!(parse("false ||", 0, &[], false, &HashMap::new()), Err(ParseError::IncompleteInput));
-        let list = parse("false ||\necho ok", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        assert_eq!(parse("false ||", 0, "rush", &[], false, &HashMap::new()), Err(ParseError::IncompleteInput));
+        let list = parse("false ||\necho ok", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items.len(), 2);
         assert_eq!(list.items[0].connector, Connector::Or);
     }
@@ -2687,15 +4183,15 @@ mod tests {
     #[test]
     fn multiline_quoted_string() {
         // 最初のパースは UnterminatedQuote
-        assert!(matches!(parse("echo \"hello", 0, &[], false, &HashMap::new()), Err(ParseError::UnterminatedQuote('"'))));
+        assert!(matches!(parse("echo \"hello", 0, "rush", &[], false, &HashMap::new()), Err(ParseError::UnterminatedQuote('"', _))));
         // 継続入力後は成功
-        let list = parse("echo \"hello\nworld\"", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo \"hello\nworld\"", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items[0].pipeline.commands[0].args[1], "hello\nworld");
     }
 
     #[test]
     fn inline_assignment_only() {
-        let list = parse("FOO=bar", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("FOO=bar", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         let cmd = &list.items[0].pipeline.commands[0];
         assert!(cmd.args.is_empty());
         assert_eq!(cmd.assignments, vec![("FOO".to_string(), "bar".to_string())]);
@@ -2703,7 +4199,7 @@ mod tests {
 
     #[test]
     fn inline_assignment_with_command() {
-        let list = parse("FOO=bar echo hello", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("FOO=bar echo hello", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         let cmd = &list.items[0].pipeline.commands[0];
         assert_eq!(cmd.args[0], "echo");
         assert_eq!(cmd.args[1], "hello");
@@ -2712,7 +4208,7 @@ mod tests {
 
     #[test]
     fn multiple_assignments() {
-        let list = parse("A=1 B=2 cmd", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("A=1 B=2 cmd", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         let cmd = &list.items[0].pipeline.commands[0];
         assert_eq!(cmd.args[0], "cmd");
         assert_eq!(cmd.assignments.len(), 2);
@@ -2723,7 +4219,7 @@ mod tests {
     #[test]
     fn assignment_not_after_command() {
         // FOO=bar should not be treated as assignment when after a command word
-        let list = parse("echo FOO=bar", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo FOO=bar", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         let cmd = &list.items[0].pipeline.commands[0];
         assert!(cmd.assignments.is_empty());
         assert_eq!(cmd.args[1], "FOO=bar");
@@ -2733,15 +4229,15 @@ mod tests {
 
     #[test]
     fn dollar_1_no_positional() {
-        // $1 with no positional args → empty
-        let list = parse("echo $1", 0, &[], false, &HashMap::new()).unwrap().unwrap();
-        assert_eq!(list.items[0].pipeline.commands[0].args[1], "");
+        // $1 with no positional args → empty (the unquoted word is removed by the executor)
+        let list = parse("echo $1", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
+        assert_eq!(list.items[0].pipeline.commands[0].args[1], "\x1F");
     }
 
     #[test]
     fn dollar_1_with_positional() {
         let args = vec!["hello".to_string(), "world".to_string()];
-        let list = parse("echo $1 $2", 0, &args, false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo $1 $2", 0, "rush", &args, false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items[0].pipeline.commands[0].args[1], "hello");
         assert_eq!(list.items[0].pipeline.commands[0].args[2], "world");
     }
@@ -2749,15 +4245,75 @@ mod tests {
     #[test]
     fn dollar_hash_count() {
         let args = vec!["a".to_string(), "b".to_string(), "c".to_string()];
-        let list = parse("echo $#", 0, &args, false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo $#", 0, "rush", &args, false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items[0].pipeline.commands[0].args[1], "3");
     }
 
     #[test]
     fn dollar_star_all_args() {
         let args = vec!["a".to_string(), "b".to_string()];
-        let list = parse("echo $*", 0, &args, false, &HashMap::new()).unwrap().unwrap();
-        assert_eq!(list.items[0].pipeline.commands[0].args[1], "a b");
+        let list = parse("echo $*", 0, "rush", &args, false, &HashMap::new()).unwrap().unwrap();
+        assert_eq!(list.items[0].pipeline.commands[0].args[1], "a\x1Fb");
+    }
+
+    #[test]
+    fn quoted_dollar_at_separates_args() {
+        let args: Vec<String> = ["a", "b c", "d"].iter().map(|s| s.to_string()).collect();
+        let list = parse("echo \"$@\"", 0, "rush", &args, false, &HashMap::new()).unwrap().unwrap();
+        assert_eq!(list.items[0].pipeline.commands[0].args[1], "a\x1Fb c\x1Fd");
+        // エスケープを含む文字列でも同様
+        let list = parse("echo \"\\$ $@\"", 0, "rush", &args, false, &HashMap::new()).unwrap().unwrap();
+        assert_eq!(list.items[0].pipeline.commands[0].args[1], "$ a\x1Fb c\x1Fd");
+    }
+
+    #[test]
+    fn unquoted_expansion_is_field_split() {
+        std::env::set_var("RUSH_TEST_SPLIT", "a b  c");
+        let list = parse("echo $RUSH_TEST_SPLIT x${RUSH_TEST_SPLIT}y", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
+        assert_eq!(list.items[0].pipeline.commands[0].args[1], "a\x1Fb\x1F\x1Fc");
+        assert_eq!(list.items[0].pipeline.commands[0].args[2], "xa\x1Fb\x1F\x1Fcy");
+        // クォート内は分割しない
+        let list = parse("echo \"$RUSH_TEST_SPLIT\"", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
+        assert_eq!(list.items[0].pipeline.commands[0].args[1], "a b  c");
+        // リテラル部分（エスケープした空白）は分割しない
+        let list = parse("echo a\\ b$RUSH_TEST_SPLIT", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
+        assert_eq!(list.items[0].pipeline.commands[0].args[1], "a ba\x1Fb\x1F\x1Fc");
+        // リダイレクト先も区切りを残す（executor が ambiguous redirect として扱う）
+        let list = parse("echo > $RUSH_TEST_SPLIT", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
+        assert_eq!(list.items[0].pipeline.commands[0].redirects[0].target, "a\x1Fb\x1F\x1Fc");
+        std::env::remove_var("RUSH_TEST_SPLIT");
+    }
+
+    #[test]
+    fn non_whitespace_ifs_keeps_empty_fields() {
+        let split = |val: &str, ifs: &str| {
+            let mut result = String::new();
+            push_split(&mut result, val, ifs);
+            result
+        };
+        assert_eq!(split("a::b", ":"), "a\x1F\x1C\x1Fb");
+        assert_eq!(split(":a:", ":"), "\x1C\x1Fa\x1F");
+        // 空白は連続しても 1 つの区切りで、隣の `:` とも合わせて 1 つ
+        assert_eq!(split("a : b", " :"), "a\x1F\x1F\x1Fb");
+        assert_eq!(split("a  b", " :"), "a\x1F\x1Fb");
+    }
+
+    #[test]
+    fn quoted_dollar_star_joins_with_ifs() {
+        let args: Vec<String> = ["a", "b", "c"].iter().map(|s| s.to_string()).collect();
+        let list = parse("echo \"$*\"", 0, "rush", &args, false, &HashMap::new()).unwrap().unwrap();
+        assert_eq!(list.items[0].pipeline.commands[0].args[1], "a b c");
+        std::env::set_var("IFS", ",");
+        let list = parse("echo \"$*\"", 0, "rush", &args, false, &HashMap::new()).unwrap().unwrap();
+        std::env::remove_var("IFS");
+        assert_eq!(list.items[0].pipeline.commands[0].args[1], "a,b,c");
+    }
+
+    #[test]
+    fn dollar_at_in_assignment_is_space_joined() {
+        let args: Vec<String> = ["a", "b"].iter().map(|s| s.to_string()).collect();
+        let list = parse("X=$@", 0, "rush", &args, false, &HashMap::new()).unwrap().unwrap();
+        assert_eq!(list.items[0].pipeline.commands[0].assignments[0].1, "a b");
     }
 
     // ── set -u (nounset) ──
@@ -2765,7 +4321,7 @@ mod tests {
     #[test]
     fn nounset_undefined_var_error() {
         std::env::remove_var("RUSH_NOUNSET_TEST_UNDEF");
-        let result = parse("echo $RUSH_NOUNSET_TEST_UNDEF", 0, &[], true, &HashMap::new());
+        let result = parse("echo $RUSH_NOUNSET_TEST_UNDEF", 0, "rush", &[], true, &HashMap::new());
         assert!(result.is_err());
         match result.unwrap_err() {
             ParseError::UnboundVariable(name) => assert_eq!(name, "RUSH_NOUNSET_TEST_UNDEF"),
@@ -2776,7 +4332,7 @@ mod tests {
     #[test]
     fn nounset_defined_var_ok() {
         std::env::set_var("RUSH_NOUNSET_TEST_DEF", "hello");
-        let result = parse("echo $RUSH_NOUNSET_TEST_DEF", 0, &[], true, &HashMap::new());
+        let result = parse("echo $RUSH_NOUNSET_TEST_DEF", 0, "rush", &[], true, &HashMap::new());
         assert!(result.is_ok());
         let list = result.unwrap().unwrap();
         assert_eq!(list.items[0].pipeline.commands[0].args[1], "hello");
@@ -2787,16 +4343,94 @@ mod tests {
     fn nounset_default_operator_exempt() {
         std::env::remove_var("RUSH_NOUNSET_TEST_DFLT");
         // ${var:-default} は nounset エラーにならない
-        let result = parse("echo ${RUSH_NOUNSET_TEST_DFLT:-ok}", 0, &[], true, &HashMap::new());
+        let result = parse("echo ${RUSH_NOUNSET_TEST_DFLT:-ok}", 0, "rush", &[], true, &HashMap::new());
         assert!(result.is_ok());
         let list = result.unwrap().unwrap();
         assert_eq!(list.items[0].pipeline.commands[0].args[1], "ok");
     }
 
+    #[test]
+    fn nounset_braced_forms_match_plain_reference() {
+        std::env::remove_var("RUSH_NOUNSET_TEST_BRACED");
+        let p = |input| parse(input, 0, "rush", &[], true, &HashMap::new()).map(|_| ());
+        let unbound = Err(ParseError::UnboundVariable("RUSH_NOUNSET_TEST_BRACED".to_string()));
+        assert_eq!(p("echo $RUSH_NOUNSET_TEST_BRACED"), unbound);
+        assert_eq!(p("echo ${RUSH_NOUNSET_TEST_BRACED}"), unbound);
+        assert_eq!(p("echo \"${RUSH_NOUNSET_TEST_BRACED}\""), unbound);
+        assert_eq!(p("echo ${#RUSH_NOUNSET_TEST_BRACED}"), unbound);
+        assert_eq!(p("echo ${RUSH_NOUNSET_TEST_BRACED%x}"), unbound);
+        assert_eq!(p("echo ${RUSH_NOUNSET_TEST_BRACED/a/b}"), unbound);
+        assert_eq!(ParseError::UnboundVariable("UNDEF".to_string()).to_string(), "UNDEF: unbound variable");
+        // 既定値系の演算子は免除、空文字列の変数は設定済み
+        assert_eq!(p("echo ${RUSH_NOUNSET_TEST_BRACED:+x} ${RUSH_NOUNSET_TEST_BRACED:-y}"), Ok(()));
+        std::env::set_var("RUSH_NOUNSET_TEST_BRACED", "");
+        assert_eq!(p("echo ${RUSH_NOUNSET_TEST_BRACED} ${RUSH_NOUNSET_TEST_BRACED%x}"), Ok(()));
+        std::env::remove_var("RUSH_NOUNSET_TEST_BRACED");
+        // ${N} は位置パラメータを参照する
+        let args = vec!["ab".to_string()];
+        let list = parse("echo ${1} ${1%b} ${#1}", 0, "rush", &args, true, &HashMap::new()).unwrap().unwrap();
+        assert_eq!(list.items[0].pipeline.commands[0].args[1..], ["ab", "a", "2"]);
+        assert_eq!(p("echo ${1}"), Err(ParseError::UnboundVariable("1".to_string())));
+    }
+
+    #[test]
+    fn nounset_exempts_default_operators_without_colon() {
+        std::env::remove_var("RUSH_NOUNSET_TEST_NOCOLON");
+        let args = |input| {
+            let list = parse(input, 0, "rush", &[], true, &HashMap::new()).unwrap().unwrap();
+            list.items[0].pipeline.commands[0].args[1..].iter().map(|a| a.to_string()).collect::<Vec<_>>()
+        };
+        assert_eq!(args("echo \"${RUSH_NOUNSET_TEST_NOCOLON+x}\" \"${RUSH_NOUNSET_TEST_NOCOLON-def}\""), ["", "def"]);
+        assert_eq!(args("echo \"${RUSH_NOUNSET_TEST_NOCOLON?}\""), [""]);
+        // `:` なしは空文字列の変数を設定済みとして扱う
+        std::env::set_var("RUSH_NOUNSET_TEST_NOCOLON", "");
+        assert_eq!(
+            args("echo \"${RUSH_NOUNSET_TEST_NOCOLON+x}\" \"${RUSH_NOUNSET_TEST_NOCOLON-def}\" \"${RUSH_NOUNSET_TEST_NOCOLON:-def}\""),
+            ["x", "", "def"]
+        );
+        std::env::remove_var("RUSH_NOUNSET_TEST_NOCOLON");
+        assert_eq!(args("echo ${RUSH_NOUNSET_TEST_NOCOLON=set}"), ["set"]);
+        assert_eq!(std::env::var("RUSH_NOUNSET_TEST_NOCOLON").unwrap(), "set");
+        std::env::remove_var("RUSH_NOUNSET_TEST_NOCOLON");
+    }
+
+    #[test]
+    fn default_assignment_names_are_returned_with_the_list() {
+        std::env::remove_var("RUSH_DEFAULT_LIST_NEW");
+        std::env::set_var("RUSH_DEFAULT_LIST_OLD", "");
+        let list = parse("echo ${RUSH_DEFAULT_LIST_NEW:=a} ${RUSH_DEFAULT_LIST_OLD:=b}", 0, "rush", &[], false, &HashMap::new())
+            .unwrap()
+            .unwrap();
+        std::env::remove_var("RUSH_DEFAULT_LIST_NEW");
+        std::env::remove_var("RUSH_DEFAULT_LIST_OLD");
+        // 既に設定されていた変数は含めない
+        assert_eq!(list.default_assigned, ["RUSH_DEFAULT_LIST_NEW"]);
+        // 記録はパースごとに閉じ、次のパースに持ち越さない
+        let list = parse("echo x", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
+        assert!(list.default_assigned.is_empty());
+    }
+
+    #[test]
+    fn prefix_name_listing() {
+        std::env::set_var("RUSH_PFX_2", "b");
+        std::env::set_var("RUSH_PFX_1", "a");
+        std::env::set_var("RUSH_PFXOTHER", "c");
+        let mut arrays = HashMap::new();
+        arrays.insert("RUSH_PFX_arr".to_string(), BTreeMap::from([(0, "x".to_string())]));
+        let list = parse("echo ${!RUSH_PFX_@} \"${!RUSH_PFX_*}\" ${!RUSH_PFX_NONE@}", 0, "rush", &[], false, &arrays).unwrap().unwrap();
+        assert_eq!(
+            list.items[0].pipeline.commands[0].args[1..],
+            ["RUSH_PFX_1\x1FRUSH_PFX_2\x1FRUSH_PFX_arr", "RUSH_PFX_1 RUSH_PFX_2 RUSH_PFX_arr", "\x1F"]
+        );
+        std::env::remove_var("RUSH_PFX_1");
+        std::env::remove_var("RUSH_PFX_2");
+        std::env::remove_var("RUSH_PFXOTHER");
+    }
+
     #[test]
     fn nounset_special_vars_exempt() {
         // $@, $#, $?, $$ 等は nounset 対象外
-        let result = parse("echo $@ $# $? $$", 0, &[], true, &HashMap::new());
+        let result = parse("echo $@ $# $? $$", 0, "rush", &[], true, &HashMap::new());
         assert!(result.is_ok());
     }
 
@@ -2804,7 +4438,7 @@ mod tests {
     fn nounset_disabled_no_error() {
         std::env::remove_var("RUSH_NOUNSET_TEST_OFF");
         // nounset=false なら未定義変数はエラーにならない
-        let result = parse("echo $RUSH_NOUNSET_TEST_OFF", 0, &[], false, &HashMap::new());
+        let result = parse("echo $RUSH_NOUNSET_TEST_OFF", 0, "rush", &[], false, &HashMap::new());
         assert!(result.is_ok());
     }
 
@@ -2812,7 +4446,7 @@ mod tests {
 
     #[test]
     fn subshell_basic() {
-        let list = parse("(echo hello)", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("(echo hello)", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items.len(), 1);
         let cmd = &list.items[0].pipeline.commands[0];
         assert!(cmd.subshell_body.is_some());
@@ -2822,7 +4456,7 @@ mod tests {
 
     #[test]
     fn subshell_with_semicolons() {
-        let list = parse("(cd /tmp; pwd)", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("(cd /tmp; pwd)", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         let cmd = &list.items[0].pipeline.commands[0];
         assert!(cmd.subshell_body.is_some());
         assert_eq!(cmd.subshell_body.as_ref().unwrap(), "cd /tmp; pwd");
@@ -2830,7 +4464,7 @@ mod tests {
 
     #[test]
     fn subshell_nested() {
-        let list = parse("( (echo nested) )", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("( (echo nested) )", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         let cmd = &list.items[0].pipeline.commands[0];
         assert!(cmd.subshell_body.is_some());
         assert_eq!(cmd.subshell_body.as_ref().unwrap(), "(echo nested)");
@@ -2838,7 +4472,7 @@ mod tests {
 
     #[test]
     fn subshell_in_pipeline() {
-        let list = parse("(echo hello) | cat", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("(echo hello) | cat", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         let pipeline = &list.items[0].pipeline;
         assert_eq!(pipeline.commands.len(), 2);
         assert!(pipeline.commands[0].subshell_body.is_some());
@@ -2847,7 +4481,7 @@ mod tests {
 
     #[test]
     fn subshell_with_redirect() {
-        let list = parse("(echo hello) > out.txt", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("(echo hello) > out.txt", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         let cmd = &list.items[0].pipeline.commands[0];
         assert!(cmd.subshell_body.is_some());
         assert_eq!(cmd.redirects.len(), 1);
@@ -2856,7 +4490,7 @@ mod tests {
 
     #[test]
     fn subshell_with_connector() {
-        let list = parse("(false) || echo ok", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("(false) || echo ok", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert_eq!(list.items.len(), 2);
         assert!(list.items[0].pipeline.commands[0].subshell_body.is_some());
         assert_eq!(list.items[1].pipeline.commands[0].args[0], "echo");
@@ -2864,19 +4498,19 @@ mod tests {
 
     #[test]
     fn subshell_background() {
-        let list = parse("(sleep 1) &", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("(sleep 1) &", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert!(list.items[0].pipeline.background);
         assert!(list.items[0].pipeline.commands[0].subshell_body.is_some());
     }
 
     #[test]
     fn subshell_incomplete() {
-        assert_eq!(parse("(echo hello", 0, &[], false, &HashMap::new()), Err(ParseError::IncompleteInput));
+        assert_eq!(parse("(echo hello", 0, "rush", &[], false, &HashMap::new()), Err(ParseError::IncompleteInput));
     }
 
     #[test]
     fn subshell_empty() {
-        let list = parse("()", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("()", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         let cmd = &list.items[0].pipeline.commands[0];
         assert!(cmd.subshell_body.is_some());
         assert_eq!(cmd.subshell_body.as_ref().unwrap(), "");
@@ -2885,7 +4519,7 @@ mod tests {
     #[test]
     fn subshell_quoted_parens() {
         // クォート内の ) はサブシェル終了とみなさない
-        let list = parse("(echo ')')", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("(echo ')')", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         let cmd = &list.items[0].pipeline.commands[0];
         assert!(cmd.subshell_body.is_some());
         assert_eq!(cmd.subshell_body.as_ref().unwrap(), "echo ')'");
@@ -2894,7 +4528,7 @@ mod tests {
     #[test]
     fn normal_command_no_subshell() {
         // 通常コマンドは subshell_body が None
-        let list = parse("echo hello", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("echo hello", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         assert!(list.items[0].pipeline.commands[0].subshell_body.is_none());
     }
 
@@ -2902,7 +4536,7 @@ mod tests {
 
     #[test]
     fn array_assignment_basic() {
-        let list = parse("arr=(a b c)", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("arr=(a b c)", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         let cmd = &list.items[0].pipeline.commands[0];
         assert_eq!(cmd.array_assignments.len(), 1);
         assert_eq!(cmd.array_assignments[0].0, "arr");
@@ -2911,7 +4545,7 @@ mod tests {
 
     #[test]
     fn array_assignment_empty() {
-        let list = parse("arr=()", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("arr=()", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         let cmd = &list.items[0].pipeline.commands[0];
         assert_eq!(cmd.array_assignments.len(), 1);
         assert_eq!(cmd.array_assignments[0].0, "arr");
@@ -2920,7 +4554,7 @@ mod tests {
 
     #[test]
     fn array_append() {
-        let list = parse("arr+=(x y)", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("arr+=(x y)", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         let cmd = &list.items[0].pipeline.commands[0];
         assert_eq!(cmd.array_appends.len(), 1);
         assert_eq!(cmd.array_appends[0].0, "arr");
@@ -2929,7 +4563,7 @@ mod tests {
 
     #[test]
     fn array_indexed_assignment() {
-        let list = parse("arr[2]=val", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("arr[2]=val", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         let cmd = &list.items[0].pipeline.commands[0];
         assert_eq!(cmd.indexed_assignments.len(), 1);
         assert_eq!(cmd.indexed_assignments[0], ("arr".to_string(), 2, "val".to_string()));
@@ -2938,7 +4572,7 @@ mod tests {
     #[test]
     fn normal_assignment_not_array() {
         // VAR=val は通常代入（配列と混同しない）
-        let list = parse("VAR=hello", 0, &[], false, &HashMap::new()).unwrap().unwrap();
+        let list = parse("VAR=hello", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
         let cmd = &list.items[0].pipeline.commands[0];
         assert_eq!(cmd.assignments.len(), 1);
         assert!(cmd.array_assignments.is_empty());
@@ -2952,7 +4586,7 @@ mod tests {
         btree.insert(1, "world".to_string());
         arrays.insert("arr".to_string(), btree);
 
-        let list = parse("echo ${arr[0]}", 0, &[], false, &arrays).unwrap().unwrap();
+        let list = parse("echo ${arr[0]}", 0, "rush", &[], false, &arrays).unwrap().unwrap();
         let args: Vec<String> = list.items[0].pipeline.commands[0].args.iter().map(|a| a.to_string()).collect();
         assert_eq!(args, vec!["echo", "hello"]);
     }
@@ -2966,7 +4600,7 @@ mod tests {
         btree.insert(2, "c".to_string());
         arrays.insert("arr".to_string(), btree);
 
-        let list = parse("echo ${arr[@]}", 0, &[], false, &arrays).unwrap().unwrap();
+        let list = parse("echo ${arr[@]}", 0, "rush", &[], false, &arrays).unwrap().unwrap();
         let args: Vec<String> = list.items[0].pipeline.commands[0].args.iter().map(|a| a.to_string()).collect();
         // ${arr[@]} は \x1F 区切り — パーサーレベルではまだ1ワード
         assert_eq!(args[0], "echo");
@@ -2981,9 +4615,10 @@ mod tests {
         btree.insert(1, "y".to_string());
         arrays.insert("arr".to_string(), btree);
 
-        let list = parse("echo ${arr[*]}", 0, &[], false, &arrays).unwrap().unwrap();
+        let list = parse("echo ${arr[*]}", 0, "rush", &[], false, &arrays).unwrap().unwrap();
         let args: Vec<String> = list.items[0].pipeline.commands[0].args.iter().map(|a| a.to_string()).collect();
-        assert_eq!(args, vec!["echo", "x y"]);
+        // 非クォートなので IFS で分割される
+        assert_eq!(args, vec!["echo", "x\x1Fy"]);
     }
 
     #[test]
@@ -2995,7 +4630,7 @@ mod tests {
         btree.insert(2, "ccc".to_string());
         arrays.insert("arr".to_string(), btree);
 
-        let list = parse("echo ${#arr[@]}", 0, &[], false, &arrays).unwrap().unwrap();
+        let list = parse("echo ${#arr[@]}", 0, "rush", &[], false, &arrays).unwrap().unwrap();
         let args: Vec<String> = list.items[0].pipeline.commands[0].args.iter().map(|a| a.to_string()).collect();
         assert_eq!(args, vec!["echo", "3"]);
     }
@@ -3007,7 +4642,7 @@ mod tests {
         btree.insert(0, "hello".to_string());
         arrays.insert("arr".to_string(), btree);
 
-        let list = parse("echo ${#arr[0]}", 0, &[], false, &arrays).unwrap().unwrap();
+        let list = parse("echo ${#arr[0]}", 0, "rush", &[], false, &arrays).unwrap().unwrap();
         let args: Vec<String> = list.items[0].pipeline.commands[0].args.iter().map(|a| a.to_string()).collect();
         assert_eq!(args, vec!["echo", "5"]);
     }
@@ -3021,7 +4656,7 @@ mod tests {
         arrays.insert("arr".to_string(), btree);
 
         // $arr → arr[0]
-        let list = parse("echo $arr", 0, &[], false, &arrays).unwrap().unwrap();
+        let list = parse("echo $arr", 0, "rush", &[], false, &arrays).unwrap().unwrap();
         let args: Vec<String> = list.items[0].pipeline.commands[0].args.iter().map(|a| a.to_string()).collect();
         assert_eq!(args, vec!["echo", "first"]);
     }
@@ -3031,7 +4666,7 @@ mod tests {
     #[test]
     fn proc_sub_in_basic() {
         let arrays = HashMap::new();
-        let list = parse("cat <(echo hello)", 0, &[], false, &arrays).unwrap().unwrap();
+        let list = parse("cat <(echo hello)", 0, "rush", &[], false, &arrays).unwrap().unwrap();
         let args: Vec<String> = list.items[0].pipeline.commands[0].args.iter().map(|a| a.to_string()).collect();
         assert_eq!(args, vec!["cat", "\x1E<echo hello"]);
     }
@@ -3039,7 +4674,7 @@ mod tests {
     #[test]
     fn proc_sub_out_basic() {
         let arrays = HashMap::new();
-        let list = parse("echo hello >(cat)", 0, &[], false, &arrays).unwrap().unwrap();
+        let list = parse("echo hello >(cat)", 0, "rush", &[], false, &arrays).unwrap().unwrap();
         let args: Vec<String> = list.items[0].pipeline.commands[0].args.iter().map(|a| a.to_string()).collect();
         assert_eq!(args, vec!["echo", "hello", "\x1E>cat"]);
     }
@@ -3047,7 +4682,7 @@ mod tests {
     #[test]
     fn proc_sub_multiple() {
         let arrays = HashMap::new();
-        let list = parse("diff <(sort a) <(sort b)", 0, &[], false, &arrays).unwrap().unwrap();
+        let list = parse("diff <(sort a) <(sort b)", 0, "rush", &[], false, &arrays).unwrap().unwrap();
         let args: Vec<String> = list.items[0].pipeline.commands[0].args.iter().map(|a| a.to_string()).collect();
         assert_eq!(args, vec!["diff", "\x1E<sort a", "\x1E<sort b"]);
     }
@@ -3055,7 +4690,7 @@ mod tests {
     #[test]
     fn proc_sub_redirect_target() {
         let arrays = HashMap::new();
-        let list = parse("cmd < <(echo hello)", 0, &[], false, &arrays).unwrap().unwrap();
+        let list = parse("cmd < <(echo hello)", 0, "rush", &[], false, &arrays).unwrap().unwrap();
         let cmd = &list.items[0].pipeline.commands[0];
         assert_eq!(cmd.args.len(), 1);
         assert_eq!(cmd.args[0], "cmd");
@@ -3066,7 +4701,7 @@ mod tests {
     #[test]
     fn proc_sub_with_pipe() {
         let arrays = HashMap::new();
-        let list = parse("cat <(ls | sort)", 0, &[], false, &arrays).unwrap().unwrap();
+        let list = parse("cat <(ls | sort)", 0, "rush", &[], false, &arrays).unwrap().unwrap();
         let args: Vec<String> = list.items[0].pipeline.commands[0].args.iter().map(|a| a.to_string()).collect();
         assert_eq!(args, vec!["cat", "\x1E<ls | sort"]);
     }
@@ -3075,14 +4710,199 @@ mod tests {
     fn normal_redirect_not_affected() {
         let arrays = HashMap::new();
         // 通常の < リダイレクトが壊れていないこと
-        let list = parse("cat < file.txt", 0, &[], false, &arrays).unwrap().unwrap();
+        let list = parse("cat < file.txt", 0, "rush", &[], false, &arrays).unwrap().unwrap();
         let cmd = &list.items[0].pipeline.commands[0];
         assert_eq!(cmd.args.len(), 1);
         assert_eq!(cmd.redirects[0].target, "file.txt");
 
         // 通常の > リダイレクトも壊れていないこと
-        let list2 = parse("echo hello > out.txt", 0, &[], false, &arrays).unwrap().unwrap();
+        let list2 = parse("echo hello > out.txt", 0, "rush", &[], false, &arrays).unwrap().unwrap();
         let cmd2 = &list2.items[0].pipeline.commands[0];
         assert_eq!(cmd2.redirects[0].target, "out.txt");
     }
+
+    // ── 複合コマンド ──
+
+    /// 1 コマンドだけの入力をパースし、その複合コマンドを返す。
+    fn compound_of(input: &str) -> Compound {
+        let list = parse(input, 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
+        list.items[0].pipeline.commands[0].compound.clone().expect("compound command")
+    }
+
+    /// 先頭行（0 行目）から始まる節を作る。
+    fn text(s: &str) -> Clause {
+        text_at(s, 0)
+    }
+
+    /// `line` 行目から始まる節を作る。
+    fn text_at(s: &str, line: usize) -> Clause {
+        Clause { text: s.to_string(), line }
+    }
+
+    /// 先頭行にある `(条件, 本体)` の組を作る。
+    fn clause(cond: &str, body: &str) -> (Clause, Clause) {
+        (text(cond), text(body))
+    }
+
+    #[test]
+    fn compound_if_sections() {
+        assert_eq!(
+            compound_of("if true; then echo yes; fi"),
+            Compound::If { clauses: vec![clause("true;", "echo yes;")], else_body: None },
+        );
+        assert_eq!(
+            compound_of("if false; then echo first; elif true; then echo second; else echo third; fi"),
+            Compound::If {
+                clauses: vec![clause("false;", "echo first;"), clause("true;", "echo second;")],
+                else_body: Some(text("echo third;")),
+            },
+        );
+        assert_eq!(
+            compound_of("if true\nthen\necho hello\necho world\nfi"),
+            Compound::If { clauses: vec![(text("true"), text_at("echo hello\necho world", 2))], else_body: None },
+        );
+    }
+
+    #[test]
+    fn compound_keywords_ignored_in_quotes_and_substitutions() {
+        assert_eq!(
+            compound_of("if [ \"$(echo a; echo then)\" = 'fi; x' ]; then echo `echo fi`; fi"),
+            Compound::If {
+                clauses: vec![clause("[ \"$(echo a; echo then)\" = 'fi; x' ];", "echo `echo fi`;")],
+                else_body: None,
+            },
+        );
+        // 引数位置の予約語・${...} の中の `;` もワードの一部
+        assert_eq!(
+            compound_of("while echo done; do echo ${x:-a; fi}; done"),
+            Compound::While { condition: text("echo done;"), body: text("echo ${x:-a; fi};"), until: false },
+        );
+    }
+
+    #[test]
+    fn compound_for_header() {
+        assert_eq!(
+            compound_of("for x in a \"b c\" $(ls; pwd); do echo $x; done"),
+            Compound::For {
+                var: "x".to_string(),
+                words: Some(vec!["a".to_string(), "\"b c\"".to_string(), "$(ls; pwd)".to_string()]),
+                body: text("echo $x;"),
+            },
+        );
+        assert_eq!(
+            compound_of("for x\nin a b\ndo\n  echo $x\ndone"),
+            Compound::For { var: "x".to_string(), words: Some(vec!["a".to_string(), "b".to_string()]), body: text_at("echo $x", 3) },
+        );
+        assert_eq!(
+            compound_of("for x; do :; done"),
+            Compound::For { var: "x".to_string(), words: None, body: text(":;") },
+        );
+    }
+
+    #[test]
+    fn compound_nesting_and_case_patterns() {
+        assert_eq!(
+            compound_of("until false; do for i in 1; do if true; then :; fi; done; done"),
+            Compound::While {
+                condition: text("false;"),
+                body: text("for i in 1; do if true; then :; fi; done;"),
+                until: true,
+            },
+        );
+        // case のパターンの `done` / `fi` は予約語ではない
+        assert_eq!(
+            compound_of("while true; do case $x in done) break;; fi|*) :;; esac; done"),
+            Compound::While {
+                condition: text("true;"),
+                body: text("case $x in done) break;; fi|*) :;; esac;"),
+                until: false,
+            },
+        );
+    }
+
+    #[test]
+    fn compound_followed_by_list_items() {
+        let list = parse("if true; then :; fi && echo ok; echo done", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
+        assert_eq!(list.items.len(), 3);
+        assert!(list.items[0].pipeline.commands[0].compound.is_some());
+        assert_eq!(list.items[0].connector, Connector::And);
+        assert_eq!(list.items[2].pipeline.commands[0].args[0], "echo");
+        let list = parse("for x in a; do echo; done | sort > out", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
+        let commands = &list.items[0].pipeline.commands;
+        assert_eq!(commands.len(), 2);
+        assert!(commands[0].compound.is_some());
+        assert_eq!(commands[1].redirects[0].target, "out");
+        // クォートした予約語は普通のコマンド名
+        let list = parse("'if' true", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
+        assert!(list.items[0].pipeline.commands[0].compound.is_none());
+    }
+
+    #[test]
+    fn compound_syntax_errors() {
+        let p = |input| parse(input, 0, "rush", &[], false, &HashMap::new());
+        assert_eq!(p("if true; then echo"), Err(ParseError::IncompleteInput));
+        assert_eq!(p("while true; do"), Err(ParseError::IncompleteInput));
+        assert_eq!(p("if true; done"), Err(ParseError::UnexpectedToken("done".to_string(), at(9))));
+        assert_eq!(p("if then echo; fi"), Err(ParseError::UnexpectedToken("then".to_string(), at(3))));
+        assert_eq!(p("while; do :; done"), Err(ParseError::UnexpectedToken("do".to_string(), at(7))));
+        assert_eq!(p("for 1x in a; do :; done"), Err(ParseError::UnexpectedToken("1x".to_string(), at(4))));
+        assert_eq!(p("for x in a b do"), Err(ParseError::IncompleteInput));
+        assert_eq!(p("if true; then :; fi foo"), Err(ParseError::UnexpectedToken("foo".to_string(), at(20))));
+    }
+
+    #[test]
+    fn compound_if_empty_bodies() {
+        assert_eq!(
+            compound_of("if true; then; fi"),
+            Compound::If { clauses: vec![clause("true;", "")], else_body: None }
+        );
+        assert_eq!(
+            compound_of("if false; then :; elif true; then ; ; else\nfi"),
+            Compound::If {
+                clauses: vec![clause("false;", ":;"), clause("true;", "")],
+                else_body: Some(text_at("", 1)),
+            }
+        );
+    }
+
+    #[test]
+    fn compound_case_keeps_block_text() {
+        assert_eq!(
+            compound_of("case $x in done) echo ';; esac';; *) case y in y) :;; esac;& esac"),
+            Compound::Case { block: "case $x in done) echo ';; esac';; *) case y in y) :;; esac;& esac".to_string(), line: 0 }
+        );
+        let list = parse("case a in\na) echo hi;;\nesac > out | cat", 0, "rush", &[], false, &HashMap::new()).unwrap().unwrap();
+        let commands = &list.items[0].pipeline.commands;
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[0].compound, Some(Compound::Case { block: "case a in\na) echo hi;;\nesac".to_string(), line: 0 }));
+        assert_eq!(commands[0].redirects[0].target, "out");
+        assert_eq!(
+            parse("case a in a) :;;", 0, "rush", &[], false, &HashMap::new()),
+            Err(ParseError::IncompleteInput)
+        );
+    }
+
+    #[test]
+    fn compound_malformed_elif() {
+        let p = |input| parse(input, 0, "rush", &[], false, &HashMap::new());
+        // then のない elif
+        assert_eq!(
+            p("if false; then :; elif true; fi"),
+            Err(ParseError::UnexpectedToken("fi".to_string(), at(29)))
+        );
+        // 条件のない elif
+        assert_eq!(
+            p("if false; then :; elif; then :; fi"),
+            Err(ParseError::UnexpectedToken("then".to_string(), at(24)))
+        );
+        // else の後の elif、重複した then
+        assert_eq!(
+            p("if false; then :; else :; elif true; then :; fi"),
+            Err(ParseError::UnexpectedToken("elif".to_string(), at(26)))
+        );
+        assert_eq!(
+            p("if true; then :; then :; fi"),
+            Err(ParseError::UnexpectedToken("then".to_string(), at(17)))
+        );
+    }
 }
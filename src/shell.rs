@@ -2,19 +2,141 @@
 //!
 //! 環境変数は `std::env` を直接使用し、子プロセスへの自動継承を活用する。
 //! ジョブテーブル（[`JobTable`]）、プロセスグループ/ターミナル制御、
-//! `$PATH` キャッシュ（[`PathCache`]）、ユーザー定義関数マップ、
+//! コマンド位置キャッシュ（[`CommandHash`]）、ユーザー定義関数マップ、
 //! 位置パラメータ（`$1`〜`$9`）を保持する。
 //!
-//! [`PathCache`] は [`editor`](crate::editor) とは別インスタンスで管理される。
-//! エディタの PathCache はハイライト・補完用で `read_line` 呼び出し毎にリフレッシュされ、
-//! Shell の PathCache は executor での将来的な PATH 検索最適化用に保持される。
+//! [`Shell::run`] は文字列をスクリプトとして実行する非インタラクティブ API で、
+//! `rush -c` / スクリプト実行 / rc 読み込みと、ライブラリとしての組み込み利用で共有する。
+//! 起動オプションの解析（[`parse_invocation`]）と rc ファイルの決定（[`rc_file_path`]）もここに置く。
+//!
+//! [`CommandHash`] はエディタの [`PathCache`](crate::highlight::PathCache) とは別物。
+//! PathCache はハイライト・補完用に PATH 内のコマンド名一覧を保持し、
+//! CommandHash は executor が実行したコマンドの絶対パスを名前ごとに記憶する。
 
-use std::collections::{BTreeMap, HashMap};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::os::unix::fs::PermissionsExt;
 
 use libc::pid_t;
 
-use crate::highlight::PathCache;
 use crate::job::JobTable;
+use crate::{executor, glob, parser, spawn};
+
+// ── CommandHash ─────────────────────────────────────────────────────
+
+/// [`CommandHash`] の1エントリ。
+pub struct HashEntry {
+    /// 解決済みの絶対パス。
+    pub path: String,
+    /// キャッシュヒット回数（`hash` 一覧表示用）。
+    pub hits: usize,
+}
+
+/// コマンド名 → 絶対パスのキャッシュ（bash の `hash` テーブル相当）。
+///
+/// 外部コマンド実行のたびに `$PATH` を走査するのを避ける。
+/// `$PATH` が変わったら全エントリを破棄する。`hash -r` でも明示的に破棄できる。
+#[derive(Default)]
+pub struct CommandHash {
+    entries: HashMap<String, HashEntry>,
+    /// エントリ登録時の `$PATH` 値。変更検出に使う。
+    path_str: String,
+}
+
+impl CommandHash {
+    pub fn new() -> Self {
+        Self {
+            entries: HashMap::new(),
+            path_str: String::new(),
+        }
+    }
+
+    /// 現在の `$PATH` でコマンドを解決する。キャッシュにあればヒット数を加算して返す。
+    pub fn lookup(&mut self, name: &str) -> Option<String> {
+        let path_var = std::env::var("PATH").unwrap_or_default();
+        self.lookup_in(name, &path_var)
+    }
+
+    /// `path_var` を `$PATH` とみなしてコマンドを解決する。
+    ///
+    /// キャッシュ済みのパスが実行可能でなくなっていれば（削除・移動）再検索する。
+    pub fn lookup_in(&mut self, name: &str, path_var: &str) -> Option<String> {
+        if self.path_str != path_var {
+            self.entries.clear();
+            self.path_str = path_var.to_string();
+        }
+        if let Some(entry) = self.entries.get_mut(name) {
+            if is_executable_file(&entry.path) {
+                entry.hits += 1;
+                return Some(entry.path.clone());
+            }
+            self.entries.remove(name);
+        }
+        let path = search_path(name, path_var)?;
+        self.entries.insert(name.to_string(), HashEntry { path: path.clone(), hits: 1 });
+        Some(path)
+    }
+
+    /// キャッシュを無視して現在の `$PATH` を再検索し、ヒット数 0 で登録する（`hash name`）。
+    pub fn rehash(&mut self, name: &str) -> Option<String> {
+        let path_var = std::env::var("PATH").unwrap_or_default();
+        if self.path_str != path_var {
+            self.entries.clear();
+            self.path_str = path_var.clone();
+        }
+        self.entries.remove(name);
+        let path = search_path(name, &path_var)?;
+        self.entries.insert(name.to_string(), HashEntry { path: path.clone(), hits: 0 });
+        Some(path)
+    }
+
+    /// 1エントリを破棄する（`hash -d name`）。存在しなければ `false`。
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.entries.remove(name).is_some()
+    }
+
+    /// 全エントリを破棄する（`hash -r`）。
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// キャッシュ済みエントリをコマンド名順で返す。
+    pub fn entries(&self) -> Vec<(&str, &HashEntry)> {
+        let mut list: Vec<(&str, &HashEntry)> =
+            self.entries.iter().map(|(k, v)| (k.as_str(), v)).collect();
+        list.sort_by(|a, b| a.0.cmp(b.0));
+        list
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// `path_var` の各ディレクトリから `name` の実行可能ファイルを探す。
+pub fn search_path(name: &str, path_var: &str) -> Option<String> {
+    for dir in path_var.split(':') {
+        let dir = if dir.is_empty() { "." } else { dir };
+        let full = format!("{}/{}", dir, name);
+        if is_executable_file(&full) {
+            return Some(full);
+        }
+    }
+    None
+}
+
+/// 通常ファイルかつ実行ビットが立っているか判定する。
+fn is_executable_file(path: &str) -> bool {
+    match std::fs::metadata(path) {
+        Ok(meta) => meta.is_file() && meta.permissions().mode() & 0o111 != 0,
+        Err(_) => false,
+    }
+}
+
+// ── Shell ───────────────────────────────────────────────────────────
+
+/// [`Shell::max_nesting_depth`] の既定値（zsh の FUNCNEST と同じ）。
+pub const DEFAULT_MAX_NESTING_DEPTH: usize = 500;
 
 /// シェルの実行状態。REPLループ全体で共有される。
 pub struct Shell {
@@ -28,15 +150,22 @@ pub struct Shell {
     pub shell_pgid: pid_t,
     /// ターミナルのファイルディスクリプタ（通常 STDIN_FILENO）。
     pub terminal_fd: i32,
-    /// `$PATH` 内コマンドのキャッシュ。executor での PATH 検索最適化用（将来使用予定）。
-    #[allow(dead_code)]
-    pub path_cache: PathCache,
+    /// コマンド名 → 絶対パスのキャッシュ。外部コマンド起動時の PATH 走査を省く。
+    pub command_hash: CommandHash,
+    /// 外部コマンド起動で使い回す argv / envp のバッファ。
+    pub spawn_ctx: spawn::SpawnContext,
     /// エイリアスマップ。`alias name=value` で定義される。
     pub aliases: HashMap<String, String>,
     /// 直前のバックグラウンドプロセスの PID（`$!` 展開用）。
     pub last_bg_pid: i32,
     /// `source` / 関数実行中のネスト深さ。`return` ビルトインの有効性判定に使用。
     pub source_depth: usize,
+    /// 実行中の関数呼び出しのネスト深さ。
+    pub function_depth: usize,
+    /// `source` と関数呼び出しのネスト深さの上限の既定値。超えると
+    /// "maximum nesting level exceeded" で失敗させ、無限再帰によるスタック溢れを防ぐ。
+    /// シェル変数 `FUNCNEST` に正の整数があればそちらを使う（[`Shell::nesting_limit`]）。
+    pub max_nesting_depth: usize,
     /// `return` が呼ばれたら true にセットし、`source` のループを中断する。
     pub should_return: bool,
     /// ディレクトリスタック（`pushd`/`popd` 用）。スタックトップが最新。
@@ -52,6 +181,8 @@ pub struct Shell {
     pub loop_depth: usize,
     /// ユーザー定義関数マップ。`name() { body }` で定義される。
     pub functions: HashMap<String, String>,
+    /// `$0` の値。既定は `rush`、スクリプト実行時はスクリプト名（[`set_script_args`](Self::set_script_args)）。
+    pub shell_name: String,
     /// 位置パラメータ（`$1`〜`$N`）。関数呼び出し時に設定される。
     pub positional_args: Vec<String>,
     /// `set -e` (errexit): コマンド失敗時にシェルを終了する。
@@ -60,12 +191,22 @@ pub struct Shell {
     pub set_nounset: bool,
     /// `set -o pipefail`: パイプライン中の最初の非ゼロ終了コードを返す。
     pub set_pipefail: bool,
+    /// 実行中の関数呼び出しごとに 1 要素。`local -` を実行した関数では呼び出し時点の
+    /// `(errexit, nounset, pipefail)` を保持し、関数から戻るときに復元する。
+    pub saved_set_options: Vec<Option<(bool, bool, bool)>>,
     /// if/while/until 条件文脈の深さ。0 = 通常、>0 = 条件評価中（errexit 免除）。
     pub in_condition: usize,
     /// errexit 発動フラグ。run_command_string の早期リターンに使用。
     pub errexit_pending: bool,
     /// 配列変数ストレージ。BTreeMap でスパース配列 + 順序付きイテレーションをサポート。
     pub arrays: HashMap<String, BTreeMap<usize, String>>,
+    /// export 属性を持たないシェル変数名（`export -n` や `${var:=val}` で作った変数）。
+    /// 値はプロセス環境に置いたまま、子プロセスに渡す環境と `export -p` の一覧から除外する。
+    pub unexported: HashSet<String>,
+    /// `shopt -s` / `shopt -u` で切り替えるパス名展開のオプション。
+    pub shopt: glob::GlobOptions,
+    /// failglob でパターンがマッチしなかったことを示す。コマンドを実行せずに失敗させる。
+    pub glob_failed: bool,
 }
 
 impl Shell {
@@ -77,10 +218,13 @@ impl Shell {
             jobs: JobTable::new(),
             shell_pgid,
             terminal_fd: libc::STDIN_FILENO,
-            path_cache: PathCache::new(),
+            command_hash: CommandHash::new(),
+            spawn_ctx: spawn::SpawnContext::new(),
             aliases: HashMap::new(),
             last_bg_pid: 0,
             source_depth: 0,
+            function_depth: 0,
+            max_nesting_depth: DEFAULT_MAX_NESTING_DEPTH,
             should_return: false,
             dir_stack: Vec::new(),
             traps: HashMap::new(),
@@ -88,13 +232,953 @@ impl Shell {
             continue_level: 0,
             loop_depth: 0,
             functions: HashMap::new(),
+            shell_name: "rush".to_string(),
             positional_args: Vec::new(),
             set_errexit: false,
             set_nounset: false,
             set_pipefail: false,
+            saved_set_options: Vec::new(),
             in_condition: 0,
             errexit_pending: false,
             arrays: HashMap::new(),
+            unexported: HashSet::new(),
+            shopt: glob::GlobOptions::default(),
+            glob_failed: false,
+        }
+    }
+
+    /// 文字列を 1 行ずつパースして実行し、最後の終了ステータスを返す。
+    ///
+    /// 非インタラクティブ実行（`rush -c`、スクリプト、rc ファイル）と
+    /// ライブラリ利用者向けの入口。各行の先頭ワードにエイリアス展開を適用する。
+    /// `if` で始まる行は `fi` まで収集して [`executor::execute_block`] で実行。
+    /// `for`/`while`/`until` で始まる行は `done` まで収集してループとして実行。
+    /// ヒアドキュメントの本文収集にも対応。
+    pub fn run(&mut self, input: &str) -> i32 {
+        let input = join_continued_lines(input);
+        let lines: Vec<&str> = input.lines().collect();
+        let saved_lineno = parser::lineno();
+        let mut i = 0;
+        while i < lines.len() {
+            let trimmed = lines[i].trim();
+            i += 1;
+            parser::set_lineno(i);
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            let expanded = expand_alias(trimmed, &self.aliases);
+
+            // if ブロックの検出: `if` で始まる行を見つけたら `fi` まで収集
+            if executor::starts_with_if(&expanded) {
+                let (block, next_i) = executor::collect_if_block(&lines, i - 1);
+                self.last_status = executor::execute_block(self, &block);
+                i = next_i;
+                if self.should_exit || self.errexit_pending {
+                    break;
+                }
+                continue;
+            }
+
+            // for/while/until ブロックの検出
+            if executor::starts_with_for(&expanded)
+                || executor::starts_with_while(&expanded)
+                || executor::starts_with_until(&expanded)
+            {
+                let (block, next_i) = executor::collect_loop_block(&lines, i - 1);
+                self.last_status = executor::execute_block(self, &block);
+                i = next_i;
+                if self.should_exit || self.errexit_pending {
+                    break;
+                }
+                continue;
+            }
+
+            // case ブロックの検出
+            if executor::starts_with_case(&expanded) {
+                let (block, next_i) = executor::collect_case_block(&lines, i - 1);
+                self.last_status = executor::execute_block(self, &block);
+                i = next_i;
+                if self.should_exit || self.errexit_pending {
+                    break;
+                }
+                continue;
+            }
+
+            // 関数定義の検出（`name()` の次の行が `{` なら連結して扱う）
+            let expanded = match lines.get(i) {
+                Some(next) if executor::is_function_header(&expanded) && next.trim_start().starts_with('{') => {
+                    i += 1;
+                    format!("{} {}", expanded, next.trim())
+                }
+                _ => expanded,
+            };
+            if let Some((name, rest)) = executor::parse_function_def(&expanded) {
+                let (body, next_i) = executor::collect_function_body(&lines, i - 1, &rest);
+                self.functions.insert(name, body);
+                i = next_i;
+                continue;
+            }
+
+            match parser::parse(&expanded, self.last_status, &self.shell_name, &self.positional_args, self.set_nounset, &self.arrays) {
+                Ok(Some(mut list)) => {
+                    // ヒアドキュメントの本文を収集
+                    let delims = parser::heredoc_delimiters(&list);
+                    if !delims.is_empty() {
+                        let mut bodies = Vec::new();
+                        for delim in &delims {
+                            let mut body = String::new();
+                            while i < lines.len() {
+                                let line = lines[i];
+                                i += 1;
+                                if line.trim() == delim.as_str() {
+                                    break;
+                                }
+                                body.push_str(line);
+                                body.push('\n');
+                            }
+                            bodies.push(body);
+                        }
+                        parser::fill_heredoc_bodies(&mut list, &bodies);
+                    }
+                    let cmd_text = expanded.trim().to_string();
+                    self.last_status = executor::execute(self, &list, &cmd_text);
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    eprintln!("rush: {}", e);
+                    self.last_status = 2;
+                }
+            }
+            if self.should_exit || self.errexit_pending {
+                break;
+            }
+        }
+        parser::set_lineno(saved_lineno);
+        self.last_status
+    }
+
+    /// シェル終了時の後始末をして、プロセスの終了ステータスを返す。
+    ///
+    /// `exit`（関数内・`source` 中を含む）、EOF、スクリプト末尾のいずれで終わる場合も
+    /// `main` はこれを経由してから `process::exit` する。EXIT トラップは一度だけ実行し、
+    /// トラップ内で `exit` した場合はそのステータスで終了する。
+    /// 履歴は [`History::add`](crate::history::History::add) が 1 行ごとに追記済みなので、ここで書き出すものはない。
+    pub fn shutdown(&mut self) -> i32 {
+        // トラップ内の `$?` は終了のきっかけになったステータス。トラップ実行後に戻すため退避する
+        let status = self.last_status;
+        // 先に取り除き、トラップ内の exit や再度の shutdown で二重に実行しない
+        if let Some(command) = self.traps.remove(&0) {
+            self.should_exit = false;
+            self.should_return = false;
+            self.run(&command);
+            if self.should_exit {
+                return self.last_status;
+            }
+        }
+        self.last_status = status;
+        status
+    }
+
+    /// `source` と関数呼び出しのネスト深さの上限。
+    ///
+    /// `FUNCNEST` が正の整数ならその値、未設定・不正値・0 なら [`max_nesting_depth`](Self::max_nesting_depth)。
+    pub fn nesting_limit(&self) -> usize {
+        std::env::var("FUNCNEST")
+            .ok()
+            .and_then(|v| v.trim().parse::<usize>().ok())
+            .filter(|&n| n > 0)
+            .unwrap_or(self.max_nesting_depth)
+    }
+
+    /// `$0` と位置パラメータを設定する（`rush script.sh a b` / `rush -c CMD name a b` 用）。
+    pub fn set_script_args(&mut self, name: &str, args: &[String]) {
+        self.shell_name = name.to_string();
+        self.positional_args = args.to_vec();
+    }
+
+    /// `reader` を最後まで読み込み、スクリプトとして [`run`](Self::run) する。
+    ///
+    /// パイプされた stdin からのスクリプト実行（`echo 'cmd' | rush`）に使う。
+    /// 読み込みに失敗した場合はメッセージを表示して 1 を返す。
+    pub fn run_reader(&mut self, mut reader: impl std::io::Read) -> i32 {
+        let mut content = String::new();
+        if let Err(e) = reader.read_to_string(&mut content) {
+            eprintln!("rush: {}", e);
+            self.last_status = 1;
+            return 1;
+        }
+        self.run(&content)
+    }
+}
+
+// ── 行継続 ──────────────────────────────────────────────────────────
+
+/// 末尾がクォート外のバックスラッシュで終わる物理行を次の行と連結する。
+/// バックスラッシュと改行は両方除去する。該当行がなければゼロコピー。
+fn join_continued_lines(input: &str) -> Cow<'_, str> {
+    if !input.contains("\\\n") {
+        return Cow::Borrowed(input);
+    }
+    let mut out = String::with_capacity(input.len());
+    let mut logical_start = 0;
+    for line in input.split_inclusive('\n') {
+        let body = line.strip_suffix('\n').unwrap_or(line);
+        out.push_str(body);
+        if line.ends_with('\n') && ends_with_continuation(&out[logical_start..]) {
+            out.pop(); // 末尾の `\` を除去して次の行と連結
+        } else {
+            if line.ends_with('\n') { out.push('\n'); }
+            logical_start = out.len();
+        }
+    }
+    Cow::Owned(out)
+}
+
+/// 論理行がシングルクォート・コメントの外にある未エスケープの `\` で終わるか。
+fn ends_with_continuation(line: &str) -> bool {
+    let bytes = line.as_bytes();
+    let mut i = 0;
+    let mut in_double = false;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => {
+                if i + 1 == bytes.len() { return true; }
+                i += 1;
+            }
+            b'\'' if !in_double => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != b'\'' { i += 1; }
+                if i == bytes.len() { return false; }
+            }
+            b'"' => in_double = !in_double,
+            b'#' if !in_double && (i == 0 || bytes[i - 1].is_ascii_whitespace()) => return false,
+            _ => {}
+        }
+        i += 1;
+    }
+    false
+}
+
+// ── エイリアス展開 ──────────────────────────────────────────────────
+
+/// エイリアス展開: 各コマンド位置（行頭、`;` `&` `&&` `||` `|` 改行の直後）の
+/// 最初のワードがエイリアスならその値に置換する。
+/// 再帰ガード付き（展開中のエイリアスはその値の中では再展開しない）。クォート内は展開しない。
+/// 値が空白で終わるエイリアス（`alias sudo='sudo '`）は続くワードも展開対象にする。
+pub fn expand_alias(line: &str, aliases: &HashMap<String, String>) -> String {
+    if aliases.is_empty() {
+        return line.to_string();
+    }
+    expand_alias_guarded(line, aliases, &[])
+}
+
+/// [`expand_alias`] の本体。`guard` は展開中のエイリアス名（`line` のどのコマンド位置でも再展開しない）。
+fn expand_alias_guarded(line: &str, aliases: &HashMap<String, String>, guard: &[&str]) -> String {
+    let bytes = line.as_bytes();
+    let len = bytes.len();
+    let mut out = String::with_capacity(len);
+    let mut i = 0;
+    let mut at_command = true;
+
+    while i < len {
+        if at_command {
+            at_command = false;
+            // 空白をコピーしてから最初のワードを読む
+            let ws_start = i;
+            while i < len && (bytes[i] == b' ' || bytes[i] == b'\t') {
+                i += 1;
+            }
+            out.push_str(&line[ws_start..i]);
+            let word_start = i;
+            while i < len && !is_alias_word_end(bytes[i]) {
+                i += 1;
+            }
+            let word = &line[word_start..i];
+            match aliases.get(word) {
+                Some(value) if !guard.contains(&word) => {
+                    // 展開結果のどのコマンド位置でも同じエイリアスを再展開しないよう、ガードに追加して再帰
+                    let mut next_guard = guard.to_vec();
+                    next_guard.push(word);
+                    out.push_str(&expand_alias_guarded(value, aliases, &next_guard));
+                    // 値が空白で終わる場合は次のワードもエイリアス展開する
+                    if value.ends_with([' ', '\t']) {
+                        at_command = true;
+                    }
+                }
+                _ => out.push_str(word),
+            }
+            continue;
+        }
+
+        match bytes[i] {
+            b'\'' | b'"' => {
+                let q = bytes[i];
+                let start = i;
+                i += 1;
+                while i < len && bytes[i] != q {
+                    if q == b'"' && bytes[i] == b'\\' { i += 1; }
+                    i += 1;
+                }
+                i = (i + 1).min(len);
+                out.push_str(&line[start..i]);
+            }
+            b'\\' => {
+                let start = i;
+                i = (i + 2).min(len);
+                // 非 ASCII 文字の途中で切らないよう、文字境界まで進める
+                while i < len && !line.is_char_boundary(i) { i += 1; }
+                out.push_str(&line[start..i]);
+            }
+            b';' | b'|' | b'\n' => {
+                out.push(bytes[i] as char);
+                i += 1;
+                // `;;` `||` `|&` はまとめて 1 演算子
+                if i < len && (bytes[i] == bytes[i - 1] || (bytes[i - 1] == b'|' && bytes[i] == b'&')) && bytes[i] != b'\n' {
+                    out.push(bytes[i] as char);
+                    i += 1;
+                }
+                at_command = true;
+            }
+            // `>&` `<&` `&>` はリダイレクトの一部
+            b'&' if (i > 0 && matches!(bytes[i - 1], b'>' | b'<')) || bytes.get(i + 1) == Some(&b'>') => {
+                out.push('&');
+                i += 1;
+            }
+            b'&' => {
+                out.push('&');
+                i += 1;
+                if i < len && bytes[i] == b'&' {
+                    out.push('&');
+                    i += 1;
+                }
+                at_command = true;
+            }
+            _ => {
+                let start = i;
+                i += 1;
+                while i < len && !line.is_char_boundary(i) { i += 1; }
+                out.push_str(&line[start..i]);
+            }
+        }
+    }
+    out
+}
+
+/// エイリアス候補ワードの終端となるバイトか。クォートを含むワードは展開対象外になる。
+fn is_alias_word_end(b: u8) -> bool {
+    matches!(b, b' ' | b'\t' | b'\n' | b';' | b'&' | b'|' | b'<' | b'>' | b'(' | b')' | b'\'' | b'"' | b'\\')
+}
+
+// ── 起動オプション ──────────────────────────────────────────────────
+
+/// コマンドライン引数から解析した起動オプション。[`parse_invocation`] が返す。
+#[derive(Debug, Default, PartialEq)]
+pub struct Invocation {
+    /// ログインシェルとして起動された（`-l` / `--login`、または `argv[0]` が `-` で始まる）。
+    pub login: bool,
+    /// `--norc`: rc ファイルを読み込まない。
+    pub norc: bool,
+    /// `--rcfile PATH`: `~/.rushrc` の代わりに読み込む rc ファイル。
+    pub rcfile: Option<String>,
+    /// `-c CMD`: 実行するコマンド文字列。
+    pub command: Option<String>,
+    /// 実行するスクリプトファイル（最初の非オプション引数）。
+    pub script: Option<String>,
+    /// スクリプト名（または `-c CMD`）に続く引数。スクリプトでは位置パラメータ、
+    /// `-c` では先頭が `$0`・残りが位置パラメータになる。
+    pub args: Vec<String>,
+}
+
+/// `argv`（先頭はプログラム名）を解析する。
+///
+/// オプションは最初の非オプション引数（スクリプト名）より前にのみ置ける。
+/// `login(1)` が付ける `-rush` のような `-` 始まりのプログラム名はログインシェルを示す。
+/// 不正なオプションや引数の欠けたオプションはメッセージを `Err` で返す。
+pub fn parse_invocation(args: &[String]) -> Result<Invocation, String> {
+    let mut inv = Invocation {
+        login: args.first().is_some_and(|argv0| argv0.starts_with('-')),
+        ..Invocation::default()
+    };
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-l" | "--login" => inv.login = true,
+            "--norc" => inv.norc = true,
+            "--rcfile" => match iter.next() {
+                Some(path) => inv.rcfile = Some(path.clone()),
+                None => return Err("--rcfile: option requires an argument".to_string()),
+            },
+            "-c" => match iter.next() {
+                Some(cmd) => {
+                    inv.command = Some(cmd.clone());
+                    inv.args = iter.cloned().collect();
+                    break;
+                }
+                None => return Err("-c: option requires an argument".to_string()),
+            },
+            _ if arg.starts_with("--rcfile=") => {
+                inv.rcfile = Some(arg["--rcfile=".len()..].to_string());
+            }
+            _ if arg.starts_with('-') && arg.len() > 1 => {
+                return Err(format!("{}: invalid option", arg));
+            }
+            _ => {
+                inv.script = Some(arg.clone());
+                inv.args = iter.cloned().collect();
+                break;
+            }
+        }
+    }
+    Ok(inv)
+}
+
+impl Invocation {
+    /// スクリプトを stdin から読むべきか。`-c` もスクリプト名もなく、stdin が端末でない場合に真。
+    pub fn reads_script_from_stdin(&self, stdin_is_tty: bool) -> bool {
+        self.command.is_none() && self.script.is_none() && !stdin_is_tty
+    }
+}
+
+/// 対話シェルが読み込む rc ファイルのパスを返す。読み込まない場合は `None`。
+///
+/// 優先順位: `--norc` → `--rcfile` → `$RUSHRC` → `$ENV` → `~/.rushrc`。
+pub fn rc_file_path(inv: &Invocation) -> Option<String> {
+    if inv.norc {
+        return None;
+    }
+    if let Some(ref path) = inv.rcfile {
+        return Some(path.clone());
+    }
+    for var in ["RUSHRC", "ENV"] {
+        if let Ok(path) = std::env::var(var) {
+            if !path.is_empty() {
+                return Some(path);
+            }
         }
     }
+    std::env::var("HOME").ok().map(|home| format!("{}/.rushrc", home))
+}
+
+/// 起動時に読み込むファイルを読み込み順に返す。
+///
+/// ログインシェルは `/etc/profile` → `~/.rush_profile` を（対話・非対話を問わず）読み、rc は読まない。
+/// 非ログインの対話シェルは [`rc_file_path`] の rc ファイルのみを読む。非対話の非ログインシェルは何も読まない。
+pub fn startup_files(inv: &Invocation, interactive: bool) -> Vec<String> {
+    if inv.login {
+        let mut files = vec!["/etc/profile".to_string()];
+        if let Ok(home) = std::env::var("HOME") {
+            files.push(format!("{}/.rush_profile", home));
+        }
+        files
+    } else if interactive {
+        rc_file_path(inv).into_iter().collect()
+    } else {
+        Vec::new()
+    }
+}
+
+// ── 端末サイズ ──────────────────────────────────────────────────────
+
+/// SIGWINCH を受け取り、`COLUMNS` / `LINES` の更新待ちであることを示す。
+static WINCH_PENDING: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+/// 端末の `(列数, 行数)` を返す。
+///
+/// stdin・stdout・stderr の順に `ioctl(TIOCGWINSZ)` を試し、どれも端末でなければ
+/// `$COLUMNS` / `$LINES`、それもなければ 80x24 を返す。
+pub fn terminal_size() -> (u16, u16) {
+    query_winsize().unwrap_or_else(|| {
+        let from_env = |name: &str, default: u16| {
+            std::env::var(name).ok().and_then(|v| v.parse().ok()).filter(|&n| n > 0).unwrap_or(default)
+        };
+        (from_env("COLUMNS", 80), from_env("LINES", 24))
+    })
+}
+
+/// `ioctl(TIOCGWINSZ)` で端末サイズを問い合わせる。端末がなければ `None`。
+fn query_winsize() -> Option<(u16, u16)> {
+    [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO].into_iter().find_map(|fd| {
+        let mut ws: libc::winsize = unsafe { std::mem::zeroed() };
+        let ok = unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut ws) } == 0;
+        (ok && ws.ws_col > 0 && ws.ws_row > 0).then_some((ws.ws_col, ws.ws_row))
+    })
+}
+
+/// 端末サイズを `COLUMNS` / `LINES` に設定する。端末がなければ既存の値をそのまま残す。
+pub fn update_terminal_size_vars() {
+    if let Some((cols, rows)) = query_winsize() {
+        std::env::set_var("COLUMNS", cols.to_string());
+        std::env::set_var("LINES", rows.to_string());
+    }
+}
+
+extern "C" fn on_sigwinch(_sig: libc::c_int) {
+    WINCH_PENDING.store(true, std::sync::atomic::Ordering::Relaxed);
+}
+
+/// SIGWINCH ハンドラを設置する（対話モード用）。ハンドラはフラグを立てるだけで、
+/// 実際の更新は [`refresh_terminal_size`] がプロンプト表示前に行う。
+pub fn install_winch_handler() {
+    unsafe {
+        libc::signal(libc::SIGWINCH, on_sigwinch as extern "C" fn(libc::c_int) as libc::sighandler_t);
+    }
+}
+
+/// SIGWINCH を受け取っていれば `COLUMNS` / `LINES` を更新する。
+pub fn refresh_terminal_size() {
+    if WINCH_PENDING.swap(false, std::sync::atomic::Ordering::Relaxed) {
+        update_terminal_size_vars();
+    }
+}
+
+/// ウィンドウタイトルに表示するコマンドの最大文字数。超えた分は `…` で省略する。
+const TITLE_MAX_CHARS: usize = 60;
+
+/// ウィンドウタイトルを `title` にする OSC 0 エスケープ列（`ESC ] 0 ; title BEL`）を返す。
+///
+/// 改行や ESC / BEL などの制御文字は空白に置き換えてエスケープ列を壊さないようにし、
+/// 長いコマンドは [`TITLE_MAX_CHARS`] 文字で切り詰める。
+pub fn title_escape(title: &str) -> String {
+    let title = title.trim();
+    let mut out = String::from("\x1b]0;");
+    for (i, ch) in title.chars().enumerate() {
+        if i == TITLE_MAX_CHARS {
+            out.push('…');
+            break;
+        }
+        out.push(if ch.is_control() { ' ' } else { ch });
+    }
+    out.push('\x07');
+    out
+}
+
+/// `RUSH_SET_TITLE=1` のときだけ、ウィンドウタイトルを `title` に設定する（対話モード用）。
+pub fn set_terminal_title(title: &str) {
+    if std::env::var("RUSH_SET_TITLE").as_deref() == Ok("1") {
+        use std::io::Write;
+        let mut out = std::io::stdout();
+        let _ = out.write_all(title_escape(title).as_bytes());
+        let _ = out.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn title_escape_sanitizes_and_truncates() {
+        assert_eq!(title_escape("  make test "), "\x1b]0;make test\x07");
+        assert_eq!(title_escape("echo a\nprintf '\x07\x1b'"), "\x1b]0;echo a printf '  '\x07");
+        let long = "x".repeat(100);
+        assert_eq!(title_escape(&long), format!("\x1b]0;{}…\x07", "x".repeat(TITLE_MAX_CHARS)));
+        let exact = "日".repeat(TITLE_MAX_CHARS);
+        assert_eq!(title_escape(&exact), format!("\x1b]0;{}\x07", exact));
+    }
+
+    #[test]
+    fn terminal_size_is_plausible() {
+        let (cols, rows) = terminal_size();
+        assert!(cols > 0 && rows > 0);
+        // 端末に繋がっていなければ ioctl は失敗し、フォールバック値になる
+        match query_winsize() {
+            Some(size) => assert_eq!(size, (cols, rows)),
+            None => assert!(std::env::var("COLUMNS").is_ok() || (cols, rows) == (80, 24)),
+        }
+    }
+
+    // ── Shell::run ──
+
+    #[test]
+    fn run_returns_last_status() {
+        let mut shell = Shell::new();
+        assert_eq!(shell.run("echo hi && true"), 0);
+        assert_eq!(shell.run("true\nfalse"), 1);
+        assert_eq!(shell.last_status, 1);
+    }
+
+    // ── 起動オプション ──
+
+    fn argv(args: &[&str]) -> Vec<String> {
+        std::iter::once("rush").chain(args.iter().copied()).map(String::from).collect()
+    }
+
+    #[test]
+    fn parse_invocation_options() {
+        assert_eq!(parse_invocation(&argv(&[])).unwrap(), Invocation::default());
+        let inv = parse_invocation(&argv(&["--norc", "--rcfile", "/tmp/rc", "script.sh", "--norc"])).unwrap();
+        assert!(inv.norc);
+        assert_eq!(inv.rcfile.as_deref(), Some("/tmp/rc"));
+        assert_eq!(inv.script.as_deref(), Some("script.sh"));
+        assert_eq!(inv.args, vec!["--norc".to_string()]);
+        let inv = parse_invocation(&argv(&["--rcfile=/x", "-c", "echo hi"])).unwrap();
+        assert_eq!(inv.rcfile.as_deref(), Some("/x"));
+        assert_eq!(inv.command.as_deref(), Some("echo hi"));
+        assert!(inv.args.is_empty());
+        let inv = parse_invocation(&argv(&["-c", "echo $0", "name", "a"])).unwrap();
+        assert_eq!(inv.args, vec!["name".to_string(), "a".to_string()]);
+        assert!(parse_invocation(&argv(&["-c"])).is_err());
+        assert!(parse_invocation(&argv(&["--rcfile"])).is_err());
+        assert!(parse_invocation(&argv(&["--bogus"])).is_err());
+    }
+
+    #[test]
+    fn parse_invocation_detects_login_mode() {
+        let with_argv0 = |argv0: &str, rest: &[&str]| {
+            let args: Vec<String> = std::iter::once(argv0).chain(rest.iter().copied()).map(String::from).collect();
+            parse_invocation(&args).unwrap().login
+        };
+        assert!(with_argv0("-rush", &[]));
+        assert!(with_argv0("-/usr/bin/rush", &["-c", "true"]));
+        assert!(with_argv0("rush", &["-l"]));
+        assert!(with_argv0("rush", &["--login", "script.sh"]));
+        assert!(!with_argv0("rush", &[]));
+        assert!(!with_argv0("/bin/rush", &["--norc"]));
+        // スクリプト名以降の引数はオプションとして扱わない
+        assert!(!with_argv0("rush", &["script.sh", "-l"]));
+    }
+
+    #[test]
+    fn piped_stdin_is_read_as_script() {
+        assert!(parse_invocation(&argv(&[])).unwrap().reads_script_from_stdin(false));
+        assert!(!parse_invocation(&argv(&[])).unwrap().reads_script_from_stdin(true));
+        assert!(!parse_invocation(&argv(&["-c", "true"])).unwrap().reads_script_from_stdin(false));
+        assert!(!parse_invocation(&argv(&["script.sh"])).unwrap().reads_script_from_stdin(false));
+
+        let mut shell = Shell::new();
+        assert_eq!(shell.run_reader("test a = a
+false
+".as_bytes()), 1);
+        assert_eq!(shell.run_reader("true
+exit 3
+echo unreachable
+".as_bytes()), 3);
+    }
+
+    #[test]
+    fn script_args_set_dollar_zero_and_positionals() {
+        let mut shell = Shell::new();
+        shell.set_script_args("script.sh", &["a".to_string(), "b c".to_string()]);
+        assert_eq!(shell.run("test \"$0 $1 $#\" = 'script.sh a 2' && test \"$2\" = 'b c'"), 0);
+        // $0 は Shell ごとの状態で、別のインスタンスには影響しない
+        assert_eq!(Shell::new().run("test \"$0\" = rush"), 0);
+    }
+
+    #[test]
+    fn startup_files_by_mode() {
+        let login = parse_invocation(&argv(&["-l"])).unwrap();
+        let files = startup_files(&login, true);
+        assert_eq!(files[0], "/etc/profile");
+        if let Ok(home) = std::env::var("HOME") {
+            assert_eq!(files, vec!["/etc/profile".to_string(), format!("{}/.rush_profile", home)]);
+        }
+        assert_eq!(startup_files(&login, false), files);
+
+        let interactive = parse_invocation(&argv(&["--rcfile", "/tmp/rc"])).unwrap();
+        assert_eq!(startup_files(&interactive, true), vec!["/tmp/rc".to_string()]);
+        assert!(startup_files(&interactive, false).is_empty());
+    }
+
+    #[test]
+    fn rc_file_path_respects_norc_and_overrides() {
+        let norc = parse_invocation(&argv(&["--norc", "--rcfile", "/tmp/rc"])).unwrap();
+        assert_eq!(rc_file_path(&norc), None);
+        let rcfile = parse_invocation(&argv(&["--rcfile", "/tmp/rc"])).unwrap();
+        assert_eq!(rc_file_path(&rcfile).as_deref(), Some("/tmp/rc"));
+
+        std::env::set_var("RUSHRC", "/tmp/rush_rc_env");
+        assert_eq!(rc_file_path(&Invocation::default()).as_deref(), Some("/tmp/rush_rc_env"));
+        std::env::remove_var("RUSHRC");
+        if std::env::var("ENV").is_err() {
+            if let Ok(home) = std::env::var("HOME") {
+                assert_eq!(rc_file_path(&Invocation::default()), Some(format!("{}/.rushrc", home)));
+            }
+        }
+    }
+
+    // ── 行継続 ──
+
+    #[test]
+    fn join_continued_lines_splices() {
+        assert_eq!(join_continued_lines("echo a\\\nb\n"), "echo ab\n");
+        assert_eq!(join_continued_lines("echo a \\\n  b \\\n c"), "echo a   b  c");
+        // エスケープされたバックスラッシュ・シングルクォート・コメントは継続しない
+        assert_eq!(join_continued_lines("echo a\\\\\nb"), "echo a\\\\\nb");
+        assert_eq!(join_continued_lines("echo 'a\\\nb'"), "echo 'a\\\nb'");
+        assert_eq!(join_continued_lines("# note \\\necho b"), "# note \\\necho b");
+    }
+
+    #[test]
+    fn run_joins_backslash_newline() {
+        let mut shell = Shell::new();
+        assert_eq!(shell.run("test a\\\nb = ab"), 0);
+        assert_eq!(shell.run("test \\\n  x = y"), 1);
+    }
+
+    // ── expand_alias ──
+
+    fn alias_map(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn expand_alias_first_word() {
+        let aliases = alias_map(&[("ll", "ls -l")]);
+        assert_eq!(expand_alias("ll /tmp", &aliases), "ls -l /tmp");
+        assert_eq!(expand_alias("echo ll", &aliases), "echo ll");
+    }
+
+    #[test]
+    fn expand_alias_every_command_position() {
+        let aliases = alias_map(&[("st", "git status"), ("co", "git checkout")]);
+        assert_eq!(expand_alias("st && co main", &aliases), "git status && git checkout main");
+        assert_eq!(expand_alias("st; co || st", &aliases), "git status; git checkout || git status");
+        assert_eq!(expand_alias("st | co &", &aliases), "git status | git checkout &");
+    }
+
+    #[test]
+    fn expand_alias_skips_quotes_and_redirects() {
+        let aliases = alias_map(&[("x", "EXPANDED")]);
+        assert_eq!(expand_alias("echo 'a; x' \"b | x\"", &aliases), "echo 'a; x' \"b | x\"");
+        assert_eq!(expand_alias("echo hi 2>&1 x", &aliases), "echo hi 2>&1 x");
+        assert_eq!(expand_alias("'x' arg", &aliases), "'x' arg");
+    }
+
+    #[test]
+    fn expand_alias_trailing_space_chains() {
+        let aliases = alias_map(&[("sudo", "sudo "), ("ll", "ls -l"), ("nice", "nice")]);
+        assert_eq!(expand_alias("sudo ll /tmp", &aliases), "sudo  ls -l /tmp");
+        // 末尾空白のないエイリアスは次のワードを展開しない
+        assert_eq!(expand_alias("nice ll", &aliases), "nice ll");
+        // 連鎖は末尾空白がある限り続く
+        assert_eq!(expand_alias("sudo sudo ll", &aliases), "sudo  sudo  ls -l");
+    }
+
+    #[test]
+    fn expand_alias_recursion_guard() {
+        let aliases = alias_map(&[("ls", "ls -F"), ("a", "b"), ("b", "a x")]);
+        assert_eq!(expand_alias("ls; ls", &aliases), "ls -F; ls -F");
+        // 相互再帰も各位置で 1 回ずつに制限される
+        assert_eq!(expand_alias("a", &aliases), "a x");
+    }
+
+    #[test]
+    fn expand_alias_self_reference_after_operators() {
+        let aliases = alias_map(&[("a", "echo x; a"), ("p", "echo y | p"), ("c", "true && c")]);
+        assert_eq!(expand_alias("a", &aliases), "echo x; a");
+        assert_eq!(expand_alias("p", &aliases), "echo y | p");
+        assert_eq!(expand_alias("c", &aliases), "true && c");
+        // 入力行の別のコマンド位置では改めて展開する
+        assert_eq!(expand_alias("a; a", &aliases), "echo x; a; echo x; a");
+    }
+
+    #[test]
+    fn run_expands_aliases_and_blocks() {
+        let mut shell = Shell::new();
+        shell.aliases.insert("fail".to_string(), "false".to_string());
+        assert_eq!(shell.run("fail"), 1);
+        assert_eq!(shell.run("if true; then\n  RUSH_SHELL_RUN_TEST=yes\nfi"), 0);
+        assert_eq!(std::env::var("RUSH_SHELL_RUN_TEST").unwrap(), "yes");
+        std::env::remove_var("RUSH_SHELL_RUN_TEST");
+    }
+
+    #[test]
+    fn run_defines_multiline_functions() {
+        let mut shell = Shell::new();
+        let script = "greet() {\n  export RUSH_FN_GREET=hi_$1\n}\ngreet bob";
+        assert_eq!(shell.run(script), 0);
+        assert_eq!(std::env::var("RUSH_FN_GREET").unwrap(), "hi_bob");
+        let script = "function later\n{\n  export RUSH_FN_GREET=later\n}\nlater";
+        assert_eq!(shell.run(script), 0);
+        assert_eq!(std::env::var("RUSH_FN_GREET").unwrap(), "later");
+        assert!(shell.functions.contains_key("later"));
+        std::env::remove_var("RUSH_FN_GREET");
+    }
+
+    #[test]
+    fn exit_trap_runs_once_on_shutdown() {
+        let out = std::env::temp_dir().join(format!("rush_exit_trap_{}", std::process::id()));
+        let _ = std::fs::remove_file(&out);
+        let mut shell = Shell::new();
+        let script = format!(
+            "trap 'echo bye >> {}' EXIT\nf() {{ exit 3; }}\nf\necho unreachable >> {}",
+            out.display(),
+            out.display()
+        );
+        assert_eq!(shell.run(&script), 3);
+        assert!(shell.should_exit);
+        assert_eq!(shell.shutdown(), 3);
+        assert_eq!(shell.shutdown(), 3);
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), "bye\n");
+        let _ = std::fs::remove_file(&out);
+
+        // トラップ内の exit は終了ステータスを上書きする
+        let mut shell = Shell::new();
+        shell.run("trap 'exit 7' EXIT\ntrue");
+        assert_eq!(shell.shutdown(), 7);
+    }
+
+    #[test]
+    fn exit_trap_sees_triggering_status() {
+        let out = std::env::temp_dir().join(format!("rush_exit_trap_status_{}", std::process::id()));
+        let mut shell = Shell::new();
+        // トラップ内の $? は終了のきっかけになったステータス。トラップ内のコマンドは終了ステータスを変えない
+        shell.run(&format!("trap 'echo status=$? > {}; true' EXIT\nfalse", out.display()));
+        assert_eq!(shell.shutdown(), 1);
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), "status=1\n");
+
+        let mut shell = Shell::new();
+        shell.run(&format!("trap 'echo status=$? > {}' EXIT\nexit 4\ntrue", out.display()));
+        assert_eq!(shell.shutdown(), 4);
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), "status=4\n");
+        let _ = std::fs::remove_file(&out);
+    }
+
+    #[test]
+    fn run_tracks_lineno() {
+        let out = std::env::temp_dir().join(format!("rush_lineno_{}", std::process::id()));
+        let mut shell = Shell::new();
+        let script = format!("echo $LINENO > {0}\n\n# comment\necho $LINENO >> {0}", out.display());
+        assert_eq!(shell.run(&script), 0);
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), "1\n4\n");
+        let _ = std::fs::remove_file(&out);
+    }
+
+    #[test]
+    fn run_tracks_lineno_in_compound_blocks() {
+        let out = std::env::temp_dir().join(format!("rush_lineno_compound_{}", std::process::id()));
+        let mut shell = Shell::new();
+        let script = format!(
+            "echo $LINENO > {0}\nif true\nthen\n  echo $LINENO >> {0}\n\n  echo $LINENO >> {0}\nfi\n\
+             for i in a b\ndo\n  echo $i$LINENO >> {0}\n  if true; then\n    echo n$LINENO >> {0}\n  fi\ndone\n\
+             case x in\nx)\n  echo c$LINENO >> {0};;\nesac\necho $LINENO >> {0}",
+            out.display()
+        );
+        assert_eq!(shell.run(&script), 0);
+        assert_eq!(
+            std::fs::read_to_string(&out).unwrap(),
+            "1\n4\n6\na10\nn12\nb10\nn12\nc17\n19\n"
+        );
+        let _ = std::fs::remove_file(&out);
+    }
+
+    #[test]
+    fn run_collects_heredoc_body() {
+        let mut shell = Shell::new();
+        assert_eq!(shell.run("grep -q needle <<EOF\nhay\nneedle\nEOF"), 0);
+        assert_eq!(shell.run("grep -q needle <<EOF\nhay\nEOF\ntrue"), 0);
+        assert_eq!(shell.run("grep -q needle <<EOF\nhay\nEOF"), 1);
+    }
+
+    #[test]
+    fn run_large_heredoc_does_not_deadlock() {
+        let out = std::env::temp_dir().join(format!("rush_heredoc_large_{}", std::process::id()));
+        let line = "0123456789abcdefghijklmnopqrstuvwxyz0123456789ABCDEFGHIJKLMN";
+        let body: String = (0..2500).map(|i| format!("{} {}\n", i, line)).collect();
+        assert!(body.len() > 128 * 1024);
+        let mut shell = Shell::new();
+        let script = format!("cat > {} <<'EOF'\n{}EOF", out.display(), body);
+        assert_eq!(shell.run(&script), 0);
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), body);
+        let _ = std::fs::remove_file(&out);
+    }
+
+    #[test]
+    fn run_large_heredoc_into_pipeline_finishes() {
+        // 書き込み子プロセスがパイプラインのパイプ端を握っていると head の終了後も cat が詰まる。
+        // 詰まっても alarm で打ち切れるよう fork した子プロセス内で実行し、出力はファイルで受け取る
+        let out = std::env::temp_dir().join(format!("rush_heredoc_pipeline_{}", std::process::id()));
+        let line = "x".repeat(99) + "\n";
+        let script = format!(
+            "exec > {}\ncat <<EOF | head -1 | wc -c\n{}EOF\necho done",
+            out.display(),
+            line.repeat(20000)
+        );
+        let (_, status) = crate::executor::run_forked(1, || {
+            unsafe { libc::alarm(10); }
+            Shell::new().run(&script)
+        });
+        let written = std::fs::read_to_string(&out).unwrap_or_default();
+        let _ = std::fs::remove_file(&out);
+        assert_eq!(status, 0);
+        assert_eq!(written.split_whitespace().collect::<Vec<_>>(), ["100", "done"]);
+    }
+
+    #[test]
+    fn run_expands_unquoted_heredoc_only() {
+        let out = std::env::temp_dir().join(format!("rush_heredoc_expand_{}", std::process::id()));
+        std::env::set_var("RUSH_HEREDOC_VAR", "v");
+        let mut shell = Shell::new();
+        let script = format!(
+            "cat > {0} <<EOF\nx $RUSH_HEREDOC_VAR $(echo y) $((1+2)) \"q\"\nEOF\ncat >> {0} <<'EOF'\nl $RUSH_HEREDOC_VAR $(echo y)\nEOF",
+            out.display()
+        );
+        assert_eq!(shell.run(&script), 0);
+        assert_eq!(
+            std::fs::read_to_string(&out).unwrap(),
+            "x v y 3 \"q\"\nl $RUSH_HEREDOC_VAR $(echo y)\n"
+        );
+        std::env::remove_var("RUSH_HEREDOC_VAR");
+        let _ = std::fs::remove_file(&out);
+    }
+
+    /// 実行可能ファイルを含む一時ディレクトリを作成する。
+    fn make_bin_dir(tag: &str, names: &[&str]) -> String {
+        let dir = std::env::temp_dir().join(format!("rush_hash_{}_{}", tag, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in names {
+            let path = dir.join(name);
+            std::fs::write(&path, "#!/bin/sh\n").unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        dir.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn command_hash_miss_then_hit() {
+        let dir = make_bin_dir("hit", &["mytool"]);
+        let mut hash = CommandHash::new();
+        let first = hash.lookup_in("mytool", &dir).unwrap();
+        assert_eq!(first, format!("{}/mytool", dir));
+        assert_eq!(hash.entries()[0].1.hits, 1);
+        let second = hash.lookup_in("mytool", &dir).unwrap();
+        assert_eq!(second, first);
+        assert_eq!(hash.entries()[0].1.hits, 2);
+        assert!(hash.lookup_in("no_such_tool", &dir).is_none());
+        assert_eq!(hash.entries().len(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn command_hash_invalidated_on_path_change() {
+        let dir_a = make_bin_dir("a", &["tool"]);
+        let dir_b = make_bin_dir("b", &["tool"]);
+        let mut hash = CommandHash::new();
+        assert_eq!(hash.lookup_in("tool", &dir_a).unwrap(), format!("{}/tool", dir_a));
+        assert_eq!(hash.lookup_in("tool", &dir_b).unwrap(), format!("{}/tool", dir_b));
+        assert_eq!(hash.entries()[0].1.hits, 1);
+        std::fs::remove_dir_all(&dir_a).unwrap();
+        std::fs::remove_dir_all(&dir_b).unwrap();
+    }
+
+    #[test]
+    fn command_hash_drops_removed_binary() {
+        let dir = make_bin_dir("gone", &["tool"]);
+        let mut hash = CommandHash::new();
+        assert!(hash.lookup_in("tool", &dir).is_some());
+        std::fs::remove_file(format!("{}/tool", dir)).unwrap();
+        assert!(hash.lookup_in("tool", &dir).is_none());
+        assert!(hash.is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn command_hash_clear() {
+        let dir = make_bin_dir("clear", &["tool"]);
+        let mut hash = CommandHash::new();
+        hash.lookup_in("tool", &dir);
+        hash.clear();
+        assert!(hash.is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
@@ -9,45 +9,98 @@
 //! |-----|------|
 //! | [`SpawnAttr`] | `posix_spawnattr_t` の RAII ラッパー（プロセスグループ、シグナル設定） |
 //! | [`FileActions`] | `posix_spawn_file_actions_t` の RAII ラッパー（fd 操作） |
-//! | [`CStringVec`] | argv/envp 用の NULL 終端ポインタ配列 |
+//! | [`SpawnContext`] | argv/envp の NULL 終端ポインタ配列。バッファを使い回し、envp は内容が変わったときだけ作り直す |
 //! | [`spawn`] | 上記を組み合わせて `posix_spawnp` を呼ぶ公開関数 |
+//!
+//! 子の作業ディレクトリ（`cwd`）は `posix_spawn_file_actions_addchdir_np` で設定する。
+//! これがないプラットフォームでは、`cwd` を指定したときだけ fork + exec にフォールバックする。
+//! executor 自身は `cwd` を使わない（サブシェルやプロセス置換はシェルごと fork して子で `cd` する）。
+//! シェルの cwd を変えずに別ディレクトリでコマンドを起動したいライブラリ利用者向けの引数。
 
 use std::ffi::CString;
 use std::fmt;
+use std::path::Path;
 
 // ── エラー型 ──────────────────────────────────────────────────────
 
 /// `posix_spawnp` の失敗を表すエラー。
 pub struct SpawnError {
-    /// errno 値。
+    /// errno 値。ディレクトリを実行しようとした場合は `EACCES` ではなく `EISDIR` に正規化される。
     pub errno: i32,
     /// コマンド名（エラーメッセージ用）。
     pub command: String,
 }
 
-impl fmt::Display for SpawnError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let msg = match self.errno {
-            libc::ENOENT => "command not found",
-            libc::EACCES => "permission denied",
-            _ => "spawn failed",
+impl SpawnError {
+    /// errno と起動しようとした実行ファイルからエラーを構築する。
+    ///
+    /// `EACCES` かつ `program` がディレクトリなら `EISDIR` として扱う（bash の "Is a directory"）。
+    pub fn new(errno: i32, command: &str, program: &str) -> Self {
+        let errno = if errno == libc::EACCES && std::path::Path::new(program).is_dir() {
+            libc::EISDIR
+        } else {
+            errno
         };
-        write!(f, "rush: {}: {}", self.command, msg)
+        Self {
+            errno,
+            command: command.to_string(),
+        }
+    }
+
+    /// PATH にコマンドが見つからなかったことを表すエラーを構築する。
+    pub fn not_found(command: &str) -> Self {
+        Self {
+            errno: libc::ENOENT,
+            command: command.to_string(),
+        }
+    }
+
+    /// `rush: <cmd>: <message>` を stderr に表示し、終了ステータスを返す。
+    ///
+    /// 起動失敗の報告はすべてここを通し、"command not found" の文言と 127 を揃える。
+    pub fn report(&self) -> i32 {
+        eprintln!("{}", self);
+        self.exit_status()
+    }
+
+    /// bash と同じ文言のエラーメッセージを返す。
+    ///
+    /// `ENOENT` はコマンド名に `/` を含むかで "command not found" と
+    /// "No such file or directory" を使い分ける。
+    pub fn message(&self) -> String {
+        match self.errno {
+            libc::ENOENT if !self.command.contains('/') => "command not found".to_string(),
+            libc::ENOENT => "No such file or directory".to_string(),
+            libc::EACCES => "Permission denied".to_string(),
+            libc::EISDIR => "Is a directory".to_string(),
+            errno => {
+                let err = std::io::Error::from_raw_os_error(errno).to_string();
+                // "Exec format error (os error 8)" → "Exec format error"
+                match err.find(" (os error") {
+                    Some(pos) => err[..pos].to_string(),
+                    None => err,
+                }
+            }
+        }
     }
-}
 
-impl SpawnError {
     /// エラーに対応する終了ステータスを返す。
-    /// 127 = command not found, 126 = permission denied, 1 = その他。
+    /// 127 = 見つからない, 126 = 見つかったが実行できない, 1 = その他。
     pub fn exit_status(&self) -> i32 {
         match self.errno {
             libc::ENOENT => 127,
-            libc::EACCES => 126,
+            libc::EACCES | libc::EISDIR | libc::ENOEXEC => 126,
             _ => 1,
         }
     }
 }
 
+impl fmt::Display for SpawnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "rush: {}: {}", self.command, self.message())
+    }
+}
+
 // ── SpawnAttr ─────────────────────────────────────────────────────
 
 /// `posix_spawnattr_t` の RAII ラッパー。Drop で自動 destroy。
@@ -144,11 +197,34 @@ impl FileActions {
         }
     }
 
+    /// `chdir(dir)` アクションを追加する。exec の直前に子の作業ディレクトリを移す。
+    ///
+    /// 追加に失敗したら（`ENOMEM` 等）その errno を返す。
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    fn add_chdir(&mut self, dir: &CString) -> Result<(), i32> {
+        match unsafe { posix_spawn_file_actions_addchdir_np(&mut self.inner, dir.as_ptr()) } {
+            0 => Ok(()),
+            errno => Err(errno),
+        }
+    }
+
     fn as_ptr(&self) -> *const libc::posix_spawn_file_actions_t {
         &self.inner
     }
 }
 
+#[cfg(target_os = "linux")]
+use libc::posix_spawn_file_actions_addchdir_np;
+
+// libc クレートは macOS 向けに宣言していないが、10.15 以降の libSystem にある
+#[cfg(target_os = "macos")]
+extern "C" {
+    fn posix_spawn_file_actions_addchdir_np(
+        actions: *mut libc::posix_spawn_file_actions_t,
+        path: *const libc::c_char,
+    ) -> libc::c_int;
+}
+
 impl Drop for FileActions {
     fn drop(&mut self) {
         unsafe {
@@ -157,66 +233,191 @@ impl Drop for FileActions {
     }
 }
 
-// ── CStringVec ────────────────────────────────────────────────────
+// ── SpawnContext ──────────────────────────────────────────────────
 
-/// argv/envp 用の CString ベクタ。NULL 終端のポインタ配列を構築する。
-struct CStringVec {
-    _strings: Vec<CString>,
-    ptrs: Vec<*mut libc::c_char>,
+/// 繰り返しの起動で使い回す argv / envp のバッファ。
+///
+/// argv は 1 本のバイト列に NUL 終端で詰め、ポインタ配列と一緒に毎回クリアして再利用するので、
+/// 容量が足りていれば起動ごとの確保は起きない。明示的な環境（`KEY=VALUE` の列）を渡したときの envp は
+/// 前回と同じ内容なら作り直さない。環境を渡さなければシェルの `environ` をそのまま継承する。
+///
+/// [`Shell`](crate::shell::Shell) が 1 つ所有し、executor の外部コマンド起動で使う。
+pub struct SpawnContext {
+    argv_buf: Vec<u8>,
+    argv_offsets: Vec<usize>,
+    argv_ptrs: Vec<*mut libc::c_char>,
+    program_buf: Vec<u8>,
+    /// envp の作成元。同じ内容で呼ばれたら `env_buf` / `env_ptrs` をそのまま使う。
+    env_vars: Option<Vec<String>>,
+    env_buf: Vec<u8>,
+    env_offsets: Vec<usize>,
+    env_ptrs: Vec<*mut libc::c_char>,
 }
 
-impl CStringVec {
-    /// 引数リストから構築する。各要素を `CString` に変換し、NULL 終端ポインタ配列を作る。
-    fn from_args(args: &[&str]) -> Self {
-        let strings: Vec<CString> = args
-            .iter()
-            .map(|s| CString::new(*s).unwrap_or_else(|_| CString::new("").unwrap()))
-            .collect();
-        let mut ptrs: Vec<*mut libc::c_char> = strings
-            .iter()
-            .map(|s| s.as_ptr() as *mut libc::c_char)
-            .collect();
-        ptrs.push(std::ptr::null_mut()); // NULL 終端
+impl Default for SpawnContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SpawnContext {
+    pub fn new() -> Self {
         Self {
-            _strings: strings,
-            ptrs,
+            argv_buf: Vec::new(),
+            argv_offsets: Vec::new(),
+            argv_ptrs: Vec::new(),
+            program_buf: Vec::new(),
+            env_vars: None,
+            env_buf: Vec::new(),
+            env_offsets: Vec::new(),
+            env_ptrs: Vec::new(),
         }
     }
 
-    /// NULL 終端ポインタ配列を返す。
-    fn as_ptr(&self) -> *const *mut libc::c_char {
-        self.ptrs.as_ptr()
+    /// 実行ファイル `program` を指定して子プロセスを起動する。引数の意味は [`spawn_program`] と同じ。
+    ///
+    /// `env` が `Some` なら `KEY=VALUE` の列をそのまま子の環境にし、`None` なら `environ` を継承する。
+    /// `cwd` が `Some` なら子だけがそのディレクトリに移ってから exec する。
+    #[allow(clippy::too_many_arguments)]
+    pub fn spawn_program(
+        &mut self,
+        program: &str,
+        args: &[&str],
+        env: Option<&[String]>,
+        cwd: Option<&Path>,
+        pgid: libc::pid_t,
+        stdin_fd: Option<i32>,
+        stdout_fd: Option<i32>,
+        stderr_fd: Option<i32>,
+        fds_to_close: &[i32],
+        extra_dup2s: &[(i32, i32)],
+    ) -> Result<libc::pid_t, SpawnError> {
+        let program_bytes = crate::glob::raw_bytes(program);
+        if program_bytes.contains(&0) {
+            return Err(SpawnError::new(libc::ENOENT, args[0], program));
+        }
+        self.program_buf.clear();
+        self.program_buf.extend_from_slice(&program_bytes);
+        self.program_buf.push(0);
+
+        let cwd = match cwd {
+            Some(dir) => match CString::new(std::os::unix::ffi::OsStrExt::as_bytes(dir.as_os_str())) {
+                Ok(dir) => Some(dir),
+                Err(_) => return Err(SpawnError::new(libc::ENOENT, args[0], program)),
+            },
+            None => None,
+        };
+
+        pack_cstrings(
+            args.iter().map(|a| crate::glob::raw_bytes(a)),
+            &mut self.argv_buf,
+            &mut self.argv_offsets,
+            &mut self.argv_ptrs,
+        );
+
+        let envp = match env {
+            Some(vars) => {
+                if self.env_vars.as_deref() != Some(vars) {
+                    pack_cstrings(
+                        vars.iter().map(|v| crate::glob::raw_bytes(v)),
+                        &mut self.env_buf,
+                        &mut self.env_offsets,
+                        &mut self.env_ptrs,
+                    );
+                    self.env_vars = Some(vars.to_vec());
+                }
+                self.env_ptrs.as_ptr()
+            }
+            // environ を継承（std::env::set_var で設定済みの環境がそのまま渡る）
+            None => unsafe { environ as *const *mut libc::c_char },
+        };
+
+        #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+        if let Some(dir) = &cwd {
+            return fork_spawn(
+                &self.program_buf,
+                &self.argv_ptrs,
+                envp,
+                dir,
+                pgid,
+                stdin_fd,
+                stdout_fd,
+                stderr_fd,
+                fds_to_close,
+                extra_dup2s,
+            )
+            .map_err(|errno| SpawnError::new(errno, args[0], program));
+        }
+
+        // 属性: プロセスグループ + シグナルリセット
+        let mut attr = SpawnAttr::new();
+        attr.set_pgroup(pgid);
+        attr.set_sigdefault();
+
+        #[allow(unused_mut)]
+        let mut actions = file_actions(stdin_fd, stdout_fd, stderr_fd, fds_to_close, extra_dup2s);
+        #[cfg(any(target_os = "linux", target_os = "macos"))]
+        if let Some(dir) = &cwd {
+            actions.add_chdir(dir).map_err(|errno| SpawnError::new(errno, args[0], program))?;
+        }
+
+        let mut pid: libc::pid_t = 0;
+        let ret = unsafe {
+            libc::posix_spawnp(
+                &mut pid,
+                self.program_buf.as_ptr() as *const libc::c_char,
+                actions.as_ptr(),
+                attr.as_ptr(),
+                self.argv_ptrs.as_ptr(),
+                envp,
+            )
+        };
+
+        if ret != 0 {
+            return Err(SpawnError::new(ret, args[0], program));
+        }
+
+        Ok(pid)
     }
 }
 
-// ── spawn 関数 ────────────────────────────────────────────────────
+extern "C" {
+    static mut environ: *const *mut libc::c_char;
+}
 
-/// `posix_spawnp` で子プロセスを起動する。成功時は子 PID を返す。
+/// `items` を NUL 終端で `buf` に詰め、各要素を指す NULL 終端のポインタ配列を `ptrs` に作る。
 ///
-/// - `args`: コマンドと引数（`args[0]` がコマンド名、PATH 検索付き）
-/// - `pgid`: プロセスグループ ID（0 なら子 PID をリーダーにする）
-/// - `stdin_fd`: stdin に接続する fd（`None` なら継承）
-/// - `stdout_fd`: stdout に接続する fd（`None` なら継承）
-/// - `stderr_fd`: stderr に接続する fd（`None` なら継承）
-/// - `fds_to_close`: 子プロセスで閉じる fd のリスト（パイプの未使用端など）
-/// - `extra_dup2s`: 追加の fd 複製リスト（`2>&1` 等）。各タプル `(src_fd, dst_fd)` で `dup2(dst, src)` を実行
-pub fn spawn(
-    args: &[&str],
-    pgid: libc::pid_t,
+/// NUL を含む要素は `CString` に変換できないので空文字列にする。
+fn pack_cstrings<'a>(
+    items: impl Iterator<Item = std::borrow::Cow<'a, [u8]>>,
+    buf: &mut Vec<u8>,
+    offsets: &mut Vec<usize>,
+    ptrs: &mut Vec<*mut libc::c_char>,
+) {
+    buf.clear();
+    offsets.clear();
+    ptrs.clear();
+    for item in items {
+        offsets.push(buf.len());
+        if !item.contains(&0) {
+            buf.extend_from_slice(&item);
+        }
+        buf.push(0);
+    }
+    // 詰め終わってからポインタを取る（途中の再確保でずれないように）
+    let base = buf.as_mut_ptr();
+    ptrs.extend(offsets.iter().map(|&off| unsafe { base.add(off) } as *mut libc::c_char));
+    ptrs.push(std::ptr::null_mut()); // NULL 終端
+}
+
+/// fd のリダイレクトとクローズのファイルアクションを作る。
+fn file_actions(
     stdin_fd: Option<i32>,
     stdout_fd: Option<i32>,
     stderr_fd: Option<i32>,
     fds_to_close: &[i32],
     extra_dup2s: &[(i32, i32)],
-) -> Result<libc::pid_t, SpawnError> {
-    let argv = CStringVec::from_args(args);
-
-    // 属性: プロセスグループ + シグナルリセット
-    let mut attr = SpawnAttr::new();
-    attr.set_pgroup(pgid);
-    attr.set_sigdefault();
-
-    // ファイルアクション: fd のリダイレクト + クローズ
+) -> FileActions {
     let mut actions = FileActions::new();
 
     if let Some(fd) = stdin_fd {
@@ -252,31 +453,280 @@ pub fn spawn(
             actions.add_close(fd);
         }
     }
+    actions
+}
 
-    // environ を継承（std::env::set_var で設定済みの環境がそのまま渡る）
-    extern "C" {
-        static environ: *const *mut libc::c_char;
+/// `posix_spawn_file_actions_addchdir_np` がないプラットフォーム向けに、fork した子で
+/// [`file_actions`] と同じ fd 操作と `chdir` を行ってから exec する。
+///
+/// exec までに失敗した errno は close-on-exec のパイプで親に返す。
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+#[allow(clippy::too_many_arguments)]
+fn fork_spawn(
+    program: &[u8],
+    argv: &[*mut libc::c_char],
+    envp: *const *mut libc::c_char,
+    cwd: &CString,
+    pgid: libc::pid_t,
+    stdin_fd: Option<i32>,
+    stdout_fd: Option<i32>,
+    stderr_fd: Option<i32>,
+    fds_to_close: &[i32],
+    extra_dup2s: &[(i32, i32)],
+) -> Result<libc::pid_t, i32> {
+    let mut err_pipe = [0i32; 2];
+    unsafe {
+        if libc::pipe(err_pipe.as_mut_ptr()) != 0 {
+            return Err(std::io::Error::last_os_error().raw_os_error().unwrap_or(libc::EIO));
+        }
+        libc::fcntl(err_pipe[0], libc::F_SETFD, libc::FD_CLOEXEC);
+        libc::fcntl(err_pipe[1], libc::F_SETFD, libc::FD_CLOEXEC);
     }
 
-    let mut pid: libc::pid_t = 0;
-
-    let ret = unsafe {
-        libc::posix_spawnp(
-            &mut pid,
-            argv.as_ptr().read() as *const libc::c_char,
-            actions.as_ptr(),
-            attr.as_ptr(),
-            argv.as_ptr(),
-            environ as *const *mut libc::c_char,
-        )
-    };
+    let pid = unsafe { libc::fork() };
+    if pid < 0 {
+        let errno = std::io::Error::last_os_error().raw_os_error().unwrap_or(libc::EIO);
+        unsafe {
+            libc::close(err_pipe[0]);
+            libc::close(err_pipe[1]);
+        }
+        return Err(errno);
+    }
 
-    if ret != 0 {
-        return Err(SpawnError {
-            errno: ret,
-            command: args[0].to_string(),
-        });
+    if pid == 0 {
+        // 子プロセス: async-signal-safe な呼び出しだけを使う
+        unsafe {
+            libc::close(err_pipe[0]);
+            libc::setpgid(0, pgid);
+            for sig in [libc::SIGINT, libc::SIGTSTP, libc::SIGTTOU, libc::SIGTTIN] {
+                libc::signal(sig, libc::SIG_DFL);
+            }
+            for (fd, target) in [
+                (stdin_fd, libc::STDIN_FILENO),
+                (stdout_fd, libc::STDOUT_FILENO),
+                (stderr_fd, libc::STDERR_FILENO),
+            ] {
+                if let Some(fd) = fd {
+                    libc::dup2(fd, target);
+                    if fd != target {
+                        libc::close(fd);
+                    }
+                }
+            }
+            for &(src, dst) in extra_dup2s {
+                libc::dup2(dst, src);
+            }
+            for &fd in fds_to_close {
+                if ![stdin_fd, stdout_fd, stderr_fd].contains(&Some(fd)) {
+                    libc::close(fd);
+                }
+            }
+            if libc::chdir(cwd.as_ptr()) == 0 {
+                environ = envp;
+                libc::execvp(program.as_ptr() as *const libc::c_char, argv.as_ptr() as *const *const libc::c_char);
+            }
+            let errno = std::io::Error::last_os_error().raw_os_error().unwrap_or(libc::EIO);
+            libc::write(err_pipe[1], &errno as *const i32 as *const libc::c_void, std::mem::size_of::<i32>());
+            libc::_exit(127);
+        }
     }
 
+    // 親プロセス: exec に成功すればパイプは何も読めずに閉じる
+    unsafe {
+        libc::close(err_pipe[1]);
+        libc::setpgid(pid, if pgid == 0 { pid } else { pgid });
+        let mut errno: i32 = 0;
+        let n = libc::read(err_pipe[0], &mut errno as *mut i32 as *mut libc::c_void, std::mem::size_of::<i32>());
+        libc::close(err_pipe[0]);
+        if n == std::mem::size_of::<i32>() as isize {
+            libc::waitpid(pid, std::ptr::null_mut(), 0);
+            return Err(errno);
+        }
+    }
     Ok(pid)
 }
+
+// ── spawn 関数 ────────────────────────────────────────────────────
+
+/// `posix_spawnp` で子プロセスを起動する。成功時は子 PID を返す。
+///
+/// - `args`: コマンドと引数（`args[0]` がコマンド名、PATH 検索付き）
+/// - `env`: 子の環境（`KEY=VALUE` の列）。`None` ならシェルの `environ` を継承する
+/// - `cwd`: 子の作業ディレクトリ（`None` ならシェルと同じ）。シェル自身の cwd は変えない
+/// - `pgid`: プロセスグループ ID（0 なら子 PID をリーダーにする）
+/// - `stdin_fd`: stdin に接続する fd（`None` なら継承）
+/// - `stdout_fd`: stdout に接続する fd（`None` なら継承）
+/// - `stderr_fd`: stderr に接続する fd（`None` なら継承）
+/// - `fds_to_close`: 子プロセスで閉じる fd のリスト（パイプの未使用端など）
+/// - `extra_dup2s`: 追加の fd 複製リスト（`2>&1` 等）。各タプル `(src_fd, dst_fd)` で `dup2(dst, src)` を実行
+///
+/// executor は [`Shell`](crate::shell::Shell) が持つ [`SpawnContext::spawn_program`] を使う。
+/// こちらはベンチマーク等のライブラリ利用向け。
+#[allow(dead_code, clippy::too_many_arguments)]
+pub fn spawn(
+    args: &[&str],
+    env: Option<&[String]>,
+    cwd: Option<&Path>,
+    pgid: libc::pid_t,
+    stdin_fd: Option<i32>,
+    stdout_fd: Option<i32>,
+    stderr_fd: Option<i32>,
+    fds_to_close: &[i32],
+    extra_dup2s: &[(i32, i32)],
+) -> Result<libc::pid_t, SpawnError> {
+    spawn_program(args[0], args, env, cwd, pgid, stdin_fd, stdout_fd, stderr_fd, fds_to_close, extra_dup2s)
+}
+
+/// 実行ファイル `program` を指定して子プロセスを起動する。`argv[0]` は `args[0]` のまま。
+///
+/// [`CommandHash`](crate::shell::CommandHash) で解決した絶対パスなどを渡す用途。
+/// `program` に `/` が含まれれば `posix_spawnp` は PATH 検索を行わない。
+/// 呼び出しごとにバッファを確保するので、繰り返し起動するなら（executor と同じく）[`SpawnContext`] を使う。
+#[allow(clippy::too_many_arguments, dead_code)]
+pub fn spawn_program(
+    program: &str,
+    args: &[&str],
+    env: Option<&[String]>,
+    cwd: Option<&Path>,
+    pgid: libc::pid_t,
+    stdin_fd: Option<i32>,
+    stdout_fd: Option<i32>,
+    stderr_fd: Option<i32>,
+    fds_to_close: &[i32],
+    extra_dup2s: &[(i32, i32)],
+) -> Result<libc::pid_t, SpawnError> {
+    SpawnContext::new().spawn_program(
+        program, args, env, cwd, pgid, stdin_fd, stdout_fd, stderr_fd, fds_to_close, extra_dup2s,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    /// 起動に失敗するはずのコマンドを spawn し、エラーを返す。
+    fn spawn_err(program: &str) -> SpawnError {
+        match spawn_program(program, &[program], None, None, 0, None, None, None, &[], &[]) {
+            Ok(pid) => {
+                unsafe { libc::waitpid(pid, std::ptr::null_mut(), 0) };
+                panic!("{} should fail to spawn", program);
+            }
+            Err(e) => e,
+        }
+    }
+
+    /// stdout をパイプにして起動し、終了まで待って出力を返す。`ctx` が `None` なら [`spawn_program`] を使う。
+    fn spawn_output(ctx: Option<&mut SpawnContext>, args: &[&str], env: Option<&[String]>) -> String {
+        spawn_output_in(ctx, args, env, None)
+    }
+
+    fn spawn_output_in(
+        ctx: Option<&mut SpawnContext>,
+        args: &[&str],
+        env: Option<&[String]>,
+        cwd: Option<&Path>,
+    ) -> String {
+        let mut fds = [0i32; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        let pid = match ctx {
+            Some(ctx) => ctx.spawn_program(args[0], args, env, cwd, 0, None, Some(fds[1]), None, &[fds[0]], &[]),
+            None => spawn_program(args[0], args, env, cwd, 0, None, Some(fds[1]), None, &[fds[0]], &[]),
+        }
+        .unwrap_or_else(|e| panic!("{}", e));
+        unsafe { libc::close(fds[1]) };
+        let mut out = Vec::new();
+        let mut buf = [0u8; 256];
+        loop {
+            let n = unsafe { libc::read(fds[0], buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+            if n <= 0 {
+                break;
+            }
+            out.extend_from_slice(&buf[..n as usize]);
+        }
+        unsafe {
+            libc::close(fds[0]);
+            libc::waitpid(pid, std::ptr::null_mut(), 0);
+        }
+        String::from_utf8_lossy(&out).into_owned()
+    }
+
+    #[test]
+    fn spawn_context_matches_one_shot_spawn() {
+        let mut ctx = SpawnContext::new();
+        // 引数の数・長さが変わってもバッファの使い回しで壊れない
+        for args in [
+            &["printf", "%s|", "a", "bb", "ccc"][..],
+            &["printf", "%s|", "x"][..],
+            &["printf", "%s|", "long argument with spaces", "", "日本"][..],
+        ] {
+            assert_eq!(spawn_output(Some(&mut ctx), args, None), spawn_output(None, args, None));
+        }
+        assert_eq!(spawn_output(Some(&mut ctx), &["printf", "%s", "x"], None), "x");
+
+        // 明示的な環境は内容が変わったときだけ作り直す
+        let env = vec!["RUSH_CTX_A=1".to_string()];
+        assert_eq!(spawn_output(Some(&mut ctx), &["env"], Some(&env)), "RUSH_CTX_A=1\n");
+        assert_eq!(spawn_output(Some(&mut ctx), &["env"], Some(&env)), "RUSH_CTX_A=1\n");
+        let env = vec!["RUSH_CTX_A=2".to_string(), "RUSH_CTX_B=3".to_string()];
+        assert_eq!(spawn_output(Some(&mut ctx), &["env"], Some(&env)), "RUSH_CTX_A=2\nRUSH_CTX_B=3\n");
+        assert_eq!(ctx.env_vars.as_deref(), Some(&env[..]));
+    }
+
+    #[test]
+    fn spawn_with_cwd_runs_in_that_directory() {
+        let dir = std::env::temp_dir().join(format!("rush_spawn_cwd_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir = dir.canonicalize().unwrap();
+        let before = std::env::current_dir().unwrap();
+        assert_eq!(spawn_output_in(None, &["pwd", "-P"], None, Some(&dir)).trim_end(), dir.to_str().unwrap());
+        let mut ctx = SpawnContext::new();
+        assert_eq!(spawn_output_in(Some(&mut ctx), &["ls"], None, Some(&dir)), "");
+        // シェル自身の cwd は変わらない
+        assert_eq!(std::env::current_dir().unwrap(), before);
+        // 存在しないディレクトリは起動エラーになる
+        let missing = dir.join("missing");
+        assert!(spawn_program("pwd", &["pwd"], None, Some(&missing), 0, None, None, None, &[], &[]).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn spawn_command_not_found() {
+        let e = spawn_err("rush_no_such_command_xyz");
+        assert_eq!(e.exit_status(), 127);
+        assert_eq!(e.to_string(), "rush: rush_no_such_command_xyz: command not found");
+    }
+
+    #[test]
+    fn not_found_matches_spawn_failure() {
+        let e = SpawnError::not_found("rush_no_such_command_xyz");
+        assert_eq!(e.exit_status(), 127);
+        assert_eq!(e.to_string(), spawn_err("rush_no_such_command_xyz").to_string());
+    }
+
+    #[test]
+    fn spawn_missing_path() {
+        let e = spawn_err("./rush_no_such_file_xyz");
+        assert_eq!(e.exit_status(), 127);
+        assert_eq!(e.message(), "No such file or directory");
+    }
+
+    #[test]
+    fn spawn_directory() {
+        let dir = std::env::temp_dir();
+        let e = spawn_err(dir.to_str().unwrap());
+        assert_eq!(e.exit_status(), 126);
+        assert_eq!(e.message(), "Is a directory");
+    }
+
+    #[test]
+    fn spawn_not_executable() {
+        let path = std::env::temp_dir().join(format!("rush_spawn_noexec_{}", std::process::id()));
+        std::fs::write(&path, "echo hi\n").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        let e = spawn_err(path.to_str().unwrap());
+        assert_eq!(e.exit_status(), 126);
+        assert_eq!(e.message(), "Permission denied");
+        std::fs::remove_file(&path).unwrap();
+    }
+}